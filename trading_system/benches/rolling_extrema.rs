@@ -0,0 +1,63 @@
+// cargo bench --bench rolling_extrema
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+
+use trading_system::indicators::max_in_range::MaxmimumInRange;
+use trading_system::indicators::NextI;
+
+// the old ring buffer: rescans the whole window whenever the max leaves it
+struct RescanMax {
+    window: Vec<f64>,
+    index: usize,
+    max_index: usize,
+}
+
+impl RescanMax {
+    fn new(period: usize) -> Self {
+        RescanMax {
+            window: vec![f64::NEG_INFINITY; period],
+            index: 0,
+            max_index: 0,
+        }
+    }
+
+    fn next(&mut self, input: f64) -> f64 {
+        self.index = (self.index + 1) % self.window.len();
+        self.window[self.index] = input;
+        if self.index == self.max_index {
+            self.max_index = (0..self.window.len())
+                .max_by(|a, b| self.window[*a].partial_cmp(&self.window[*b]).unwrap())
+                .unwrap_or(0);
+        } else if input > self.window[self.max_index] {
+            self.max_index = self.index;
+        }
+        self.window[self.max_index]
+    }
+}
+
+fn rolling_max(c: &mut Criterion) {
+    // a falling market, the max leaves the window on every bar
+    let inputs: Vec<f64> = (0..10_000).map(|x| 100_000.0 - x as f64).collect();
+    let mut group = c.benchmark_group("rolling max of 10000 falling bars");
+    for period in [14u64, 200, 1440].iter() {
+        group.bench_with_input(BenchmarkId::new("deque", period), period, |b, period| {
+            b.iter(|| {
+                let mut max = MaxmimumInRange::new(*period, 0);
+                for input in inputs.iter() {
+                    black_box(max.next(*input));
+                }
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("rescan", period), period, |b, period| {
+            b.iter(|| {
+                let mut max = RescanMax::new(*period as usize);
+                for input in inputs.iter() {
+                    black_box(max.next(*input));
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, rolling_max);
+criterion_main!(benches);
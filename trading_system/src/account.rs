@@ -0,0 +1,446 @@
+use crate::bars::BarKind;
+use crate::credentials::{self, Credentials};
+use crate::execution::ExecConfig;
+use crate::market::Pair;
+use crate::portfolio::SizingConfig;
+use crate::trading_strategies;
+use crate::universe::{TickerStats, UniverseRules};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::sync::Arc;
+
+// an account is one book: its own keys, pairs, strategies, capital split, state files and risk limits.
+// every account runs its own action and order threads. they all share one market data feed.
+// see docs/accounts.txt for the config file.
+
+pub const DEFAULT_ACCOUNT_NAME: &str = "main";
+// algorithms start out in the home asset, unless an account sets its own
+pub const DEFAULT_HOME_ASSET: &str = "USDT";
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RiskLimits {
+    // largest buy in the reporting currency. bigger buys are cut down to this.
+    pub max_order_notional: Option<f64>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AccountConfig {
+    pub name: String,
+    // env var prefix for the account's keys, see credentials.rs
+    pub credentials_prefix: String,
+    // asset every algorithm starts in(algo_status 0), and the currency positions are valued in
+    pub home: String,
+    pub reporting: String,
+    // traded pairs, e.g. ETHUSDT, ETHBTC, BNBETH. with a universe, these are always traded on top of the selected pairs.
+    pub symbols: Vec<String>,
+    pub universe: Option<UniverseRules>,
+    // bars built from the trade stream, None trades on the exchange's 1m klines. see bars.rs.
+    pub bars: Option<BarKind>,
+    // how signals are turned into orders, see execution.rs
+    pub execution: ExecConfig,
+    // one strategy per algorithm, with the share of capital it trades
+    pub strategies: Vec<String>,
+    pub capital_split: Vec<f64>,
+    // how strategy targets are sized, see portfolio.rs
+    pub sizing: SizingConfig,
+    pub var_file: String,
+    pub journal_file: String,
+    pub risk: RiskLimits,
+}
+
+impl AccountConfig {
+    pub fn default_account() -> Self {
+        // the single account used when there is no accounts file, same as before accounts existed
+        AccountConfig {
+            name: DEFAULT_ACCOUNT_NAME.to_string(),
+            credentials_prefix: credentials::DEFAULT_PREFIX.to_string(),
+            home: DEFAULT_HOME_ASSET.to_string(),
+            reporting: DEFAULT_HOME_ASSET.to_string(),
+            symbols: vec![
+                "ETHUSDT".to_string(),
+                "BTCUSDT".to_string(),
+                "LTCUSDT".to_string(),
+            ],
+            universe: None,
+            bars: None,
+            execution: ExecConfig::default(),
+            strategies: vec!["ema_sma_crossover".to_string()],
+            capital_split: vec![1.0],
+            sizing: SizingConfig::default(),
+            var_file: "../var_files.txt".to_string(),
+            journal_file: "../logs/journal.jsonl".to_string(),
+            risk: RiskLimits::default(),
+        }
+    }
+
+    pub fn from_value(account: &Value) -> Result<Self, String> {
+        let name = match account["name"].as_str() {
+            Some(name)
+                if !name.is_empty()
+                    && name
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') =>
+            {
+                name.to_string()
+            }
+            _ => {
+                return Err(
+                    "account name is missing or has characters other than a-z, 0-9, _ and -"
+                        .to_string(),
+                )
+            }
+        };
+        let string_list = |field: &str| -> Result<Vec<String>, String> {
+            account[field]
+                .as_array()
+                .and_then(|x| {
+                    x.iter()
+                        .map(|y| y.as_str().map(|z| z.to_string()))
+                        .collect()
+                })
+                .ok_or(format!(
+                    "account {}: {} must be a list of strings",
+                    name, field
+                ))
+        };
+        let home = account["home"]
+            .as_str()
+            .unwrap_or(DEFAULT_HOME_ASSET)
+            .to_uppercase();
+        let reporting = account["reporting"]
+            .as_str()
+            .map(|x| x.to_uppercase())
+            .unwrap_or_else(|| home.clone());
+        // "coins" is shorthand for pairs against the home asset
+        let mut symbols = Vec::new();
+        if !account["coins"].is_null() {
+            for coin in string_list("coins")? {
+                let coin = coin.to_uppercase();
+                if coin == home {
+                    return Err(format!(
+                        "account {}: {} is the home asset, not a coin",
+                        name, coin
+                    ));
+                }
+                symbols.push(format!("{}{}", coin, home));
+            }
+        }
+        if !account["symbols"].is_null() {
+            for symbol in string_list("symbols")? {
+                let symbol = symbol.to_uppercase();
+                if !symbols.contains(&symbol) {
+                    symbols.push(symbol);
+                }
+            }
+        }
+        let universe = if account["universe"].is_null() {
+            None
+        } else {
+            Some(
+                UniverseRules::from_value(&account["universe"], &home)
+                    .map_err(|e| format!("account {}: {}", name, e))?,
+            )
+        };
+        let bars = match account["bars"].as_str() {
+            Some(spec) => {
+                Some(BarKind::parse(spec).map_err(|e| format!("account {}: {}", name, e))?)
+            }
+            None if account["bars"].is_null() => None,
+            None => return Err(format!("account {}: bars must be a string", name)),
+        };
+        let execution = ExecConfig::from_value(&account["execution"])
+            .map_err(|e| format!("account {}: {}", name, e))?;
+        let sizing = SizingConfig::from_value(&account["sizing"])
+            .map_err(|e| format!("account {}: {}", name, e))?;
+        // a combination of strategies is an object, kept as its json text
+        let strategies: Vec<String> = account["strategies"]
+            .as_array()
+            .and_then(|x| {
+                x.iter()
+                    .map(|y| match y {
+                        Value::String(z) => Some(z.clone()),
+                        Value::Object(_) => Some(y.to_string()),
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .ok_or(format!(
+                "account {}: strategies must be a list of names and combinations",
+                name
+            ))?;
+        let capital_split: Vec<f64> = account["capital_split"]
+            .as_array()
+            .and_then(|x| x.iter().map(|y| y.as_f64()).collect())
+            .ok_or(format!(
+                "account {}: capital_split must be a list of numbers",
+                name
+            ))?;
+
+        if symbols.is_empty() && universe.is_none() {
+            return Err(format!(
+                "account {}: no coins, symbols or universe to trade",
+                name
+            ));
+        }
+        for strategy in strategies.iter() {
+            trading_strategies::PairStrategy::from_spec(strategy)
+                .map_err(|e| format!("account {}: {}", name, e))?;
+        }
+        if strategies.is_empty() || strategies.len() != capital_split.len() {
+            return Err(format!(
+                "account {}: needs one capital_split entry per strategy",
+                name
+            ));
+        }
+        let split_sum: f64 = capital_split.iter().sum();
+        if capital_split.iter().any(|x| *x <= 0.0) || split_sum > 1.0 + 1e-9 {
+            return Err(format!(
+                "account {}: capital_split must be positive and add up to at most 1",
+                name
+            ));
+        }
+
+        Ok(AccountConfig {
+            credentials_prefix: account["credentials"]
+                .as_str()
+                .unwrap_or(credentials::DEFAULT_PREFIX)
+                .to_string(),
+            home,
+            reporting,
+            symbols,
+            universe,
+            bars,
+            execution,
+            strategies,
+            capital_split,
+            sizing,
+            var_file: account["var_file"]
+                .as_str()
+                .map(|x| x.to_string())
+                .unwrap_or_else(|| format!("../var_files_{}.txt", name)),
+            journal_file: account["journal"]
+                .as_str()
+                .map(|x| x.to_string())
+                .unwrap_or_else(|| format!("../logs/journal_{}.jsonl", name)),
+            risk: RiskLimits {
+                max_order_notional: account["max_order_notional"].as_f64(),
+            },
+            name,
+        })
+    }
+
+    pub fn select_symbols(
+        &self,
+        all_pairs: &HashMap<String, Pair>,
+        stats: &HashMap<String, TickerStats>,
+    ) -> Vec<String> {
+        /*
+            The configured symbols, then the pairs the universe rules pick.
+        */
+        let mut symbols = self.symbols.clone();
+        if let Some(universe) = &self.universe {
+            for symbol in universe.select(all_pairs, stats) {
+                if !symbols.contains(&symbol) {
+                    symbols.push(symbol);
+                }
+            }
+        }
+        symbols
+    }
+}
+
+pub fn parse_config(config: &Value) -> Result<Vec<AccountConfig>, String> {
+    let mut accounts: Vec<AccountConfig> = Vec::new();
+    for account in config["accounts"].as_array().unwrap_or(&Vec::new()) {
+        let account = AccountConfig::from_value(account)?;
+        // two accounts writing the same state file would overwrite each other, and two on the same keys would both
+        // spend the one exchange balance
+        for other in accounts.iter() {
+            if other.name == account.name
+                || other.var_file == account.var_file
+                || other.journal_file == account.journal_file
+                || other.credentials_prefix == account.credentials_prefix
+            {
+                return Err(format!(
+                    "accounts {} and {} share a name, var_file, journal or credentials",
+                    other.name, account.name
+                ));
+            }
+        }
+        accounts.push(account);
+    }
+    if accounts.is_empty() {
+        return Err("no accounts configured".to_string());
+    }
+    Ok(accounts)
+}
+
+pub fn load_configs(path: &str) -> Result<Vec<AccountConfig>, String> {
+    /*
+        Reads the accounts file. Without one, there is a single account with the built-in settings.
+    */
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            return Ok(vec![AccountConfig::default_account()])
+        }
+        Err(e) => return Err(format!("could not read {}: {}", path, e)),
+    };
+    let config: Value = serde_json::from_str(&contents).map_err(|e| format!("{}: {}", path, e))?;
+    parse_config(&config)
+}
+
+pub struct Account {
+    pub config: AccountConfig,
+    pub credentials: Arc<Credentials>,
+}
+
+impl Account {
+    pub fn pairs(
+        &self,
+        symbols: &[String],
+        all_pairs: &HashMap<String, Pair>,
+    ) -> Result<Vec<Pair>, String> {
+        /*
+            Looks up the account's symbols in exchange info, in the same order.
+        */
+        let mut pairs = Vec::new();
+        for symbol in symbols.iter() {
+            match all_pairs.get(symbol) {
+                Some(pair) => pairs.push(pair.clone()),
+                None => {
+                    return Err(format!(
+                        "account {}: {} is not listed on the exchange",
+                        self.config.name, symbol
+                    ))
+                }
+            }
+        }
+        let touches_home = pairs
+            .iter()
+            .any(|x| x.base == self.config.home || x.quote == self.config.home);
+        // an empty universe is allowed, the account waits for the next refresh
+        if !touches_home && !pairs.is_empty() {
+            return Err(format!(
+                "account {}: no pair trades the home asset {}",
+                self.config.name, self.config.home
+            ));
+        }
+        Ok(pairs)
+    }
+}
+
+pub fn open_accounts(configs: Vec<AccountConfig>) -> Result<Vec<Account>, String> {
+    /*
+        Loads every account's credentials. Every account trades its own exchange account, so two prefixes with the same
+        api key are refused, like two accounts with the same prefix are(see parse_config).
+    */
+    let mut accounts: Vec<Account> = Vec::new();
+    for config in configs {
+        let (credentials, source) = credentials::load(&config.credentials_prefix)
+            .map_err(|e| format!("account {}: {}", config.name, e))?;
+        if let Some(other) = accounts
+            .iter()
+            .find(|x| x.credentials.api_key() == credentials.api_key())
+        {
+            return Err(format!(
+                "accounts {} and {} use the same api key",
+                other.config.name, config.name
+            ));
+        }
+        log_info!(
+            "account {}: loaded api credentials from {}",
+            config.name,
+            source
+        );
+        accounts.push(Account {
+            config,
+            credentials: Arc::new(credentials),
+        });
+    }
+    Ok(accounts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn parses_accounts_with_defaults() {
+        let config = json!({"accounts": [
+            {"name": "conservative", "coins": ["eth", "btc"], "symbols": ["ethbtc"], "strategies": ["ema_sma_crossover"], "capital_split": [0.5]},
+            {"name": "aggressive", "credentials": "JANE_AGGRESSIVE", "home": "BTC", "symbols": ["BNBETH", "ETHBTC"], "strategies": ["ema_sma_crossover"],
+             "capital_split": [1.0], "max_order_notional": 250.0, "var_file": "../aggressive.txt"},
+        ]});
+        let accounts = parse_config(&config).unwrap();
+        assert_eq!(accounts[0].symbols, vec!["ETHUSDT", "BTCUSDT", "ETHBTC"]);
+        assert_eq!(accounts[0].home, "USDT");
+        assert_eq!(accounts[0].reporting, "USDT");
+        assert_eq!(accounts[0].credentials_prefix, "JANE");
+        assert_eq!(accounts[0].var_file, "../var_files_conservative.txt");
+        assert_eq!(
+            accounts[0].journal_file,
+            "../logs/journal_conservative.jsonl"
+        );
+        assert_eq!(accounts[0].risk.max_order_notional, None);
+        assert_eq!(accounts[1].credentials_prefix, "JANE_AGGRESSIVE");
+        assert_eq!(accounts[1].symbols, vec!["BNBETH", "ETHBTC"]);
+        assert_eq!(accounts[1].reporting, "BTC");
+        assert_eq!(accounts[1].var_file, "../aggressive.txt");
+        assert_eq!(accounts[1].risk.max_order_notional, Some(250.0));
+    }
+
+    #[test]
+    fn rejects_bad_accounts() {
+        let account = |fields: Value| {
+            let mut account = json!({"name": "a", "coins": ["ETH"], "strategies": ["ema_sma_crossover"], "capital_split": [1.0]});
+            for (key, value) in fields.as_object().unwrap() {
+                account[key] = value.clone();
+            }
+            parse_config(&json!({ "accounts": [account] }))
+        };
+        assert!(account(json!({})).is_ok());
+        assert!(account(json!({"name": "a b"})).is_err());
+        assert!(account(json!({"coins": ["USDT"]})).is_err());
+        assert!(account(json!({"coins": []})).is_err());
+        assert!(account(json!({"coins": [], "universe": {"top": 10}})).is_ok());
+        assert!(account(json!({"universe": {"refresh_minutes": 0}})).is_err());
+        assert!(account(json!({"bars": "dollar:1000000"})).is_ok());
+        assert!(account(json!({"bars": "renko:10"})).is_err());
+        assert!(account(json!({"execution": {"algo": "twap", "urgency": "high"}})).is_ok());
+        assert!(account(json!({"execution": {"algo": "twap", "slices": 0}})).is_err());
+        assert!(account(json!({"strategies": ["no_such_strategy"]})).is_err());
+        assert!(account(json!({"strategies": ["sma_crossover:50,200"]})).is_ok());
+        assert!(account(json!({"strategies": ["sma_crossover:50"]})).is_err());
+        // the old adx:<period>,<periods> reads as nothing now
+        assert!(account(json!({"strategies": ["adx:14,14"]})).is_err());
+        assert!(account(json!({"strategies": ["ema_sma_adx:12,26,14,25"]})).is_err());
+        let combined =
+            json!({"strategy": {"all": ["sma_crossover:5,20", "adx:14,14,25"]}, "cooldown": 30});
+        assert!(account(json!({ "strategies": [combined] })).is_ok());
+        assert!(account(json!({"strategies": [{"vote": ["ema_sma_crossover"]}]})).is_err());
+        assert!(account(
+            json!({"strategies": [{"weighted": ["adx:14,14,25"], "weights": [1, 2]}]})
+        )
+        .is_err());
+        assert!(account(json!({"sizing": {"method": "vol_target", "target_vol": 0.002}})).is_ok());
+        assert!(account(json!({"sizing": {"method": "martingale"}})).is_err());
+        assert!(account(json!({"capital_split": [0.5, 0.5]})).is_err());
+        assert!(account(json!({"capital_split": [1.5]})).is_err());
+
+        let same_name = json!({"accounts": [
+            {"name": "a", "coins": ["ETH"], "strategies": ["ema_sma_crossover"], "capital_split": [1.0]},
+            {"name": "a", "coins": ["BTC"], "strategies": ["ema_sma_crossover"], "capital_split": [1.0]},
+        ]});
+        assert!(parse_config(&same_name).is_err());
+        let same_credentials = json!({"accounts": [
+            {"name": "a", "coins": ["ETH"], "strategies": ["ema_sma_crossover"], "capital_split": [1.0]},
+            {"name": "b", "coins": ["BTC"], "strategies": ["ema_sma_crossover"], "capital_split": [1.0]},
+        ]});
+        assert!(parse_config(&same_credentials).is_err());
+        assert!(parse_config(&json!({"accounts": []})).is_err());
+    }
+}
@@ -0,0 +1,273 @@
+use crate::portfolio::{PlayStats, Position, Sizer, SizingConfig};
+use crate::strategies::{Target, TradingStrategy};
+use serde_json::Value;
+use std::fs;
+
+// runs one stateful strategy over a pair's bar history, the way the action loop would with a single algo.
+// a target decided on a bar's close is traded at the next bar's open, with a fee on what's traded, and sized and
+// stopped like a live position, see portfolio.rs. see docs/optimizer.txt.
+
+// 1m bars
+const DEFAULT_BARS_PER_YEAR: f64 = 365.0 * 24.0 * 60.0;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BacktestConfig {
+    // charged on the notional of every trade, in basis points
+    pub fee_bps: f64,
+    // to annualize the sharpe and sortino ratios
+    pub bars_per_year: f64,
+    pub sizing: SizingConfig,
+}
+
+impl Default for BacktestConfig {
+    fn default() -> Self {
+        BacktestConfig {
+            fee_bps: 10.0,
+            bars_per_year: DEFAULT_BARS_PER_YEAR,
+            sizing: SizingConfig::default(),
+        }
+    }
+}
+
+impl BacktestConfig {
+    pub fn from_value(backtest: &Value) -> Result<Self, String> {
+        let defaults = BacktestConfig::default();
+        let config = BacktestConfig {
+            fee_bps: backtest["fee_bps"].as_f64().unwrap_or(defaults.fee_bps),
+            bars_per_year: backtest["bars_per_year"]
+                .as_f64()
+                .unwrap_or(defaults.bars_per_year),
+            sizing: SizingConfig::from_value(&backtest["sizing"])?,
+        };
+        if config.fee_bps < 0.0 || config.bars_per_year <= 0.0 {
+            return Err(
+                "backtest: fee_bps can't be negative and bars_per_year must be above 0".to_string(),
+            );
+        }
+        Ok(config)
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BacktestResult {
+    pub total_return: f64,
+    pub sharpe: f64,
+    pub sortino: f64,
+    // largest fall from a high of the equity, as a share of the high
+    pub max_drawdown: f64,
+    pub trades: usize,
+    pub bars: usize,
+}
+
+pub fn read_bars(path: &str) -> Result<Vec<Vec<f64>>, String> {
+    /*
+        A csv of open,high,low,close,volume rows, oldest first. Lines that aren't five numbers, like a header, are skipped.
+    */
+    let contents = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    let bars: Vec<Vec<f64>> = contents
+        .lines()
+        .filter_map(|line| {
+            line.split(',')
+                .map(|x| x.trim().parse::<f64>().ok())
+                .collect::<Option<Vec<f64>>>()
+        })
+        .filter(|x| x.len() == 5)
+        .collect();
+    if bars.is_empty() {
+        return Err(format!("{}: no open,high,low,close,volume rows", path));
+    }
+    Ok(bars)
+}
+
+pub fn run(
+    strategy: &mut dyn TradingStrategy,
+    bars: &[Vec<f64>],
+    config: &BacktestConfig,
+) -> BacktestResult {
+    let fee = config.fee_bps / 10000.0;
+    let mut sizer = Sizer::new(&config.sizing);
+    let mut stats = PlayStats::default();
+    let mut position = Position::default();
+    // starts with 1 in cash
+    let (mut cash, mut units) = (1.0, 0.0);
+    let mut entry_equity = 0.0;
+    let mut pending: Option<Target> = None;
+    let mut equity = Vec::with_capacity(bars.len());
+    let mut trades = 0;
+
+    for bar in bars {
+        let (open, close) = (bar[0], bar[3]);
+        // trade to the last bar's target at this bar's open
+        if let Some(target) = pending.take() {
+            let value = cash + units * open;
+            let current = if value > 0.0 {
+                units * open / value
+            } else {
+                0.0
+            };
+            let band = config.sizing.rebalance_band;
+            let trade = target.weight <= 0.0 && units > 0.0
+                || target.weight > 0.0 && units == 0.0
+                || target.weight > 0.0 && (target.weight - current).abs() > band;
+            if trade && open > 0.0 {
+                let wanted = target.weight.max(0.0) * value / open;
+                cash -= (wanted - units) * open + (wanted - units).abs() * open * fee;
+                if units == 0.0 {
+                    entry_equity = value;
+                    position = Position {
+                        weight: target.weight,
+                        stop: target.stop,
+                        take_profit: target.take_profit,
+                        ..Position::default()
+                    };
+                }
+                units = wanted;
+                trades += 1;
+                if units == 0.0 {
+                    stats.record(cash / entry_equity - 1.0);
+                }
+            }
+        }
+
+        sizer.next(bar);
+        let target = strategy
+            .target(&bar.to_vec())
+            .filter(|_| strategy.is_ready());
+        if units > 0.0 {
+            if let Some(target) = target {
+                position.stop = target.stop.or(position.stop);
+                position.take_profit = target.take_profit.or(position.take_profit);
+            }
+        }
+        pending = if units > 0.0 && position.exit_hit(bar) {
+            Some(Target::weight(0.0))
+        } else {
+            target.map(|x| sizer.size(&config.sizing, x, &stats))
+        };
+        equity.push(cash + units * close);
+    }
+
+    metrics(&equity, trades, config.bars_per_year)
+}
+
+fn metrics(equity: &[f64], trades: usize, bars_per_year: f64) -> BacktestResult {
+    let returns: Vec<f64> = equity.windows(2).map(|x| x[1] / x[0] - 1.0).collect();
+    let n = returns.len().max(1) as f64;
+    let mean = returns.iter().sum::<f64>() / n;
+    let deviation = (returns.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / n).sqrt();
+    let downside = (returns.iter().map(|x| x.min(0.0).powi(2)).sum::<f64>() / n).sqrt();
+    let ratio = |risk: f64| {
+        if risk > 0.0 {
+            mean / risk * bars_per_year.sqrt()
+        } else {
+            0.0
+        }
+    };
+    let mut peak: f64 = 1.0;
+    let mut max_drawdown: f64 = 0.0;
+    for value in equity {
+        peak = peak.max(*value);
+        max_drawdown = max_drawdown.max(1.0 - value / peak);
+    }
+    BacktestResult {
+        total_return: equity.last().unwrap_or(&1.0) - 1.0,
+        sharpe: ratio(deviation),
+        sortino: ratio(downside),
+        max_drawdown,
+        trades,
+        bars: equity.len(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::strategies::sma_crossover::SMACrossover;
+
+    // the targets to give on each bar, then none
+    struct Targets(Vec<Option<Target>>, usize);
+
+    impl TradingStrategy for Targets {
+        fn run(&mut self, _ohlc: &Vec<f64>) -> i8 {
+            0
+        }
+
+        fn target(&mut self, _ohlc: &Vec<f64>) -> Option<Target> {
+            self.1 += 1;
+            self.0.get(self.1 - 1).cloned().flatten()
+        }
+
+        fn warm_up(&self) -> u64 {
+            0
+        }
+
+        fn is_ready(&self) -> bool {
+            true
+        }
+
+        fn to_string(&self) -> String {
+            "targets".to_string()
+        }
+    }
+
+    #[test]
+    fn trades_the_crossover_at_the_next_open() {
+        // up from 100 to 120, then back down to 100
+        let closes: Vec<f64> = (0..=20)
+            .chain((0..20).rev())
+            .map(|x| 100.0 + x as f64)
+            .collect();
+        let bars: Vec<Vec<f64>> = closes.iter().map(|x| vec![*x, *x, *x, *x, 1.0]).collect();
+        let config = BacktestConfig {
+            fee_bps: 0.0,
+            ..BacktestConfig::default()
+        };
+        let mut strategy = SMACrossover::new(vec![2.0, 4.0]);
+        let result = run(&mut strategy, &bars, &config);
+        // ready on the 4th bar at 103 and bought at the next open at 104. the fall crosses
+        // the averages at 118, sold at the next open at 117.
+        assert_eq!(result.trades, 2);
+        assert!((result.total_return - (117.0 / 104.0 - 1.0)).abs() < 1e-12);
+        assert!((result.max_drawdown - (1.0 - (117.0 / 104.0) / (120.0 / 104.0))).abs() < 1e-12);
+        assert!(result.sharpe > 0.0 && result.sortino > result.sharpe);
+
+        let config = BacktestConfig::default();
+        let with_fees = run(&mut SMACrossover::new(vec![2.0, 4.0]), &bars, &config);
+        assert!(with_fees.total_return < result.total_return);
+    }
+
+    #[test]
+    fn holds_a_partial_weight_until_the_stop() {
+        let bars = vec![
+            vec![100.0, 100.0, 100.0, 100.0, 1.0],
+            vec![100.0, 100.0, 100.0, 100.0, 1.0],
+            vec![110.0, 110.0, 110.0, 110.0, 1.0],
+            vec![100.0, 100.0, 94.0, 96.0, 1.0],
+            vec![96.0, 120.0, 96.0, 120.0, 1.0],
+        ];
+        let config = BacktestConfig {
+            fee_bps: 0.0,
+            ..BacktestConfig::default()
+        };
+        let half = |stop| Target {
+            weight: 0.5,
+            stop,
+            take_profit: None,
+        };
+        // half in at the second bar's open at 100, the other half kept in cash. the fourth bar trades
+        // through the stop at 95, sold at the next open at 96.
+        let result = run(
+            &mut Targets(vec![Some(half(Some(95.0)))], 0),
+            &bars,
+            &config,
+        );
+        assert_eq!(result.trades, 2);
+        assert!((result.total_return - (0.5 + 0.5 * 96.0 / 100.0 - 1.0)).abs() < 1e-12);
+        assert!((result.max_drawdown - (1.0 - 0.98 / 1.05)).abs() < 1e-12);
+
+        // without the stop it's still half in at the last close
+        let result = run(&mut Targets(vec![Some(half(None))], 0), &bars, &config);
+        assert_eq!(result.trades, 1);
+        assert!((result.total_return - (0.5 + 0.5 * 120.0 / 100.0 - 1.0)).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,234 @@
+use crate::binance_structs::{KLineMinute, OccuredTrade};
+
+// bars built from the trade stream instead of the exchange's 1m klines.
+// time bars cover a fixed period, volume, dollar and tick bars close after a set amount of trading.
+// an account picks its bars with "bars" in the accounts file, see _Bars_ in docs/accounts.txt.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BarKind {
+    // period in ms
+    Time(u64),
+    // base asset traded
+    Volume(f64),
+    // quote asset traded
+    Dollar(f64),
+    // number of trades
+    Tick(u64),
+}
+
+impl BarKind {
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        /*
+            Reads "time:<seconds>", "volume:<base qty>", "dollar:<quote qty>" or "tick:<trades>".
+        */
+        let (kind, size) = spec
+            .split_once(':')
+            .ok_or(format!("bars: {} is not <kind>:<size>", spec))?;
+        let size: f64 = size
+            .trim()
+            .parse()
+            .map_err(|_| format!("bars: {} is not a number", size))?;
+        if !size.is_finite() || size <= 0.0 {
+            return Err(format!("bars: size of {} must be positive", spec));
+        }
+        match kind.trim() {
+            "time" if size * 1000.0 >= 1.0 => Ok(BarKind::Time((size * 1000.0) as u64)),
+            "volume" => Ok(BarKind::Volume(size)),
+            "dollar" => Ok(BarKind::Dollar(size)),
+            "tick" if size >= 1.0 => Ok(BarKind::Tick(size as u64)),
+            "time" | "tick" => Err(format!("bars: size of {} is too small", spec)),
+            other => Err(format!("bars: unknown bar kind {}", other)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bar {
+    pub symbol: String,
+    pub start_time: u64,
+    pub end_time: u64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: f64,
+    pub quote_volume: f64,
+    pub ticks: u64,
+}
+
+impl Bar {
+    fn new(trade: &OccuredTrade) -> Self {
+        Bar {
+            symbol: trade.symbol.clone(),
+            start_time: trade.trade_time,
+            end_time: trade.trade_time,
+            open: trade.price,
+            high: trade.price,
+            low: trade.price,
+            close: trade.price,
+            volume: trade.quantity,
+            quote_volume: trade.price * trade.quantity,
+            ticks: 1,
+        }
+    }
+
+    fn add(&mut self, trade: &OccuredTrade) {
+        self.high = self.high.max(trade.price);
+        self.low = self.low.min(trade.price);
+        self.close = trade.price;
+        self.volume += trade.quantity;
+        self.quote_volume += trade.price * trade.quantity;
+        self.ticks += 1;
+        self.end_time = self.end_time.max(trade.trade_time);
+    }
+
+    pub fn to_kline(&self) -> KLineMinute {
+        // closed bars go down the same path as exchange klines
+        KLineMinute {
+            start_time: self.start_time,
+            end_time: self.end_time,
+            symbol: self.symbol.clone(),
+            open: self.open,
+            high: self.high,
+            low: self.low,
+            close: self.close,
+            quantity: self.volume,
+            num_trades: self.ticks,
+            closed: true,
+        }
+    }
+}
+
+pub struct BarBuilder {
+    kind: BarKind,
+    current: Option<Bar>,
+}
+
+impl BarBuilder {
+    pub fn new(kind: BarKind) -> Self {
+        BarBuilder {
+            kind,
+            current: None,
+        }
+    }
+
+    pub fn push(&mut self, trade: &OccuredTrade) -> Option<Bar> {
+        /*
+            Adds a trade and returns the bar it closed, if any.
+            A time bar closes with the first trade of the next period, so a period without trades has no bar.
+            Volume, dollar and tick bars close on the trade that reaches the size. That trade is not split,
+            it goes into the closing bar whole.
+        */
+        if let BarKind::Time(period) = self.kind {
+            let start_time = trade.trade_time / period * period;
+            let closed = match &self.current {
+                Some(bar) if bar.start_time != start_time => self.current.take(),
+                _ => None,
+            };
+            match &mut self.current {
+                Some(bar) => bar.add(trade),
+                None => {
+                    let mut bar = Bar::new(trade);
+                    bar.start_time = start_time;
+                    bar.end_time = start_time + period - 1;
+                    self.current = Some(bar);
+                }
+            }
+            return closed;
+        }
+        let bar = match &mut self.current {
+            Some(bar) => {
+                bar.add(trade);
+                bar
+            }
+            None => self.current.insert(Bar::new(trade)),
+        };
+        let full = match self.kind {
+            BarKind::Volume(size) => bar.volume >= size,
+            BarKind::Dollar(size) => bar.quote_volume >= size,
+            BarKind::Tick(size) => bar.ticks >= size,
+            BarKind::Time(_) => false,
+        };
+        if full {
+            self.current.take()
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trade(trade_time: u64, price: f64, quantity: f64) -> OccuredTrade {
+        OccuredTrade {
+            event_type: "aggTrade".to_string(),
+            event_time: trade_time,
+            symbol: "ETHUSDT".to_string(),
+            trade_id: trade_time,
+            price,
+            quantity,
+            buyer_id: 0,
+            seller_id: 0,
+            trade_time,
+            buyermm: false,
+            ignore: false,
+        }
+    }
+
+    #[test]
+    fn builds_time_bars() {
+        let mut builder = BarBuilder::new(BarKind::parse("time:60").unwrap());
+        assert_eq!(builder.push(&trade(60_500, 10.0, 1.0)), None);
+        assert_eq!(builder.push(&trade(61_000, 12.0, 1.0)), None);
+        assert_eq!(builder.push(&trade(119_999, 9.0, 2.0)), None);
+        // the gap from 120000 to 180000 has no trades and no bar
+        let bar = builder.push(&trade(180_000, 11.0, 1.0)).unwrap();
+        assert_eq!((bar.start_time, bar.end_time), (60_000, 119_999));
+        assert_eq!(
+            (bar.open, bar.high, bar.low, bar.close),
+            (10.0, 12.0, 9.0, 9.0)
+        );
+        assert_eq!((bar.volume, bar.ticks), (4.0, 3));
+        assert_eq!(bar.quote_volume, 40.0);
+    }
+
+    #[test]
+    fn builds_volume_dollar_and_tick_bars() {
+        let trades = [
+            trade(1, 10.0, 1.0),
+            trade(2, 11.0, 1.5),
+            trade(3, 12.0, 1.0),
+            trade(4, 10.0, 3.0),
+        ];
+        let closed = |spec: &str| -> Vec<Bar> {
+            let mut builder = BarBuilder::new(BarKind::parse(spec).unwrap());
+            trades.iter().filter_map(|x| builder.push(x)).collect()
+        };
+
+        let bars = closed("volume:2");
+        assert_eq!(bars.len(), 2);
+        assert_eq!((bars[0].volume, bars[0].end_time), (2.5, 2));
+        assert_eq!(
+            (bars[1].open, bars[1].close, bars[1].volume),
+            (12.0, 10.0, 4.0)
+        );
+
+        let bars = closed("dollar:30");
+        assert_eq!(bars.len(), 2);
+        assert_eq!((bars[0].quote_volume, bars[0].ticks), (38.5, 3));
+        assert_eq!((bars[1].quote_volume, bars[1].ticks), (30.0, 1));
+
+        let bars = closed("tick:2");
+        assert_eq!(bars.len(), 2);
+        assert_eq!(
+            (bars[1].start_time, bars[1].high, bars[1].low),
+            (3, 12.0, 10.0)
+        );
+
+        assert!(BarKind::parse("renko:5").is_err());
+        assert!(BarKind::parse("tick:0").is_err());
+        assert!(BarKind::parse("volume").is_err());
+    }
+}
@@ -1,17 +1,32 @@
 use crate::binance_structs;
-use curl::easy::Easy;
+use crate::credentials::{self, Credentials};
+use crate::metrics;
+use crate::network;
+use crate::rate_limiter;
+use crate::time_sync;
+use crossbeam_channel::Sender;
 use hmac::{Hmac, Mac, NewMac};
 use serde_json::{json, Error, Value};
 use sha2::Sha256;
 use std::collections::HashMap;
-use std::fs::File;
-use std::io::Read;
-use std::io::Write;
 use std::str;
-use std::sync::mpsc::Sender;
-use tungstenite::connect;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+use tokio::stream::StreamExt;
+use tokio::time::{delay_for, timeout};
+use tokio_tls::TlsStream;
+use tokio_tungstenite::{client_async, WebSocketStream};
 use url::Url;
 
+// delay before reconnecting a dropped websocket stream
+const WS_RECONNECT_DELAY_SECS: u64 = 5;
+// a kline stream sends an update every few seconds, so this much silence means the connection is dead
+const WS_READ_TIMEOUT_SECS: u64 = 60;
+
+type BinanceSocket = WebSocketStream<TlsStream<TcpStream>>;
+
 fn sign_hmac256(key: &str, message: &str) -> String {
     // returns hmac256 signature with hex
     type HmacSha256 = Hmac<Sha256>;
@@ -31,77 +46,166 @@ fn sign_hmac256(key: &str, message: &str) -> String {
     return signature_str;
 }
 
-fn binance_rest_req(api_key: &str, url: String, req_type: String) -> String {
-    let client = reqwest::blocking::Client::new();
-    let mut response_str = String::new();
-    println!("requesting url: {}", url);
-    if req_type == "get" {
-        response_str = client
-            .get(&url)
-            .header("X-MBX-APIKEY", api_key)
-            .send()
-            .unwrap()
-            .text()
-            .unwrap();
+async fn binance_rest_req(
+    api_key: &str,
+    secret_key: Option<&str>,
+    url: String,
+    req_type: String,
+) -> String {
+    /*
+        Sends the request on the shared client. Failures and timeouts come back in binance's own error format,
+        {"code": -1, "msg": ...}, so callers handle them like any other rejected request.
+        With a secret_key, the request is stamped and signed right before it is sent.
+    */
+    log_debug!("requesting url: {}", url);
+    let endpoint = endpoint_label(&url);
+    let is_order = endpoint == "/api/v3/order" && req_type == "post";
+    rate_limiter::acquire(
+        &endpoint,
+        rate_limiter::endpoint_weight(&endpoint, &url),
+        is_order,
+    )
+    .await;
+    let url = match secret_key {
+        Some(secret_key) => sign_url(secret_key, &url),
+        None => url,
+    };
+    let request = if req_type == "get" {
+        network::client().get(&url)
     } else if req_type == "post" {
-        response_str = client
-            .post(&url)
-            .header("X-MBX-APIKEY", api_key)
-            .body("")
-            .send()
-            .unwrap()
-            .text()
-            .unwrap();
+        network::client().post(&url).body("")
+    } else {
+        return String::new();
+    };
+    // public endpoints are requested without a key before one is loaded
+    let request = if api_key.is_empty() {
+        request
+    } else {
+        request.header("X-MBX-APIKEY", api_key)
+    };
+    let request_start = Instant::now();
+    let response = match request.send().await {
+        Ok(response) => {
+            let success = response.status().is_success();
+            rate_limiter::record_response(
+                &endpoint,
+                response.status().as_u16(),
+                response.headers(),
+            );
+            response.text().await.map(|text| (success, text))
+        }
+        Err(e) => Err(e),
+    };
+    match response {
+        Ok((success, response_str)) => {
+            metrics::global().observe_rest(&endpoint, request_start.elapsed(), success);
+            response_str
+        }
+        Err(e) => {
+            metrics::global().observe_rest(&endpoint, request_start.elapsed(), false);
+            let reason = request_error_reason(&e);
+            log_warn!("request to {} failed: {}", endpoint, reason);
+            json!({ "code": -1, "msg": reason }).to_string()
+        }
+    }
+}
+
+fn sign_url(secret_key: &str, url: &str) -> String {
+    /*
+        Adds the timestamp(binance server time, see time_sync.rs) and recvWindow to the query, then signs it.
+        "https://api.binance.us/api/v3/account?" -> "...?timestamp=..&recvWindow=..&signature=.."
+    */
+    let mut url_parts = url.splitn(2, '?');
+    let base = url_parts.next().unwrap_or("");
+    let query = url_parts.next().unwrap_or("");
+    let stamp = format!(
+        "timestamp={}&recvWindow={}",
+        time_sync::server_time_ms(),
+        time_sync::recv_window_ms()
+    );
+    let message = if query.is_empty() {
+        stamp
+    } else {
+        format!("{}&{}", query, stamp)
+    };
+    let generated_hmac = sign_hmac256(secret_key, &message);
+    format!("{}?{}&signature={}", base, message, generated_hmac)
+}
+
+fn request_error_reason(error: &reqwest::Error) -> &'static str {
+    // the error's own message contains the full url, including signatures
+    if error.is_timeout() {
+        "request timed out"
+    } else if error.is_connect() {
+        "could not connect"
+    } else {
+        "request failed"
     }
-    return response_str;
+}
+
+fn endpoint_label(url: &str) -> String {
+    // "https://api.binance.us/api/v3/order?symbol=..." -> "/api/v3/order"
+    let path_start = url.find("://").map(|x| x + 3).unwrap_or(0);
+    let path = match url[path_start..].find('/') {
+        Some(i) => &url[path_start + i..],
+        None => "/",
+    };
+    path.split('?').next().unwrap_or(path).to_string()
 }
 
 pub fn json_rest_req(url: String, req_type: String, msg: HashMap<&str, String>) -> String {
-    let client = reqwest::blocking::Client::new();
-    let mut response_str = String::new();
-    println!("requesting url: {}", url);
-    if req_type == "get" {
-        let response = client
-            .get(&url)
-            .header("Content-Type", "application/json")
-            .json(&msg)
-            .send()
-            .unwrap();
-        let returned_headers = response.headers();
-        response_str = response.text().unwrap();
+    log_debug!("requesting url: {}", url);
+    let request = if req_type == "get" {
+        network::client().get(&url)
     } else if req_type == "post" {
-        let response = client
-            .post(&url)
-            .header("Content-Type", "application/json")
-            .json(&msg)
-            .send()
-            .unwrap();
-        let returned_headers = response.headers();
-        response_str = response.text().unwrap();
-    }
-    return response_str;
+        network::client().post(&url)
+    } else {
+        return String::new();
+    };
+    let request = request
+        .header("Content-Type", "application/json")
+        .json(&msg);
+    network::block_on(async {
+        match request.send().await {
+            Ok(response) => response.text().await.unwrap_or_default(),
+            Err(e) => {
+                log_warn!("request failed: {}", request_error_reason(&e));
+                String::new()
+            }
+        }
+    })
+}
+
+pub fn binance_trade_api(
+    credentials: &Credentials,
+    request: binance_structs::MarketRequest,
+) -> Value {
+    network::block_on(binance_trade_api_async(credentials, request))
 }
 
-pub fn binance_trade_api(request: binance_structs::MarketRequest) -> Value {
-    // find keys
-    let mut key_file = File::open("../v0_1_0.key").unwrap();
-    let mut contents = String::new();
-    key_file.read_to_string(&mut contents);
-    let keys_json: Value = serde_json::from_str(&contents).unwrap();
-    let api_key = keys_json["api_key"].as_str().unwrap();
-    let secret_key = keys_json["secret_key"].as_str().unwrap();
+pub async fn binance_trade_api_async(
+    credentials: &Credentials,
+    request: binance_structs::MarketRequest,
+) -> Value {
+    let api_key = credentials.api_key();
+    let secret_key = credentials.secret_key();
 
     let endpoint = "https://api.binance.us/api/v3/order?";
-    let message = request.to_string();
-    let generated_hmac = sign_hmac256(secret_key, &message);
-    let final_url = format!("{}{}&signature={}", endpoint, message, generated_hmac);
-    let raw_response_str = binance_rest_req(api_key, final_url, "post".to_string());
-    return serde_json::from_str(&raw_response_str).unwrap();
+    let final_url = format!("{}{}", endpoint, request.to_string());
+    let raw_response_str =
+        binance_rest_req(api_key, Some(secret_key), final_url, "post".to_string()).await;
+    let response: Value = serde_json::from_str(&raw_response_str).unwrap();
+    // orders are counted by their status, or as rejected when binance returns an error code
+    match response["status"].as_str() {
+        Some(status) => metrics::global().inc_order(status),
+        None => metrics::global().inc_order("rejected"),
+    }
+    return response;
 }
 
 // wrapper functions for convenient access to certain api elements
-pub fn new_listenkey(timestamp: u64) -> String {
-    let listen_key = binance_rest_api("new_listenkey", timestamp, "");
+pub fn new_listenkey() -> String {
+    let listen_key = binance_rest_api("new_listenkey", "");
     return listen_key["listenKey"].as_str().unwrap().to_string();
 }
 
@@ -116,7 +220,7 @@ pub fn fetch_klines(symbol_str: &String, end_time: u64, lookback: u64) -> Vec<Ve
     );
     let mut vec_to_return: Vec<Vec<f64>> = Vec::new();
     // let message = format!("symbol={}&interval=1m", symbol);
-    let klines_value = binance_rest_api("historicalkline", end_time, &message)
+    let klines_value = binance_rest_api("historicalkline", &message)
         .as_array()
         .unwrap()
         .clone();
@@ -132,24 +236,59 @@ pub fn fetch_klines(symbol_str: &String, end_time: u64, lookback: u64) -> Vec<Ve
     return vec_to_return;
 }
 
-pub fn binance_rest_api(interface: &str, timestamp: u64, arguments: &str) -> Value {
+pub fn fetch_kline_history(symbol: &String, end_time: u64, lookback_ms: u64) -> Vec<Vec<f64>> {
+    /*
+        1m klines from end_time - lookback_ms up to end_time, oldest first. fetched in chunks of 500.
+    */
+    let api_limit = 500;
+    let start_window = end_time - lookback_ms;
+    let mut end_chunk = end_time;
+    let mut history = Vec::new();
+    while end_chunk >= start_window {
+        let mut new_ohlcs = fetch_klines(symbol, end_chunk, api_limit);
+        new_ohlcs.append(&mut history);
+        history = new_ohlcs;
+        end_chunk -= api_limit * 60 * 1000;
+    }
+    history
+}
+
+pub async fn fetch_depth_snapshot(symbol: &str, limit: u64) -> Value {
+    binance_rest_api_async("depth", &format!("symbol={}&limit={}", symbol, limit)).await
+}
+
+pub fn binance_rest_api(interface: &str, arguments: &str) -> Value {
+    network::block_on(binance_rest_api_async(interface, arguments))
+}
+
+pub async fn binance_rest_api_async(interface: &str, arguments: &str) -> Value {
+    // uses the default credentials. account specific requests go through binance_account_api.
+    binance_account_api_async(credentials::get(), interface, arguments).await
+}
+
+pub fn binance_account_api(credentials: &Credentials, interface: &str, arguments: &str) -> Value {
+    network::block_on(binance_account_api_async(credentials, interface, arguments))
+}
+
+pub async fn binance_account_api_async(
+    credentials: &Credentials,
+    interface: &str,
+    arguments: &str,
+) -> Value {
     /*
         Requests certain things fron the Binance REST API based on predefined settings.
         This function returns the raw Serde Value, so if you want to parse the output, write a wrapper function.
+        Signed requests get their timestamp when they are sent, and are signed with the given credentials.
     */
     let base_url = "https://api.binance.us";
     let mut return_data = String::new();
 
-    // find keys
-    let mut key_file = File::open("../v0_1_0.key").unwrap();
-    let mut contents = String::new();
-    key_file.read_to_string(&mut contents);
-    let keys_json: Value = serde_json::from_str(&contents).unwrap();
-    let api_key = keys_json["api_key"].as_str().unwrap();
-    let secret_key = keys_json["secret_key"].as_str().unwrap();
+    let api_key = credentials.api_key();
+    let secret_key = credentials.secret_key();
 
     let mut final_url = String::new();
     let mut req_type = String::new();
+    let mut signed = false;
 
     // types of requests
     if interface == "new_listenkey" {
@@ -157,15 +296,35 @@ pub fn binance_rest_api(interface: &str, timestamp: u64, arguments: &str) -> Val
         final_url = format!("{}{}", base_url, endpoint);
         req_type = "post".to_string();
     } else if interface == "get_accountinfo" {
-        println!("running get_accountinfo...");
+        log_debug!("running get_accountinfo...");
         let endpoint = "/api/v3/account";
-        let appended_endpoint = format!("{}{}?", base_url, endpoint);
-        let message = &format!("timestamp={}&recvWindow=5000", timestamp);
-        let generated_hmac = sign_hmac256(secret_key, message);
-        final_url = format!(
-            "{}{}&signature={}",
-            appended_endpoint, message, generated_hmac
-        );
+        final_url = format!("{}{}?", base_url, endpoint);
+        req_type = "get".to_string();
+        signed = true;
+    } else if interface == "my_trades" {
+        // arguments must contain the symbol, e.g. "symbol=ETHUSDT&startTime=..."
+        let endpoint = "/api/v3/myTrades";
+        final_url = format!("{}{}?{}", base_url, endpoint, arguments);
+        req_type = "get".to_string();
+        signed = true;
+    } else if interface == "all_orders" {
+        // arguments must contain the symbol, e.g. "symbol=ETHUSDT&startTime=..."
+        let endpoint = "/api/v3/allOrders";
+        final_url = format!("{}{}?{}", base_url, endpoint, arguments);
+        req_type = "get".to_string();
+        signed = true;
+    } else if interface == "ticker_price" {
+        let endpoint = "/api/v3/ticker/price";
+        final_url = format!("{}{}?{}", base_url, endpoint, arguments);
+        req_type = "get".to_string();
+    } else if interface == "depth" {
+        // arguments must contain the symbol, e.g. "symbol=ETHUSDT&limit=1000"
+        let endpoint = "/api/v3/depth";
+        final_url = format!("{}{}?{}", base_url, endpoint, arguments);
+        req_type = "get".to_string();
+    } else if interface == "ticker_24hr" {
+        let endpoint = "/api/v3/ticker/24hr";
+        final_url = format!("{}{}?{}", base_url, endpoint, arguments);
         req_type = "get".to_string();
     } else if interface == "test_ping" {
         let endpoint = "/api/v3/ping";
@@ -189,89 +348,140 @@ pub fn binance_rest_api(interface: &str, timestamp: u64, arguments: &str) -> Val
         req_type = "get".to_string();
     }
 
-    let raw_response_str = binance_rest_req(api_key, final_url, req_type);
+    let secret = if signed { Some(secret_key) } else { None };
+    let raw_response_str = binance_rest_req(api_key, secret, final_url, req_type).await;
     // println!("raw_response_str: {}", raw_response_str);
     return serde_json::from_str(&raw_response_str).unwrap();
 }
 
-pub fn live_binance_stream(
-    stream_name: &str,
-    data_tx: &Sender<binance_structs::ReceivedData>,
-    init_tx: &Sender<bool>,
+async fn connect_stream(access_url: &str) -> Result<BinanceSocket, String> {
+    let url = Url::parse(access_url).map_err(|e| e.to_string())?;
+    let host = url.host_str().ok_or("url has no host")?.to_string();
+    let port = url.port_or_known_default().unwrap_or(443);
+    let tcp_stream = TcpStream::connect((host.as_str(), port))
+        .await
+        .map_err(|e| e.to_string())?;
+    let tls_connector = native_tls::TlsConnector::new().map_err(|e| e.to_string())?;
+    let tls_stream = tokio_tls::TlsConnector::from(tls_connector)
+        .connect(&host, tcp_stream)
+        .await
+        .map_err(|e| e.to_string())?;
+    let (socket, _response) = client_async(access_url, tls_stream)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(socket)
+}
+
+pub async fn live_binance_stream(
+    stream_name: String,
+    data_tx: Sender<binance_structs::ReceivedData>,
+    init_tx: Option<Sender<bool>>,
     stream_type: binance_structs::StreamType,
+    stop: Arc<AtomicBool>,
 ) {
-    let binance_base_endpoint = "wss://stream.binance.com:9443";
+    /*
+        Runs as a task on the network runtime. Reconnects whenever the stream drops or goes quiet
+        for WS_READ_TIMEOUT_SECS.
+        Reports to init_tx once it first connects, and ends once stop is set.
+    */
+    // order books are synced with snapshots from api.binance.us, so their diffs have to come from the same exchange
+    let binance_base_endpoint = match stream_type {
+        binance_structs::StreamType::Depth => "wss://stream.binance.us:9443",
+        _ => "wss://stream.binance.com:9443",
+    };
 
     let access_url = format!("{}/ws/{}", binance_base_endpoint, stream_name);
 
-    println!("attempting to access: {}", access_url);
-
-    let (mut socket, response) = connect(Url::parse(&access_url).unwrap()).expect("Can't connect.");
-
-    // println!("Connected to the server");
-    // println!("Response HTTP code: {}", response.status());
-    // println!("Response contains the following headers:");
-
-    for (ref header, _value) in response.headers() {
-        // println!("* {}", header);
-        // println!("{}", header);
-    }
-
-    init_tx.send(true).unwrap();
-    drop(init_tx);
-
+    let mut initialized = false;
     loop {
-        let msg = socket.read_message().expect("Error reading message");
-        let msg_string = format!("{}", msg);
-        if msg_string.chars().next().unwrap() != '{' {
-            continue;
+        if stop.load(Ordering::SeqCst) {
+            break;
         }
-        let parsed_msg: Value = serde_json::from_str(&msg_string).unwrap();
-        match stream_type {
-            binance_structs::StreamType::Trade => {
-                let constructed_trade = binance_structs::deserialize_trade(parsed_msg);
-                data_tx
-                    .send(binance_structs::ReceivedData::Trade(constructed_trade))
-                    .unwrap();
+        log_info!("attempting to access: {}", access_url);
+        let connect_timeout = Duration::from_secs(network::REQUEST_TIMEOUT_SECS);
+        let mut socket = match timeout(connect_timeout, connect_stream(&access_url)).await {
+            Ok(Ok(socket)) => socket,
+            Ok(Err(e)) => {
+                log_warn!("could not connect to {}: {}. retrying...", stream_name, e);
+                metrics::global().inc_ws_reconnect(&stream_name);
+                delay_for(Duration::from_secs(WS_RECONNECT_DELAY_SECS)).await;
+                continue;
             }
-            binance_structs::StreamType::Depth => {
-                data_tx
-                    .send(binance_structs::ReceivedData::Value(parsed_msg))
-                    .unwrap();
+            Err(_) => {
+                log_warn!("connecting to {} timed out. retrying...", stream_name);
+                metrics::global().inc_ws_reconnect(&stream_name);
+                delay_for(Duration::from_secs(WS_RECONNECT_DELAY_SECS)).await;
+                continue;
             }
-            binance_structs::StreamType::KLine => {
-                let constructed_kline = binance_structs::deserialize_kline(parsed_msg);
-                data_tx
-                    .send(binance_structs::ReceivedData::KLine(constructed_kline))
-                    .unwrap();
+        };
+
+        if !initialized {
+            if let Some(init_tx) = &init_tx {
+                init_tx.send(true).unwrap();
             }
-            binance_structs::StreamType::UserData => {
-                data_tx
-                    .send(binance_structs::ReceivedData::Value(parsed_msg))
-                    .unwrap();
+            initialized = true;
+        }
+
+        loop {
+            let next_msg = timeout(Duration::from_secs(WS_READ_TIMEOUT_SECS), socket.next()).await;
+            let msg = match next_msg {
+                Ok(Some(Ok(msg))) => msg,
+                Ok(Some(Err(e))) => {
+                    log_warn!("stream {} dropped: {}. reconnecting...", stream_name, e);
+                    break;
+                }
+                Ok(None) => {
+                    log_warn!("stream {} was closed. reconnecting...", stream_name);
+                    break;
+                }
+                Err(_) => {
+                    log_warn!(
+                        "no data on stream {} for {}s. reconnecting...",
+                        stream_name,
+                        WS_READ_TIMEOUT_SECS
+                    );
+                    break;
+                }
+            };
+            // nothing is sent after stop is set, even if the message was already in flight
+            if stop.load(Ordering::SeqCst) {
+                break;
+            }
+            let msg_string = format!("{}", msg);
+            if !msg_string.starts_with('{') {
+                continue;
+            }
+            let parsed_msg: Value = serde_json::from_str(&msg_string).unwrap();
+            match stream_type {
+                binance_structs::StreamType::Trade => {
+                    let constructed_trade = binance_structs::deserialize_trade(parsed_msg);
+                    data_tx
+                        .send(binance_structs::ReceivedData::Trade(constructed_trade))
+                        .unwrap();
+                }
+                binance_structs::StreamType::Depth => {
+                    data_tx
+                        .send(binance_structs::ReceivedData::Value(parsed_msg))
+                        .unwrap();
+                }
+                binance_structs::StreamType::KLine => {
+                    let constructed_kline = binance_structs::deserialize_kline(parsed_msg);
+                    data_tx
+                        .send(binance_structs::ReceivedData::KLine(constructed_kline))
+                        .unwrap();
+                }
+                binance_structs::StreamType::UserData => {
+                    data_tx
+                        .send(binance_structs::ReceivedData::Value(parsed_msg))
+                        .unwrap();
+                }
             }
         }
+        if stop.load(Ordering::SeqCst) {
+            break;
+        }
+        metrics::global().inc_ws_reconnect(&stream_name);
+        delay_for(Duration::from_secs(WS_RECONNECT_DELAY_SECS)).await;
     }
-}
-
-pub fn get_depth_snapshot(file_to_write: &str) -> std::io::Result<()> {
-    //let mut buf = Vec::new();
-    let mut buffer = File::create(file_to_write)?;
-    let mut handle = Easy::new();
-    handle
-        .url("https://www.binance.com/api/v1/depth?symbol=BNBBTC&limit=1000")
-        .unwrap();
-
-    let mut transfer = handle.transfer();
-    transfer
-        .write_function(|data| {
-            buffer.write(data).unwrap();
-            Ok(data.len())
-        })
-        .unwrap();
-    transfer.perform().unwrap();
-    //drop(transfer);
-    //let s = String::from_utf8(buf).expect("Found invalid UTF-8");
-    //return serde_json::from_str(&s).unwrap();
-    Ok(())
+    log_info!("stream {} stopped.", stream_name);
 }
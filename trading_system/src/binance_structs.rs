@@ -63,6 +63,7 @@ pub struct OccuredTrade {
     pub ignore: bool,
 }
 
+#[derive(Clone)]
 pub enum StreamType {
     Trade,
     Depth,
@@ -75,25 +76,33 @@ pub struct MarketRequest {
     /*
         Struct for a market order.
         quantity or quoteOrderQty must be -1.0. One and only one must be a valid value.
+        timestamp is when the order was decided on. the signed request is stamped with server time when it is sent.
+        limit_price other than -1.0 makes it an immediate-or-cancel limit order for quantity.
     */
     pub symbol: String,
     pub side: String,
     pub timestamp: u64,
     pub quantity: f64,
     pub quote_order_qty: f64,
+    pub limit_price: f64,
 }
 
 impl MarketRequest {
     pub fn to_string(self) -> String {
-        if self.quantity == -1.0 && self.quote_order_qty != -1.0 {
+        if self.limit_price != -1.0 && self.quantity != -1.0 {
+            return format!(
+                "symbol={}&side={}&quantity={:.8}&price={:.8}&type=LIMIT&timeInForce=IOC",
+                self.symbol, self.side, self.quantity, self.limit_price
+            );
+        } else if self.quantity == -1.0 && self.quote_order_qty != -1.0 {
             return format!(
-                "symbol={}&side={}&timestamp={}&quoteOrderQty={:.8}&type=MARKET",
-                self.symbol, self.side, self.timestamp, self.quote_order_qty
+                "symbol={}&side={}&quoteOrderQty={:.8}&type=MARKET",
+                self.symbol, self.side, self.quote_order_qty
             );
         } else if self.quote_order_qty == -1.0 && self.quantity != -1.0 {
             return format!(
-                "symbol={}&side={}&timestamp={}&quantity={:.8}&type=MARKET",
-                self.symbol, self.side, self.timestamp, self.quantity
+                "symbol={}&side={}&quantity={:.8}&type=MARKET",
+                self.symbol, self.side, self.quantity
             );
         } else {
             panic!(
@@ -121,17 +130,28 @@ pub fn deserialize_kline(raw_kline: Value) -> KLineMinute {
 
 pub fn deserialize_trade(received_trade: Value) -> OccuredTrade {
     // manual deserialization because serde's derive has incompatible dependencies
+    // reads @trade and @aggTrade events. aggTrade's id is "a", and it has no buyer or seller order ids.
+    let aggregated = received_trade["e"].as_str() == Some("aggTrade");
+    let (trade_id, buyer_id, seller_id) = if aggregated {
+        (received_trade["a"].as_u64().unwrap(), 0, 0)
+    } else {
+        (
+            received_trade["t"].as_u64().unwrap(),
+            received_trade["b"].as_u64().unwrap_or(0),
+            received_trade["a"].as_u64().unwrap_or(0),
+        )
+    };
     return OccuredTrade {
-        event_type: received_trade["e"].to_string(),
+        event_type: received_trade["e"].as_str().unwrap().to_string(),
         event_time: received_trade["E"].as_u64().unwrap(),
-        symbol: received_trade["s"].to_string(),
-        trade_id: received_trade["t"].as_u64().unwrap(),
+        symbol: received_trade["s"].as_str().unwrap().to_string(),
+        trade_id,
         price: received_trade["p"].as_str().unwrap().parse().unwrap(),
         quantity: received_trade["q"].as_str().unwrap().parse().unwrap(),
-        buyer_id: received_trade["b"].as_u64().unwrap(),
-        seller_id: received_trade["a"].as_u64().unwrap(),
+        buyer_id,
+        seller_id,
         trade_time: received_trade["T"].as_u64().unwrap(),
         buyermm: received_trade["m"].as_bool().unwrap(),
-        ignore: received_trade["M"].as_bool().unwrap(),
+        ignore: received_trade["M"].as_bool().unwrap_or(false),
     };
 }
@@ -0,0 +1,294 @@
+use aes_gcm::aead::generic_array::GenericArray;
+use aes_gcm::aead::{Aead, NewAead};
+use aes_gcm::Aes256Gcm;
+use hmac::Hmac;
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::sync::{Arc, OnceLock};
+use zeroize::Zeroizing;
+
+// binance api credentials, loaded once at startup from the first source that is set:
+//   1. <PREFIX>_API_KEY and <PREFIX>_SECRET_KEY
+//   2. an encrypted keystore at <PREFIX>_KEYSTORE, unlocked with <PREFIX>_KEYSTORE_PASSPHRASE or a passphrase prompt
+//   3. a key file at <PREFIX>_KEY_FILE that only its owner can read. JANE falls back to ../v0_1_0.key.
+// the prefix is JANE unless an account sets its own(see account.rs).
+// secrets are wiped from memory when dropped and never printed.
+
+pub const DEFAULT_PREFIX: &str = "JANE";
+const DEFAULT_KEY_FILE: &str = "../v0_1_0.key";
+const KEYSTORE_VERSION: u64 = 1;
+const KDF_ROUNDS: u32 = 200_000;
+
+pub struct Credentials {
+    api_key: Zeroizing<String>,
+    secret_key: Zeroizing<String>,
+}
+
+impl Credentials {
+    pub fn new(api_key: String, secret_key: String) -> Self {
+        Credentials {
+            api_key: Zeroizing::new(api_key),
+            secret_key: Zeroizing::new(secret_key),
+        }
+    }
+
+    pub fn api_key(&self) -> &str {
+        &self.api_key
+    }
+
+    pub fn secret_key(&self) -> &str {
+        &self.secret_key
+    }
+
+    pub fn from_json(contents: &str) -> Result<Self, String> {
+        /*
+            Parses {"api_key": "...", "secret_key": "..."}, the format of the key file.
+            The strings are moved out of the parsed value rather than copied.
+        */
+        let mut keys_json: Value =
+            serde_json::from_str(contents).map_err(|_| "credentials are not valid json")?;
+        let api_key = match keys_json["api_key"].take() {
+            Value::String(api_key) => Zeroizing::new(api_key),
+            _ => return Err("credentials have no api_key".to_string()),
+        };
+        let secret_key = match keys_json["secret_key"].take() {
+            Value::String(secret_key) => Zeroizing::new(secret_key),
+            _ => return Err("credentials have no secret_key".to_string()),
+        };
+        Ok(Credentials {
+            api_key,
+            secret_key,
+        })
+    }
+
+    fn to_json(&self) -> Zeroizing<String> {
+        Zeroizing::new(
+            json!({ "api_key": self.api_key.as_str(), "secret_key": self.secret_key.as_str() })
+                .to_string(),
+        )
+    }
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Credentials {{ api_key: <redacted>, secret_key: <redacted> }}"
+        )
+    }
+}
+
+static DEFAULT_CREDENTIALS: OnceLock<Arc<Credentials>> = OnceLock::new();
+// no keys, for market data requested before the default is set
+static NO_CREDENTIALS: OnceLock<Credentials> = OnceLock::new();
+
+pub fn set_default(credentials: Arc<Credentials>) {
+    // the default credentials are used for requests that don't belong to an account, e.g. market data
+    let _ = DEFAULT_CREDENTIALS.set(credentials);
+}
+
+pub fn get() -> &'static Credentials {
+    // the tools that run before the accounts are loaded, e.g. optimize fetching klines, go without keys
+    match DEFAULT_CREDENTIALS.get() {
+        Some(credentials) => credentials,
+        None => NO_CREDENTIALS.get_or_init(|| Credentials::new(String::new(), String::new())),
+    }
+}
+
+pub fn load(prefix: &str) -> Result<(Credentials, String), String> {
+    /*
+        Loads the credentials named by prefix, e.g. JANE reads JANE_API_KEY, JANE_KEYSTORE, JANE_KEY_FILE.
+        Returns the credentials and a description of where they came from.
+    */
+    let var = |name: &str| env::var(format!("{}_{}", prefix, name));
+    if let (Ok(api_key), Ok(secret_key)) = (var("API_KEY"), var("SECRET_KEY")) {
+        return Ok((
+            Credentials::new(api_key, secret_key),
+            format!("{}_API_KEY/{}_SECRET_KEY", prefix, prefix),
+        ));
+    }
+
+    if let Ok(keystore_path) = var("KEYSTORE") {
+        let keystore = fs::read_to_string(&keystore_path)
+            .map_err(|e| format!("could not read keystore {}: {}", keystore_path, e))?;
+        let passphrase = match var("KEYSTORE_PASSPHRASE") {
+            Ok(passphrase) => Zeroizing::new(passphrase),
+            Err(_) => Zeroizing::new(
+                rpassword::read_password_from_tty(Some(&format!(
+                    "passphrase for {}: ",
+                    keystore_path
+                )))
+                .map_err(|e| format!("could not read passphrase: {}", e))?,
+            ),
+        };
+        let credentials = decrypt_keystore(&keystore, &passphrase)?;
+        return Ok((credentials, format!("keystore {}", keystore_path)));
+    }
+
+    let key_file = match var("KEY_FILE") {
+        Ok(key_file) => key_file,
+        Err(_) if prefix == DEFAULT_PREFIX => DEFAULT_KEY_FILE.to_string(),
+        Err(_) => {
+            return Err(format!(
+                "none of {}_API_KEY/{}_SECRET_KEY, {}_KEYSTORE or {}_KEY_FILE is set",
+                prefix, prefix, prefix, prefix
+            ))
+        }
+    };
+    let credentials = read_key_file(&key_file)?;
+    // the default path is relative to the working directory, so say which file it ended up being
+    let shown_path = fs::canonicalize(&key_file)
+        .map(|x| x.display().to_string())
+        .unwrap_or(key_file);
+    Ok((credentials, format!("key file {}", shown_path)))
+}
+
+pub fn read_key_file(path: &str) -> Result<Credentials, String> {
+    check_permissions(path)?;
+    let contents = Zeroizing::new(
+        fs::read_to_string(path).map_err(|e| format!("could not read key file {}: {}", path, e))?,
+    );
+    Credentials::from_json(&contents)
+}
+
+#[cfg(unix)]
+fn check_permissions(path: &str) -> Result<(), String> {
+    // refuses files that group or others can access, like ssh does with private keys
+    use std::os::unix::fs::PermissionsExt;
+    let mode = fs::metadata(path)
+        .map_err(|e| format!("could not read key file {}: {}", path, e))?
+        .permissions()
+        .mode();
+    if mode & 0o077 != 0 {
+        return Err(format!(
+            "key file {} is accessible by other users(mode {:o}). run chmod 600 on it.",
+            path,
+            mode & 0o777
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn check_permissions(_path: &str) -> Result<(), String> {
+    Ok(())
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], rounds: u32) -> Zeroizing<[u8; 32]> {
+    let mut key = Zeroizing::new([0u8; 32]);
+    pbkdf2::pbkdf2::<Hmac<Sha256>>(passphrase.as_bytes(), salt, rounds, &mut key[..]);
+    key
+}
+
+pub fn encrypt_keystore(credentials: &Credentials, passphrase: &str) -> Result<String, String> {
+    /*
+        Encrypts the credentials with aes-256-gcm under a key derived from the passphrase(pbkdf2-sha256).
+        The keystore is a json object, see _Credentials_ in docs/operations.txt.
+    */
+    let mut salt = [0u8; 16];
+    let mut nonce = [0u8; 12];
+    getrandom::getrandom(&mut salt).map_err(|e| e.to_string())?;
+    getrandom::getrandom(&mut nonce).map_err(|e| e.to_string())?;
+    let key = derive_key(passphrase, &salt, KDF_ROUNDS);
+    let cipher = Aes256Gcm::new(GenericArray::from_slice(&key[..]));
+    let plaintext = credentials.to_json();
+    let ciphertext = cipher
+        .encrypt(GenericArray::from_slice(&nonce), plaintext.as_bytes())
+        .map_err(|_| "encryption failed")?;
+    Ok(json!({
+        "version": KEYSTORE_VERSION,
+        "kdf": "pbkdf2-sha256",
+        "rounds": KDF_ROUNDS,
+        "salt": hex::encode(salt),
+        "cipher": "aes-256-gcm",
+        "nonce": hex::encode(nonce),
+        "ciphertext": hex::encode(ciphertext),
+    })
+    .to_string())
+}
+
+pub fn decrypt_keystore(keystore: &str, passphrase: &str) -> Result<Credentials, String> {
+    let keystore: Value =
+        serde_json::from_str(keystore).map_err(|_| "keystore is not valid json")?;
+    if keystore["version"].as_u64() != Some(KEYSTORE_VERSION) {
+        return Err(format!(
+            "unsupported keystore version {}",
+            keystore["version"]
+        ));
+    }
+    let hex_field = |field: &str| {
+        keystore[field]
+            .as_str()
+            .and_then(|x| hex::decode(x).ok())
+            .ok_or(format!("keystore field {} is missing or not hex", field))
+    };
+    let salt = hex_field("salt")?;
+    let nonce = hex_field("nonce")?;
+    let ciphertext = hex_field("ciphertext")?;
+    if nonce.len() != 12 {
+        return Err("keystore nonce has the wrong length".to_string());
+    }
+    let rounds = keystore["rounds"].as_u64().unwrap_or(KDF_ROUNDS as u64) as u32;
+    let key = derive_key(passphrase, &salt, rounds);
+    let cipher = Aes256Gcm::new(GenericArray::from_slice(&key[..]));
+    let plaintext = Zeroizing::new(
+        cipher
+            .decrypt(GenericArray::from_slice(&nonce), ciphertext.as_slice())
+            .map_err(|_| "wrong passphrase or corrupted keystore")?,
+    );
+    let contents =
+        std::str::from_utf8(&plaintext).map_err(|_| "keystore contents are not utf-8")?;
+    Credentials::from_json(contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keystore_round_trip() {
+        let credentials = Credentials::new("my api key".to_string(), "my secret".to_string());
+        let keystore = encrypt_keystore(&credentials, "correct horse").unwrap();
+        assert!(!keystore.contains("my secret"));
+
+        let decrypted = decrypt_keystore(&keystore, "correct horse").unwrap();
+        assert_eq!(decrypted.api_key(), "my api key");
+        assert_eq!(decrypted.secret_key(), "my secret");
+        assert!(decrypt_keystore(&keystore, "wrong horse").is_err());
+    }
+
+    #[test]
+    fn debug_output_is_redacted() {
+        let credentials = Credentials::new("my api key".to_string(), "my secret".to_string());
+        let printed = format!("{:?}", credentials);
+        assert!(!printed.contains("my api key"));
+        assert!(!printed.contains("my secret"));
+    }
+
+    #[test]
+    fn goes_without_keys_until_the_default_is_set() {
+        assert_eq!(get().api_key(), "");
+        set_default(Arc::new(Credentials::new("a".to_string(), "s".to_string())));
+        assert_eq!(get().api_key(), "a");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn key_file_needs_owner_only_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+        let path = env::temp_dir().join(format!("jane_key_test_{}.key", std::process::id()));
+        let path_str = path.to_str().unwrap();
+        fs::write(&path, r#"{"api_key": "a", "secret_key": "s"}"#).unwrap();
+
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        assert!(read_key_file(path_str).is_err());
+
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+        let credentials = read_key_file(path_str).unwrap();
+        assert_eq!(credentials.secret_key(), "s");
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,613 @@
+use crate::binance_structs::MarketRequest;
+use crate::order_book::Side;
+use serde_json::Value;
+
+// execution algorithms between a signal and the exchange. a signal becomes a parent order, which is worked
+// through child orders: all at once(market), evenly over time(twap), along yesterday's volume curve(vwap),
+// as a share of the traded volume(pov) or as limit clips at the touch(iceberg). see docs/execution.txt.
+
+const DEFAULT_SLICES: usize = 10;
+// ohlc_history is 1m bars over the last day, so the bar a day before now starts the vwap volume curve
+const BAR_MS: u64 = 60 * 1000;
+const DAY_BARS: usize = 24 * 60;
+// children below min_notional are raised to this multiple of it, so rounding down doesn't get them rejected
+const MIN_CHILD_MARGIN: f64 = 1.1;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Style {
+    Market,
+    Twap,
+    Vwap,
+    Pov,
+    Iceberg,
+}
+
+impl Style {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Style::Market => "market",
+            Style::Twap => "twap",
+            Style::Vwap => "vwap",
+            Style::Pov => "pov",
+            Style::Iceberg => "iceberg",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Urgency {
+    Low,
+    Normal,
+    High,
+}
+
+impl Urgency {
+    fn defaults(&self) -> (u64, f64, f64) {
+        // (horizon in seconds, participation, limit_bps)
+        match self {
+            Urgency::Low => (1800, 0.05, 2.0),
+            Urgency::Normal => (600, 0.1, 5.0),
+            Urgency::High => (120, 0.25, 15.0),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExecConfig {
+    pub style: Style,
+    pub urgency: Urgency,
+    // how long a parent order is worked for. every parent is complete by the end of it.
+    pub horizon_ms: u64,
+    pub slices: usize,
+    // pov: share of the market's volume to take
+    pub participation: f64,
+    // iceberg: how far past the touch a child's limit price may go, in basis points
+    pub limit_bps: f64,
+}
+
+impl Default for ExecConfig {
+    fn default() -> Self {
+        // one market order per signal, as before execution algorithms existed
+        ExecConfig {
+            style: Style::Market,
+            urgency: Urgency::Normal,
+            horizon_ms: 0,
+            slices: 1,
+            participation: 0.0,
+            limit_bps: 0.0,
+        }
+    }
+}
+
+impl ExecConfig {
+    pub fn from_value(execution: &Value) -> Result<Self, String> {
+        /*
+            Reads an account's "execution" object. urgency sets the defaults of horizon_secs,
+            participation and limit_bps, which can each be set on their own too.
+        */
+        let style = match execution["algo"].as_str().unwrap_or("market") {
+            "market" => Style::Market,
+            "twap" => Style::Twap,
+            "vwap" => Style::Vwap,
+            "pov" => Style::Pov,
+            "iceberg" => Style::Iceberg,
+            other => return Err(format!("execution: unknown algo {}", other)),
+        };
+        if style == Style::Market {
+            return Ok(ExecConfig::default());
+        }
+        let urgency = match execution["urgency"].as_str().unwrap_or("normal") {
+            "low" => Urgency::Low,
+            "normal" => Urgency::Normal,
+            "high" => Urgency::High,
+            other => return Err(format!("execution: unknown urgency {}", other)),
+        };
+        let (horizon_secs, participation, limit_bps) = urgency.defaults();
+        let config = ExecConfig {
+            style,
+            urgency,
+            horizon_ms: execution["horizon_secs"].as_u64().unwrap_or(horizon_secs) * 1000,
+            slices: execution["slices"]
+                .as_u64()
+                .map(|x| x as usize)
+                .unwrap_or(DEFAULT_SLICES),
+            participation: execution["participation"].as_f64().unwrap_or(participation),
+            limit_bps: execution["limit_bps"].as_f64().unwrap_or(limit_bps),
+        };
+        if config.horizon_ms == 0 || config.slices == 0 {
+            return Err("execution: horizon_secs and slices must be at least 1".to_string());
+        }
+        if config.participation <= 0.0 || config.participation > 1.0 {
+            return Err("execution: participation must be above 0 and at most 1".to_string());
+        }
+        if config.limit_bps < 0.0 {
+            return Err("execution: limit_bps can't be negative".to_string());
+        }
+        Ok(config)
+    }
+}
+
+// what a child order is sized and priced against
+pub struct ChildContext {
+    // last close
+    pub price: f64,
+    pub step_size: f64,
+    pub min_notional: f64,
+    pub tick_size: Option<f64>,
+    // best ask for a buy, best bid for a sell, from the local order book
+    pub touch: Option<f64>,
+}
+
+// a child order sent to the exchange, kept until its response comes back
+#[derive(Clone)]
+pub struct SentChild {
+    pub parent: u64,
+    pub algo: usize,
+    pub request: MarketRequest,
+    // the parent's arrival price, the close when it was sent and the book's mid, for the fill and its costs
+    pub decision_price: f64,
+    pub close: f64,
+    pub arrival_mid: Option<f64>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ParentOrder {
+    pub id: u64,
+    pub algo: usize,
+    pub symbol: String,
+    pub side: Side,
+    pub style: Style,
+    // quote asset to spend on a buy, base asset to sell on a sell
+    pub amount: f64,
+    pub remaining: f64,
+    // the asset the parent spends, held back from other algos' balances until it's done
+    pub spend_asset: String,
+    pub executed_qty: f64,
+    pub executed_quote: f64,
+    // close of the bar the signal came on
+    pub arrival_price: f64,
+    pub start_time: u64,
+    pub children: u32,
+    weights: Vec<f64>,
+    interval_ms: u64,
+    next_slice: usize,
+    pov_volume: f64,
+    participation: f64,
+    limit_bps: f64,
+    // children sent that haven't come back yet. the next one waits for them, so it's sized on what they filled.
+    in_flight: u32,
+    done: bool,
+}
+
+impl ParentOrder {
+    pub fn avg_price(&self) -> Option<f64> {
+        if self.executed_qty > 0.0 {
+            Some(self.executed_quote / self.executed_qty)
+        } else {
+            None
+        }
+    }
+
+    pub fn shortfall_bps(&self) -> Option<f64> {
+        /*
+            Implementation shortfall against the signal bar's close, in basis points. Positive is a cost:
+            buying above or selling below the close.
+        */
+        let avg_price = self.avg_price()?;
+        if self.arrival_price <= 0.0 {
+            return None;
+        }
+        let shortfall = (avg_price - self.arrival_price) / self.arrival_price * 10000.0;
+        match self.side {
+            Side::Buy => Some(shortfall),
+            Side::Sell => Some(-shortfall),
+        }
+    }
+
+    pub fn progress(&self) -> f64 {
+        // share of the parent done so far
+        if self.amount <= 0.0 {
+            return 1.0;
+        }
+        (1.0 - self.remaining / self.amount).clamp(0.0, 1.0)
+    }
+
+    pub fn next_time(&self) -> u64 {
+        self.start_time + self.next_slice as u64 * self.interval_ms
+    }
+
+    fn notional(&self, size: f64, price: f64) -> f64 {
+        match self.side {
+            Side::Buy => size,
+            Side::Sell => size * price,
+        }
+    }
+}
+
+fn volume_weights(history: &[Vec<f64>], slices: usize, interval_ms: u64) -> Vec<f64> {
+    /*
+        Weight of each slice from the volume traded over the same time of day yesterday.
+        Equal weights(twap) when there isn't a full day of history or it had no volume.
+    */
+    if history.len() < DAY_BARS {
+        return vec![1.0; slices];
+    }
+    let day = &history[history.len() - DAY_BARS..];
+    let weights: Vec<f64> = (0..slices)
+        .map(|k| {
+            let first = (k as u64 * interval_ms / BAR_MS) as usize;
+            let last = ((k as u64 + 1) * interval_ms / BAR_MS).max(first as u64 + 1) as usize;
+            day[first.min(DAY_BARS)..last.min(DAY_BARS)]
+                .iter()
+                .map(|x| x[4])
+                .sum()
+        })
+        .collect();
+    if weights.iter().sum::<f64>() > 0.0 {
+        weights
+    } else {
+        vec![1.0; slices]
+    }
+}
+
+fn round_down(value: f64, step: f64) -> f64 {
+    if step > 0.0 {
+        (value / step + 1e-9).floor() * step
+    } else {
+        value
+    }
+}
+
+pub struct Executor {
+    config: ExecConfig,
+    parents: Vec<ParentOrder>,
+    next_id: u64,
+}
+
+impl Executor {
+    pub fn new(config: ExecConfig, first_id: u64) -> Self {
+        Executor {
+            config,
+            parents: Vec::new(),
+            next_id: first_id,
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn submit(
+        &mut self,
+        algo: usize,
+        symbol: &str,
+        side: Side,
+        amount: f64,
+        spend_asset: &str,
+        arrival_price: f64,
+        history: Option<&[Vec<f64>]>,
+        time_now: u64,
+    ) -> &ParentOrder {
+        /*
+            Starts a parent order. Its first child is due right away.
+            history is the pair's 1m bars, for the vwap volume curve.
+        */
+        let (slices, interval_ms) = match self.config.style {
+            Style::Market => (1, 0),
+            _ => (
+                self.config.slices,
+                self.config.horizon_ms / self.config.slices as u64,
+            ),
+        };
+        let weights = match (self.config.style, history) {
+            (Style::Vwap, Some(history)) => volume_weights(history, slices, interval_ms),
+            _ => vec![1.0; slices],
+        };
+        // pov's first child takes its share of the signal bar's volume
+        let pov_volume = history.and_then(|x| x.last()).map(|x| x[4]).unwrap_or(0.0);
+        self.parents.push(ParentOrder {
+            id: self.next_id,
+            algo,
+            symbol: symbol.to_string(),
+            side,
+            style: self.config.style,
+            amount,
+            remaining: amount,
+            spend_asset: spend_asset.to_string(),
+            executed_qty: 0.0,
+            executed_quote: 0.0,
+            arrival_price,
+            start_time: time_now,
+            children: 0,
+            weights,
+            interval_ms,
+            next_slice: 0,
+            pov_volume,
+            participation: self.config.participation,
+            limit_bps: self.config.limit_bps,
+            in_flight: 0,
+            done: false,
+        });
+        self.next_id += 1;
+        self.parents.last().unwrap()
+    }
+
+    pub fn parents(&self) -> &[ParentOrder] {
+        &self.parents
+    }
+
+    pub fn parent(&self, id: u64) -> Option<&ParentOrder> {
+        self.parents.iter().find(|x| x.id == id)
+    }
+
+    pub fn is_working(&self, algo: usize) -> bool {
+        self.parents.iter().any(|x| x.algo == algo)
+    }
+
+    pub fn is_working_on(&self, symbol: &str) -> bool {
+        self.parents.iter().any(|x| x.symbol == symbol)
+    }
+
+    pub fn reserved(&self, asset: &str) -> f64 {
+        // what working parents are still going to spend of an asset
+        self.parents
+            .iter()
+            .filter(|x| x.spend_asset == asset)
+            .map(|x| x.remaining)
+            .sum()
+    }
+
+    pub fn next_due(&self) -> Option<u64> {
+        self.parents
+            .iter()
+            .filter(|x| !x.done && x.in_flight == 0)
+            .map(|x| x.next_time())
+            .min()
+    }
+
+    pub fn due(&self, time_now: u64) -> Vec<u64> {
+        self.parents
+            .iter()
+            .filter(|x| !x.done && x.in_flight == 0 && x.next_time() <= time_now)
+            .map(|x| x.id)
+            .collect()
+    }
+
+    pub fn on_bar(&mut self, symbol: &str, volume: f64) {
+        // the market volume pov children take their share of
+        for parent in self.parents.iter_mut().filter(|x| x.symbol == symbol) {
+            parent.pov_volume += volume;
+        }
+    }
+
+    pub fn child(
+        &mut self,
+        id: u64,
+        time_now: u64,
+        context: &ChildContext,
+    ) -> Option<MarketRequest> {
+        /*
+            The parent's next child order, or None if there is nothing to send this slice.
+            The last slice sends whatever is left at market. Sizes are kept above min_notional,
+            and a child that would leave less than that behind takes the rest with it.
+            After the last slice the parent is done, and any dust below min_notional stays unsold.
+        */
+        let parent = self.parents.iter_mut().find(|x| x.id == id)?;
+        let slices = parent.weights.len();
+        let last = parent.next_slice + 1 >= slices;
+        let mut size = match parent.style {
+            Style::Market => parent.remaining,
+            Style::Twap | Style::Vwap => {
+                let left: f64 = parent.weights[parent.next_slice..].iter().sum();
+                parent.remaining * parent.weights[parent.next_slice] / left
+            }
+            Style::Pov => {
+                let volume = parent.participation * parent.pov_volume;
+                match parent.side {
+                    Side::Buy => volume * context.price,
+                    Side::Sell => volume,
+                }
+            }
+            Style::Iceberg => parent.amount / slices as f64,
+        };
+        parent.pov_volume = 0.0;
+        parent.next_slice += 1;
+        if last {
+            parent.done = true;
+            size = parent.remaining;
+        }
+        if size <= 0.0 || context.price <= 0.0 {
+            return None;
+        }
+        let floor = context.min_notional * MIN_CHILD_MARGIN;
+        if parent.notional(size, context.price) < floor {
+            size = match parent.side {
+                Side::Buy => floor,
+                Side::Sell => floor / context.price,
+            };
+        }
+        if parent.notional(parent.remaining - size, context.price) < floor {
+            size = parent.remaining;
+        }
+        size = size.min(parent.remaining);
+
+        // iceberg clips are limit orders that can't go further than limit_bps past the touch
+        let limit_price = match parent.style {
+            Style::Iceberg if !last => {
+                let touch = context.touch.unwrap_or(context.price);
+                let tick = context.tick_size.unwrap_or(0.0);
+                Some(match parent.side {
+                    Side::Buy => round_down(touch * (1.0 + parent.limit_bps / 10000.0), tick),
+                    Side::Sell => {
+                        let price = touch * (1.0 - parent.limit_bps / 10000.0);
+                        if tick > 0.0 {
+                            (price / tick - 1e-9).ceil() * tick
+                        } else {
+                            price
+                        }
+                    }
+                })
+            }
+            _ => None,
+        };
+        let (quantity, quote_order_qty) = match (parent.side, limit_price) {
+            (Side::Buy, Some(price)) => (round_down(size / price, context.step_size), -1.0),
+            (Side::Buy, None) => (-1.0, size),
+            (Side::Sell, _) => (round_down(size, context.step_size), -1.0),
+        };
+        let notional = match quantity {
+            x if x > 0.0 => x * limit_price.unwrap_or(context.price),
+            _ => quote_order_qty,
+        };
+        if notional < context.min_notional {
+            return None;
+        }
+        parent.children += 1;
+        parent.in_flight += 1;
+        Some(MarketRequest {
+            symbol: parent.symbol.clone(),
+            side: match parent.side {
+                Side::Buy => "BUY".to_string(),
+                Side::Sell => "SELL".to_string(),
+            },
+            timestamp: time_now,
+            quantity,
+            quote_order_qty,
+            limit_price: limit_price.unwrap_or(-1.0),
+        })
+    }
+
+    pub fn on_fill(&mut self, id: u64, quantity: f64, quote: f64) {
+        // a child's response, with what it filled. 0 for one that didn't trade.
+        if let Some(parent) = self.parents.iter_mut().find(|x| x.id == id) {
+            parent.in_flight = parent.in_flight.saturating_sub(1);
+            parent.executed_qty += quantity;
+            parent.executed_quote += quote;
+            let filled = match parent.side {
+                Side::Buy => quote,
+                Side::Sell => quantity,
+            };
+            parent.remaining = (parent.remaining - filled).max(0.0);
+            if parent.remaining <= 0.0 {
+                parent.done = true;
+            }
+        }
+    }
+
+    pub fn take_done(&mut self) -> Vec<ParentOrder> {
+        // a parent whose last child is still out isn't done until it comes back
+        let (done, working): (Vec<ParentOrder>, Vec<ParentOrder>) = self
+            .parents
+            .drain(..)
+            .partition(|x| x.done && x.in_flight == 0);
+        self.parents = working;
+        done
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn context(price: f64) -> ChildContext {
+        ChildContext {
+            price,
+            step_size: 0.001,
+            min_notional: 10.0,
+            tick_size: Some(0.01),
+            touch: Some(price + 0.5),
+        }
+    }
+
+    #[test]
+    fn twap_slices_a_parent_over_the_horizon() {
+        let config =
+            ExecConfig::from_value(&json!({"algo": "twap", "horizon_secs": 300, "slices": 5}))
+                .unwrap();
+        let mut executor = Executor::new(config, 1);
+        let id = executor
+            .submit(0, "ETHUSDT", Side::Buy, 1000.0, "USDT", 100.0, None, 0)
+            .id;
+        assert_eq!(executor.reserved("USDT"), 1000.0);
+        let mut time_now = 0;
+        let mut children = Vec::new();
+        while let Some(due) = executor.next_due() {
+            time_now = due;
+            for id in executor.due(time_now) {
+                if let Some(request) = executor.child(id, time_now, &context(100.0)) {
+                    // fills 1% above the arrival price
+                    executor.on_fill(id, request.quote_order_qty / 101.0, request.quote_order_qty);
+                    children.push(request.quote_order_qty);
+                }
+            }
+        }
+        assert_eq!(time_now, 240_000);
+        assert_eq!(children, vec![200.0; 5]);
+        let done = executor.take_done();
+        assert_eq!(done[0].id, id);
+        assert_eq!(done[0].children, 5);
+        assert!((done[0].shortfall_bps().unwrap() - 100.0).abs() < 1e-6);
+        assert!(executor.parents().is_empty());
+
+        // a child out on the exchange holds back the next one, and the parent, until it comes back
+        let config = ExecConfig::from_value(&json!({"algo": "twap", "slices": 2})).unwrap();
+        let mut executor = Executor::new(config, 1);
+        executor.submit(0, "ETHUSDT", Side::Buy, 100.0, "USDT", 100.0, None, 0);
+        assert!(executor.child(1, 0, &context(100.0)).is_some());
+        assert!(executor.due(u64::MAX).is_empty());
+        assert_eq!(executor.next_due(), None);
+        executor.on_fill(1, 0.0, 0.0);
+        assert_eq!(executor.due(u64::MAX), vec![1]);
+        let request = executor.child(1, u64::MAX, &context(100.0)).unwrap();
+        assert_eq!(request.quote_order_qty, 100.0);
+        assert!(executor.take_done().is_empty());
+        executor.on_fill(1, 1.0, 100.0);
+        assert_eq!(executor.take_done().len(), 1);
+    }
+
+    #[test]
+    fn sizes_children_by_volume_and_limits() {
+        // vwap follows yesterday's volume: the first of two 1m slices traded 3 times as much
+        let mut history = vec![vec![0.0, 0.0, 0.0, 100.0, 1.0]; DAY_BARS];
+        history[0][4] = 3.0;
+        assert_eq!(volume_weights(&history, 2, BAR_MS), vec![3.0, 1.0]);
+        assert_eq!(volume_weights(&history[1..], 2, BAR_MS), vec![1.0, 1.0]);
+
+        let config = ExecConfig::from_value(&json!({"algo": "pov", "participation": 0.5})).unwrap();
+        assert_eq!(config.horizon_ms, 600_000);
+        let mut executor = Executor::new(config, 1);
+        executor.submit(
+            0,
+            "ETHUSDT",
+            Side::Sell,
+            5.0,
+            "ETH",
+            100.0,
+            Some(&history),
+            0,
+        );
+        // half of the signal bar's volume, then nothing until more volume trades
+        let request = executor.child(1, 0, &context(100.0)).unwrap();
+        assert_eq!(request.quantity, 0.5);
+        assert!(executor.child(1, 60_000, &context(100.0)).is_none());
+        executor.on_bar("ETHUSDT", 0.1);
+        // too small for min_notional on its own, so raised to it
+        let request = executor.child(1, 120_000, &context(100.0)).unwrap();
+        assert!((request.quantity - 0.11).abs() < 1e-9);
+
+        let config =
+            ExecConfig::from_value(&json!({"algo": "iceberg", "slices": 4, "limit_bps": 10}))
+                .unwrap();
+        let mut executor = Executor::new(config, 1);
+        executor.submit(0, "ETHUSDT", Side::Buy, 400.0, "USDT", 100.0, None, 0);
+        let request = executor.child(1, 0, &context(100.0)).unwrap();
+        // a clip of 100 at no more than 10bps above the 100.5 ask
+        assert!((request.limit_price - 100.6).abs() < 1e-9);
+        assert!((request.quantity - 0.994).abs() < 1e-9);
+
+        assert!(ExecConfig::from_value(&json!({"algo": "sniper"})).is_err());
+        assert!(ExecConfig::from_value(&json!({"algo": "pov", "participation": 2.0})).is_err());
+        assert_eq!(
+            ExecConfig::from_value(&json!({})).unwrap(),
+            ExecConfig::default()
+        );
+    }
+}
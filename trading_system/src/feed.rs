@@ -0,0 +1,224 @@
+use crate::bars::{BarBuilder, BarKind};
+use crate::binance_interface;
+use crate::binance_structs::{ReceivedData, StreamType};
+use crate::network;
+use crate::order_book;
+use crossbeam_channel::Sender;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+// the market data feed every account shares. one kline stream per symbol any account trades,
+// each kline is passed on to the accounts trading its symbol.
+// accounts trading on bars built from ticks(see bars.rs) get an aggTrade stream per symbol instead, and their own bars.
+// with order books on, every symbol also gets a depth stream that keeps its local order book in sync(see order_book.rs).
+// streams are started and stopped as accounts change their symbols, see universe.rs.
+
+struct Route {
+    account: String,
+    symbols: Vec<String>,
+    kline_tx: Sender<ReceivedData>,
+    // None trades on exchange klines
+    bars: Option<BarKind>,
+    builders: HashMap<String, BarBuilder>,
+}
+
+struct Stream {
+    symbol: String,
+    stream_type: StreamType,
+    stop: Arc<AtomicBool>,
+}
+
+pub struct Feed {
+    feed_tx: Sender<ReceivedData>,
+    routes: Vec<Route>,
+    // running streams by stream name
+    streams: HashMap<String, Stream>,
+    started: bool,
+    order_books: bool,
+}
+
+impl Feed {
+    pub fn new(feed_tx: Sender<ReceivedData>, order_books: bool) -> Self {
+        Feed {
+            feed_tx,
+            routes: Vec::new(),
+            streams: HashMap::new(),
+            started: false,
+            order_books,
+        }
+    }
+
+    pub fn add_account(
+        &mut self,
+        account: &str,
+        symbols: Vec<String>,
+        kline_tx: Sender<ReceivedData>,
+        bars: Option<BarKind>,
+    ) {
+        self.routes.push(Route {
+            account: account.to_string(),
+            symbols,
+            kline_tx,
+            bars,
+            builders: HashMap::new(),
+        });
+        if self.started {
+            self.sync_streams(None);
+        }
+    }
+
+    pub fn start(&mut self, init_tx: &Sender<bool>) -> usize {
+        /*
+            Starts the streams of every symbol the accounts trade so far.
+            Returns the number of kline and trade streams, each of which reports to init_tx once it connects.
+        */
+        self.started = true;
+        self.sync_streams(Some(init_tx))
+    }
+
+    pub fn set_symbols(&mut self, account: &str, symbols: Vec<String>) {
+        if let Some(route) = self.routes.iter_mut().find(|x| x.account == account) {
+            route.builders.retain(|symbol, _| symbols.contains(symbol));
+            route.symbols = symbols;
+        }
+        if self.started {
+            self.sync_streams(None);
+        }
+    }
+
+    pub fn symbols(&self) -> Vec<String> {
+        // every symbol any account trades, in the order accounts added them
+        let mut symbols: Vec<String> = Vec::new();
+        for route in self.routes.iter() {
+            for symbol in route.symbols.iter() {
+                if !symbols.contains(symbol) {
+                    symbols.push(symbol.clone());
+                }
+            }
+        }
+        symbols
+    }
+
+    fn wanted_streams(&self) -> Vec<(String, String, StreamType)> {
+        // (stream name, symbol, type) of every stream the accounts need
+        let mut wanted = Vec::new();
+        for symbol in self.symbols() {
+            let trading = self.routes.iter().filter(|x| x.symbols.contains(&symbol));
+            let (on_bars, on_klines): (Vec<&Route>, Vec<&Route>) =
+                trading.partition(|x| x.bars.is_some());
+            let lowercase = symbol.to_lowercase();
+            if !on_klines.is_empty() {
+                wanted.push((
+                    format!("{}@kline_1m", lowercase),
+                    symbol.clone(),
+                    StreamType::KLine,
+                ));
+            }
+            if !on_bars.is_empty() {
+                wanted.push((
+                    format!("{}@aggTrade", lowercase),
+                    symbol.clone(),
+                    StreamType::Trade,
+                ));
+            }
+            if self.order_books {
+                wanted.push((
+                    format!("{}@depth@100ms", lowercase),
+                    symbol,
+                    StreamType::Depth,
+                ));
+            }
+        }
+        wanted
+    }
+
+    fn sync_streams(&mut self, init_tx: Option<&Sender<bool>>) -> usize {
+        /*
+            Stops streams nobody needs any more and starts the missing ones.
+            Returns the number of kline and trade streams started.
+        */
+        let wanted = self.wanted_streams();
+        self.streams.retain(|name, stream| {
+            if wanted.iter().any(|(x, _, _)| x == name) {
+                return true;
+            }
+            log_info!(target: "feed", "unsubscribing from {}", name);
+            stream.stop.store(true, Ordering::SeqCst);
+            if let StreamType::Depth = stream.stream_type {
+                order_book::remove(&stream.symbol);
+            }
+            false
+        });
+        let mut started = 0;
+        for (name, symbol, stream_type) in wanted {
+            if self.streams.contains_key(&name) {
+                continue;
+            }
+            log_info!(target: "feed", "subscribing to {}", name);
+            let stop = Arc::new(AtomicBool::new(false));
+            let reports_init = match stream_type {
+                StreamType::Depth => false,
+                _ => {
+                    started += 1;
+                    true
+                }
+            };
+            network::spawn(binance_interface::live_binance_stream(
+                name.clone(),
+                self.feed_tx.clone(),
+                init_tx.filter(|_| reports_init).cloned(),
+                stream_type.clone(),
+                stop.clone(),
+            ));
+            self.streams.insert(
+                name,
+                Stream {
+                    symbol,
+                    stream_type,
+                    stop,
+                },
+            );
+        }
+        started
+    }
+
+    pub fn route(&mut self, data: ReceivedData) {
+        /*
+            Klines go to the accounts trading the symbol on klines, trades to the bar builders of the accounts
+            trading it on bars, depth diffs to the symbol's order book.
+            Diffs of a symbol that was just unsubscribed are dropped, so its book isn't rebuilt.
+        */
+        match data {
+            ReceivedData::Value(update) => {
+                let symbol = update["s"].as_str().unwrap_or("");
+                let name = format!("{}@depth@100ms", symbol.to_lowercase());
+                if self.streams.contains_key(&name) {
+                    order_book::on_depth_update(&update);
+                }
+            }
+            ReceivedData::Trade(trade) => {
+                for route in self.routes.iter_mut() {
+                    let kind = match route.bars {
+                        Some(kind) if route.symbols.contains(&trade.symbol) => kind,
+                        _ => continue,
+                    };
+                    let builder = route
+                        .builders
+                        .entry(trade.symbol.clone())
+                        .or_insert_with(|| BarBuilder::new(kind));
+                    if let Some(bar) = builder.push(&trade) {
+                        let _ = route.kline_tx.send(ReceivedData::KLine(bar.to_kline()));
+                    }
+                }
+            }
+            ReceivedData::KLine(kline) => {
+                for route in self.routes.iter() {
+                    if route.bars.is_none() && route.symbols.contains(&kline.symbol) {
+                        let _ = route.kline_tx.send(ReceivedData::KLine(kline.clone()));
+                    }
+                }
+            }
+        }
+    }
+}
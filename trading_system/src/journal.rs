@@ -0,0 +1,752 @@
+use crate::binance_structs::MarketRequest;
+use crate::helpers::epoch_ms;
+use crate::notifier::Notification;
+use crate::trading_strategies::HISTORY_BARS;
+use crossbeam_channel::Sender;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::io::{BufRead, BufReader};
+use std::sync::{Arc, Mutex};
+
+// append-only event journal. one json object per line, see docs/journal_specifications.txt.
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    BarClosed {
+        symbol: String,
+        start_time: u64,
+        end_time: u64,
+        open: f64,
+        high: f64,
+        low: f64,
+        close: f64,
+        quantity: f64,
+    },
+    SignalGenerated {
+        symbol: String,
+        algo: usize,
+        signal: i32,
+    },
+    OrderSubmitted {
+        symbol: String,
+        side: String,
+        quantity: f64,
+        quote_order_qty: f64,
+        // None for market orders
+        limit_price: Option<f64>,
+        algo: Option<usize>,
+        // parent order the child belongs to, see execution.rs
+        parent: Option<u64>,
+    },
+    ParentOrderStarted {
+        id: u64,
+        symbol: String,
+        side: String,
+        algo: usize,
+        style: String,
+        amount: f64,
+        arrival_price: f64,
+    },
+    ParentOrderDone {
+        id: u64,
+        symbol: String,
+        side: String,
+        algo: usize,
+        executed_qty: f64,
+        quote_qty: f64,
+        arrival_price: f64,
+        shortfall_bps: Option<f64>,
+        children: u32,
+    },
+    // costs of one order of a parent, see tca.rs
+    OrderCost {
+        symbol: String,
+        side: String,
+        algo: usize,
+        parent: Option<u64>,
+        order_id: u64,
+        // close of the signal bar
+        decision_price: f64,
+        // order book mid when the order was sent
+        arrival_mid: Option<f64>,
+        // vwap of the order's fills
+        fill_price: f64,
+        quantity: f64,
+        quote_qty: f64,
+        commissions: Vec<(String, f64)>,
+        // commissions valued in the quote asset
+        fee_quote: f64,
+        // quote_qty in the reporting currency
+        notional: f64,
+    },
+    OrderAck {
+        symbol: String,
+        order_id: u64,
+        status: String,
+        executed_qty: f64,
+        quote_qty: f64,
+    },
+    Fill {
+        symbol: String,
+        order_id: u64,
+        price: f64,
+        quantity: f64,
+        commission: f64,
+        commission_asset: String,
+    },
+    StateChange {
+        algo_status: Vec<i32>,
+        running: bool,
+    },
+    Command {
+        line: String,
+    },
+    UniverseChange {
+        symbols: Vec<String>,
+        assets: Vec<String>,
+    },
+}
+
+impl Event {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::BarClosed { .. } => "BarClosed",
+            Event::SignalGenerated { .. } => "SignalGenerated",
+            Event::OrderSubmitted { .. } => "OrderSubmitted",
+            Event::ParentOrderStarted { .. } => "ParentOrderStarted",
+            Event::ParentOrderDone { .. } => "ParentOrderDone",
+            Event::OrderCost { .. } => "OrderCost",
+            Event::OrderAck { .. } => "OrderAck",
+            Event::Fill { .. } => "Fill",
+            Event::StateChange { .. } => "StateChange",
+            Event::Command { .. } => "Command",
+            Event::UniverseChange { .. } => "UniverseChange",
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        // written out by hand, so the field names stay the ones in docs/journal_specifications.txt
+        match self {
+            Event::BarClosed {
+                symbol,
+                start_time,
+                end_time,
+                open,
+                high,
+                low,
+                close,
+                quantity,
+            } => json!({
+                "symbol": symbol, "start_time": start_time, "end_time": end_time,
+                "open": open, "high": high, "low": low, "close": close, "quantity": quantity,
+            }),
+            Event::SignalGenerated {
+                symbol,
+                algo,
+                signal,
+            } => json!({ "symbol": symbol, "algo": algo, "signal": signal }),
+            Event::OrderSubmitted {
+                symbol,
+                side,
+                quantity,
+                quote_order_qty,
+                limit_price,
+                algo,
+                parent,
+            } => json!({
+                "symbol": symbol, "side": side, "quantity": quantity,
+                "quote_order_qty": quote_order_qty, "limit_price": limit_price,
+                "algo": algo, "parent": parent,
+            }),
+            Event::ParentOrderStarted {
+                id,
+                symbol,
+                side,
+                algo,
+                style,
+                amount,
+                arrival_price,
+            } => json!({
+                "id": id, "symbol": symbol, "side": side, "algo": algo, "style": style,
+                "amount": amount, "arrival_price": arrival_price,
+            }),
+            Event::ParentOrderDone {
+                id,
+                symbol,
+                side,
+                algo,
+                executed_qty,
+                quote_qty,
+                arrival_price,
+                shortfall_bps,
+                children,
+            } => json!({
+                "id": id, "symbol": symbol, "side": side, "algo": algo,
+                "executed_qty": executed_qty, "quote_qty": quote_qty, "arrival_price": arrival_price,
+                "shortfall_bps": shortfall_bps, "children": children,
+            }),
+            Event::OrderCost {
+                symbol,
+                side,
+                algo,
+                parent,
+                order_id,
+                decision_price,
+                arrival_mid,
+                fill_price,
+                quantity,
+                quote_qty,
+                commissions,
+                fee_quote,
+                notional,
+            } => json!({
+                "symbol": symbol, "side": side, "algo": algo, "parent": parent, "order_id": order_id,
+                "decision_price": decision_price, "arrival_mid": arrival_mid, "fill_price": fill_price,
+                "quantity": quantity, "quote_qty": quote_qty,
+                "commissions": commissions.iter().map(|(asset, amount)| json!({ "asset": asset, "amount": amount })).collect::<Vec<Value>>(),
+                "fee_quote": fee_quote, "notional": notional,
+            }),
+            Event::OrderAck {
+                symbol,
+                order_id,
+                status,
+                executed_qty,
+                quote_qty,
+            } => json!({
+                "symbol": symbol, "order_id": order_id, "status": status,
+                "executed_qty": executed_qty, "quote_qty": quote_qty,
+            }),
+            Event::Fill {
+                symbol,
+                order_id,
+                price,
+                quantity,
+                commission,
+                commission_asset,
+            } => json!({
+                "symbol": symbol, "order_id": order_id, "price": price, "quantity": quantity,
+                "commission": commission, "commission_asset": commission_asset,
+            }),
+            Event::StateChange {
+                algo_status,
+                running,
+            } => json!({ "algo_status": algo_status, "running": running }),
+            Event::Command { line } => json!({ "line": line }),
+            Event::UniverseChange { symbols, assets } => {
+                json!({ "symbols": symbols, "assets": assets })
+            }
+        }
+    }
+
+    fn from_json(value: &Value) -> Option<Event> {
+        let symbol = || value["symbol"].as_str().map(|x| x.to_string());
+        let event = match value["type"].as_str()? {
+            "BarClosed" => Event::BarClosed {
+                symbol: symbol()?,
+                start_time: value["start_time"].as_u64()?,
+                end_time: value["end_time"].as_u64()?,
+                open: value["open"].as_f64()?,
+                high: value["high"].as_f64()?,
+                low: value["low"].as_f64()?,
+                close: value["close"].as_f64()?,
+                quantity: value["quantity"].as_f64()?,
+            },
+            "SignalGenerated" => Event::SignalGenerated {
+                symbol: symbol()?,
+                algo: value["algo"].as_u64()? as usize,
+                signal: value["signal"].as_i64()? as i32,
+            },
+            "OrderSubmitted" => Event::OrderSubmitted {
+                symbol: symbol()?,
+                side: value["side"].as_str()?.to_string(),
+                quantity: value["quantity"].as_f64()?,
+                quote_order_qty: value["quote_order_qty"].as_f64()?,
+                limit_price: value["limit_price"].as_f64(),
+                algo: value["algo"].as_u64().map(|x| x as usize),
+                parent: value["parent"].as_u64(),
+            },
+            "ParentOrderStarted" => Event::ParentOrderStarted {
+                id: value["id"].as_u64()?,
+                symbol: symbol()?,
+                side: value["side"].as_str()?.to_string(),
+                algo: value["algo"].as_u64()? as usize,
+                style: value["style"].as_str()?.to_string(),
+                amount: value["amount"].as_f64()?,
+                arrival_price: value["arrival_price"].as_f64()?,
+            },
+            "ParentOrderDone" => Event::ParentOrderDone {
+                id: value["id"].as_u64()?,
+                symbol: symbol()?,
+                side: value["side"].as_str()?.to_string(),
+                algo: value["algo"].as_u64()? as usize,
+                executed_qty: value["executed_qty"].as_f64()?,
+                quote_qty: value["quote_qty"].as_f64()?,
+                arrival_price: value["arrival_price"].as_f64()?,
+                shortfall_bps: value["shortfall_bps"].as_f64(),
+                children: value["children"].as_u64()? as u32,
+            },
+            "OrderCost" => Event::OrderCost {
+                symbol: symbol()?,
+                side: value["side"].as_str()?.to_string(),
+                algo: value["algo"].as_u64()? as usize,
+                parent: value["parent"].as_u64(),
+                order_id: value["order_id"].as_u64()?,
+                decision_price: value["decision_price"].as_f64()?,
+                arrival_mid: value["arrival_mid"].as_f64(),
+                fill_price: value["fill_price"].as_f64()?,
+                quantity: value["quantity"].as_f64()?,
+                quote_qty: value["quote_qty"].as_f64()?,
+                commissions: value["commissions"]
+                    .as_array()?
+                    .iter()
+                    .map(|x| Some((x["asset"].as_str()?.to_string(), x["amount"].as_f64()?)))
+                    .collect::<Option<Vec<(String, f64)>>>()?,
+                fee_quote: value["fee_quote"].as_f64()?,
+                notional: value["notional"].as_f64()?,
+            },
+            "OrderAck" => Event::OrderAck {
+                symbol: symbol()?,
+                order_id: value["order_id"].as_u64()?,
+                status: value["status"].as_str()?.to_string(),
+                executed_qty: value["executed_qty"].as_f64()?,
+                quote_qty: value["quote_qty"].as_f64()?,
+            },
+            "Fill" => Event::Fill {
+                symbol: symbol()?,
+                order_id: value["order_id"].as_u64()?,
+                price: value["price"].as_f64()?,
+                quantity: value["quantity"].as_f64()?,
+                commission: value["commission"].as_f64()?,
+                commission_asset: value["commission_asset"].as_str()?.to_string(),
+            },
+            "StateChange" => Event::StateChange {
+                algo_status: value["algo_status"]
+                    .as_array()?
+                    .iter()
+                    .map(|x| x.as_i64().unwrap_or(0) as i32)
+                    .collect(),
+                running: value["running"].as_bool()?,
+            },
+            "Command" => Event::Command {
+                line: value["line"].as_str()?.to_string(),
+            },
+            "UniverseChange" => {
+                let string_list = |field: &str| -> Option<Vec<String>> {
+                    value[field]
+                        .as_array()?
+                        .iter()
+                        .map(|x| x.as_str().map(|y| y.to_string()))
+                        .collect()
+                };
+                Event::UniverseChange {
+                    symbols: string_list("symbols")?,
+                    assets: string_list("assets")?,
+                }
+            }
+            _ => return None,
+        };
+        Some(event)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct JournalEntry {
+    pub seq: u64,
+    pub timestamp: u64,
+    pub event: Event,
+}
+
+impl JournalEntry {
+    pub fn to_line(&self) -> String {
+        let mut value = self.event.to_json();
+        value["seq"] = json!(self.seq);
+        value["timestamp"] = json!(self.timestamp);
+        value["type"] = json!(self.event.name());
+        format!("{}\n", value)
+    }
+
+    pub fn from_line(line: &str) -> Option<JournalEntry> {
+        let value: Value = serde_json::from_str(line).ok()?;
+        Some(JournalEntry {
+            seq: value["seq"].as_u64()?,
+            timestamp: value["timestamp"].as_u64()?,
+            event: Event::from_json(&value)?,
+        })
+    }
+}
+
+pub struct Journal {
+    file: File,
+    next_seq: u64,
+}
+
+impl Journal {
+    pub fn open(path: &str) -> std::io::Result<Journal> {
+        // sequence numbers continue from the last entry already in the file
+        let next_seq = match read_entries(path) {
+            Ok(entries) => entries.last().map(|x| x.seq + 1).unwrap_or(0),
+            Err(_) => 0,
+        };
+        let file = OpenOptions::new().append(true).create(true).open(path)?;
+        Ok(Journal { file, next_seq })
+    }
+
+    pub fn append(&mut self, event: Event) -> std::io::Result<u64> {
+        /*
+            Writes the event and syncs it to disk before returning, so callers can act on it afterwards.
+            Returns the sequence number assigned to the event.
+        */
+        let entry = JournalEntry {
+            seq: self.next_seq,
+            timestamp: epoch_ms(),
+            event,
+        };
+        self.file.write_all(entry.to_line().as_bytes())?;
+        self.file.sync_data()?;
+        self.next_seq += 1;
+        Ok(entry.seq)
+    }
+}
+
+// shared handle for threads that write to the same journal
+#[derive(Clone)]
+pub struct JournalHandle {
+    journal: Arc<Mutex<Journal>>,
+    logging_tx: Sender<Notification>,
+}
+
+impl JournalHandle {
+    pub fn new(journal: Journal, logging_tx: Sender<Notification>) -> Self {
+        JournalHandle {
+            journal: Arc::new(Mutex::new(journal)),
+            logging_tx,
+        }
+    }
+
+    pub fn record(&self, event: Event) {
+        let append_status = self.journal.lock().unwrap().append(event);
+        if append_status.is_err() {
+            let _ = self.logging_tx.send(Notification::warning(
+                "warning: errors with writing to journal".to_string(),
+            ));
+        }
+    }
+}
+
+pub fn order_submitted_event(
+    request: &MarketRequest,
+    algo: Option<usize>,
+    parent: Option<u64>,
+) -> Event {
+    Event::OrderSubmitted {
+        symbol: request.symbol.clone(),
+        side: request.side.clone(),
+        quantity: request.quantity,
+        quote_order_qty: request.quote_order_qty,
+        limit_price: Some(request.limit_price).filter(|x| *x != -1.0),
+        algo,
+        parent,
+    }
+}
+
+pub fn order_response_events(symbol: &str, response: &Value) -> Vec<Event> {
+    // turns a binance order response(newOrderRespType FULL) into an OrderAck followed by its Fills
+    let mut events = Vec::new();
+    let order_id = match response["orderId"].as_u64() {
+        Some(order_id) => order_id,
+        None => return events,
+    };
+    let parse_str = |field: &Value| field.as_str().unwrap_or("0").parse().unwrap_or(0.0);
+    events.push(Event::OrderAck {
+        symbol: symbol.to_string(),
+        order_id,
+        status: response["status"].as_str().unwrap_or("").to_string(),
+        executed_qty: parse_str(&response["executedQty"]),
+        quote_qty: parse_str(&response["cummulativeQuoteQty"]),
+    });
+    if let Some(fills) = response["fills"].as_array() {
+        for fill in fills {
+            events.push(Event::Fill {
+                symbol: symbol.to_string(),
+                order_id,
+                price: parse_str(&fill["price"]),
+                quantity: parse_str(&fill["qty"]),
+                commission: parse_str(&fill["commission"]),
+                commission_asset: fill["commissionAsset"].as_str().unwrap_or("").to_string(),
+            });
+        }
+    }
+    events
+}
+
+pub fn read_entries(path: &str) -> std::io::Result<Vec<JournalEntry>> {
+    // malformed lines(e.g. a torn final write) are skipped
+    let reader = BufReader::new(File::open(path)?);
+    let mut entries = Vec::new();
+    for line in reader.lines() {
+        if let Some(entry) = JournalEntry::from_line(&line?) {
+            entries.push(entry);
+        }
+    }
+    Ok(entries)
+}
+
+// system state as rebuilt from the journal
+#[derive(Default, Debug)]
+pub struct ReplayState {
+    pub last_seq: Option<u64>,
+    pub last_timestamp: u64,
+    pub running: bool,
+    pub algo_status: Vec<i32>,
+    pub ohlc_history: HashMap<String, Vec<Vec<f64>>>,
+    pub previous_signals: HashMap<String, Vec<i32>>,
+    pub orders: Vec<Event>,
+    pub fills: Vec<Event>,
+    pub commands: Vec<String>,
+    // traded pairs and the asset list algo_status indexes into, from the last UniverseChange
+    pub symbols: Vec<String>,
+    pub assets: Vec<String>,
+}
+
+impl ReplayState {
+    pub fn apply(&mut self, entry: &JournalEntry) {
+        self.last_seq = Some(entry.seq);
+        self.last_timestamp = entry.timestamp;
+        match &entry.event {
+            Event::BarClosed {
+                symbol,
+                open,
+                high,
+                low,
+                close,
+                quantity,
+                ..
+            } => {
+                // the live window, so a long journal doesn't grow it without bound
+                let history = self.ohlc_history.entry(symbol.clone()).or_default();
+                history.push(vec![*open, *high, *low, *close, *quantity]);
+                let excess = history.len().saturating_sub(HISTORY_BARS as usize);
+                history.drain(..excess);
+            }
+            Event::SignalGenerated {
+                symbol,
+                algo,
+                signal,
+            } => {
+                let signals = self.previous_signals.entry(symbol.clone()).or_default();
+                if signals.len() <= *algo {
+                    signals.resize(*algo + 1, -2);
+                }
+                signals[*algo] = *signal;
+            }
+            Event::OrderSubmitted { .. }
+            | Event::OrderAck { .. }
+            | Event::OrderCost { .. }
+            | Event::ParentOrderStarted { .. }
+            | Event::ParentOrderDone { .. } => {
+                self.orders.push(entry.event.clone());
+            }
+            Event::Fill { .. } => {
+                self.fills.push(entry.event.clone());
+            }
+            Event::StateChange {
+                algo_status,
+                running,
+            } => {
+                self.algo_status = algo_status.clone();
+                self.running = *running;
+            }
+            Event::Command { line } => {
+                self.commands.push(line.clone());
+            }
+            Event::UniverseChange { symbols, assets } => {
+                self.symbols = symbols.clone();
+                self.assets = assets.clone();
+            }
+        }
+    }
+}
+
+pub fn replay(path: &str, until_ms: u64) -> std::io::Result<ReplayState> {
+    /*
+        Rebuilds the system state from the journal at path.
+        Every entry with a timestamp at or before until_ms is applied in sequence order.
+    */
+    let mut state = ReplayState::default();
+    for entry in read_entries(path)? {
+        if entry.timestamp > until_ms {
+            break;
+        }
+        state.apply(&entry);
+    }
+    Ok(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn events() -> Vec<Event> {
+        let symbol = "ETHUSDT".to_string();
+        vec![
+            Event::BarClosed {
+                symbol: symbol.clone(),
+                start_time: 60000,
+                end_time: 119999,
+                open: 2000.0,
+                high: 2010.5,
+                low: 1995.25,
+                close: 2005.0,
+                quantity: 12.5,
+            },
+            Event::SignalGenerated {
+                symbol: symbol.clone(),
+                algo: 1,
+                signal: -1,
+            },
+            Event::OrderSubmitted {
+                symbol: symbol.clone(),
+                side: "BUY".to_string(),
+                quantity: 0.0,
+                quote_order_qty: 100.0,
+                limit_price: Some(2004.5),
+                algo: Some(1),
+                parent: Some(3),
+            },
+            Event::OrderSubmitted {
+                symbol: symbol.clone(),
+                side: "SELL".to_string(),
+                quantity: 0.05,
+                quote_order_qty: 0.0,
+                limit_price: None,
+                algo: None,
+                parent: None,
+            },
+            Event::ParentOrderStarted {
+                id: 3,
+                symbol: symbol.clone(),
+                side: "BUY".to_string(),
+                algo: 1,
+                style: "twap".to_string(),
+                amount: 100.0,
+                arrival_price: 2005.0,
+            },
+            Event::ParentOrderDone {
+                id: 3,
+                symbol: symbol.clone(),
+                side: "BUY".to_string(),
+                algo: 1,
+                executed_qty: 0.0499,
+                quote_qty: 99.9,
+                arrival_price: 2005.0,
+                shortfall_bps: Some(-1.5),
+                children: 4,
+            },
+            Event::OrderCost {
+                symbol: symbol.clone(),
+                side: "BUY".to_string(),
+                algo: 1,
+                parent: Some(3),
+                order_id: 42,
+                decision_price: 2005.0,
+                arrival_mid: None,
+                fill_price: 2004.0,
+                quantity: 0.0125,
+                quote_qty: 25.05,
+                commissions: vec![("BNB".to_string(), 0.0001), ("ETH".to_string(), 0.00001)],
+                fee_quote: 0.05,
+                notional: 25.05,
+            },
+            Event::OrderAck {
+                symbol: symbol.clone(),
+                order_id: 42,
+                status: "FILLED".to_string(),
+                executed_qty: 0.0125,
+                quote_qty: 25.05,
+            },
+            Event::Fill {
+                symbol,
+                order_id: 42,
+                price: 2004.0,
+                quantity: 0.0125,
+                commission: 0.0001,
+                commission_asset: "BNB".to_string(),
+            },
+            Event::StateChange {
+                algo_status: vec![0, 2],
+                running: true,
+            },
+            Event::Command {
+                line: "ack 0 1".to_string(),
+            },
+            Event::UniverseChange {
+                symbols: vec!["ETHUSDT".to_string(), "BTCUSDT".to_string()],
+                assets: vec!["USDT".to_string(), "ETH".to_string(), "BTC".to_string()],
+            },
+        ]
+    }
+
+    #[test]
+    fn reads_back_every_event() {
+        let path = std::env::temp_dir().join(format!("jane_journal_{}.jsonl", std::process::id()));
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+        let mut journal = Journal::open(path).unwrap();
+        for event in events() {
+            journal.append(event).unwrap();
+        }
+        let entries = read_entries(path).unwrap();
+        assert_eq!(entries.len(), events().len());
+        for (i, (entry, event)) in entries.iter().zip(events()).enumerate() {
+            assert_eq!(entry.seq, i as u64);
+            assert_eq!(entry.event, event, "{}", event.name());
+        }
+
+        // sequence numbers carry on after a reopen, and replay ends on the last state
+        let mut journal = Journal::open(path).unwrap();
+        assert_eq!(
+            journal
+                .append(Event::Command {
+                    line: "stop".to_string()
+                })
+                .unwrap(),
+            12
+        );
+        let state = replay(path, u64::MAX).unwrap();
+        assert_eq!(state.algo_status, vec![0, 2]);
+        assert_eq!(
+            state.commands,
+            vec!["ack 0 1".to_string(), "stop".to_string()]
+        );
+        assert_eq!(state.orders.len(), 6);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn replay_keeps_the_live_window_of_bars() {
+        let mut state = ReplayState::default();
+        for i in 0..HISTORY_BARS + 10 {
+            state.apply(&JournalEntry {
+                seq: i,
+                timestamp: i,
+                event: Event::BarClosed {
+                    symbol: "ETHUSDT".to_string(),
+                    start_time: i * 60000,
+                    end_time: i * 60000 + 59999,
+                    open: i as f64,
+                    high: i as f64,
+                    low: i as f64,
+                    close: i as f64,
+                    quantity: 1.0,
+                },
+            });
+        }
+        let history = &state.ohlc_history["ETHUSDT"];
+        assert_eq!(history.len(), HISTORY_BARS as usize);
+        assert_eq!(history[0][0], 10.0);
+        assert_eq!(history.last().unwrap()[0], (HISTORY_BARS + 9) as f64);
+    }
+}
@@ -0,0 +1,4 @@
+// the indicators as a library, so benches can use them without the bot. main.rs uses them from here too,
+// as strategies::indicators.
+#[path = "strategies/indicators/mod.rs"]
+pub mod indicators;
@@ -0,0 +1,379 @@
+use chrono::prelude::*;
+use serde_json::{json, Map, Value};
+use std::cell::RefCell;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+
+// leveled logging with per-module filtering and span context.
+// console output is human readable, file output is one json object per line with size-based rotation.
+
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum Level {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl Level {
+    pub fn parse(level_str: &str) -> Option<Level> {
+        match level_str.trim().to_lowercase().as_str() {
+            "trace" => Some(Level::Trace),
+            "debug" => Some(Level::Debug),
+            "info" => Some(Level::Info),
+            "warn" | "warning" => Some(Level::Warn),
+            "error" => Some(Level::Error),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Level::Trace => "TRACE",
+            Level::Debug => "DEBUG",
+            Level::Info => "INFO",
+            Level::Warn => "WARN",
+            Level::Error => "ERROR",
+        }
+    }
+}
+
+pub struct LogConfig {
+    pub default_level: Level,
+    // (target prefix, level). the longest matching prefix wins.
+    pub target_levels: Vec<(String, Level)>,
+    pub file_path: Option<String>,
+    pub max_file_bytes: u64,
+    pub max_files: usize,
+}
+
+impl LogConfig {
+    pub fn from_env() -> Self {
+        /*
+            Reads the config from the environment.
+                JANE_LOG: filter, e.g. "info,binance_interface=debug,strategies=warn"
+                JANE_LOG_FILE: json log path, default ../logs/jane.log. "none" disables the file.
+                JANE_LOG_MAX_BYTES / JANE_LOG_MAX_FILES: rotation settings
+        */
+        let mut config = LogConfig::parse_filter(&env::var("JANE_LOG").unwrap_or_default());
+        config.file_path = match env::var("JANE_LOG_FILE") {
+            Ok(path) if path == "none" => None,
+            Ok(path) => Some(path),
+            Err(_) => Some("../logs/jane.log".to_string()),
+        };
+        if let Some(max_bytes) = env::var("JANE_LOG_MAX_BYTES")
+            .ok()
+            .and_then(|x| x.parse().ok())
+        {
+            config.max_file_bytes = max_bytes;
+        }
+        if let Some(max_files) = env::var("JANE_LOG_MAX_FILES")
+            .ok()
+            .and_then(|x| x.parse().ok())
+        {
+            config.max_files = max_files;
+        }
+        config
+    }
+
+    pub fn parse_filter(filter: &str) -> Self {
+        let mut config = LogConfig {
+            default_level: Level::Info,
+            target_levels: Vec::new(),
+            file_path: None,
+            max_file_bytes: 10 * 1024 * 1024,
+            max_files: 5,
+        };
+        for directive in filter.split(',').filter(|x| !x.trim().is_empty()) {
+            let mut parts = directive.splitn(2, '=');
+            let first = parts.next().unwrap_or("").trim();
+            match parts.next() {
+                Some(level_str) => {
+                    if let Some(level) = Level::parse(level_str) {
+                        config.target_levels.push((first.to_string(), level));
+                    }
+                }
+                None => {
+                    if let Some(level) = Level::parse(first) {
+                        config.default_level = level;
+                    }
+                }
+            }
+        }
+        config
+    }
+
+    pub fn level_for(&self, target: &str) -> Level {
+        let mut best: Option<(usize, Level)> = None;
+        for (prefix, level) in self.target_levels.iter() {
+            let matches = target == prefix
+                || (target.starts_with(prefix.as_str())
+                    && target[prefix.len()..].starts_with("::"));
+            if matches && best.map(|(len, _)| prefix.len() > len).unwrap_or(true) {
+                best = Some((prefix.len(), *level));
+            }
+        }
+        best.map(|(_, level)| level).unwrap_or(self.default_level)
+    }
+}
+
+struct RotatingFile {
+    path: String,
+    file: File,
+    size: u64,
+    max_bytes: u64,
+    max_files: usize,
+}
+
+impl RotatingFile {
+    fn open(path: &str, max_bytes: u64, max_files: usize) -> std::io::Result<Self> {
+        let file = OpenOptions::new().append(true).create(true).open(path)?;
+        let size = file.metadata()?.len();
+        Ok(RotatingFile {
+            path: path.to_string(),
+            file,
+            size,
+            max_bytes,
+            max_files,
+        })
+    }
+
+    fn write_line(&mut self, line: &str) -> std::io::Result<()> {
+        if self.size + line.len() as u64 > self.max_bytes && self.size > 0 {
+            self.rotate()?;
+        }
+        self.file.write_all(line.as_bytes())?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> std::io::Result<()> {
+        // jane.log -> jane.log.1 -> jane.log.2 ... the oldest falls off the end
+        for i in (1..self.max_files).rev() {
+            let from = format!("{}.{}", self.path, i);
+            if fs::metadata(&from).is_ok() {
+                fs::rename(&from, format!("{}.{}", self.path, i + 1))?;
+            }
+        }
+        if self.max_files > 0 {
+            fs::rename(&self.path, format!("{}.1", self.path))?;
+        }
+        self.file = OpenOptions::new()
+            .write(true)
+            .truncate(true)
+            .create(true)
+            .open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+struct Logger {
+    config: LogConfig,
+    file: Mutex<Option<RotatingFile>>,
+}
+
+static LOGGER: OnceLock<Logger> = OnceLock::new();
+
+thread_local! {
+    static SPAN_FIELDS: RefCell<Vec<(String, String)>> = const { RefCell::new(Vec::new()) };
+}
+
+pub fn init(config: LogConfig) {
+    let file = match &config.file_path {
+        Some(path) => match RotatingFile::open(path, config.max_file_bytes, config.max_files) {
+            Ok(file) => Some(file),
+            Err(e) => {
+                eprintln!(
+                    "logger: could not open {}: {}. logging to console only.",
+                    path, e
+                );
+                None
+            }
+        },
+        None => None,
+    };
+    let _ = LOGGER.set(Logger {
+        config,
+        file: Mutex::new(file),
+    });
+}
+
+fn logger() -> &'static Logger {
+    // falls back to console-only info logging if init was never called
+    LOGGER.get_or_init(|| Logger {
+        config: LogConfig::parse_filter(""),
+        file: Mutex::new(None),
+    })
+}
+
+// strips the crate name so targets read "binance_interface", "strategies::adx", ...
+pub fn normalize_target(module_path: &str) -> &str {
+    match module_path.find("::") {
+        Some(i) if module_path[..i] == *env!("CARGO_PKG_NAME") => &module_path[i + 2..],
+        _ => module_path,
+    }
+}
+
+pub fn enabled(level: Level, target: &str) -> bool {
+    level >= logger().config.level_for(normalize_target(target))
+}
+
+pub fn log(level: Level, target: &str, message: String) {
+    if !enabled(level, target) {
+        return;
+    }
+    let target = normalize_target(target);
+    let now: DateTime<Local> = Local::now();
+    let fields = SPAN_FIELDS.with(|fields| fields.borrow().clone());
+
+    // console
+    let mut field_str = String::new();
+    for (key, value) in fields.iter() {
+        field_str = format!("{}{}={} ", field_str, key, value);
+    }
+    let console_line = format!(
+        "{} {:<5} {} {}{}",
+        now.format("%Y-%m-%d %H:%M:%S%.3f"),
+        level.as_str(),
+        target,
+        field_str,
+        message
+    );
+    if level >= Level::Warn {
+        eprintln!("{}", console_line);
+    } else {
+        println!("{}", console_line);
+    }
+
+    // json file
+    let mut file_guard = logger().file.lock().unwrap();
+    if let Some(file) = file_guard.as_mut() {
+        let mut field_map = Map::new();
+        for (key, value) in fields {
+            field_map.insert(key, Value::String(value));
+        }
+        let record = json!({
+            "ts": now.timestamp_millis(),
+            "time": now.to_rfc3339(),
+            "level": level.as_str(),
+            "target": target,
+            "thread": thread::current().name().unwrap_or("unnamed"),
+            "fields": field_map,
+            "msg": message,
+        });
+        if let Err(e) = file.write_line(&format!("{}\n", record)) {
+            eprintln!("logger: could not write log file: {}", e);
+        }
+    }
+}
+
+// fields added to every record logged on this thread until the guard is dropped
+pub struct SpanGuard {
+    len_before: usize,
+}
+
+impl Drop for SpanGuard {
+    fn drop(&mut self) {
+        SPAN_FIELDS.with(|fields| fields.borrow_mut().truncate(self.len_before));
+    }
+}
+
+pub fn span(fields: &[(&str, String)]) -> SpanGuard {
+    SPAN_FIELDS.with(|span_fields| {
+        let mut span_fields = span_fields.borrow_mut();
+        let len_before = span_fields.len();
+        for (key, value) in fields {
+            span_fields.push((key.to_string(), value.clone()));
+        }
+        SpanGuard { len_before }
+    })
+}
+
+#[macro_export]
+macro_rules! log_at {
+    ($level:expr, target: $target:expr, $($arg:tt)+) => {
+        if $crate::logger::enabled($level, $target) {
+            $crate::logger::log($level, $target, format!($($arg)+));
+        }
+    };
+    ($level:expr, $($arg:tt)+) => {
+        $crate::log_at!($level, target: module_path!(), $($arg)+)
+    };
+}
+
+#[macro_export]
+macro_rules! log_trace {
+    ($($arg:tt)+) => { $crate::log_at!($crate::logger::Level::Trace, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! log_debug {
+    ($($arg:tt)+) => { $crate::log_at!($crate::logger::Level::Debug, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! log_info {
+    ($($arg:tt)+) => { $crate::log_at!($crate::logger::Level::Info, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! log_warn {
+    ($($arg:tt)+) => { $crate::log_at!($crate::logger::Level::Warn, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! log_error {
+    ($($arg:tt)+) => { $crate::log_at!($crate::logger::Level::Error, $($arg)+) };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filters_levels_by_target() {
+        let config = LogConfig::parse_filter(
+            "warn,binance_interface=debug,strategies=error,strategies::adx=trace,bogus=loud",
+        );
+        assert_eq!(config.default_level, Level::Warn);
+        // the bad directive is skipped
+        assert_eq!(config.target_levels.len(), 3);
+        assert_eq!(config.level_for("main"), Level::Warn);
+        assert_eq!(config.level_for("binance_interface"), Level::Debug);
+        // prefixes match whole module path segments, and the longest wins
+        assert_eq!(config.level_for("strategies::sma_crossover"), Level::Error);
+        assert_eq!(config.level_for("strategies::adx"), Level::Trace);
+        assert_eq!(config.level_for("strategies_extra"), Level::Warn);
+        assert_eq!(config.level_for("binance_interface_v2"), Level::Warn);
+
+        let empty = LogConfig::parse_filter("");
+        assert_eq!(empty.default_level, Level::Info);
+        assert!(Level::Debug < empty.level_for("anything"));
+        assert_eq!(Level::parse(" Warning "), Some(Level::Warn));
+    }
+
+    #[test]
+    fn routes_module_paths_to_targets() {
+        assert_eq!(
+            normalize_target("trading_system::binance_interface"),
+            "binance_interface"
+        );
+        assert_eq!(
+            normalize_target("trading_system::strategies::adx"),
+            "strategies::adx"
+        );
+        // targets given by hand and other crates are left alone
+        assert_eq!(normalize_target("action"), "action");
+        assert_eq!(normalize_target("reqwest::connect"), "reqwest::connect");
+        let config = LogConfig::parse_filter("error,strategies=debug");
+        assert_eq!(
+            config.level_for(normalize_target("trading_system::strategies::ema_sma_adx")),
+            Level::Debug
+        );
+    }
+}
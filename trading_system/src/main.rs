@@ -1,157 +1,233 @@
+#[macro_use]
+mod logger;
+
+mod account;
+mod backtest;
+mod bars;
 mod binance_interface;
 mod binance_structs;
+mod credentials;
+mod execution;
+mod feed;
 mod helpers;
+mod journal;
+mod market;
+mod metrics;
+mod network;
+mod notifier;
+mod optimizer;
+mod order_book;
+mod persistence;
+mod portfolio;
+mod rate_limiter;
+mod reconciliation;
 mod strategies;
+mod tca;
+mod time_sync;
 mod trading_strategies;
+mod universe;
 
 use binance_structs::{MarketRequest, ReceivedData};
+use execution::{ChildContext, Executor, SentChild};
 use chrono::prelude::*;
+use crossbeam_channel::{after, unbounded, Receiver, Select, Sender, TryRecvError};
+use feed::Feed;
 use helpers::epoch_ms;
-use serde_json::json;
-use std::collections::HashMap;
-use std::collections::VecDeque;
+use journal::{Event, Journal, JournalHandle};
+use market::{Pair, PriceGraph};
+use notifier::{Notification, NotificationDispatcher, Severity};
+use persistence::PersistedState;
+use portfolio::{PlayStats, Position, Rebalance, Sizer};
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::env;
-use std::fs::{File, OpenOptions};
+use std::fs::OpenOptions;
 use std::io::Write;
-use std::io::{BufRead, BufReader};
-use std::sync::mpsc;
-use std::sync::mpsc::{Receiver, Sender};
+use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::Duration;
+use strategies::Target;
+use trading_strategies::PairStrategy;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     // global vars
     let mut diagnostic = false;
 
+    logger::init(logger::LogConfig::from_env());
+
     // command line args
     let args: Vec<String> = env::args().collect();
+    let journal_path = "../logs/journal.jsonl";
+
+    // replay tool: rebuild the state from the journal and exit
+    // usage: trading_system replay [until_ms] [journal_path]
+    if args.len() > 1 && args[1] == "replay" {
+        let until_ms: u64 = match args.get(2) {
+            Some(until_str) => until_str.parse()?,
+            None => u64::MAX,
+        };
+        let replay_path = args.get(3).map(|x| x.as_str()).unwrap_or(journal_path);
+        let state = journal::replay(replay_path, until_ms)?;
+        println!("last_seq: {:?}", state.last_seq);
+        println!("last_timestamp: {}", state.last_timestamp);
+        println!("running: {}", state.running);
+        println!("algo_status: {:?}", state.algo_status);
+        println!("previous_signals: {:?}", state.previous_signals);
+        for (symbol, history) in state.ohlc_history.iter() {
+            println!("ohlc_history {}: {} bars, last: {:?}", symbol, history.len(), history.last());
+        }
+        println!("orders: {:?}", state.orders);
+        println!("fills: {:?}", state.fills);
+        println!("commands: {:?}", state.commands);
+        println!("symbols: {:?}", state.symbols);
+        println!("assets: {:?}", state.assets);
+        return Ok(());
+    }
+
+    // transaction cost report from the journal
+    // usage: trading_system tca [since_ms] [journal_path]
+    if args.len() > 1 && args[1] == "tca" {
+        let since_ms: u64 = match args.get(2) {
+            Some(since_str) => since_str.parse()?,
+            None => 0,
+        };
+        let tca_path = args.get(3).map(|x| x.as_str()).unwrap_or(journal_path);
+        let entries = journal::read_entries(tca_path)?;
+        print!("{}", tca::TcaReport::from_entries(&entries, since_ms).format("(reporting)"));
+        return Ok(());
+    }
+
+    // parameter optimizer: backtest a strategy over a space of its settings, write the results and exit
+    // usage: trading_system optimize <config_file>
+    if args.len() > 1 && args[1] == "optimize" {
+        let config_path = args.get(2).ok_or("usage: trading_system optimize <config_file>")?;
+        let config = optimizer::OptimizerConfig::load(config_path)?;
+        let bars = match &config.bars {
+            optimizer::BarSource::File(bars_path) => backtest::read_bars(bars_path)?,
+            // klines are public, so they're fetched without the accounts' keys, which aren't loaded yet
+            optimizer::BarSource::Klines { symbol, days } => binance_interface::fetch_kline_history(symbol, epoch_ms(), days * 24 * 60 * 60 * 1000),
+        };
+        println!("backtesting {} on {} bars", config.strategy, bars.len());
+        let trials = optimizer::optimize(&config, &bars);
+        let order = optimizer::rank(&trials, config.rank_by);
+        std::fs::write(&config.results, optimizer::results_csv(&config.params, &trials, &order))?;
+        print!("{}", optimizer::format_top(&config, &trials, &order, 10));
+        println!("wrote {} trials to {}", trials.len(), config.results);
+        if let Some(heatmap) = optimizer::heatmap_csv(&config, &trials) {
+            std::fs::write(&config.heatmap, heatmap)?;
+            println!("wrote the heat map to {}", config.heatmap);
+        }
+        return Ok(());
+    }
+
+    // keystore tool: encrypt a key file with a passphrase and exit
+    // usage: trading_system keystore <key_file> <keystore_path>
+    if args.len() > 1 && args[1] == "keystore" {
+        if args.len() != 4 {
+            return Err("usage: trading_system keystore <key_file> <keystore_path>".into());
+        }
+        let keys = credentials::read_key_file(&args[2])?;
+        let passphrase = zeroize::Zeroizing::new(rpassword::prompt_password_stderr("new keystore passphrase: ")?);
+        let confirmation = zeroize::Zeroizing::new(rpassword::prompt_password_stderr("repeat passphrase: ")?);
+        if passphrase != confirmation {
+            return Err("passphrases don't match".into());
+        }
+        let keystore = credentials::encrypt_keystore(&keys, &passphrase)?;
+        let mut keystore_options = OpenOptions::new();
+        keystore_options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut keystore_options, 0o600);
+        keystore_options.open(&args[3])?.write_all(keystore.as_bytes())?;
+        println!("wrote keystore to {}. start with JANE_KEYSTORE={} to use it.", args[3], args[3]);
+        return Ok(());
+    }
 
     if args.len() != 0 {
         // diagnostic flag
         if args.contains(&"diagnostic".to_string()) {
-            println!("DIAGNOSTIC MODE IS ON.");
+            log_warn!(target: "main", "DIAGNOSTIC MODE IS ON.");
             diagnostic = true;
         } else{
-            println!("LIVE MODE IS ON. DIAGNOSTIC MODE IS OFF.");
+            log_info!(target: "main", "LIVE MODE IS ON. DIAGNOSTIC MODE IS OFF.");
         }
     }
 
+    // accounts(books), each with its own keys, coins, capital split, state files and risk limits. see docs/accounts.txt.
+    let accounts = match account::load_configs("../accounts.json").and_then(account::open_accounts) {
+        Ok(accounts) => accounts,
+        Err(e) => {
+            log_error!(target: "main", "could not load accounts: {}", e);
+            return Err(e.into());
+        }
+    };
+    // requests that don't belong to an account(market data, server time) go out with the first account's keys
+    credentials::set_default(accounts[0].credentials.clone());
+
     // tx/rx for init
-    let (init_tx1, init_rx): (Sender<bool>, Receiver<bool>) = mpsc::channel();
-    let init_tx2 = init_tx1.clone();
-    let init_tx3 = init_tx1.clone();
-    let init_tx4 = init_tx1.clone();
+    let (init_tx, init_rx): (Sender<bool>, Receiver<bool>) = unbounded();
 
     // tx/rx for human-readable logs
-    let (humanlog_tx1, humanlog_rx): (Sender<String>, Receiver<String>) = mpsc::channel();
+    let (humanlog_tx1, humanlog_rx): (Sender<Notification>, Receiver<Notification>) =
+        unbounded();
     let humanlog_tx2 = humanlog_tx1.clone();
-    let humanlog_tx3 = humanlog_tx1.clone();
 
     // tx/rx for file logs
     let (filelog_tx1, filelog_rx): (
         Sender<HashMap<String, String>>,
         Receiver<HashMap<String, String>>,
-    ) = mpsc::channel();
-
-    // tx/rx for trades out
-    let (marketreq_tx1, marketreq_rx): (Sender<MarketRequest>, Receiver<MarketRequest>) =
-        mpsc::channel();
+    ) = unbounded();
 
-    // tx/rx for trades confirm
-    let (reqconfirm_tx1, reqconfirm_rx): (Sender<bool>, Receiver<bool>) = mpsc::channel();
+    // tx/rx for the shared market data feed. the feed thread passes each kline, or each bar built from trades, on to the accounts trading its symbol.
+    let (feed_tx, feed_rx): (Sender<ReceivedData>, Receiver<ReceivedData>) = unbounded();
 
-    // tx/rx for klines update line
-    let (kline_tx1, kline_rx): (Sender<ReceivedData>, Receiver<ReceivedData>) = mpsc::channel();
-    let kline_tx2 = kline_tx1.clone();
-    let kline_tx3 = kline_tx1.clone();
-
-    // tx/rx for command lines
-    let (cmd_tx1, cmd_rx): (Sender<String>, Receiver<String>) = mpsc::channel();
-
-    // thread(s) to pull live webstream data from binance
-    let _klines_thread1 = thread::Builder::new()
-        .name("klines_data_thread".to_string())
-        .spawn(move || {
-            binance_interface::live_binance_stream(
-                "ethusdt@kline_1m",
-                &kline_tx1,
-                &init_tx1,
-                binance_structs::StreamType::KLine,
-            );
-        });
-    let _klines_thread2 = thread::Builder::new()
-        .name("klines_data_thread".to_string())
-        .spawn(move || {
-            binance_interface::live_binance_stream(
-                "ltcusdt@kline_1m",
-                &kline_tx2,
-                &init_tx3,
-                binance_structs::StreamType::KLine,
-            );
-        });
-    let _klines_thread3 = thread::Builder::new()
-        .name("klines_data_thread".to_string())
-        .spawn(move || {
-            binance_interface::live_binance_stream(
-                "btcusdt@kline_1m",
-                &kline_tx3,
-                &init_tx4,
-                binance_structs::StreamType::KLine,
-            );
-        });
+    // health metrics on /metrics. JANE_METRICS_ADDR sets the address, "none" disables the endpoint.
+    let metrics_addr = env::var("JANE_METRICS_ADDR").unwrap_or_else(|_| "127.0.0.1:9184".to_string());
+    if metrics_addr != "none" {
+        if let Err(e) = metrics::serve(&metrics_addr) {
+            log_warn!(target: "main", "metrics: could not listen on {}: {}", metrics_addr, e);
+        }
+    }
+    let feed_backlog = feed_rx.clone();
+    metrics::global().register_backlog("kline", Box::new(move || feed_backlog.len()));
+    let humanlog_backlog = humanlog_rx.clone();
+    metrics::global().register_backlog("humanlog", Box::new(move || humanlog_backlog.len()));
+    let filelog_backlog = filelog_rx.clone();
+    metrics::global().register_backlog("filelog", Box::new(move || filelog_backlog.len()));
 
-    // thread for writing discord output(human friendly)
-    let _humanlog_thread = thread::Builder::new().name("humanlog_thread".to_string()).spawn (move || {
-        let mut last_timestamp = epoch_ms();
-        let delta_time = 60000;
-        let mut webhook_logs: VecDeque<String> = VecDeque::new();
-        let mut humanlog_iter = humanlog_rx.try_iter();
-        let webhook_url = "https://discordapp.com/api/webhooks/744067382850486372/eMESqfoTfgc1tYvkF9smKdkkyL5W1bMPn7cT_e1R-rtVXz-xfXnGO0TW5gDe7z0Lvy0U";
-        loop {
-            let next_data = humanlog_iter.next();
-            let local_now: DateTime<Local> = Local::now();
-            if next_data.is_some() {
-                println!("{} ms until webhook write. ", last_timestamp as i64+delta_time as i64-epoch_ms() as i64);
-                let raw_str = next_data.unwrap();
-                webhook_logs.push_back(raw_str.clone());
-            } 
-            
-            // write to discord if delta_t passed
-            if epoch_ms() >= last_timestamp + delta_time {
-                while webhook_logs.len() > 0 {
-                    println!("webhook_logs: {:?}", webhook_logs);
-                    let mut joined = String::new();
-                    let mut total_length = 0;
-                    
-                    loop {
-                        if webhook_logs.len() == 0 {
-                            break;
-                        }
-                        if webhook_logs[0].chars().count() + total_length >= 1500 {
-                            break;
-                        } else {
-                            total_length += webhook_logs[0].chars().count();
-                            joined = format!("{}\n{}", joined, webhook_logs.pop_front().unwrap());
-                        }
-                    }
+    // sync with binance's clock before any signed request goes out, then keep it synced in the background
+    network::block_on(time_sync::sync_and_report(&humanlog_tx1));
+    network::spawn(time_sync::run(humanlog_tx1.clone()));
 
-                    if diagnostic {
-                        println!("diagnostic print on. ");
-                        joined = format!("```fix\n{}\n{}```", local_now, joined);
-                    } else {
-                        joined = format!("```\n{}\n\n{}```", local_now, joined);
-                    }
+    // exchange info is shared by every account
+    let exchange_info = binance_interface::binance_rest_api("exchange_info", "");
+    if !rate_limiter::global().lock().unwrap().set_limits(&exchange_info["rateLimits"]) {
+        log_warn!(target: "main", "exchange_info has no rate limits, using the defaults.");
+    }
+    let all_pairs = market::pairs_from_exchange_info(&exchange_info);
+    // 24h stats for the accounts that pick their pairs with universe rules
+    let ticker_stats = if accounts.iter().any(|x| x.config.universe.is_some()) {
+        universe::stats_from_ticker_24hr(&binance_interface::binance_rest_api("ticker_24hr", ""))
+    } else {
+        HashMap::new()
+    };
 
-                    // discord webhook write
-                    let mut message = HashMap::new();
-                    message.insert("content", joined);
-                    let response = binance_interface::json_rest_req(webhook_url.to_string(), "post".to_string(), message);
-                    println!("discord webhook response: {}", response);
-                }
+    // live webstream data from binance, one kline stream per symbol any account trades. the streams run on the shared network runtime
+    // and are started once every account has added its symbols.
+    // local order books are kept for the same symbols unless JANE_ORDER_BOOKS is "off".
+    let order_books = env::var("JANE_ORDER_BOOKS").map(|x| x != "off").unwrap_or(true);
+    let feed = Arc::new(Mutex::new(Feed::new(feed_tx, order_books)));
 
-                last_timestamp += delta_time;
-            }
+    // thread for notifications(human friendly). sinks are configured in ../notifiers.json.
+    let notification_dispatcher = match NotificationDispatcher::from_file("../notifiers.json", diagnostic) {
+        Ok(dispatcher) => dispatcher,
+        Err(e) => {
+            log_warn!(target: "main", "notifications: {}. notifications will only be logged.", e);
+            NotificationDispatcher::new(Vec::new(), Severity::Alert, 60000)
         }
-    });
+    };
+    let _humanlog_thread = thread::Builder::new()
+        .name("humanlog_thread".to_string())
+        .spawn(move || notification_dispatcher.run(humanlog_rx));
 
     // thread for writing file output(machine friendly)
     // writes one json object per line.
@@ -165,446 +241,883 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .create(true)
                 .open(file_name)
                 .unwrap();
-            let mut filelog_iter = filelog_rx.try_iter();
-            loop {
-                let next_data = filelog_iter.next();
-                if next_data.is_some() {
-                    let mut data_to_write = next_data.unwrap().clone();
-                    data_to_write.insert("timestamp".to_string(), format!("{}", epoch_ms()));
-                    let str_write = format!("{}\n", json!(data_to_write).to_string());
-                    let write_status = log_file.write(str_write.as_bytes());
-                    if write_status.is_err() {
-                        let _ = humanlog_tx2
-                            .send("warning: errors with writing to log file".to_string());
-                    }
+            // blocks until data comes in. the thread ends once every sender is dropped.
+            for mut data_to_write in filelog_rx.iter() {
+                data_to_write.insert("timestamp".to_string(), format!("{}", epoch_ms()));
+                let str_write = format!("{}\n", json!(data_to_write).to_string());
+                let write_status = log_file.write(str_write.as_bytes());
+                if write_status.is_err() {
+                    let _ = humanlog_tx2
+                        .send(Notification::warning("warning: errors with writing to log file".to_string()));
                 }
             }
         });
 
-    // thread for sending/processing market requests
-    let _marketreq_thread = thread::Builder::new()
-        .name("marketreq_thread".to_string())
-        .spawn(move || loop {
-            let mut marketreq_iter = marketreq_rx.try_iter();
-            loop {
-                let next_data = marketreq_iter.next();
-                if !next_data.is_none() && !diagnostic {
-                    let result = binance_interface::binance_trade_api(next_data.unwrap());
-                    println!("Printing API result from market order.");
-                    println!("{}", result);
+    // per account tx/rx for command lines and universe updates
+    let mut command_routes: Vec<(String, Sender<String>)> = Vec::new();
+    let mut universe_routes: Vec<(account::AccountConfig, Sender<Vec<Pair>>)> = Vec::new();
+
+    for account in accounts {
+        // tx/rx for trades out, numbered so their responses can be told apart
+        let (marketreq_tx1, marketreq_rx) = unbounded::<(u64, MarketRequest)>();
+
+        // tx/rx for trades confirm
+        // carries the order response back under the request's number, or null in diagnostic mode
+        let (reqconfirm_tx1, reqconfirm_rx) = unbounded::<(u64, Value)>();
+
+        // tx/rx for this account's klines
+        let (kline_tx, kline_rx): (Sender<ReceivedData>, Receiver<ReceivedData>) = unbounded();
+        let kline_backlog = kline_rx.clone();
+        metrics::global().register_backlog(&format!("kline_{}", account.config.name), Box::new(move || kline_backlog.len()));
+        let symbols = account.config.select_symbols(&all_pairs, &ticker_stats);
+        let pairs = account.pairs(&symbols, &all_pairs)?;
+        feed.lock().unwrap().add_account(&account.config.name, symbols.clone(), kline_tx, account.config.bars);
+        let feed1 = feed.clone();
+
+        // tx/rx for the pairs picked by the account's universe rules
+        let (universe_tx, universe_rx): (Sender<Vec<Pair>>, Receiver<Vec<Pair>>) = unbounded();
+        if account.config.universe.is_some() {
+            universe_routes.push((account.config.clone(), universe_tx));
+        }
+
+        // tx/rx for command lines
+        let (cmd_tx, cmd_rx): (Sender<String>, Receiver<String>) = unbounded();
+        command_routes.push((account.config.name.clone(), cmd_tx));
+
+        // write-ahead journal of everything the account sees and does
+        let journal1 = JournalHandle::new(Journal::open(&account.config.journal_file)?, humanlog_tx1.clone());
+        let journal2 = journal1.clone();
+
+        let init_tx2 = init_tx.clone();
+        let humanlog_tx1 = humanlog_tx1.clone();
+        let humanlog_tx3 = humanlog_tx1.clone();
+        let filelog_tx1 = filelog_tx1.clone();
+        let all_pairs = all_pairs.clone();
+        let order_credentials = account.credentials.clone();
+        let order_account_name = account.config.name.clone();
+
+        // thread for sending/processing market requests
+        let _marketreq_thread = thread::Builder::new()
+            .name(format!("marketreq_{}", order_account_name))
+            .spawn(move || {
+                let _account_span = logger::span(&[("account", order_account_name.clone())]);
+                // requests are sent one at a time, in the order they were made
+                for (seq, request) in marketreq_rx.iter() {
+                    if diagnostic {
+                        let _ = reqconfirm_tx1.send((seq, Value::Null));
+                        continue;
+                    }
+                    let symbol = request.symbol.clone();
+                    let _symbol_span = logger::span(&[("ticker", symbol.clone())]);
+                    let result = binance_interface::binance_trade_api(&order_credentials, request);
+                    let _order_span = logger::span(&[("order_id", result["orderId"].to_string())]);
+                    log_info!(target: "marketreq", "market order result: {}", result);
+                    for event in journal::order_response_events(&symbol, &result) {
+                        journal2.record(event);
+                    }
                     if !result["status"].as_str().is_none() {
                         let filled_status: String =
                             result["status"].as_str().unwrap().parse().unwrap();
                         if filled_status == "FILLED".to_string() {
-                            let _ = humanlog_tx3.send("The order has been filled.".to_string());
+                            let _ = humanlog_tx3.send(Notification::alert("The order has been filled.".to_string()));
                         } else {
                             let _ = humanlog_tx3
-                                .send("The order has not been filled for some reason.".to_string());
+                                .send(Notification::warning("The order has not been filled for some reason.".to_string()));
                         }
                     }
                     let log_str = format!("trading_result: {}", result.to_string());
-                    let _ = humanlog_tx3.send(log_str);
-                    let _ = reqconfirm_tx1.send(true);
-                } else if !next_data.is_none() && diagnostic {
-                    let _ = reqconfirm_tx1.send(true);
+                    let _ = humanlog_tx3.send(Notification::alert(log_str));
+                    let _ = reqconfirm_tx1.send((seq, result));
                 }
-            }
-        });
+            });
 
-    // action thread(main trading system)
-    let _action_thread = thread::Builder::new().name("action_data_thread".to_string()).spawn(move || {
-
-        // process flags
-        let mut running = false;
-
-        // generate/initializeportfolio management variables
-        let number_algos = 1;
-        let mut ohlc_history: Vec<Vec<Vec<f64>>> = Vec::new();
-        let mut algo_status: Vec<i32> = vec![0; number_algos];
-        let capital_split = vec![1.0];
-        let symbols_interest = [
-            "USDT".to_string(), 
-            "ETH".to_string(), 
-            "BTC".to_string(),
-            "LTC".to_string(),
-        ];
-        let mut ticker_list = Vec::new();
-        for i in 1..symbols_interest.len() {
-            let ticker = format!("{}{}", symbols_interest[i], symbols_interest[0]);
-            ticker_list.push(ticker);
-        }
-        let mut stepsize: Vec<f64> = vec![-1.0; symbols_interest.len()-1];
-        let mut min_notional: Vec<f64> = vec![-1.0; symbols_interest.len()-1];
-        let mut previous_signals: Vec<Vec<i32>> = vec![vec![-2; number_algos]; ticker_list.len()];
-        let mut p_data: Vec<Vec<Vec<f64>>> = vec![vec![Vec::new(); number_algos]; ticker_list.len()];
-
-        // settings(numerical only)
-        let mut settings = HashMap::new();
-        settings.insert("ohlc_period", 60 * 1000);
-        settings.insert("max_lookback_ms", settings["ohlc_period"] * 24 * 60);
-
-        // generate stepsize and min_notional
-        let exchange_info = binance_interface::binance_rest_api("exchange_info", epoch_ms(), "");
-        let symbols_arr = exchange_info["symbols"].as_array().unwrap();
-
-        for asset in symbols_arr {
-            let symbol = asset["baseAsset"].as_str().unwrap().to_string();
-            let quote_asset = asset["quoteAsset"].as_str().unwrap().to_string();
-            if symbols_interest.iter().position(|x| x == &symbol) != None && quote_asset == "USDT".to_string() {
-                println!("symbol: {}", symbol);
-                let index = symbols_interest.iter().position(|x| x == &symbol).unwrap() - 1;
-                println!("index: {}", index);
-                for filter in asset["filters"].as_array().unwrap() {
-                    if filter["filterType"].as_str().unwrap() == "LOT_SIZE" {
-                        stepsize[index] = filter["stepSize"].as_str().unwrap().parse().unwrap();
-                    } else if filter["filterType"].as_str().unwrap() == "MIN_NOTIONAL" {
-                        min_notional[index] = filter["minNotional"].as_str().unwrap().parse().unwrap();
+        // action thread(main trading system)
+        let _action_thread = thread::Builder::new().name(format!("action_{}", account.config.name)).spawn(move || {
+            let _account_span = logger::span(&[("account", account.config.name.clone())]);
+
+            // process flags
+            let mut running = false;
+
+            // generate/initializeportfolio management variables
+            let number_algos = account.config.strategies.len();
+            let mut ohlc_history: Vec<Vec<Vec<f64>>> = Vec::new();
+            let mut algo_status: Vec<i32> = vec![0; number_algos];
+            let capital_split = account.config.capital_split.clone();
+            let mut ticker_list = symbols;
+            let mut pairs = pairs;
+
+            // saved state from the last run, reconciled with the exchange further down
+            let var_file_path = account.config.var_file.as_str();
+            let mut persisted = match persistence::load_vars(var_file_path) {
+                Some(state) => state,
+                None => {
+                    let _ = humanlog_tx1.send(Notification::warning(format!("reconciliation: no usable variable file found, assuming every algorithm is in {}.", account.config.home)));
+                    PersistedState { algo_status: vec![0; number_algos], saved_at: 0, assets: Vec::new() }
+                }
+            };
+
+            // assets the account can hold, home first. algo_status[i] is the index of the asset algo i is in.
+            // the list only grows while running, so the indices stay valid as pairs enter and leave the universe.
+            let mut symbols_interest = market::assets_of(&account.config.home, &pairs);
+            // an algo saved in an asset none of the pairs trade gets its pair back to home, so it can still sell out
+            for asset in persistence::remap_assets(&mut persisted, &mut symbols_interest) {
+                let symbol = format!("{}{}", asset, account.config.home);
+                match all_pairs.get(&symbol) {
+                    Some(pair) => {
+                        log_info!(target: "action", "an algo is in {}, adding {}.", asset, symbol);
+                        ticker_list.push(symbol);
+                        pairs.push(pair.clone());
+                    }
+                    None => {
+                        let _ = humanlog_tx1.send(Notification::warning(format!("an algo is in {}, but there is no {} pair to sell it.", asset, symbol)));
                     }
                 }
             }
-        }
+            // index of each pair's base and quote asset in symbols_interest
+            let pair_assets = |pairs: &[Pair], assets: &[String]| -> (Vec<i32>, Vec<i32>) {
+                let index = |asset: &String| assets.iter().position(|x| x == asset).unwrap() as i32;
+                (pairs.iter().map(|x| index(&x.base)).collect(), pairs.iter().map(|x| index(&x.quote)).collect())
+            };
+            let (mut pair_base, mut pair_quote) = pair_assets(&pairs, &symbols_interest);
+            let mut previous_signals: Vec<Vec<i32>> = vec![vec![-2; number_algos]; ticker_list.len()];
+            let mut p_data: Vec<Vec<Vec<f64>>> = vec![vec![Vec::new(); number_algos]; ticker_list.len()];
+            // every pair runs its own instance of each algo's strategy. stateful ones are warmed from predata.
+            let mut pair_strategies: Vec<Vec<PairStrategy>> = ticker_list.iter().map(|_| trading_strategies::pair_strategies(&account.config.strategies)).collect();
+            // targets are sized against each pair's atr, and positions rebalanced to them. see portfolio.rs.
+            let mut pair_sizers: Vec<Sizer> = ticker_list.iter().map(|_| Sizer::new(&account.config.sizing)).collect();
+            let mut positions: Vec<Position> = vec![Position::default(); number_algos];
+            let mut play_stats: Vec<PlayStats> = vec![PlayStats::default(); number_algos];
+            // parent orders that rebalance an algo that stays in its pair
+            let mut rebalances: HashSet<u64> = HashSet::new();
+            // algo_status and position of the algo before each parent order, put back if nothing fills
+            let mut before_parent: HashMap<u64, (i32, Position)> = HashMap::new();
 
-        println!("stepsize: {:?}", stepsize);
-        println!("min_notional: {:?}", min_notional);
+            // per-algo position bookkeeping for the notional/pnl metrics. quantity is in the asset the algo holds,
+            // cost and pnl in the reporting currency.
+            let mut algo_quantity: Vec<f64> = vec![0.0; number_algos];
+            let mut algo_cost: Vec<f64> = vec![0.0; number_algos];
+            let mut algo_realized_pnl: Vec<f64> = vec![0.0; number_algos];
 
-        // check if any values are unpopulated
-        if stepsize.contains(&-1.0) {
-            let _ = humanlog_tx1.send("One or more elements of stepsize was not calculated. Panicking.".to_string());
-            panic!("One or more elements of stepsize was not calculated.");
-        }
+            // parent orders being worked by the account's execution algorithm
+            let mut executor = Executor::new(account.config.execution.clone(), epoch_ms());
 
-        if min_notional.contains(&-1.0) {
-            let _ = humanlog_tx1.send("One or more elements of min_notional was not calculated. Panicking.".to_string());
-            panic!("One or more elements of min_notional was not calculated.");
-        }
+            // settings(numerical only)
+            let mut settings = HashMap::new();
+            settings.insert("ohlc_period", 60 * 1000);
+            settings.insert("max_lookback_ms", settings["ohlc_period"] * trading_strategies::HISTORY_BARS);
 
-        println!("Action initialization successful!");
-        init_tx2.send(true).unwrap();
-
-        // main loop
-        loop {
-            // system time 
-            let time_now = epoch_ms();
-
-            // check if command exists
-            let mut command_good = true;
-            let command = match cmd_rx.try_recv() {
-                Ok(data) => data,
-                Err(_) => {
-                    command_good = false;
-                    "error".to_string()
-                }
+            // generate stepsize and min_notional. min_notional is in each pair's quote asset.
+            let mut stepsize: Vec<f64> = pairs.iter().map(|x| x.step_size.unwrap_or(-1.0)).collect();
+            let mut min_notional: Vec<f64> = pairs.iter().map(|x| x.min_notional.unwrap_or(-1.0)).collect();
+
+            // prices of every pair, to value positions in the reporting currency. the account's own pairs follow the klines.
+            let mut prices = PriceGraph::from_ticker_prices(&binance_interface::binance_rest_api("ticker_price", ""), &all_pairs);
+
+            log_info!(target: "action", "stepsize: {:?}", stepsize);
+            log_info!(target: "action", "min_notional: {:?}", min_notional);
+
+            // check if any values are unpopulated
+            if stepsize.contains(&-1.0) {
+                let _ = humanlog_tx1.send(Notification::error("One or more elements of stepsize was not calculated. Panicking.".to_string()));
+                panic!("One or more elements of stepsize was not calculated.");
+            }
+
+            if min_notional.contains(&-1.0) {
+                let _ = humanlog_tx1.send(Notification::error("One or more elements of min_notional was not calculated. Panicking.".to_string()));
+                panic!("One or more elements of min_notional was not calculated.");
+            }
+
+            // startup reconciliation. trading stays locked until every discrepancy is resolved or acknowledged.
+            let mut reconciliation = reconciliation::run_reconciliation(&account.credentials, &persisted, &symbols_interest, &pairs, &all_pairs, &account.config.reporting, epoch_ms(), &humanlog_tx1);
+            algo_status = reconciliation.algo_status.clone();
+            // epoch ms algo_status last matched the exchange, so 'reconcile' only replays fills jane didn't see
+            let mut status_at = reconciliation.reconciled_at;
+
+            // last state written to the journal, so only changes are recorded
+            let mut journaled_status = algo_status.clone();
+            let mut journaled_running = running;
+            journal1.record(Event::StateChange { algo_status: algo_status.clone(), running });
+            for (i, status) in algo_status.iter().enumerate() {
+                metrics::global().set_algo_status(&account.config.name, i, *status);
+            }
+            journal1.record(Event::UniverseChange { symbols: ticker_list.clone(), assets: symbols_interest.clone() });
+            feed1.lock().unwrap().set_symbols(&account.config.name, ticker_list.clone());
+
+            log_info!(target: "action", "Action initialization successful!");
+            init_tx2.send(true).unwrap();
+
+            // klines taken off the channel but not handled yet, and the end of the fetched history, which buffered klines
+            // up to are already in
+            let mut pending_klines: VecDeque<ReceivedData> = VecDeque::new();
+            let mut history_end: u64 = 0;
+
+            // the account's balances, requested on the network runtime so a slow exchange doesn't hold up commands and
+            // market data. a fill moves the balances under what working parents reserve, so they're stale from then on
+            // until a request sent after it answers. requests are numbered to tell.
+            let (account_info_tx, account_info_rx) = unbounded::<(u64, Result<Value, String>)>();
+            let account_info_credentials = account.credentials.clone();
+            let request_account_info = |seq: u64| {
+                let credentials = account_info_credentials.clone();
+                network::request(seq, async move { binance_interface::binance_account_api_async(&credentials, "get_accountinfo", "").await }, account_info_tx.clone());
             };
+            let mut account_info = Value::Null;
+            // last request sent, the one the balances are from, and the first one whose answer is up to date
+            let (mut account_info_seq, mut account_info_applied, mut account_info_fresh_from): (u64, u64, u64) = (1, 0, 1);
+            let mut account_info_requested_at = epoch_ms();
+            let mut account_info_in_flight = true;
+            request_account_info(account_info_seq);
 
-            // commands list 
-            if command_good {
-                if command == "start" {
-                    running = true;
-                } else if command == "stop" {
-                    running = false;
-                } else if command == "autostart" {
-                    // COPYPASTE OF FETCHING PREDATA
-                    println!("fetching predata...");
-                    let api_limit = 500;
-                    let end_window = epoch_ms();
-                    
-                    for ticker in ticker_list.iter() {
-                        println!("fetching predata for {}...", ticker);
-                        let start_window = end_window - settings["max_lookback_ms"];
-                        let mut end_chunk = end_window;
-                        let mut ticker_ohlc = Vec::new();
-                        while end_chunk >= start_window {
-                            let mut new_ohlcs = binance_interface::fetch_klines(&ticker, end_chunk, api_limit);
-                            let mut swap = Vec::new();
-                            swap.append(&mut new_ohlcs);
-                            swap.append(&mut ticker_ohlc);
-                            ticker_ohlc = swap.clone();
-                            end_chunk -= api_limit * 60 * 1000;
+            // orders sent to the market thread and not answered yet. the responses come back numbered like the requests,
+            // so the loop goes on while they're out.
+            let mut order_seq: u64 = 0;
+            let mut orders_in_flight: HashMap<u64, SentChild> = HashMap::new();
+            let mut market_alive = true;
+
+            // main loop
+            // blocks until a command, a universe update or market data comes in, then handles one event per iteration.
+            // commands always go first and market data last, so the order doesn't depend on which channel woke the loop.
+            loop {
+                // wakes the loop when the next child order is due
+                let exec_timer = executor.next_due().map(|due| after(Duration::from_millis(due.saturating_sub(epoch_ms()))));
+                let mut event_select = Select::new();
+                event_select.recv(&cmd_rx);
+                event_select.recv(&kline_rx);
+                event_select.recv(&account_info_rx);
+                if market_alive {
+                    event_select.recv(&reqconfirm_rx);
+                }
+                if let Some(exec_timer) = &exec_timer {
+                    event_select.recv(exec_timer);
+                }
+                // only accounts with universe rules get updates. the sender of the others is dropped.
+                if account.config.universe.is_some() {
+                    event_select.recv(&universe_rx);
+                }
+                // buffered klines are handled without waiting for more, unless they wait for the balances to come in
+                let waiting_for_balances = account_info_in_flight && account_info_applied < account_info_fresh_from;
+                if pending_klines.is_empty() || waiting_for_balances {
+                    event_select.ready();
+                }
+
+                // system time 
+                let time_now = epoch_ms();
+
+                // balances requested earlier. replies to older requests can still come in after a newer one went out.
+                for (seq, reply) in account_info_rx.try_iter() {
+                    if seq == account_info_seq {
+                        account_info_in_flight = false;
+                    }
+                    match reply {
+                        Ok(info) if info["balances"].is_array() => {
+                            if seq > account_info_applied {
+                                account_info = info;
+                                account_info_applied = seq;
+                            }
                         }
-                        ohlc_history.push(ticker_ohlc);
+                        Ok(info) => log_warn!(target: "action", "account info request failed: {}", info),
+                        Err(e) => log_warn!(target: "action", "account info request failed: {}", e),
                     }
+                }
+                let waiting_for_balances = account_info_in_flight && account_info_applied < account_info_fresh_from;
 
-                    let _ = humanlog_tx1.send("predata: finished fetching predata.".to_string());
-                    println!("finished with fetching predata.");
+                // order responses. a child's fill goes to its parent, an order that didn't trade counts as filling nothing.
+                while market_alive {
+                    let (seq, response) = match reqconfirm_rx.try_recv() {
+                        Ok(reply) => reply,
+                        Err(TryRecvError::Empty) => break,
+                        Err(TryRecvError::Disconnected) => {
+                            // nothing more will come back, so the children still out filled nothing as far as jane knows
+                            market_alive = false;
+                            log_error!(target: "action", "the market thread is gone. {} orders got no response.", orders_in_flight.len());
+                            let _ = humanlog_tx1.send(Notification::alert(format!("the market thread is gone. {} orders got no response, check them on the exchange.", orders_in_flight.len())));
+                            for (_, child) in orders_in_flight.drain() {
+                                executor.on_fill(child.parent, 0.0, 0.0);
+                            }
+                            account_info_fresh_from = account_info_seq + 1;
+                            break;
+                        }
+                    };
+                    log_debug!(target: "action", "order response {} came in.", seq);
+                    let child = match orders_in_flight.remove(&seq) {
+                        Some(child) => child,
+                        None => {
+                            // ordertest
+                            account_info_fresh_from = account_info_seq + 1;
+                            continue;
+                        }
+                    };
+                    let request = &child.request;
 
-                    // fetch first previous_signals
-                    for ticker_i in 0..ticker_list.len() {
-                        let (signals, _p_data_temp) = trading_strategies::master_strategy(&ohlc_history[ticker_i], &p_data[ticker_i], &humanlog_tx1);
-                        previous_signals[ticker_i] = signals;
+                    // diagnostic mode has no response, so fills are estimated at the close, or the limit price of a limit child
+                    let price = if request.limit_price != -1.0 { request.limit_price } else { child.close };
+                    let parse_field = |field: &Value| field.as_str().and_then(|x| x.parse::<f64>().ok());
+                    let executed = match (parse_field(&response["executedQty"]), parse_field(&response["cummulativeQuoteQty"])) {
+                        (Some(quantity), Some(quote)) => Some((quantity, quote)),
+                        _ if diagnostic && price > 0.0 => {
+                            if request.quantity == -1.0 { Some((request.quote_order_qty / price, request.quote_order_qty)) } else { Some((request.quantity, request.quantity * price)) }
+                        }
+                        _ => None,
+                    };
+                    match executed {
+                        Some((quantity, quote)) => {
+                            executor.on_fill(child.parent, quantity, quote);
+                            account_info_fresh_from = account_info_seq + 1;
+                        }
+                        None => executor.on_fill(child.parent, 0.0, 0.0),
                     }
+                    // costs of orders the exchange filled, see tca.rs. estimated fills in diagnostic mode aren't recorded.
+                    // the pair can have left the universe while the order was out.
+                    let pair = pairs.iter().find(|x| x.symbol == request.symbol).or_else(|| all_pairs.get(&request.symbol));
+                    if let (Some(order_id), Some((quantity, quote)), Some(pair)) = (response["orderId"].as_u64(), executed, pair) {
+                        if quantity > 0.0 {
+                            let fill_price = quote / quantity;
+                            let commissions = tca::commissions(&response);
+                            // commissions in the base asset are valued at the fill price, other assets through the price graph
+                            let fee_quote = commissions.iter().map(|(asset, amount)| {
+                                if asset == &pair.quote {
+                                    *amount
+                                } else if asset == &pair.base {
+                                    amount * fill_price
+                                } else {
+                                    prices.value(asset, *amount, &pair.quote).unwrap_or(0.0)
+                                }
+                            }).sum();
+                            journal1.record(Event::OrderCost {
+                                symbol: request.symbol.clone(),
+                                side: request.side.clone(),
+                                algo: child.algo,
+                                parent: Some(child.parent),
+                                order_id,
+                                decision_price: child.decision_price,
+                                arrival_mid: child.arrival_mid,
+                                fill_price,
+                                quantity,
+                                quote_qty: quote,
+                                commissions,
+                                fee_quote,
+                                notional: prices.value(&pair.quote, quote, &account.config.reporting).unwrap_or(0.0),
+                            });
+                        }
+                    }
+                }
 
-                    running = true;
-                } else if command == "newlistenkey" {
-                    binance_interface::binance_rest_api("new_listenkey", time_now, "");
-                } else if command == "displayaccountinfo" {
-                    println!("{}", binance_interface::binance_rest_api("get_accountinfo", time_now, "").to_string());
-                } else if command == "testping" {
-                    binance_interface::binance_rest_api("test_ping", time_now, "");
-                } else if command == "exchangeinfo" {
-                    let exchange_info = binance_interface::binance_rest_api("exchange_info", epoch_ms(), "");
-                    println!("exchange_info: {}", exchange_info);
-                } else if command == "testtime" {
-                    binance_interface::binance_rest_api("test_time", time_now, "");
-                } else if command == "selltousdt" {
-                    // WARNING: untested
-
-                    // check account info and sells every asset to USDT
-                    let account_info = binance_interface::binance_rest_api("get_accountinfo", time_now, "");
-                    println!("account_info: {}", account_info);
-                    
-                    let mut i = 0;
-                    loop {
-                        if account_info["balances"][i].is_null() {
-                            break;
+                // check if command exists
+                let mut command_good = true;
+                let command_line = match cmd_rx.try_recv() {
+                    Ok(data) => data,
+                    Err(_) => {
+                        command_good = false;
+                        "error".to_string()
+                    }
+                };
+                let mut command_parts = command_line.split_whitespace();
+                let command = command_parts.next().unwrap_or("").to_string();
+                let command_args: Vec<&str> = command_parts.collect();
+
+                // commands list 
+                if command_good {
+                    journal1.record(Event::Command { line: command_line.clone() });
+                    if (command == "start" || command == "autostart") && reconciliation.unresolved() > 0 {
+                        log_warn!(target: "action", "{} unresolved discrepancies. run 'discrepancies' to list them and 'ack' to acknowledge.", reconciliation.unresolved());
+                        let _ = humanlog_tx1.send(Notification::warning(format!("refusing to {}: {} unresolved reconciliation discrepancies.", command, reconciliation.unresolved())));
+                    } else if command == "start" {
+                        running = true;
+                    } else if command == "stop" {
+                        running = false;
+                    } else if command == "reconcile" {
+                        if !executor.parents().is_empty() {
+                            // their fills would be replayed on top of what the finished parent does to algo_status
+                            println!("{} parent orders are still working. reconcile once they're done.", executor.parents().len());
+                        } else {
+                            persisted = PersistedState { algo_status: algo_status.clone(), saved_at: status_at, assets: symbols_interest.clone() };
+                            reconciliation = reconciliation::run_reconciliation(&account.credentials, &persisted, &symbols_interest, &pairs, &all_pairs, &account.config.reporting, time_now, &humanlog_tx1);
+                            algo_status = reconciliation.algo_status.clone();
+                            status_at = reconciliation.reconciled_at;
+                            positions = vec![Position::default(); number_algos];
+                        }
+                    } else if command == "discrepancies" {
+                        for (i, discrepancy) in reconciliation.discrepancies.iter().enumerate() {
+                            println!("{}: {}", i, discrepancy);
+                        }
+                        println!("{} unresolved.", reconciliation.unresolved());
+                    } else if command == "ack" {
+                        if command_args.first() == Some(&"all") {
+                            reconciliation.acknowledge_all();
                         } else {
-                            println!("{}", account_info["balances"][i]);
-                            let balance: f64 = account_info["balances"][i]["free"].as_str().unwrap().parse().unwrap();
-                            let ticker_sell: String = account_info["balances"][i]["asset"].as_str().unwrap().to_string();
-                            let symbol = format!("{}USDT", ticker_sell);
-                            if symbol != "USDTUSDT" && balance != 0.0 {
-                                let mut amt_to_sell:f64 = account_info["balances"][i]["free"].as_str().unwrap().parse().unwrap();
-                                println!("original amt_to_sell: {}", amt_to_sell);
-                                amt_to_sell = amt_to_sell - (amt_to_sell % 0.00001);
-                                println!("final amt_to_sell: {}", amt_to_sell);
-                                println!("Attempting to sell {} amount of {}...", amt_to_sell, account_info["balances"][i]["asset"]);
-                                let request = MarketRequest {
-                                    symbol: symbol, 
-                                    side: "SELL".to_string(), 
-                                    timestamp: time_now,
-                                    quantity: amt_to_sell,
-                                    quote_order_qty: -1.0,
+                            for arg in command_args.iter() {
+                                let acked = match arg.parse::<usize>() {
+                                    Ok(index) => reconciliation.acknowledge(index),
+                                    Err(_) => false,
                                 };
-                                let response = binance_interface::binance_trade_api(request);
-                                if let Some(field) = response.get("status") {
-                                    if field == "filled" {
-                                        println!("Order was filled.");
-                                    } else {
-                                        println!("Order was not filled. Status response: {}", field);
-                                    }
-                                } else {
-                                    println!("Something went wrong. Response was: {}", response);
+                                if !acked {
+                                    println!("no discrepancy with index {}.", arg);
                                 }
                             }
                         }
-                        i += 1;
-                    }
-                } else if command == "fetchpredata" {
-                    println!("fetching predata...");
-                    let api_limit = 500;
-                    let end_window = epoch_ms();
+                        let _ = humanlog_tx1.send(Notification::info(format!("reconciliation: discrepancies acknowledged, {} unresolved.", reconciliation.unresolved())));
+                    } else if command == "autostart" {
+                        // COPYPASTE OF FETCHING PREDATA
+                        log_info!(target: "action", "fetching predata...");
+                        let end_window = epoch_ms();
+                        history_end = end_window;
                     
-                    for ticker in ticker_list.iter() {
-                        println!("fetching predata for {}...", ticker);
-                        let start_window = end_window - settings["max_lookback_ms"];
-                        let mut end_chunk = end_window;
-                        let mut ticker_ohlc = Vec::new();
-                        while end_chunk >= start_window {
-                            let mut new_ohlcs = binance_interface::fetch_klines(&ticker, end_chunk, api_limit);
-                            let mut swap = Vec::new();
-                            swap.append(&mut new_ohlcs);
-                            swap.append(&mut ticker_ohlc);
-                            ticker_ohlc = swap.clone();
-                            end_chunk -= api_limit * 60 * 1000;
+                        // the history starts over, so fetching again doesn't leave the last fetch behind
+                        ohlc_history.clear();
+                        for ticker in ticker_list.iter() {
+                            // bars built from ticks have no history on the exchange, they fill up live
+                            if account.config.bars.is_some() {
+                                ohlc_history.push(Vec::new());
+                                continue;
+                            }
+                            log_info!(target: "action", "fetching predata for {}...", ticker);
+                            ohlc_history.push(binance_interface::fetch_kline_history(ticker, end_window, settings["max_lookback_ms"]));
                         }
-                        ohlc_history.push(ticker_ohlc);
-                    }
-                    
-                    let _ = humanlog_tx1.send("predata: finished fetching predata.".to_string());
-                    println!("finished with fetching predata.");
-                } else if command == "fetchvars" {
-                    println!("fetching variables...");
-                    algo_status = vec![];
-                    let var_file = File::open("../var_files.txt").unwrap();
-                    let mut reader = BufReader::new(var_file);
-                    let mut read_success = true;
+
+                        let _ = humanlog_tx1.send(Notification::info("predata: finished fetching predata.".to_string()));
+                        log_info!(target: "action", "finished with fetching predata.");
+
+                        // warm the strategies on all but the last bar, which gives the first previous_signals
+                        for ticker_i in 0..ticker_list.len() {
+                            pair_strategies[ticker_i] = trading_strategies::pair_strategies(&account.config.strategies);
+                            let warm_len = ohlc_history[ticker_i].len().saturating_sub(1);
+                            trading_strategies::warm(&mut pair_strategies[ticker_i], &ohlc_history[ticker_i][..warm_len]);
+                            pair_sizers[ticker_i] = Sizer::warmed(&account.config.sizing, &ohlc_history[ticker_i][..warm_len]);
+                            let (targets, _p_data_temp) = trading_strategies::master_strategy(&mut pair_strategies[ticker_i], &ohlc_history[ticker_i], &p_data[ticker_i], &humanlog_tx1);
+                            previous_signals[ticker_i] = targets.iter().map(trading_strategies::signal_of).collect();
+                        }
+
+                        running = true;
+                    } else if command == "newlistenkey" {
+                        // requests of commands run on the network runtime and answer when they're done
+                        let credentials = account.credentials.clone();
+                        network::spawn(async move {
+                            binance_interface::binance_account_api_async(&credentials, "new_listenkey", "").await;
+                        });
+                    } else if command == "displayaccountinfo" {
+                        let credentials = account.credentials.clone();
+                        network::spawn(async move {
+                            println!("{}", binance_interface::binance_account_api_async(&credentials, "get_accountinfo", "").await);
+                        });
+                    } else if command == "testping" {
+                        network::spawn(async {
+                            binance_interface::binance_rest_api_async("test_ping", "").await;
+                        });
+                    } else if command == "book" {
+                        // usage: book <symbol> [levels]
+                        let symbol = command_args.first().map(|x| x.to_uppercase()).unwrap_or_default();
+                        let levels: usize = command_args.get(1).and_then(|x| x.parse().ok()).unwrap_or(5);
+                        let printed = order_book::with_book(&symbol, |book| {
+                            println!("{} at update {}: best bid {:?}, best ask {:?}, spread {:?}bps", book.symbol, book.last_update_id, book.best_bid(), book.best_ask(), book.spread_bps());
+                            let (bids, asks) = book.depth(levels);
+                            println!("bids: {:?}", bids);
+                            println!("asks: {:?}", asks);
+                        });
+                        if printed.is_none() {
+                            println!("no order book in sync for {}.", symbol);
+                        }
+                    } else if command == "orders" {
+                        // parent orders still being worked
+                        for parent in executor.parents() {
+                            println!("{} algo {}: {} {:?} {} {:.8}, {:.1}% done in {} children, started {}s ago, next child in {}s", parent.id, parent.algo, parent.style.name(), parent.side, parent.symbol, parent.amount, parent.progress() * 100.0, parent.children, time_now.saturating_sub(parent.start_time) / 1000, parent.next_time().saturating_sub(time_now) / 1000);
+                        }
+                        if executor.parents().is_empty() {
+                            println!("no parent orders working.");
+                        }
+                    } else if command == "tca" {
+                        // usage: tca [hours]. transaction costs of the account's orders, from its journal.
+                        let since_ms = match command_args.first().and_then(|x| x.parse::<u64>().ok()) {
+                            Some(hours) => time_now.saturating_sub(hours * 60 * 60 * 1000),
+                            None => 0,
+                        };
+                        match journal::read_entries(&account.config.journal_file) {
+                            Ok(entries) => print!("{}", tca::TcaReport::from_entries(&entries, since_ms).format(&account.config.reporting)),
+                            Err(e) => println!("could not read the journal {}: {}", account.config.journal_file, e),
+                        }
+                    } else if command == "exchangeinfo" {
+                        network::spawn(async {
+                            let exchange_info = binance_interface::binance_rest_api_async("exchange_info", "").await;
+                            println!("exchange_info: {}", exchange_info);
+                        });
+                    } else if command == "testtime" {
+                        network::spawn(async {
+                            match time_sync::sync().await {
+                                Ok(estimate) => println!("clock offset: {}ms, rtt: {}ms, recvWindow: {}ms", estimate.offset_ms, estimate.rtt_ms, estimate.recv_window_ms()),
+                                Err(e) => println!("time sync failed: {}", e),
+                            }
+                        });
+                    } else if command == "selltousdt" {
+                        // WARNING: untested
+
+                        // check account info and sells every asset to the home asset(USDT unless the account sets its own).
+                        // the balances after the sells answer as the latest request, so they aren't refreshed in between.
+                        account_info_seq += 1;
+                        account_info_fresh_from = account_info_seq;
+                        account_info_requested_at = time_now;
+                        account_info_in_flight = true;
+                        let seq = account_info_seq;
+                        let reply_tx = account_info_tx.clone();
+                        let credentials = account.credentials.clone();
+                        let journal = journal1.clone();
+                        let home = symbols_interest[0].clone();
+                        let all_pairs = all_pairs.clone();
+                        network::spawn(async move {
+                            let account_info = binance_interface::binance_account_api_async(&credentials, "get_accountinfo", "").await;
+                            log_debug!(target: "action", "account_info: {}", account_info);
+
+                            let mut i = 0;
+                            loop {
+                                if account_info["balances"][i].is_null() {
+                                    break;
+                                } else {
+                                    log_debug!(target: "action", "{}", account_info["balances"][i]);
+                                    let balance: f64 = account_info["balances"][i]["free"].as_str().unwrap().parse().unwrap();
+                                    let ticker_sell: String = account_info["balances"][i]["asset"].as_str().unwrap().to_string();
+                                    let symbol = format!("{}{}", ticker_sell, home);
+                                    if ticker_sell != home && balance != 0.0 && all_pairs.contains_key(&symbol) {
+                                        let mut amt_to_sell:f64 = account_info["balances"][i]["free"].as_str().unwrap().parse().unwrap();
+                                        log_debug!(target: "action", "original amt_to_sell: {}", amt_to_sell);
+                                        amt_to_sell = amt_to_sell - (amt_to_sell % 0.00001);
+                                        log_debug!(target: "action", "final amt_to_sell: {}", amt_to_sell);
+                                        log_info!(target: "action", "Attempting to sell {} amount of {}...", amt_to_sell, account_info["balances"][i]["asset"]);
+                                        let symbol_sold = symbol.clone();
+                                        let request = MarketRequest {
+                                            symbol: symbol, 
+                                            side: "SELL".to_string(), 
+                                            timestamp: time_now,
+                                            quantity: amt_to_sell,
+                                            quote_order_qty: -1.0,
+                                            limit_price: -1.0,
+                                        };
+                                        journal.record(journal::order_submitted_event(&request, None, None));
+                                        let response = binance_interface::binance_trade_api_async(&credentials, request).await;
+                                        for event in journal::order_response_events(&symbol_sold, &response) {
+                                            journal.record(event);
+                                        }
+                                        if let Some(field) = response.get("status") {
+                                            if field == "filled" {
+                                                log_info!(target: "action", "Order was filled.");
+                                            } else {
+                                                log_warn!(target: "action", "Order was not filled. Status response: {}", field);
+                                            }
+                                        } else {
+                                            log_error!(target: "action", "Something went wrong. Response was: {}", response);
+                                        }
+                                    }
+                                }
+                                i += 1;
+                            }
+                            let _ = reply_tx.send((seq, Ok(binance_interface::binance_account_api_async(&credentials, "get_accountinfo", "").await)));
+                        });
+                    } else if command == "fetchpredata" {
+                        log_info!(target: "action", "fetching predata...");
+                        let end_window = epoch_ms();
+                        history_end = end_window;
                     
-                    // read number of algorithms
-                    let mut line = String::new();
-                    let n_status = reader.read_line(&mut line);
-                    if n_status.is_err() {
-                        read_success = false;
-                    } else {
-                        let n: u64 = line.trim().parse().unwrap();
-                        // read in algo_status
-                        for _i in 0..n {
-                            let mut line = String::new();
-                            let line_status = reader.read_line(&mut line);
-                            if !line_status.is_err() {
-                                let status : i32 = line.trim().parse().unwrap();
-                                algo_status.push(status);
-                            } else {
-                                read_success = false;
-                                break;
+                        // the history starts over, so fetching again doesn't leave the last fetch behind
+                        ohlc_history.clear();
+                        for ticker in ticker_list.iter() {
+                            // bars built from ticks have no history on the exchange, they fill up live
+                            if account.config.bars.is_some() {
+                                ohlc_history.push(Vec::new());
+                                continue;
                             }
-                            
+                            log_info!(target: "action", "fetching predata for {}...", ticker);
+                            ohlc_history.push(binance_interface::fetch_kline_history(ticker, end_window, settings["max_lookback_ms"]));
                         }
-                        
-                    }
                     
-                    if read_success {
-                        println!("done with fetching variables.");
-                    } else {
-                        let _ = humanlog_tx1.send("Error reading from variable file.".to_string());
-                        println!("Something went wrong reading the variable file. Maybe the format is wrong?");
-                    }
+                        // the strategies start over on the history
+                        for ticker_i in 0..ticker_list.len() {
+                            pair_strategies[ticker_i] = trading_strategies::pair_strategies(&account.config.strategies);
+                            trading_strategies::warm(&mut pair_strategies[ticker_i], &ohlc_history[ticker_i]);
+                            pair_sizers[ticker_i] = Sizer::warmed(&account.config.sizing, &ohlc_history[ticker_i]);
+                        }
                     
-                } else if command == "storevars" {
-                    println!("writing variables...");
-                    let mut log_file = OpenOptions::new()
-                        .read(true)
-                        .write(true)
-                        .create(true)
-                        .open("../var_files.txt")
-                        .unwrap();
-                    let n: u64 = algo_status.len() as u64;
-                    let mut write_str = format!("{}\n", n);
-                    for i in 0..n {
-                        write_str = format!("{}{}\n", write_str, algo_status[i as usize]);
-                    }
-                    log_file.write_all(write_str.as_bytes()).unwrap();
-                    println!("done with writing variables.");
-                } else if command == "displayvars" {
-                    println!("n: {}", algo_status.len());
-                    println!("algostatus: {:?}", algo_status);
-                    println!("stepsize: {:?}", stepsize);
-                    println!("min_notional: {:?}", min_notional);
-                    println!("previous_signals: {:?}", previous_signals);
-                    println!("running: {}", running);
-                    println!("diagnostic: {}", diagnostic);
-                    println!("\n done with printing variables.");
-                } else if command == "ordertest" {
-                    let signal = 1;
-                    if signal == 1 {
-                        let request = MarketRequest {
-                            symbol: "LTCUSDT".to_string(), 
-                            side: "BUY".to_string(), 
-                            timestamp: epoch_ms(),
-                            quantity: -1.0,
-                            quote_order_qty: 10.0, 
-                        };
-                        let _ = humanlog_tx1.send(format!("requesting trade: {}", request.clone().to_string()));
-                        println!("requesting trade: {}", request.clone().to_string());
-                        marketreq_tx1.send(request.clone()).unwrap();
-                    } else {
-                        let request = MarketRequest {
-                            symbol: "LTCUSDT".to_string(), 
-                            side: "SELL".to_string(), 
-                            timestamp: epoch_ms(),
-                            quantity: 0.0,
-                            quote_order_qty: -1.0,
-                        };
-                        let _ = humanlog_tx1.send(format!("requesting trade: {}", request.clone().to_string()));
-                        marketreq_tx1.send(request.clone()).unwrap();
-                    }
-                    println!("request sent.");
+                        let _ = humanlog_tx1.send(Notification::info("predata: finished fetching predata.".to_string()));
+                        log_info!(target: "action", "finished with fetching predata.");
+                    } else if command == "fetchvars" {
+                        log_info!(target: "action", "fetching variables...");
+                        match persistence::load_vars(var_file_path) {
+                            Some(mut state) => {
+                                for asset in persistence::remap_assets(&mut state, &mut symbols_interest) {
+                                    let _ = humanlog_tx1.send(Notification::warning(format!("fetchvars: an algo is in {}, which none of the account's pairs trade.", asset)));
+                                }
+                                persisted = state;
+                                log_info!(target: "action", "done with fetching variables.");
+                                // a manually loaded state has to be checked against the exchange again
+                                reconciliation = reconciliation::run_reconciliation(&account.credentials, &persisted, &symbols_interest, &pairs, &all_pairs, &account.config.reporting, time_now, &humanlog_tx1);
+                                algo_status = reconciliation.algo_status.clone();
+                                status_at = reconciliation.reconciled_at;
+                                positions = vec![Position::default(); number_algos];
+                            }
+                            None => {
+                                let _ = humanlog_tx1.send(Notification::error("Error reading from variable file.".to_string()));
+                                log_error!(target: "action", "Something went wrong reading the variable file. Maybe the format is wrong?");
+                            }
+                        }
+                    } else if command == "storevars" {
+                        log_info!(target: "action", "writing variables...");
+                        persisted = PersistedState { algo_status: algo_status.clone(), saved_at: epoch_ms(), assets: symbols_interest.clone() };
+                        persistence::store_vars(var_file_path, &persisted).unwrap();
+                        log_info!(target: "action", "done with writing variables.");
+                    } else if command == "displayvars" {
+                        println!("account: {}", account.config.name);
+                        println!("n: {}", algo_status.len());
+                        println!("algostatus: {:?}", algo_status);
+                        println!("assets: {:?}", symbols_interest);
+                        println!("stepsize: {:?}", stepsize);
+                        println!("min_notional: {:?}", min_notional);
+                        println!("previous_signals: {:?}", previous_signals);
+                        println!("running: {}", running);
+                        println!("diagnostic: {}", diagnostic);
+                        println!("unresolved discrepancies: {}", reconciliation.unresolved());
+                        println!("\n done with printing variables.");
+                    } else if command == "ordertest" {
+                        let signal = 1;
+                        if signal == 1 {
+                            let request = MarketRequest {
+                                symbol: "LTCUSDT".to_string(), 
+                                side: "BUY".to_string(), 
+                                timestamp: epoch_ms(),
+                                quantity: -1.0,
+                                quote_order_qty: 10.0, 
+                                limit_price: -1.0,
+                            };
+                            let _ = humanlog_tx1.send(Notification::info(format!("requesting trade: {}", request.clone().to_string())));
+                            log_info!(target: "action", "requesting trade: {}", request.clone().to_string());
+                            journal1.record(journal::order_submitted_event(&request, None, None));
+                            order_seq += 1;
+                            if marketreq_tx1.send((order_seq, request.clone())).is_err() {
+                                log_error!(target: "action", "the market thread is gone, the order wasn't sent.");
+                            }
+                        } else {
+                            let request = MarketRequest {
+                                symbol: "LTCUSDT".to_string(), 
+                                side: "SELL".to_string(), 
+                                timestamp: epoch_ms(),
+                                quantity: 0.0,
+                                quote_order_qty: -1.0,
+                                limit_price: -1.0,
+                            };
+                            let _ = humanlog_tx1.send(Notification::info(format!("requesting trade: {}", request.clone().to_string())));
+                            journal1.record(journal::order_submitted_event(&request, None, None));
+                            order_seq += 1;
+                            if marketreq_tx1.send((order_seq, request.clone())).is_err() {
+                                log_error!(target: "action", "the market thread is gone, the order wasn't sent.");
+                            }
+                        }
+                        log_debug!(target: "action", "request sent.");
 
-                    // check to make sure that the trade went through
-                    loop {
-                        let req_status = reqconfirm_rx.recv().unwrap();
-                        if req_status {
-                            println!("req_status true came in.");
-                            break;
+                    } 
+                }
+
+                // universe update: the pairs the account's universe rules picked
+                let universe_update = if command_good { None } else { universe_rx.try_recv().ok() };
+                let universe_update_taken = universe_update.is_some();
+                if let Some(selected) = universe_update {
+                    // a pair that leaves the universe is kept while an algo still holds one of its assets other than home,
+                    // or while a parent order on it is being worked
+                    let mut new_pairs = selected;
+                    for (k, pair) in pairs.iter().enumerate() {
+                        let held = algo_status.iter().any(|x| *x != 0 && (*x == pair_base[k] || *x == pair_quote[k])) || executor.is_working_on(&pair.symbol);
+                        if held && !new_pairs.iter().any(|x| x.symbol == pair.symbol) {
+                            new_pairs.push(pair.clone());
                         }
                     }
+                    let new_list: Vec<String> = new_pairs.iter().map(|x| x.symbol.clone()).collect();
+                    if new_list != ticker_list {
+                        let added: Vec<String> = new_list.iter().filter(|x| !ticker_list.contains(x)).cloned().collect();
+                        let removed: Vec<String> = ticker_list.iter().filter(|x| !new_list.contains(x)).cloned().collect();
+                        log_info!(target: "action", "universe: adding {:?}, removing {:?}", added, removed);
+                        let _ = humanlog_tx1.send(Notification::info(format!("universe: adding {:?}, removing {:?}", added, removed)));
 
-                } 
-            }
+                        // per-pair state follows its symbol. new pairs start without signals.
+                        previous_signals = universe::realign(&ticker_list, &new_list, &previous_signals, vec![-2; number_algos]);
+                        p_data = universe::realign(&ticker_list, &new_list, &p_data, vec![Vec::new(); number_algos]);
+                        pair_strategies = universe::realign_with(&ticker_list, &new_list, std::mem::take(&mut pair_strategies), |_| trading_strategies::pair_strategies(&account.config.strategies));
+                        pair_sizers = universe::realign_with(&ticker_list, &new_list, std::mem::take(&mut pair_sizers), |_| Sizer::new(&account.config.sizing));
+                        // history is only kept once predata was fetched. new pairs get theirs right away.
+                        if !ohlc_history.is_empty() && ohlc_history.len() == ticker_list.len() {
+                            ohlc_history = universe::realign(&ticker_list, &new_list, &ohlc_history, Vec::new());
+                            for (k, symbol) in new_list.iter().enumerate() {
+                                if added.contains(symbol) && account.config.bars.is_none() {
+                                    ohlc_history[k] = binance_interface::fetch_kline_history(symbol, epoch_ms(), settings["max_lookback_ms"]);
+                                    trading_strategies::warm(&mut pair_strategies[k], &ohlc_history[k]);
+                                    pair_sizers[k] = Sizer::warmed(&account.config.sizing, &ohlc_history[k]);
+                                }
+                            }
+                        }
+                        market::extend_assets(&mut symbols_interest, &new_pairs);
+                        pairs = new_pairs;
+                        ticker_list = new_list;
+                        let (new_base, new_quote) = pair_assets(&pairs, &symbols_interest);
+                        pair_base = new_base;
+                        pair_quote = new_quote;
+                        // universe rules only pick pairs that have both filters
+                        stepsize = pairs.iter().map(|x| x.step_size.unwrap_or(-1.0)).collect();
+                        min_notional = pairs.iter().map(|x| x.min_notional.unwrap_or(-1.0)).collect();
+
+                        journal1.record(Event::UniverseChange { symbols: ticker_list.clone(), assets: symbols_interest.clone() });
+                        feed1.lock().unwrap().set_symbols(&account.config.name, ticker_list.clone());
+                    }
+                }
 
-            // main trade/pm logic
-            if running {
-                // receive market data(trading only for now) and append to past list of trades
-                let mut kline_iter = kline_rx.try_iter();
+                // main trade/pm logic
+                // market data is only taken once there are no pending commands or updates. klines are buffered until then,
+                // and the history follows them while stopped too, so the strategies see every bar.
+                pending_klines.extend(kline_rx.try_iter());
+                let next_kline = if command_good || universe_update_taken || waiting_for_balances { None } else { pending_klines.pop_front() };
                 let mut kline_valid = -1;
-                loop {
-                    let next_data = kline_iter.next();
-                    if !next_data.is_none() {
-                        let raw_kline = next_data.unwrap();
-                        let kline = raw_kline.as_kline();
-                        if kline.closed {
-                            println!("kline.symbol: {}", kline.symbol);
-                            println!("ticker_list: ");
-                            for ticker in &ticker_list {
-                                println!("{}", ticker);
-                            }
-                            let index = ticker_list.iter().position(|x| x == &kline.symbol).unwrap();
-                            let append_arr = vec![kline.open, kline.high, kline.low, kline.close, kline.quantity];
-                            ohlc_history[index].push(append_arr);
+                if let Some(raw_kline) = next_kline {
+                    let kline = raw_kline.as_kline();
+                    // klines of a pair that just left the universe can still be queued. history is only kept once predata was fetched.
+                    if kline.closed && ticker_list.contains(&kline.symbol) && ohlc_history.len() == ticker_list.len() && kline.end_time > history_end {
+                        log_debug!(target: "action", "closed kline for {}", kline.symbol);
+                        metrics::global().set_last_kline(&kline.symbol, kline.end_time);
+                        let index = ticker_list.iter().position(|x| x == &kline.symbol).unwrap();
+                        prices.set_price(&pairs[index].base, &pairs[index].quote, kline.close);
+                        executor.on_bar(&kline.symbol, kline.quantity);
+                        journal1.record(Event::BarClosed {
+                            symbol: kline.symbol.clone(),
+                            start_time: kline.start_time,
+                            end_time: kline.end_time,
+                            open: kline.open,
+                            high: kline.high,
+                            low: kline.low,
+                            close: kline.close,
+                            quantity: kline.quantity,
+                        });
+                        let append_arr = vec![kline.open, kline.high, kline.low, kline.close, kline.quantity];
+                        ohlc_history[index].push(append_arr);
+                        if running {
                             kline_valid = index as i64;
+                        } else {
+                            // stopped, the strategies and sizers still take the bar
+                            let bar = &ohlc_history[index][ohlc_history[index].len() - 1];
+                            pair_sizers[index].next(bar);
+                            trading_strategies::warm(&mut pair_strategies[index], std::slice::from_ref(bar));
+                            let limit_len = (settings["max_lookback_ms"] / settings["ohlc_period"]) as usize;
+                            let excess = ohlc_history[index].len().saturating_sub(limit_len);
+                            ohlc_history[index].drain(..excess);
+                        }
 
-                            // file logging
-                            let mut filelog: HashMap<String, String> = HashMap::new();
-                            filelog.insert("symbol".to_string(), kline.symbol.clone());
-                            filelog.insert("open".to_string(), format!("{}", kline.open));
-                            filelog.insert("high".to_string(), format!("{}", kline.high));
-                            filelog.insert("low".to_string(), format!("{}", kline.low));
-                            filelog.insert("close".to_string(), format!("{}", kline.close));
-                            filelog.insert("quantity".to_string(), format!("{}", kline.quantity));
+                        // file logging
+                        let mut filelog: HashMap<String, String> = HashMap::new();
+                        filelog.insert("account".to_string(), account.config.name.clone());
+                        filelog.insert("symbol".to_string(), kline.symbol.clone());
+                        filelog.insert("open".to_string(), format!("{}", kline.open));
+                        filelog.insert("high".to_string(), format!("{}", kline.high));
+                        filelog.insert("low".to_string(), format!("{}", kline.low));
+                        filelog.insert("close".to_string(), format!("{}", kline.close));
+                        filelog.insert("quantity".to_string(), format!("{}", kline.quantity));
 
-                            let _ = filelog_tx1.send(filelog);
+                        let _ = filelog_tx1.send(filelog);
 
-                            // logging
-                            let log_str = format!("new_ohlc: {} {} {} {} {} {}", kline.symbol, kline.open, kline.high, kline.low, kline.close, kline.quantity);
-                            let _ = humanlog_tx1.send(log_str);
+                        // logging
+                        let log_str = format!("new_ohlc: {} {} {} {} {} {}", kline.symbol, kline.open, kline.high, kline.low, kline.close, kline.quantity);
+                        let _ = humanlog_tx1.send(Notification::routine(log_str));
+                    } else if kline.closed {
+                        if !ohlc_history.is_empty() && ohlc_history.len() != ticker_list.len() {
+                            log_warn!(target: "action", "dropped closed kline for {}, the history has {} pairs and the account {}.", kline.symbol, ohlc_history.len(), ticker_list.len());
+                        } else {
+                            let reason = if !ticker_list.contains(&kline.symbol) {
+                                "its pair isn't traded"
+                            } else if ohlc_history.is_empty() {
+                                "predata wasn't fetched yet"
+                            } else {
+                                "it's already in the fetched history"
+                            };
+                            log_debug!(target: "action", "dropped closed kline for {} ending {}, {}.", kline.symbol, kline.end_time, reason);
                         }
-                    } else {
-                        break;
                     }
                 }
-                
-                if kline_valid != -1 {
-                    println!("kline is valid. running trading logic.");
+
+                if running && kline_valid != -1 {
+                    log_debug!(target: "action", "kline is valid. running trading logic.");
                     let ticker_i = kline_valid as usize;
+                    let _ticker_span = logger::span(&[("ticker", ticker_list[ticker_i].clone())]);
                     // slice to relevant part
                     let limit_len = (settings["max_lookback_ms"] / settings["ohlc_period"]) as usize;
 
-                    println!("On ticker: {}", ticker_list[ticker_i]);
-                    if ohlc_history[ticker_i].len() >= limit_len {
-                        ohlc_history[ticker_i] = ohlc_history[ticker_i][ohlc_history[ticker_i].len()-limit_len..].to_vec();
-                        
+                    log_debug!(target: "action", "running trading logic");
+                    // the kline was just added, so the history has at least one bar
+                    if !ohlc_history[ticker_i].is_empty() {
+                        let excess = ohlc_history[ticker_i].len().saturating_sub(limit_len);
+                        ohlc_history[ticker_i].drain(..excess);
+                    
                         // call master strategy
-                        // keep in mind, signals returned direct from the function is either 0 or 1. This is different from algo_status, where
-                        // the numbers denote which currency the algo is playing.
-                        let (signals, new_p_data) = trading_strategies::master_strategy(&ohlc_history[ticker_i], &p_data[ticker_i], &humanlog_tx1);
+                        // keep in mind, targets are the share of an algo's capital its strategy wants in the pair's base asset. This is
+                        // different from algo_status, where the numbers denote which currency the algo is playing. algos that are still
+                        // warming up hold. signals are what the targets are journaled as, 1 for a position and 0 for none.
+                        pair_sizers[ticker_i].next(&ohlc_history[ticker_i][ohlc_history[ticker_i].len() - 1]);
+                        let (targets, new_p_data) = trading_strategies::master_strategy(&mut pair_strategies[ticker_i], &ohlc_history[ticker_i], &p_data[ticker_i], &humanlog_tx1);
                         p_data[ticker_i] = new_p_data;
+                        let signals: Vec<i32> = targets.iter().map(trading_strategies::signal_of).collect();
+                        for (i, signal) in signals.iter().enumerate().filter(|(_, x)| **x != trading_strategies::NO_SIGNAL) {
+                            journal1.record(Event::SignalGenerated { symbol: ticker_list[ticker_i].clone(), algo: i, signal: *signal });
+                        }
 
                         // logging real quick
-                        let _ = humanlog_tx1.send(format!("update: on ticker {}", ticker_list[ticker_i]));
-                        let _ = humanlog_tx1.send(format!("algo_status: {:?}", &algo_status));
-                        
-                        // process each signal
-                        for (i, signal) in signals.iter().enumerate() {
-                            println!("Current algo play is {}. ", algo_status[i]);
-                            println!("Algorithm returned {} indicator.", signal);
+                        let _ = humanlog_tx1.send(Notification::routine(format!("update: on ticker {}", ticker_list[ticker_i])));
+                        let _ = humanlog_tx1.send(Notification::routine(format!("algo_status: {:?}", &algo_status)));
+                    
+                        // process each target
+                        let bar = ohlc_history[ticker_i][ohlc_history[ticker_i].len() - 1].clone();
+                        let band = account.config.sizing.rebalance_band;
+                        for (i, target) in targets.iter().enumerate() {
+                            let _algo_span = logger::span(&[("algo", i.to_string())]);
+                            let in_base = algo_status[i] == pair_base[ticker_i];
+                            let in_quote = algo_status[i] == pair_quote[ticker_i];
+                            // strategies can move the stop and take profit of the position they're in
+                            if let (true, Some(target)) = (in_base, target) {
+                                positions[i].stop = target.stop.or(positions[i].stop);
+                                positions[i].take_profit = target.take_profit.or(positions[i].take_profit);
+                            }
+                            // a stop or take profit the bar went through takes the algo out, whatever its strategy says
+                            let target = if in_base && positions[i].exit_hit(&bar) {
+                                log_info!(target: "action", "algo {} hit its stop {:?} or take profit {:?} on {}.", i, positions[i].stop, positions[i].take_profit, ticker_list[ticker_i]);
+                                let _ = humanlog_tx1.send(Notification::info(format!("algo {} hit its stop or take profit on {}, selling out.", i, ticker_list[ticker_i])));
+                                Some(Target::weight(0.0))
+                            } else {
+                                target.map(|x| pair_sizers[ticker_i].size(&account.config.sizing, x, &play_stats[i]))
+                            };
+                            log_debug!(target: "action", "current algo play is {}, algorithm wants {:?}", algo_status[i], target);
                             /* 
                                 action_condition:
-                                    1. algorithm wants to sell out. In this case, check if the currency that the algo is current in 
-                                        is the same as the current ticker. In that case, the sell signal is valid. 
-                                    2. algorithm wants to buy in. Simply check that the algo is free and then buy in.
+                                    1. algorithm wants to sell out. In this case, check if the algo is currently in the ticker's 
+                                        base asset. In that case, the sell signal is valid and the algo moves into the quote asset. 
+                                    2. algorithm wants to buy in. Check that the algo is in the ticker's quote asset and then buy in. 
+                                        For XXXUSDT pairs, this means the algo is free.
+                                    3. algorithm wants a different weight than the one the algo is at in the ticker's base asset,
+                                        by more than the rebalance band. The algo buys or sells the difference and stays in.
                                 signal_diff_condition (CURRENTLY NOT IMPLEMENTED): 
                                     1. Only take action if the generated signal is different than the previous signal.
                             */
-                            let action_condition = ((signal == &0 && &algo_status[i] != &0) && &algo_status[i]-1 == ticker_i as i32) 
-                            || (signal == &1 && &algo_status[i] == &0);
-                            let signal_diff_condition = signal != &previous_signals[ticker_i][i];
-                            println!("action_condition: {}", action_condition);
-                            println!("signal_diff_condition: {}", signal_diff_condition);
-                            let _ = humanlog_tx1.send(format!("conditions: action_condition: {} || signal_diff_condition: {}", action_condition, signal_diff_condition));
-                            if action_condition  {
-                                println!("signal contradicts status, taking action.");
-
-                                // empty rx for trade confirm so only data in pipe is from the request we're about to send. 
-                                loop {
-                                    let status = reqconfirm_rx.try_iter().next();
-                                    if status.is_none() {
-                                        break;
-                                    }
+                            let weight = target.map(|x| x.weight);
+                            let action_condition = match weight {
+                                Some(weight) if weight <= 0.0 => in_base,
+                                Some(weight) => in_quote || (in_base && (weight - positions[i].weight).abs() > band),
+                                None => false,
+                            };
+                            let signal_diff_condition = signals[i] != previous_signals[ticker_i][i];
+                            log_debug!(target: "action", "action_condition: {}, signal_diff_condition: {}", action_condition, signal_diff_condition);
+                            let _ = humanlog_tx1.send(Notification::routine(format!("conditions: action_condition: {} || signal_diff_condition: {}", action_condition, signal_diff_condition)));
+                            if action_condition && executor.is_working(i) {
+                                log_info!(target: "action", "algo {} still has a parent order working, skipping the signal.", i);
+                            } else if action_condition  {
+                                log_info!(target: "action", "signal contradicts status, taking action.");
+                                let target = target.unwrap_or_default();
+
+                                // balances from before a fill would count what it moved twice. targets are levels, so the
+                                // signal comes back on the next bar.
+                                if account_info_applied < account_info_fresh_from {
+                                    log_info!(target: "action", "balances aren't up to date yet, skipping the signal of algo {}.", i);
+                                    continue;
                                 }
 
                                 // calculate balances
-                                // fetch account information and calculate relative split to put into play
+                                // take the account information and calculate relative split to put into play
                                 let mut balances = vec![-1.0; symbols_interest.len()];
                                 // calculate balance for each symbol in symbols_interest
-                                let account_info = binance_interface::binance_rest_api("get_accountinfo", time_now, "");
                                 let mut j = 0;
                                 loop {
                                     if account_info["balances"][j].is_null() {
@@ -620,87 +1133,311 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                                     }
                                     j += 1;
                                 }
-                                println!("listing calculated balances: {:?}", balances);
-                                
+                                // what working parent orders are still going to spend isn't free, and neither is what algos
+                                // partly in a base asset keep aside
+                                for (k, asset) in symbols_interest.iter().enumerate() {
+                                    if balances[k] != -1.0 {
+                                        let reserved: f64 = positions.iter().filter(|x| x.reserve_asset == k as i32).map(|x| x.reserve).sum();
+                                        balances[k] = (balances[k] - executor.reserved(asset) - reserved).max(0.0);
+                                    }
+                                }
+                                log_debug!(target: "action", "calculated balances: {:?}", balances);
+                            
                                 // log balance
-                                let _ = humanlog_tx1.send(format!("tickers: {:?}", symbols_interest));
-                                let _ = humanlog_tx1.send(format!("calculated_balance: {:?}", balances));
+                                let _ = humanlog_tx1.send(Notification::routine(format!("tickers: {:?}", symbols_interest)));
+                                let _ = humanlog_tx1.send(Notification::routine(format!("calculated_balance: {:?}", balances)));
 
                                 // calculate relative split
                                 let mut total_percent = 0.0;
+                                // an algo with a parent order working doesn't have its proceeds in the balance yet
                                 for (j, status) in algo_status.iter().enumerate() {
-                                    if status == &algo_status[i] {
+                                    if status == &algo_status[i] && (j == i || !executor.is_working(j)) {
                                         total_percent += capital_split[j];
                                     }
                                 }
                                 let relative_split = capital_split[i] / total_percent;
 
                                 if balances[algo_status[i] as usize] == -1.0 {
-                                    let _ = humanlog_tx1.send("warning: invalid balance. continuing to next signal in loop.".to_string());
+                                    let _ = humanlog_tx1.send(Notification::warning("warning: invalid balance. continuing to next signal in loop.".to_string()));
                                     continue;
                                 }
 
-                                // if signal is 0(back to USDT), calculate amount to sell.
-                                // if signal is positive(into a currency), calculate USDT amount then multiply by price
-                                 let mut amt = relative_split * balances[algo_status[i] as usize];
+                                // the algo's share of the asset it's in. out of the pair, it buys in with the target's share of it.
+                                // in the base asset, it trades the difference to the target against what it keeps aside.
+                                let held = relative_split * balances[algo_status[i] as usize];
+                                let order = if in_quote {
+                                    Some(Rebalance::Buy(target.weight.min(1.0) * held))
+                                } else {
+                                    portfolio::rebalance(held, positions[i].reserve, bar[3], target.weight, band)
+                                };
+                                // if buy, amt is the amount of the quote asset to spend.
+                                // if sell, amt is the amount of the base asset to sell, all of it when the target is 0.
+                                let (buy, mut amt) = match order {
+                                    Some(Rebalance::Buy(amt)) => (true, amt),
+                                    Some(Rebalance::Sell(amt)) => (false, amt),
+                                    None => {
+                                        log_debug!(target: "action", "algo {} is within {} of its target.", i, band);
+                                        positions[i].weight = target.weight;
+                                        continue;
+                                    }
+                                };
+                                let rebalancing = in_base && target.weight > 0.0;
                                 // amt processing
-                                if signal != &0 {
+                                if buy {
+                                    // the account's risk limit caps every buy, in the reporting currency
+                                    if let Some(max_order_notional) = account.config.risk.max_order_notional {
+                                        if let Some(value) = prices.value(&pairs[ticker_i].quote, amt, &account.config.reporting) {
+                                            if value > max_order_notional {
+                                                log_info!(target: "action", "buy worth {} {} capped at max_order_notional.", value, account.config.reporting);
+                                                amt *= max_order_notional / value;
+                                            }
+                                        }
+                                    }
                                     if amt <= min_notional[ticker_i] {
-                                        println!("amount is less than min_notional. breaking out of loop iter.");
-                                        let _ = humanlog_tx1.send("warning: amount is less than min_notional. breaking out of loop iter.".to_string());
-                                        break;
+                                        log_warn!(target: "action", "buy of algo {} is less than min_notional, holding.", i);
+                                        let _ = humanlog_tx1.send(Notification::warning(format!("warning: buy of algo {} is less than min_notional, holding.", i)));
+                                        continue;
                                     }
                                 } else {
                                     amt -= amt % stepsize[ticker_i];
+                                    if rebalancing && amt * bar[3] <= min_notional[ticker_i] {
+                                        log_info!(target: "action", "rebalance of algo {} is less than min_notional, holding.", i);
+                                        continue;
+                                    }
                                 }
-                                println!("final amt: {}", amt);
-
-                                if signal != &0 {
-                                    let request = MarketRequest {
-                                        symbol: ticker_list[ticker_i].to_string(), 
-                                        side: "BUY".to_string(), 
-                                        timestamp: epoch_ms(),
-                                        quantity: -1.0,
-                                        quote_order_qty: amt, 
-                                    };
-                                    let _ = humanlog_tx1.send(format!("requesting trade: {}", request.clone().to_string()));
-                                    marketreq_tx1.send(request.clone()).unwrap();
-                                    algo_status[i] = (ticker_i + 1) as i32;
-                                } else {
-                                    let request = MarketRequest {
-                                        symbol: ticker_list[ticker_i].to_string(), 
-                                        side: "SELL".to_string(), 
-                                        timestamp: epoch_ms(),
-                                        quantity: amt,
-                                        quote_order_qty: -1.0,
-                                    };
-                                    let _ = humanlog_tx1.send(format!("requesting trade: {}", request.clone().to_string()));
-                                    marketreq_tx1.send(request.clone()).unwrap();
-                                    algo_status[i] = 0;
+                                log_debug!(target: "action", "final amt: {}", amt);
+
+                                // expected fill from the local order book, to compare with the actual fill
+                                let expected_fill = order_book::with_book(&ticker_list[ticker_i], |book| {
+                                    if buy {
+                                        book.vwap_for_quote(order_book::Side::Buy, amt)
+                                    } else {
+                                        book.vwap(order_book::Side::Sell, amt)
+                                    }
+                                }).flatten();
+                                if let Some(expected_fill) = expected_fill {
+                                    log_info!(target: "action", "expected fill price from the order book: {}", expected_fill);
                                 }
 
-                                // check to make sure that the trade went through
-                                loop {
-                                    let req_status = reqconfirm_rx.recv().unwrap();
-                                    if req_status {
-                                        break;
+
+                                // the account's execution algorithm works the order from here on, see the child orders below
+                                let (side, spend_asset) = if buy {
+                                    (order_book::Side::Buy, &pairs[ticker_i].quote)
+                                } else {
+                                    (order_book::Side::Sell, &pairs[ticker_i].base)
+                                };
+                                let arrival_price = bar[3];
+                                // vwap follows the volume of the 1m klines. bars built from ticks have no time of day to go by.
+                                let history = if account.config.bars.is_none() { Some(ohlc_history[ticker_i].as_slice()) } else { None };
+                                let parent = executor.submit(i, &ticker_list[ticker_i], side, amt, spend_asset, arrival_price, history, epoch_ms());
+                                log_info!(target: "action", "parent order {}: {} {:?} {} {}", parent.id, parent.style.name(), side, parent.symbol, amt);
+                                journal1.record(Event::ParentOrderStarted {
+                                    id: parent.id,
+                                    symbol: parent.symbol.clone(),
+                                    side: if buy { "BUY".to_string() } else { "SELL".to_string() },
+                                    algo: i,
+                                    style: parent.style.name().to_string(),
+                                    amount: amt,
+                                    arrival_price,
+                                });
+                                before_parent.insert(parent.id, (algo_status[i], positions[i].clone()));
+                                // a rebalance keeps the algo in, and its fills move what it keeps aside when it's done
+                                if rebalancing {
+                                    rebalances.insert(parent.id);
+                                    if buy {
+                                        positions[i].reserve -= amt;
                                     }
+                                    positions[i].weight = target.weight;
+                                } else if buy {
+                                    positions[i] = Position {
+                                        weight: target.weight,
+                                        // what it didn't spend, the target's remainder and anything the risk limit capped away
+                                        reserve: (held - amt).max(0.0),
+                                        reserve_asset: pair_quote[ticker_i],
+                                        stop: target.stop,
+                                        take_profit: target.take_profit,
+                                    };
+                                    algo_status[i] = pair_base[ticker_i];
+                                } else {
+                                    positions[i] = Position::default();
+                                    algo_status[i] = pair_quote[ticker_i];
                                 }
                             }
                         }
 
-                        println!("previous_signals before: ");
-                        println!("{:?}", previous_signals);
+                        log_trace!(target: "action", "previous_signals before: {:?}", previous_signals);
                         // update previous signal
                         previous_signals[ticker_i] = signals;
 
-                        println!("previous_signals after: ");
-                        println!("{:?}", previous_signals);
+                        log_trace!(target: "action", "previous_signals after: {:?}", previous_signals);
+                    }
+                }
+
+                // send the child orders that are due. a market parent goes out in full right after its signal.
+                for id in executor.due(epoch_ms()) {
+                    let (symbol, algo, side, decision_price) = match executor.parent(id) {
+                        Some(parent) => (parent.symbol.clone(), parent.algo, parent.side, parent.arrival_price),
+                        None => continue,
+                    };
+                    // pairs with a parent working stay in the universe until it's done
+                    let ticker_i = ticker_list.iter().position(|x| x == &symbol).unwrap();
+                    let buy = side == order_book::Side::Buy;
+                    let close = ohlc_history.get(ticker_i).and_then(|x| x.last()).map(|x| x[3]).unwrap_or(0.0);
+                    let context = ChildContext {
+                        price: close,
+                        step_size: stepsize[ticker_i],
+                        min_notional: min_notional[ticker_i],
+                        tick_size: pairs[ticker_i].tick_size,
+                        touch: order_book::with_book(&symbol, |book| if buy { book.best_ask() } else { book.best_bid() }).flatten().map(|(price, _)| price),
+                    };
+                    let request = match executor.child(id, epoch_ms(), &context) {
+                        Some(request) => request,
+                        None => continue,
+                    };
+
+                    // the mid the order arrives at, for transaction cost analysis
+                    let arrival_mid = order_book::with_book(&symbol, |book| book.mid()).flatten();
+                    let _ = humanlog_tx1.send(Notification::info(format!("requesting trade: {}", request.clone().to_string())));
+                    journal1.record(journal::order_submitted_event(&request, Some(algo), Some(id)));
+                    // the response is handled when it comes back, the parent's next child waits for it
+                    order_seq += 1;
+                    if marketreq_tx1.send((order_seq, request.clone())).is_ok() {
+                        orders_in_flight.insert(order_seq, SentChild { parent: id, algo, request, decision_price, close, arrival_mid });
+                    } else {
+                        log_error!(target: "action", "the market thread is gone, child of parent order {} wasn't sent.", id);
+                        executor.on_fill(id, 0.0, 0.0);
                     }
                 }
+
+                // a finished parent order moves its algo's position
+                let finished = executor.take_done();
+                if !finished.is_empty() {
+                    status_at = epoch_ms();
+                }
+                for parent in finished.iter() {
+                    let i = parent.algo;
+                    let (quantity, quote) = (parent.executed_qty, parent.executed_quote);
+                    let rebalancing = rebalances.remove(&parent.id);
+                    let before = before_parent.remove(&parent.id);
+                    // what a rebalance didn't spend goes back to what the algo keeps aside, and so does what it sold for
+                    if rebalancing {
+                        positions[i].reserve += if parent.side == order_book::Side::Buy { parent.amount - quote } else { quote };
+                    }
+                    // the pair can have left the universe while the parent was worked
+                    let pair = pairs.iter().find(|x| x.symbol == parent.symbol).or_else(|| all_pairs.get(&parent.symbol));
+                    if quantity == 0.0 {
+                        // nothing filled, so the algo is still where it was
+                        if let Some((status, position)) = before {
+                            log_info!(target: "action", "parent order {} filled nothing, algo {} stays in {}.", parent.id, i, symbols_interest[status as usize]);
+                            algo_status[i] = status;
+                            positions[i] = position;
+                        }
+                    } else if let Some(pair) = pair {
+                        // what the algo gave up and what it got, in this pair's assets
+                        let (spent_asset, spent, received_asset, received) = if parent.side == order_book::Side::Buy {
+                            (&pair.quote, quote, &pair.base, quantity)
+                        } else {
+                            (&pair.base, quantity, &pair.quote, quote)
+                        };
+                        let reporting = &account.config.reporting;
+                        // a play's cost is set when it leaves home, and realized when it gets back, however many pairs it went through
+                        if spent_asset == &symbols_interest[0] {
+                            algo_cost[i] += prices.value(spent_asset, spent, reporting).unwrap_or(0.0);
+                        }
+                        if rebalancing && parent.side == order_book::Side::Buy {
+                            algo_quantity[i] += received;
+                        } else if rebalancing {
+                            // selling part of the position realizes that share of its cost
+                            let share = if algo_quantity[i] > 0.0 { (spent / algo_quantity[i]).min(1.0) } else { 1.0 };
+                            if received_asset == &symbols_interest[0] {
+                                algo_realized_pnl[i] += prices.value(received_asset, received, reporting).unwrap_or(0.0) - share * algo_cost[i];
+                                algo_cost[i] -= share * algo_cost[i];
+                            }
+                            algo_quantity[i] -= share * algo_quantity[i];
+                        } else if received_asset == &symbols_interest[0] {
+                            let value = prices.value(received_asset, received, reporting).unwrap_or(0.0);
+                            // kelly sizing works from how the algo's plays went
+                            if algo_cost[i] > 0.0 {
+                                play_stats[i].record(value / algo_cost[i] - 1.0);
+                            }
+                            algo_realized_pnl[i] += value - algo_cost[i];
+                            algo_quantity[i] = 0.0;
+                            algo_cost[i] = 0.0;
+                        } else {
+                            algo_quantity[i] = received;
+                        }
+                    } else {
+                        log_error!(target: "action", "parent order {} traded {}, which isn't on the exchange. algo {}'s pnl misses its fills.", parent.id, parent.symbol, i);
+                    }
+                    let shortfall_bps = parent.shortfall_bps();
+                    let log_str = format!("execution: parent order {} on {} done in {} children, avg price {:?} against {} at the signal, shortfall {:?}bps", parent.id, parent.symbol, parent.children, parent.avg_price(), parent.arrival_price, shortfall_bps);
+                    log_info!(target: "action", "{}", log_str);
+                    let _ = humanlog_tx1.send(Notification::info(log_str));
+                    journal1.record(Event::ParentOrderDone {
+                        id: parent.id,
+                        symbol: parent.symbol.clone(),
+                        side: if parent.side == order_book::Side::Buy { "BUY".to_string() } else { "SELL".to_string() },
+                        algo: i,
+                        executed_qty: quantity,
+                        quote_qty: quote,
+                        arrival_price: parent.arrival_price,
+                        shortfall_bps,
+                        children: parent.children,
+                    });
+                }
+
+                if kline_valid != -1 || !finished.is_empty() {
+                    // mark every algo at the latest prices, in the reporting currency
+                    for i in 0..number_algos {
+                        let notional = if algo_status[i] == 0 {
+                            0.0
+                        } else {
+                            prices.value(&symbols_interest[algo_status[i] as usize], algo_quantity[i], &account.config.reporting).unwrap_or(0.0)
+                        };
+                        metrics::global().set_algo_position(&account.config.name, i, notional, algo_realized_pnl[i] + notional - algo_cost[i]);
+                    }
+                }
+
+                // journal any change to algo_status or running from this iteration
+                if algo_status != journaled_status || running != journaled_running {
+                    journal1.record(Event::StateChange { algo_status: algo_status.clone(), running });
+                    for (i, status) in algo_status.iter().enumerate() {
+                        metrics::global().set_algo_status(&account.config.name, i, *status);
+                    }
+                    journaled_status = algo_status.clone();
+                    journaled_running = running;
+                }
+
+                // refresh the balances once they're stale, retrying every few seconds, and every minute for what changes
+                // outside of jane
+                let refresh_ms = if account_info_applied < account_info_fresh_from { 5 * 1000 } else { 60 * 1000 };
+                if !account_info_in_flight && epoch_ms().saturating_sub(account_info_requested_at) >= refresh_ms {
+                    account_info_seq += 1;
+                    account_info_requested_at = epoch_ms();
+                    account_info_in_flight = true;
+                    request_account_info(account_info_seq);
+                }
             }
-        }
-    });
+        });
+    }
+
+    // thread to pass the shared feed on to every account trading the symbol, and depth diffs on to the order books
+    let feed2 = feed.clone();
+    let _feed_thread = thread::Builder::new()
+        .name("feed_thread".to_string())
+        .spawn(move || {
+            for data in feed_rx.iter() {
+                feed2.lock().unwrap().route(data);
+            }
+        });
+    let stream_count = feed.lock().unwrap().start(&init_tx);
+
+    // thread to rebuild the universe of accounts with universe rules
+    if !universe_routes.is_empty() {
+        let _universe_thread = thread::Builder::new()
+            .name("universe_thread".to_string())
+            .spawn(move || universe::run(universe_routes));
+    }
 
     // check if initialization complete
     let mut counter = 0;
@@ -709,12 +1446,13 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         if received == true {
             counter += 1;
         }
-        if counter >= 3 {
+        // every kline stream and every account's action thread reports in once
+        if counter >= stream_count + command_routes.len() {
             break;
         }
     }
 
-    println!("Initialization finished!");
+    log_info!(target: "main", "Initialization finished!");
 
     // shell loop
     loop {
@@ -744,8 +1482,18 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             break;
         }
 
-        // send command to action thread
-        cmd_tx1.send(command.to_string()).unwrap();
+        // send command line to the action threads. "@<account> <command>" goes to one account, anything else to all of them.
+        if let Some(account_name) = command.strip_prefix('@') {
+            match command_routes.iter().find(|(name, _)| name == account_name) {
+                Some((_, cmd_tx)) if !args.is_empty() => cmd_tx.send(args.join(" ")).unwrap(),
+                Some(_) => println!("usage: @{} <command>", account_name),
+                None => println!("no account named {}.", account_name),
+            }
+        } else {
+            for (_, cmd_tx) in command_routes.iter() {
+                cmd_tx.send(input.trim().to_string()).unwrap();
+            }
+        }
     }
 
     Ok(())
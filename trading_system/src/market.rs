@@ -0,0 +1,213 @@
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+
+// trading pairs from exchange info, and a graph of their prices to value any asset in any currency.
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pair {
+    pub symbol: String,
+    pub base: String,
+    pub quote: String,
+    pub status: String,
+    // orderTypes the pair accepts, e.g. MARKET, LIMIT
+    pub order_types: Vec<String>,
+    // LOT_SIZE stepSize, in the base asset
+    pub step_size: Option<f64>,
+    // MIN_NOTIONAL(or NOTIONAL) minNotional, in the quote asset
+    pub min_notional: Option<f64>,
+    // PRICE_FILTER tickSize, for limit prices
+    pub tick_size: Option<f64>,
+}
+
+impl Pair {
+    pub fn from_value(symbol: &Value) -> Option<Self> {
+        /*
+            Reads one entry of exchangeInfo's symbols array.
+        */
+        let filter_value = |filter_type: &str, field: &str| -> Option<f64> {
+            symbol["filters"]
+                .as_array()?
+                .iter()
+                .find(|x| x["filterType"].as_str() == Some(filter_type))?[field]
+                .as_str()?
+                .parse()
+                .ok()
+        };
+        Some(Pair {
+            symbol: symbol["symbol"].as_str()?.to_string(),
+            base: symbol["baseAsset"].as_str()?.to_string(),
+            quote: symbol["quoteAsset"].as_str()?.to_string(),
+            status: symbol["status"].as_str().unwrap_or("").to_string(),
+            order_types: symbol["orderTypes"]
+                .as_array()
+                .map(|x| {
+                    x.iter()
+                        .filter_map(|y| y.as_str().map(|z| z.to_string()))
+                        .collect()
+                })
+                .unwrap_or_default(),
+            step_size: filter_value("LOT_SIZE", "stepSize"),
+            min_notional: filter_value("MIN_NOTIONAL", "minNotional")
+                .or_else(|| filter_value("NOTIONAL", "minNotional")),
+            tick_size: filter_value("PRICE_FILTER", "tickSize"),
+        })
+    }
+}
+
+pub fn pairs_from_exchange_info(exchange_info: &Value) -> HashMap<String, Pair> {
+    let mut pairs = HashMap::new();
+    for symbol in exchange_info["symbols"]
+        .as_array()
+        .map(|x| x.as_slice())
+        .unwrap_or(&[])
+    {
+        if let Some(pair) = Pair::from_value(symbol) {
+            pairs.insert(pair.symbol.clone(), pair);
+        }
+    }
+    pairs
+}
+
+pub fn assets_of(home: &str, pairs: &[Pair]) -> Vec<String> {
+    /*
+        Every asset the pairs trade, with home first.
+        algo_status is an index into this list, so 0 always means the algo is back in its home asset.
+    */
+    let mut assets = vec![home.to_string()];
+    extend_assets(&mut assets, pairs);
+    assets
+}
+
+pub fn extend_assets(assets: &mut Vec<String>, pairs: &[Pair]) {
+    // appends assets of new pairs. existing assets keep their index, so algo_status stays valid.
+    for pair in pairs.iter() {
+        for asset in [&pair.base, &pair.quote].iter() {
+            if !assets.contains(asset) {
+                assets.push(asset.to_string());
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct PriceGraph {
+    // rates[from][to]: units of to for one unit of from
+    rates: HashMap<String, HashMap<String, f64>>,
+}
+
+impl PriceGraph {
+    pub fn new() -> Self {
+        PriceGraph::default()
+    }
+
+    pub fn from_ticker_prices(ticker_prices: &Value, pairs: &HashMap<String, Pair>) -> Self {
+        /*
+            Builds the graph from /api/v3/ticker/price without a symbol: [{"symbol": "ETHBTC", "price": "0.034"}, ...]
+        */
+        let mut graph = PriceGraph::new();
+        for ticker in ticker_prices
+            .as_array()
+            .map(|x| x.as_slice())
+            .unwrap_or(&[])
+        {
+            let pair = ticker["symbol"].as_str().and_then(|x| pairs.get(x));
+            let price = ticker["price"].as_str().and_then(|x| x.parse().ok());
+            if let (Some(pair), Some(price)) = (pair, price) {
+                graph.set_price(&pair.base, &pair.quote, price);
+            }
+        }
+        graph
+    }
+
+    pub fn set_price(&mut self, base: &str, quote: &str, price: f64) {
+        if price <= 0.0 || !price.is_finite() {
+            return;
+        }
+        self.rates
+            .entry(base.to_string())
+            .or_default()
+            .insert(quote.to_string(), price);
+        self.rates
+            .entry(quote.to_string())
+            .or_default()
+            .insert(base.to_string(), 1.0 / price);
+    }
+
+    pub fn rate(&self, from: &str, to: &str) -> Option<f64> {
+        /*
+            Converts through the fewest pairs, e.g. BNB -> ETH -> BTC -> USDT when there is no BNBUSDT.
+            None if the two assets aren't connected.
+        */
+        if from == to {
+            return Some(1.0);
+        }
+        let mut visited: HashMap<&str, f64> = HashMap::new();
+        let mut queue = VecDeque::new();
+        visited.insert(from, 1.0);
+        queue.push_back(from);
+        while let Some(asset) = queue.pop_front() {
+            let asset_rate = visited[asset];
+            for (next, rate) in self.rates.get(asset).into_iter().flatten() {
+                if visited.contains_key(next.as_str()) {
+                    continue;
+                }
+                if next == to {
+                    return Some(asset_rate * rate);
+                }
+                visited.insert(next, asset_rate * rate);
+                queue.push_back(next);
+            }
+        }
+        None
+    }
+
+    pub fn value(&self, asset: &str, amount: f64, currency: &str) -> Option<f64> {
+        self.rate(asset, currency).map(|x| x * amount)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn reads_pairs_from_exchange_info() {
+        let exchange_info = json!({"symbols": [
+            {"symbol": "ETHBTC", "status": "TRADING", "baseAsset": "ETH", "quoteAsset": "BTC", "orderTypes": ["LIMIT", "MARKET"], "filters": [
+                {"filterType": "LOT_SIZE", "stepSize": "0.00100000"},
+                {"filterType": "MIN_NOTIONAL", "minNotional": "0.00010000"},
+            ]},
+            {"symbol": "BNBETH", "status": "BREAK", "baseAsset": "BNB", "quoteAsset": "ETH", "filters": []},
+        ]});
+        let pairs = pairs_from_exchange_info(&exchange_info);
+        assert_eq!(pairs["ETHBTC"].base, "ETH");
+        assert_eq!(pairs["ETHBTC"].quote, "BTC");
+        assert_eq!(pairs["ETHBTC"].step_size, Some(0.001));
+        assert_eq!(pairs["ETHBTC"].min_notional, Some(0.0001));
+        assert_eq!(pairs["BNBETH"].status, "BREAK");
+        assert_eq!(pairs["BNBETH"].step_size, None);
+        assert_eq!(pairs["ETHBTC"].order_types, vec!["LIMIT", "MARKET"]);
+
+        let account_pairs = vec![pairs["BNBETH"].clone(), pairs["ETHBTC"].clone()];
+        assert_eq!(
+            assets_of("USDT", &account_pairs),
+            vec!["USDT", "BNB", "ETH", "BTC"]
+        );
+    }
+
+    #[test]
+    fn values_through_cross_pairs() {
+        let mut graph = PriceGraph::new();
+        graph.set_price("BTC", "USDT", 50000.0);
+        graph.set_price("ETH", "BTC", 0.04);
+        graph.set_price("BNB", "ETH", 0.25);
+        graph.set_price("XRP", "BUSD", 0.5);
+
+        assert_eq!(graph.rate("USDT", "USDT"), Some(1.0));
+        assert!((graph.value("ETH", 2.0, "USDT").unwrap() - 4000.0).abs() < 1e-9);
+        assert!((graph.value("BNB", 4.0, "USDT").unwrap() - 2000.0).abs() < 1e-9);
+        assert!((graph.value("USDT", 1000.0, "BNB").unwrap() - 2.0).abs() < 1e-9);
+        assert_eq!(graph.rate("XRP", "USDT"), None);
+    }
+}
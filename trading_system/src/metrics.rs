@@ -0,0 +1,345 @@
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::Duration;
+
+// prometheus metrics for live trading health, served as text on /metrics.
+
+#[derive(Default)]
+struct RestStats {
+    count: u64,
+    errors: u64,
+    duration_sum: f64,
+}
+
+#[derive(Default)]
+struct AlgoStats {
+    status: i32,
+    notional: f64,
+    pnl: f64,
+}
+
+#[derive(Default)]
+struct MetricsData {
+    last_kline_ms: BTreeMap<String, u64>,
+    ws_reconnects: BTreeMap<String, u64>,
+    order_book_resyncs: BTreeMap<String, u64>,
+    rest: BTreeMap<String, RestStats>,
+    // keyed by account name and algo index
+    algos: BTreeMap<(String, usize), AlgoStats>,
+    orders: BTreeMap<String, u64>,
+    clock_offset_ms: i64,
+    clock_rtt_ms: u64,
+}
+
+type BacklogFn = Box<dyn Fn() -> usize + Send>;
+
+pub struct Metrics {
+    data: Mutex<MetricsData>,
+    backlogs: Mutex<Vec<(String, BacklogFn)>>,
+}
+
+static METRICS: OnceLock<Metrics> = OnceLock::new();
+
+pub fn global() -> &'static Metrics {
+    METRICS.get_or_init(Metrics::new)
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Metrics {
+            data: Mutex::new(MetricsData::default()),
+            backlogs: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn set_last_kline(&self, symbol: &str, close_time_ms: u64) {
+        let mut data = self.data.lock().unwrap();
+        data.last_kline_ms.insert(symbol.to_string(), close_time_ms);
+    }
+
+    pub fn inc_ws_reconnect(&self, stream: &str) {
+        let mut data = self.data.lock().unwrap();
+        *data.ws_reconnects.entry(stream.to_string()).or_insert(0) += 1;
+    }
+
+    pub fn inc_order_book_resync(&self, symbol: &str) {
+        let mut data = self.data.lock().unwrap();
+        *data
+            .order_book_resyncs
+            .entry(symbol.to_string())
+            .or_insert(0) += 1;
+    }
+
+    pub fn observe_rest(&self, endpoint: &str, duration: Duration, success: bool) {
+        let mut data = self.data.lock().unwrap();
+        let stats = data.rest.entry(endpoint.to_string()).or_default();
+        stats.count += 1;
+        stats.duration_sum += duration.as_secs_f64();
+        if !success {
+            stats.errors += 1;
+        }
+    }
+
+    pub fn set_algo_status(&self, account: &str, algo: usize, status: i32) {
+        let mut data = self.data.lock().unwrap();
+        data.algos
+            .entry((account.to_string(), algo))
+            .or_default()
+            .status = status;
+    }
+
+    pub fn set_algo_position(&self, account: &str, algo: usize, notional: f64, pnl: f64) {
+        let mut data = self.data.lock().unwrap();
+        let stats = data.algos.entry((account.to_string(), algo)).or_default();
+        stats.notional = notional;
+        stats.pnl = pnl;
+    }
+
+    pub fn inc_order(&self, outcome: &str) {
+        let mut data = self.data.lock().unwrap();
+        *data.orders.entry(outcome.to_lowercase()).or_insert(0) += 1;
+    }
+
+    pub fn set_clock(&self, offset_ms: i64, rtt_ms: u64) {
+        let mut data = self.data.lock().unwrap();
+        data.clock_offset_ms = offset_ms;
+        data.clock_rtt_ms = rtt_ms;
+    }
+
+    pub fn register_backlog(&self, channel: &str, backlog: BacklogFn) {
+        // backlog is called on every scrape, e.g. a channel's len()
+        self.backlogs
+            .lock()
+            .unwrap()
+            .push((channel.to_string(), backlog));
+    }
+
+    pub fn render(&self) -> String {
+        let data = self.data.lock().unwrap();
+        let mut out = String::new();
+        let header = |out: &mut String, name: &str, metric_type: &str, help: &str| {
+            out.push_str(&format!(
+                "# HELP {} {}\n# TYPE {} {}\n",
+                name, help, name, metric_type
+            ));
+        };
+
+        header(
+            &mut out,
+            "jane_last_closed_kline_timestamp_ms",
+            "gauge",
+            "Close time of the last closed kline per symbol.",
+        );
+        for (symbol, ms) in data.last_kline_ms.iter() {
+            out.push_str(&format!(
+                "jane_last_closed_kline_timestamp_ms{{symbol=\"{}\"}} {}\n",
+                symbol, ms
+            ));
+        }
+
+        header(
+            &mut out,
+            "jane_websocket_reconnects_total",
+            "counter",
+            "Websocket reconnects per stream.",
+        );
+        for (stream, count) in data.ws_reconnects.iter() {
+            out.push_str(&format!(
+                "jane_websocket_reconnects_total{{stream=\"{}\"}} {}\n",
+                stream, count
+            ));
+        }
+
+        header(
+            &mut out,
+            "jane_order_book_resyncs_total",
+            "counter",
+            "Local order book rebuilds after a desync per symbol.",
+        );
+        for (symbol, count) in data.order_book_resyncs.iter() {
+            out.push_str(&format!(
+                "jane_order_book_resyncs_total{{symbol=\"{}\"}} {}\n",
+                symbol, count
+            ));
+        }
+
+        header(
+            &mut out,
+            "jane_rest_request_duration_seconds",
+            "summary",
+            "REST request latency per endpoint.",
+        );
+        for (endpoint, stats) in data.rest.iter() {
+            out.push_str(&format!(
+                "jane_rest_request_duration_seconds_sum{{endpoint=\"{}\"}} {}\n",
+                endpoint, stats.duration_sum
+            ));
+            out.push_str(&format!(
+                "jane_rest_request_duration_seconds_count{{endpoint=\"{}\"}} {}\n",
+                endpoint, stats.count
+            ));
+        }
+        header(
+            &mut out,
+            "jane_rest_errors_total",
+            "counter",
+            "REST requests that failed or returned a non-2xx status.",
+        );
+        for (endpoint, stats) in data.rest.iter() {
+            out.push_str(&format!(
+                "jane_rest_errors_total{{endpoint=\"{}\"}} {}\n",
+                endpoint, stats.errors
+            ));
+        }
+
+        header(
+            &mut out,
+            "jane_algo_status",
+            "gauge",
+            "Asset the algorithm is in. 0 is the account's home asset.",
+        );
+        for ((account, algo), stats) in data.algos.iter() {
+            out.push_str(&format!(
+                "jane_algo_status{{account=\"{}\",algo=\"{}\"}} {}\n",
+                account, algo, stats.status
+            ));
+        }
+        header(
+            &mut out,
+            "jane_algo_notional",
+            "gauge",
+            "Value of the algorithm's open position in the account's reporting currency.",
+        );
+        for ((account, algo), stats) in data.algos.iter() {
+            out.push_str(&format!(
+                "jane_algo_notional{{account=\"{}\",algo=\"{}\"}} {}\n",
+                account, algo, stats.notional
+            ));
+        }
+        header(
+            &mut out,
+            "jane_algo_pnl",
+            "gauge",
+            "Realized plus unrealized PnL of the algorithm in the account's reporting currency.",
+        );
+        for ((account, algo), stats) in data.algos.iter() {
+            out.push_str(&format!(
+                "jane_algo_pnl{{account=\"{}\",algo=\"{}\"}} {}\n",
+                account, algo, stats.pnl
+            ));
+        }
+
+        header(
+            &mut out,
+            "jane_orders_total",
+            "counter",
+            "Orders by outcome.",
+        );
+        for (outcome, count) in data.orders.iter() {
+            out.push_str(&format!(
+                "jane_orders_total{{outcome=\"{}\"}} {}\n",
+                outcome, count
+            ));
+        }
+
+        header(
+            &mut out,
+            "jane_clock_offset_ms",
+            "gauge",
+            "Binance server time minus local time at the last sync.",
+        );
+        out.push_str(&format!("jane_clock_offset_ms {}\n", data.clock_offset_ms));
+        header(
+            &mut out,
+            "jane_clock_rtt_ms",
+            "gauge",
+            "Round trip of the last time sync request.",
+        );
+        out.push_str(&format!("jane_clock_rtt_ms {}\n", data.clock_rtt_ms));
+
+        header(
+            &mut out,
+            "jane_channel_backlog",
+            "gauge",
+            "Messages waiting in each internal channel.",
+        );
+        for (channel, backlog) in self.backlogs.lock().unwrap().iter() {
+            out.push_str(&format!(
+                "jane_channel_backlog{{channel=\"{}\"}} {}\n",
+                channel,
+                backlog()
+            ));
+        }
+
+        out
+    }
+}
+
+pub fn serve(addr: &str) -> std::io::Result<()> {
+    /*
+        Serves the global metrics on http://addr/metrics from a background thread.
+    */
+    let listener = TcpListener::bind(addr)?;
+    log_info!("serving metrics on http://{}/metrics", addr);
+    thread::Builder::new()
+        .name("metrics_thread".to_string())
+        .spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => continue,
+                };
+                let mut request_line = String::new();
+                let read_status = match stream.try_clone() {
+                    Ok(read_half) => BufReader::new(read_half).read_line(&mut request_line),
+                    Err(e) => Err(e),
+                };
+                if read_status.is_err() {
+                    continue;
+                }
+
+                let response = if request_line.starts_with("GET /metrics") {
+                    let body = global().render();
+                    format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    )
+                } else {
+                    "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                        .to_string()
+                };
+                let _ = stream.write_all(response.as_bytes());
+            }
+        })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_prometheus_text() {
+        let metrics = Metrics::new();
+        metrics.set_last_kline("ETHUSDT", 1600000000000);
+        metrics.observe_rest("/api/v3/account", Duration::from_millis(250), false);
+        metrics.set_algo_status("main", 0, 2);
+        metrics.inc_order("FILLED");
+        metrics.register_backlog("kline", Box::new(|| 3));
+
+        let rendered = metrics.render();
+        assert!(rendered
+            .contains("jane_last_closed_kline_timestamp_ms{symbol=\"ETHUSDT\"} 1600000000000\n"));
+        assert!(rendered.contains(
+            "jane_rest_request_duration_seconds_sum{endpoint=\"/api/v3/account\"} 0.25\n"
+        ));
+        assert!(rendered.contains("jane_rest_errors_total{endpoint=\"/api/v3/account\"} 1\n"));
+        assert!(rendered.contains("jane_algo_status{account=\"main\",algo=\"0\"} 2\n"));
+        assert!(rendered.contains("jane_orders_total{outcome=\"filled\"} 1\n"));
+        assert!(rendered.contains("jane_channel_backlog{channel=\"kline\"} 3\n"));
+    }
+}
@@ -0,0 +1,75 @@
+use crossbeam_channel::Sender;
+use std::future::Future;
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::runtime::{Builder, Handle};
+
+// shared async runtime and http client. every stream and request in the system runs on these.
+
+// requests that take longer than this fail instead of blocking the caller
+pub const REQUEST_TIMEOUT_SECS: u64 = 10;
+const CONNECT_TIMEOUT_SECS: u64 = 5;
+// requests sent with request() give up after this, rate limit delays(up to a minute, see rate_limiter.rs) included
+const REQUEST_DEADLINE_SECS: u64 = 75;
+
+static RUNTIME: OnceLock<Handle> = OnceLock::new();
+static HTTP_CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+
+pub fn handle() -> &'static Handle {
+    RUNTIME.get_or_init(|| {
+        let runtime = Builder::new()
+            .threaded_scheduler()
+            .enable_all()
+            .thread_name("network_thread")
+            .build()
+            .expect("could not start the network runtime");
+        let handle = runtime.handle().clone();
+        // the runtime lives for the rest of the process
+        std::mem::forget(runtime);
+        handle
+    })
+}
+
+pub fn client() -> &'static reqwest::Client {
+    /*
+        One connection-pooled client for every REST call, so connections to binance are reused.
+    */
+    HTTP_CLIENT.get_or_init(|| {
+        reqwest::Client::builder()
+            .timeout(Duration::from_secs(REQUEST_TIMEOUT_SECS))
+            .connect_timeout(Duration::from_secs(CONNECT_TIMEOUT_SECS))
+            .build()
+            .expect("could not build the http client")
+    })
+}
+
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    // for the synchronous threads(action, shell, notifications). never call this from inside a task.
+    handle().block_on(future)
+}
+
+pub fn spawn<F>(future: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    handle().spawn(future);
+}
+
+pub fn request<K, F, T>(key: K, future: F, reply_tx: Sender<(K, Result<T, String>)>)
+where
+    K: Send + 'static,
+    F: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    /*
+        Runs a request on the runtime and sends its output back over reply_tx, or an error after REQUEST_DEADLINE_SECS.
+        For the action thread, which has to keep handling commands and market data while the exchange answers.
+        Replies come back with the key they were sent with.
+    */
+    spawn(async move {
+        let result = tokio::time::timeout(Duration::from_secs(REQUEST_DEADLINE_SECS), future)
+            .await
+            .map_err(|_| format!("no answer in {}s", REQUEST_DEADLINE_SECS));
+        let _ = reply_tx.send((key, result));
+    });
+}
@@ -0,0 +1,543 @@
+use crate::helpers::epoch_ms;
+use crate::logger::{self, Level};
+use crate::network;
+use chrono::prelude::*;
+use crossbeam_channel::{Receiver, RecvTimeoutError};
+use lettre::smtp::authentication::Credentials;
+use lettre::smtp::ClientSecurity;
+use lettre::{ClientTlsParameters, SmtpClient, Transport};
+use lettre_email::EmailBuilder;
+use native_tls::TlsConnector;
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::Read;
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum Severity {
+    // high-volume status lines(new_ohlc, algo_logs, ...)
+    Routine,
+    Info,
+    // fills and trading results
+    Alert,
+    Warning,
+    Error,
+}
+
+impl Severity {
+    pub fn parse(severity_str: &str) -> Option<Severity> {
+        match severity_str.to_lowercase().as_str() {
+            "routine" => Some(Severity::Routine),
+            "info" => Some(Severity::Info),
+            "alert" => Some(Severity::Alert),
+            "warning" => Some(Severity::Warning),
+            "error" => Some(Severity::Error),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Notification {
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Notification {
+    pub fn new(severity: Severity, message: String) -> Self {
+        Notification { severity, message }
+    }
+
+    pub fn routine(message: String) -> Self {
+        Notification::new(Severity::Routine, message)
+    }
+
+    pub fn info(message: String) -> Self {
+        Notification::new(Severity::Info, message)
+    }
+
+    pub fn alert(message: String) -> Self {
+        Notification::new(Severity::Alert, message)
+    }
+
+    pub fn warning(message: String) -> Self {
+        Notification::new(Severity::Warning, message)
+    }
+
+    pub fn error(message: String) -> Self {
+        Notification::new(Severity::Error, message)
+    }
+}
+
+pub trait Notifier: Send {
+    fn name(&self) -> String;
+    // longest message the service accepts. longer digests are split.
+    fn max_message_len(&self) -> usize;
+    fn send(&self, severity: Severity, body: &str) -> Result<(), String>;
+}
+
+fn post_json(url: &str, payload: &Value) -> Result<(), String> {
+    let request = network::client().post(url).json(payload);
+    network::block_on(async {
+        let response = request.send().await.map_err(|e| e.to_string())?;
+        if !response.status().is_success() {
+            return Err(format!("{} returned {}", url, response.status()));
+        }
+        Ok(())
+    })
+}
+
+pub struct DiscordNotifier {
+    pub webhook_url: String,
+    // wraps messages in a highlighted code block so diagnostic runs stand out
+    pub diagnostic: bool,
+}
+
+impl Notifier for DiscordNotifier {
+    fn name(&self) -> String {
+        "discord".to_string()
+    }
+
+    fn max_message_len(&self) -> usize {
+        1500
+    }
+
+    fn send(&self, _severity: Severity, body: &str) -> Result<(), String> {
+        let content = if self.diagnostic {
+            format!("```fix\n{}```", body)
+        } else {
+            format!("```\n{}```", body)
+        };
+        post_json(&self.webhook_url, &json!({ "content": content }))
+    }
+}
+
+pub struct SlackNotifier {
+    pub webhook_url: String,
+}
+
+impl Notifier for SlackNotifier {
+    fn name(&self) -> String {
+        "slack".to_string()
+    }
+
+    fn max_message_len(&self) -> usize {
+        3000
+    }
+
+    fn send(&self, _severity: Severity, body: &str) -> Result<(), String> {
+        post_json(
+            &self.webhook_url,
+            &json!({ "text": format!("```{}```", body) }),
+        )
+    }
+}
+
+pub struct TelegramNotifier {
+    // https://api.telegram.org unless pointed somewhere else for testing
+    pub api_base: String,
+    pub bot_token: String,
+    pub chat_id: String,
+}
+
+impl Notifier for TelegramNotifier {
+    fn name(&self) -> String {
+        "telegram".to_string()
+    }
+
+    fn max_message_len(&self) -> usize {
+        4000
+    }
+
+    fn send(&self, _severity: Severity, body: &str) -> Result<(), String> {
+        let url = format!("{}/bot{}/sendMessage", self.api_base, self.bot_token);
+        post_json(&url, &json!({ "chat_id": self.chat_id, "text": body }))
+    }
+}
+
+pub struct EmailNotifier {
+    pub smtp_host: String,
+    pub smtp_port: u16,
+    // plain SMTP is only meant for a local relay or testing
+    pub tls: bool,
+    pub username: String,
+    pub password: String,
+    pub from: String,
+    pub to: String,
+}
+
+impl Notifier for EmailNotifier {
+    fn name(&self) -> String {
+        "email".to_string()
+    }
+
+    fn max_message_len(&self) -> usize {
+        100_000
+    }
+
+    fn send(&self, severity: Severity, body: &str) -> Result<(), String> {
+        let email = EmailBuilder::new()
+            .to(self.to.as_str())
+            .from(self.from.as_str())
+            .subject(format!("Jane {:?}", severity))
+            .text(body)
+            .build()
+            .map_err(|e| e.to_string())?;
+
+        let security = if self.tls {
+            let connector = TlsConnector::new().map_err(|e| e.to_string())?;
+            let parameters = ClientTlsParameters::new(self.smtp_host.clone(), connector);
+            // 465 is tls from the start, any other port upgrades with STARTTLS
+            if self.smtp_port == 465 {
+                ClientSecurity::Wrapper(parameters)
+            } else {
+                ClientSecurity::Required(parameters)
+            }
+        } else {
+            ClientSecurity::None
+        };
+        let client = SmtpClient::new((self.smtp_host.as_str(), self.smtp_port), security)
+            .map_err(|e| e.to_string())?;
+        let client = if self.username.is_empty() {
+            client
+        } else {
+            client.credentials(Credentials::new(
+                self.username.clone(),
+                self.password.clone(),
+            ))
+        };
+        let mut mailer = client.transport();
+        mailer.send(email.into()).map_err(|e| e.to_string())?;
+        Ok(())
+    }
+}
+
+pub struct WebhookNotifier {
+    pub url: String,
+}
+
+impl Notifier for WebhookNotifier {
+    fn name(&self) -> String {
+        "webhook".to_string()
+    }
+
+    fn max_message_len(&self) -> usize {
+        100_000
+    }
+
+    fn send(&self, severity: Severity, body: &str) -> Result<(), String> {
+        post_json(
+            &self.url,
+            &json!({ "severity": format!("{:?}", severity), "text": body }),
+        )
+    }
+}
+
+pub struct Sink {
+    pub notifier: Box<dyn Notifier>,
+    // notifications below this severity never reach the sink
+    pub min_severity: Severity,
+}
+
+pub struct NotificationDispatcher {
+    sinks: Vec<Sink>,
+    // notifications at or above this severity skip the digest
+    immediate_severity: Severity,
+    digest_interval_ms: u64,
+    digest: VecDeque<Notification>,
+    last_digest: u64,
+}
+
+impl NotificationDispatcher {
+    pub fn new(sinks: Vec<Sink>, immediate_severity: Severity, digest_interval_ms: u64) -> Self {
+        NotificationDispatcher {
+            sinks,
+            immediate_severity,
+            digest_interval_ms,
+            digest: VecDeque::new(),
+            last_digest: epoch_ms(),
+        }
+    }
+
+    pub fn from_file(path: &str, diagnostic: bool) -> Result<Self, String> {
+        /*
+            Builds the dispatcher from a json config file. See docs/notifications.txt for the format.
+        */
+        let mut contents = String::new();
+        File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut contents))
+            .map_err(|e| format!("could not read {}: {}", path, e))?;
+        let config: Value = serde_json::from_str(&contents).map_err(|e| e.to_string())?;
+        NotificationDispatcher::from_config(&config, diagnostic)
+    }
+
+    pub fn from_config(config: &Value, diagnostic: bool) -> Result<Self, String> {
+        let field = |sink: &Value, name: &str| -> Result<String, String> {
+            match sink[name].as_str() {
+                Some(field) => Ok(field.to_string()),
+                None => Err(format!("notification sink is missing {}", name)),
+            }
+        };
+        let severity = |value: &Value, default: Severity| -> Result<Severity, String> {
+            match value.as_str() {
+                Some(severity_str) => Severity::parse(severity_str)
+                    .ok_or_else(|| format!("unknown severity {}", severity_str)),
+                None => Ok(default),
+            }
+        };
+
+        let mut sinks = Vec::new();
+        for sink in config["sinks"].as_array().unwrap_or(&Vec::new()) {
+            let notifier: Box<dyn Notifier> = match sink["type"].as_str().unwrap_or("") {
+                "discord" => Box::new(DiscordNotifier {
+                    webhook_url: field(sink, "webhook_url")?,
+                    diagnostic,
+                }),
+                "slack" => Box::new(SlackNotifier {
+                    webhook_url: field(sink, "webhook_url")?,
+                }),
+                "telegram" => Box::new(TelegramNotifier {
+                    api_base: field(sink, "api_base")
+                        .unwrap_or_else(|_| "https://api.telegram.org".to_string()),
+                    bot_token: field(sink, "bot_token")?,
+                    chat_id: field(sink, "chat_id")?,
+                }),
+                "email" => {
+                    let tls = sink["tls"].as_bool().unwrap_or(true);
+                    Box::new(EmailNotifier {
+                        smtp_host: field(sink, "smtp_host")?,
+                        smtp_port: sink["smtp_port"]
+                            .as_u64()
+                            .unwrap_or(if tls { 587 } else { 25 })
+                            as u16,
+                        tls,
+                        username: field(sink, "username").unwrap_or_default(),
+                        password: field(sink, "password").unwrap_or_default(),
+                        from: field(sink, "from")?,
+                        to: field(sink, "to")?,
+                    })
+                }
+                "webhook" => Box::new(WebhookNotifier {
+                    url: field(sink, "url")?,
+                }),
+                other => return Err(format!("unknown notification sink type {}", other)),
+            };
+            sinks.push(Sink {
+                notifier,
+                min_severity: severity(&sink["min_severity"], Severity::Routine)?,
+            });
+        }
+
+        Ok(NotificationDispatcher::new(
+            sinks,
+            severity(&config["immediate_severity"], Severity::Alert)?,
+            config["digest_interval_ms"].as_u64().unwrap_or(60000),
+        ))
+    }
+
+    pub fn push(&mut self, notification: Notification) {
+        let level = match notification.severity {
+            Severity::Routine => Level::Debug,
+            Severity::Info | Severity::Alert => Level::Info,
+            Severity::Warning => Level::Warn,
+            Severity::Error => Level::Error,
+        };
+        logger::log(level, "humanlog", notification.message.clone());
+        if notification.severity >= self.immediate_severity {
+            let local_now: DateTime<Local> = Local::now();
+            let body = format!("{}\n\n{}", local_now, notification.message);
+            self.send_all(notification.severity, &[body]);
+        } else {
+            self.digest.push_back(notification);
+        }
+    }
+
+    pub fn next_digest_time(&self) -> u64 {
+        self.last_digest + self.digest_interval_ms
+    }
+
+    pub fn flush_digest(&mut self) {
+        self.last_digest = epoch_ms();
+        if self.digest.is_empty() {
+            return;
+        }
+        let notifications: Vec<Notification> = self.digest.drain(..).collect();
+        let local_now: DateTime<Local> = Local::now();
+
+        for sink in self.sinks.iter() {
+            let lines: Vec<&str> = notifications
+                .iter()
+                .filter(|n| n.severity >= sink.min_severity)
+                .map(|n| n.message.as_str())
+                .collect();
+            let max_severity = notifications
+                .iter()
+                .map(|n| n.severity)
+                .fold(Severity::Routine, |a, b| if b > a { b } else { a });
+            for chunk in chunk_lines(&lines, sink.notifier.max_message_len()) {
+                let body = format!("{}\n{}", local_now, chunk);
+                if let Err(e) = sink.notifier.send(max_severity, &body) {
+                    log_warn!("{} notification failed: {}", sink.notifier.name(), e);
+                }
+            }
+        }
+    }
+
+    fn send_all(&self, severity: Severity, bodies: &[String]) {
+        for sink in self.sinks.iter() {
+            if severity < sink.min_severity {
+                continue;
+            }
+            for body in bodies {
+                if let Err(e) = sink.notifier.send(severity, body) {
+                    log_warn!("{} notification failed: {}", sink.notifier.name(), e);
+                }
+            }
+        }
+    }
+
+    pub fn run(mut self, notification_rx: Receiver<Notification>) {
+        // blocks on the channel until the next digest is due
+        loop {
+            let wait_ms = self.next_digest_time().saturating_sub(epoch_ms());
+            match notification_rx.recv_timeout(Duration::from_millis(wait_ms)) {
+                Ok(notification) => self.push(notification),
+                Err(RecvTimeoutError::Timeout) => self.flush_digest(),
+                Err(RecvTimeoutError::Disconnected) => {
+                    self.flush_digest();
+                    break;
+                }
+            }
+        }
+    }
+}
+
+fn chunk_lines(lines: &[&str], max_len: usize) -> Vec<String> {
+    // joins lines into chunks no longer than max_len. a single longer line gets its own chunk.
+    let mut chunks = Vec::new();
+    let mut joined = String::new();
+    for line in lines {
+        if !joined.is_empty() && joined.chars().count() + line.chars().count() + 1 > max_len {
+            chunks.push(joined);
+            joined = String::new();
+        }
+        joined = format!("{}\n{}", joined, line);
+    }
+    if !joined.is_empty() {
+        chunks.push(joined);
+    }
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use std::thread;
+
+    // minimal local HTTP stand-in. answers every request with 200 and forwards the body.
+    fn stand_in(requests: usize) -> (String, mpsc::Receiver<Value>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let (body_tx, body_rx) = mpsc::channel();
+        thread::spawn(move || {
+            for stream in listener.incoming().take(requests) {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line == "\r\n" {
+                        break;
+                    }
+                    let lower = line.to_lowercase();
+                    if let Some(length) = lower.strip_prefix("content-length:") {
+                        content_length = length.trim().parse().unwrap();
+                    }
+                }
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+                stream
+                    .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+                    .unwrap();
+                body_tx
+                    .send(serde_json::from_slice(&body).unwrap())
+                    .unwrap();
+            }
+        });
+        (url, body_rx)
+    }
+
+    #[test]
+    fn discord_posts_content() {
+        let (url, body_rx) = stand_in(1);
+        let notifier = DiscordNotifier {
+            webhook_url: url,
+            diagnostic: true,
+        };
+        notifier.send(Severity::Info, "hello").unwrap();
+        assert_eq!(body_rx.recv().unwrap()["content"], "```fix\nhello```");
+    }
+
+    #[test]
+    fn telegram_posts_to_bot_endpoint() {
+        let (url, body_rx) = stand_in(1);
+        let notifier = TelegramNotifier {
+            api_base: url,
+            bot_token: "token".to_string(),
+            chat_id: "42".to_string(),
+        };
+        notifier.send(Severity::Info, "hello").unwrap();
+        let body = body_rx.recv().unwrap();
+        assert_eq!(body["chat_id"], "42");
+        assert_eq!(body["text"], "hello");
+    }
+
+    #[test]
+    fn alerts_skip_the_digest() {
+        let (url, body_rx) = stand_in(2);
+        let config = json!({
+            "immediate_severity": "alert",
+            "sinks": [{ "type": "webhook", "url": url }],
+        });
+        let mut dispatcher = NotificationDispatcher::from_config(&config, false).unwrap();
+
+        dispatcher.push(Notification::routine("new_ohlc: ETHUSDT".to_string()));
+        dispatcher.push(Notification::alert(
+            "The order has been filled.".to_string(),
+        ));
+        let body = body_rx.recv().unwrap();
+        assert_eq!(body["severity"], "Alert");
+        assert!(body["text"].as_str().unwrap().contains("filled"));
+
+        dispatcher.flush_digest();
+        let body = body_rx.recv().unwrap();
+        assert_eq!(body["severity"], "Routine");
+        assert!(body["text"].as_str().unwrap().contains("new_ohlc"));
+    }
+
+    #[test]
+    fn min_severity_filters_sinks() {
+        let (url, body_rx) = stand_in(1);
+        let config = json!({
+            "sinks": [{ "type": "slack", "webhook_url": url, "min_severity": "warning" }],
+        });
+        let mut dispatcher = NotificationDispatcher::from_config(&config, false).unwrap();
+        dispatcher.push(Notification::routine("new_ohlc: ETHUSDT".to_string()));
+        dispatcher.flush_digest();
+        dispatcher.push(Notification::error("warning: invalid balance".to_string()));
+        let body = body_rx.recv().unwrap();
+        assert!(body["text"].as_str().unwrap().contains("invalid balance"));
+    }
+
+    #[test]
+    fn chunks_respect_max_len() {
+        let lines = vec!["aaaa", "bbbb", "cccc"];
+        let chunks = chunk_lines(&lines, 10);
+        assert_eq!(chunks, vec!["\naaaa\nbbbb", "\ncccc"]);
+    }
+}
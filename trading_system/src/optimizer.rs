@@ -0,0 +1,813 @@
+use crate::backtest::{self, BacktestConfig, BacktestResult};
+use crate::trading_strategies::PairStrategy;
+use serde_json::{json, Value};
+use std::cmp::Ordering;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::thread;
+
+// parameter optimization. backtests one stateful strategy over many strategy_settings, picked from a grid, at random,
+// or by a tree-structured parzen estimator, in parallel, and ranks them. see docs/optimizer.txt.
+
+// share of the trials tpe counts as good, the trials it starts with at random, and the candidates it weighs for each
+const TPE_GAMMA: f64 = 0.25;
+const TPE_STARTUP: usize = 10;
+const TPE_CANDIDATES: usize = 24;
+// bins of a continuous parameter in the heat map
+const HEATMAP_BINS: usize = 10;
+// settings a grid search may have, unless the config sets max_grid
+const MAX_GRID: u64 = 100_000;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Domain {
+    Values(Vec<f64>),
+    // a step of 0 is continuous
+    Range { min: f64, max: f64, step: f64 },
+}
+
+// one of the strategy's settings, in the order the strategy takes them
+#[derive(Clone, Debug, PartialEq)]
+pub struct Param {
+    pub name: String,
+    pub domain: Domain,
+}
+
+impl Param {
+    pub fn from_value(param: &Value) -> Result<Self, String> {
+        let name = param["name"]
+            .as_str()
+            .ok_or("optimize: a param needs a name")?
+            .to_string();
+        let domain = if let Some(values) = param["values"].as_array() {
+            let values: Vec<f64> = values.iter().filter_map(|x| x.as_f64()).collect();
+            if values.is_empty() {
+                return Err(format!("optimize: {} has no values", name));
+            }
+            Domain::Values(values)
+        } else {
+            let number = |field: &str| {
+                param[field]
+                    .as_f64()
+                    .ok_or(format!("optimize: {} needs values or a min and max", name))
+            };
+            let (min, max) = (number("min")?, number("max")?);
+            let step = param["step"].as_f64().unwrap_or(0.0);
+            if max < min || step < 0.0 {
+                return Err(format!(
+                    "optimize: {} needs min at most max and a step of at least 0",
+                    name
+                ));
+            }
+            Domain::Range { min, max, step }
+        };
+        Ok(Param { name, domain })
+    }
+
+    pub fn grid(&self) -> Option<Vec<f64>> {
+        // every value the parameter can take, None for a continuous one
+        match &self.domain {
+            Domain::Values(values) => Some(values.clone()),
+            Domain::Range { step, .. } if *step <= 0.0 => None,
+            Domain::Range { min, step, .. } => {
+                Some((0..=self.steps()).map(|x| min + x as f64 * step).collect())
+            }
+        }
+    }
+
+    fn grid_size(&self) -> usize {
+        match &self.domain {
+            Domain::Values(values) => values.len(),
+            Domain::Range { .. } => self.steps().saturating_add(1),
+        }
+    }
+
+    fn steps(&self) -> usize {
+        match self.domain {
+            Domain::Range { min, max, step } if step > 0.0 => {
+                ((max - min) / step + 1e-9).floor() as usize
+            }
+            _ => 0,
+        }
+    }
+
+    fn bounds(&self) -> (f64, f64) {
+        /*
+            The space search works in. A list of values is searched by index. Discrete ones are widened by half a step
+            each side so the values on their ends come up as often as the rest.
+        */
+        match &self.domain {
+            Domain::Values(values) => (-0.5, values.len() as f64 - 0.5),
+            Domain::Range { min, max, step } if *step <= 0.0 => (*min, *max),
+            Domain::Range { min, step, .. } => {
+                (min - step / 2.0, min + (self.steps() as f64 + 0.5) * step)
+            }
+        }
+    }
+
+    fn decode(&self, x: f64) -> f64 {
+        match &self.domain {
+            Domain::Values(values) => values[(x.round().max(0.0) as usize).min(values.len() - 1)],
+            Domain::Range { min, max, step } if *step <= 0.0 => x.clamp(*min, *max),
+            Domain::Range { min, step, .. } => {
+                let steps = ((x - min) / step).round().clamp(0.0, self.steps() as f64);
+                min + steps * step
+            }
+        }
+    }
+
+    fn encode(&self, value: f64) -> f64 {
+        match &self.domain {
+            Domain::Values(values) => values.iter().position(|x| *x == value).unwrap_or(0) as f64,
+            _ => value,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Search {
+    Grid,
+    Random,
+    Tpe,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RankBy {
+    Sharpe,
+    Sortino,
+    Drawdown,
+    Return,
+    // sum of the ranks by the other four
+    Combined,
+}
+
+impl RankBy {
+    fn objective(&self, result: &Result<BacktestResult, String>) -> f64 {
+        // higher is better. combined is searched by sharpe.
+        match result {
+            Ok(x) => match self {
+                RankBy::Sharpe | RankBy::Combined => x.sharpe,
+                RankBy::Sortino => x.sortino,
+                RankBy::Drawdown => -x.max_drawdown,
+                RankBy::Return => x.total_return,
+            },
+            Err(_) => f64::NEG_INFINITY,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum BarSource {
+    // a csv of bars
+    File(String),
+    // the days of 1m klines of symbol to fetch
+    Klines { symbol: String, days: u64 },
+}
+
+#[derive(Clone, Debug)]
+pub struct OptimizerConfig {
+    // a stateful strategy's name, or "script:<path>"
+    pub strategy: String,
+    pub params: Vec<Param>,
+    pub search: Search,
+    // random and tpe
+    pub trials: usize,
+    pub threads: usize,
+    pub seed: u64,
+    pub rank_by: RankBy,
+    pub backtest: BacktestConfig,
+    pub bars: BarSource,
+    pub results: String,
+    pub heatmap: String,
+    // indices of the params on the heat map's rows and columns
+    pub heatmap_params: Option<(usize, usize)>,
+}
+
+impl OptimizerConfig {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let contents = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        let value: Value =
+            serde_json::from_str(&contents).map_err(|e| format!("{}: {}", path, e))?;
+        OptimizerConfig::from_value(&value)
+    }
+
+    pub fn from_value(config: &Value) -> Result<Self, String> {
+        let strategy = config["strategy"]
+            .as_str()
+            .ok_or("optimize: strategy is missing")?
+            .to_string();
+        let params = config["params"]
+            .as_array()
+            .map(|x| x.as_slice())
+            .unwrap_or(&[])
+            .iter()
+            .map(Param::from_value)
+            .collect::<Result<Vec<Param>, String>>()?;
+        if params.is_empty() {
+            return Err("optimize: params is empty".to_string());
+        }
+        let search = match config["search"].as_str().unwrap_or("grid") {
+            "grid" => Search::Grid,
+            "random" => Search::Random,
+            "tpe" => Search::Tpe,
+            other => return Err(format!("optimize: unknown search {}", other)),
+        };
+        if search == Search::Grid {
+            if let Some(param) = params.iter().find(|x| x.grid().is_none()) {
+                return Err(format!(
+                    "optimize: grid search needs a step or values for {}",
+                    param.name
+                ));
+            }
+            let size = params
+                .iter()
+                .fold(1usize, |size, x| size.saturating_mul(x.grid_size()));
+            let max_grid = config["max_grid"].as_u64().unwrap_or(MAX_GRID) as usize;
+            if size > max_grid {
+                return Err(format!(
+                    "optimize: the grid has {} settings, more than max_grid {}. narrow the params or raise max_grid",
+                    size, max_grid
+                ));
+            }
+        }
+        let rank_by = match config["rank_by"].as_str().unwrap_or("sharpe") {
+            "sharpe" => RankBy::Sharpe,
+            "sortino" => RankBy::Sortino,
+            "drawdown" => RankBy::Drawdown,
+            "return" => RankBy::Return,
+            "combined" => RankBy::Combined,
+            other => return Err(format!("optimize: unknown rank_by {}", other)),
+        };
+        let index = |name: &Value| {
+            let name = name.as_str().unwrap_or("");
+            params
+                .iter()
+                .position(|x| x.name == name)
+                .ok_or(format!("optimize: heatmap_params has no param {}", name))
+        };
+        let heatmap_params = match config["heatmap_params"].as_array() {
+            Some(names) if names.len() == 2 => Some((index(&names[0])?, index(&names[1])?)),
+            Some(_) => return Err("optimize: heatmap_params takes two params".to_string()),
+            None if params.len() > 1 => Some((0, 1)),
+            None => None,
+        };
+        if let Some((x, y)) = heatmap_params {
+            if x == y {
+                return Err(format!(
+                    "optimize: heatmap_params names {} twice, it takes two different params",
+                    params[x].name
+                ));
+            }
+        }
+        let bars = match (config["bars"].as_str(), config["symbol"].as_str()) {
+            (Some(path), _) => BarSource::File(path.to_string()),
+            (None, Some(symbol)) => BarSource::Klines {
+                symbol: symbol.to_string(),
+                days: config["days"].as_u64().unwrap_or(30),
+            },
+            (None, None) => return Err("optimize: bars or symbol is missing".to_string()),
+        };
+        let threads = thread::available_parallelism()
+            .map(|x| x.get())
+            .unwrap_or(1);
+        Ok(OptimizerConfig {
+            strategy,
+            params,
+            search,
+            trials: config["trials"].as_u64().unwrap_or(100).max(1) as usize,
+            threads: config["threads"]
+                .as_u64()
+                .map(|x| x as usize)
+                .unwrap_or(threads)
+                .max(1),
+            seed: config["seed"].as_u64().unwrap_or(1),
+            rank_by,
+            backtest: BacktestConfig::from_value(&config["backtest"])?,
+            bars,
+            results: config["results"]
+                .as_str()
+                .unwrap_or("optimize_results.csv")
+                .to_string(),
+            heatmap: config["heatmap"]
+                .as_str()
+                .unwrap_or("optimize_heatmap.csv")
+                .to_string(),
+            heatmap_params,
+        })
+    }
+
+    pub fn spec(&self, settings: &[f64]) -> String {
+        // the strategy spec of an algo with these settings
+        match self.strategy.strip_prefix("script:") {
+            Some(path) => json!({"script": path, "settings": settings}).to_string(),
+            None => {
+                let settings: Vec<String> = settings.iter().map(|x| x.to_string()).collect();
+                format!("{}:{}", self.strategy, settings.join(","))
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Trial {
+    pub settings: Vec<f64>,
+    pub result: Result<BacktestResult, String>,
+}
+
+// splitmix64, enough for picking settings
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    fn uniform(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn normal(&mut self) -> f64 {
+        let u = 1.0 - self.uniform();
+        (-2.0 * u.ln()).sqrt() * (2.0 * std::f64::consts::PI * self.uniform()).cos()
+    }
+}
+
+pub fn grid(params: &[Param]) -> Vec<Vec<f64>> {
+    // every combination of the params' values, the last param changing fastest
+    let mut settings: Vec<Vec<f64>> = vec![vec![]];
+    for param in params {
+        let values = param.grid().unwrap_or_default();
+        settings = settings
+            .iter()
+            .flat_map(|x| {
+                values.iter().map(move |value| {
+                    let mut next = x.clone();
+                    next.push(*value);
+                    next
+                })
+            })
+            .collect();
+    }
+    settings
+}
+
+fn random(params: &[Param], rng: &mut Rng) -> Vec<f64> {
+    params
+        .iter()
+        .map(|param| {
+            let (low, high) = param.bounds();
+            param.decode(low + rng.uniform() * (high - low))
+        })
+        .collect()
+}
+
+fn tpe(params: &[Param], trials: &[Trial], rank_by: RankBy, rng: &mut Rng) -> Vec<f64> {
+    /*
+        Splits the trials so far into the best TPE_GAMMA of them and the rest, and fits a parzen estimator, a
+        gaussian on each trial plus a uniform prior, to each. Each param is picked on its own: the candidate drawn
+        from the good estimator that's likeliest under it against the bad one.
+    */
+    let mut sorted: Vec<&Trial> = trials.iter().collect();
+    sorted.sort_by(|a, b| {
+        rank_by
+            .objective(&b.result)
+            .partial_cmp(&rank_by.objective(&a.result))
+            .unwrap_or(Ordering::Equal)
+    });
+    let good_len = ((sorted.len() as f64 * TPE_GAMMA).ceil() as usize).max(1);
+    let (good, bad) = sorted.split_at(good_len);
+    params
+        .iter()
+        .enumerate()
+        .map(|(i, param)| {
+            let (low, high) = param.bounds();
+            let points = |trials: &[&Trial]| -> Vec<f64> {
+                trials.iter().map(|x| param.encode(x.settings[i])).collect()
+            };
+            let (good, bad) = (points(good), points(bad));
+            let width = |n: usize| (high - low) / 2.0 / ((n.max(1)) as f64).powf(0.2);
+            let density = |points: &[f64], x: f64| {
+                let sigma = width(points.len()).max(1e-12);
+                let kernels: f64 = points
+                    .iter()
+                    .map(|p| (-0.5 * ((x - p) / sigma).powi(2)).exp())
+                    .sum::<f64>()
+                    / (sigma * (2.0 * std::f64::consts::PI).sqrt());
+                (kernels + 1.0 / (high - low).max(1e-12)) / (points.len() + 1) as f64
+            };
+            let mut best = (f64::NEG_INFINITY, low);
+            for _ in 0..TPE_CANDIDATES {
+                let pick = (rng.uniform() * (good.len() + 1) as f64) as usize;
+                let x = match good.get(pick) {
+                    Some(center) => (center + width(good.len()) * rng.normal()).clamp(low, high),
+                    None => low + rng.uniform() * (high - low),
+                };
+                let score = density(&good, x) / density(&bad, x);
+                if score > best.0 {
+                    best = (score, x);
+                }
+            }
+            param.decode(best.1)
+        })
+        .collect()
+}
+
+fn evaluate(config: &OptimizerConfig, bars: &[Vec<f64>], batch: Vec<Vec<f64>>) -> Vec<Trial> {
+    /*
+        Backtests a batch of settings on config.threads threads. Strategies aren't Send, so each is built on the
+        thread it runs on. A trial that panics fails on its own, the rest of its thread's share still runs.
+    */
+    let run = |settings: &Vec<f64>| match PairStrategy::from_spec(&config.spec(settings))? {
+        PairStrategy::Stateful(mut strategy) => {
+            Ok(backtest::run(strategy.as_mut(), bars, &config.backtest))
+        }
+        PairStrategy::Window(..) => Err(format!(
+            "{} is a window strategy, only stateful ones are backtested",
+            config.strategy
+        )),
+        PairStrategy::Failed(e) => Err(e),
+    };
+    run_batch(config.threads, batch, run)
+}
+
+fn run_batch<F>(threads: usize, batch: Vec<Vec<f64>>, run: F) -> Vec<Trial>
+where
+    F: Fn(&Vec<f64>) -> Result<BacktestResult, String> + Sync,
+{
+    let threads = threads.min(batch.len()).max(1);
+    let mut results: Vec<Option<Result<BacktestResult, String>>> = vec![None; batch.len()];
+    thread::scope(|scope| {
+        let handles: Vec<_> = (0..threads)
+            .map(|first| {
+                let (batch, run) = (&batch, &run);
+                scope.spawn(move || {
+                    (first..batch.len())
+                        .step_by(threads)
+                        .map(|i| {
+                            let result = panic::catch_unwind(AssertUnwindSafe(|| run(&batch[i])))
+                                .unwrap_or_else(|e| {
+                                    Err(format!("backtest panicked: {}", panic_message(&*e)))
+                                });
+                            (i, result)
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        for handle in handles {
+            for (i, result) in handle.join().unwrap_or_default() {
+                results[i] = Some(result);
+            }
+        }
+    });
+    batch
+        .into_iter()
+        .zip(results)
+        .map(|(settings, result)| Trial {
+            settings,
+            result: result.unwrap_or_else(|| Err("backtest panicked".to_string())),
+        })
+        .collect()
+}
+
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> &str {
+    match payload.downcast_ref::<&str>() {
+        Some(message) => message,
+        None => payload
+            .downcast_ref::<String>()
+            .map(|x| x.as_str())
+            .unwrap_or("no message"),
+    }
+}
+
+pub fn optimize(config: &OptimizerConfig, bars: &[Vec<f64>]) -> Vec<Trial> {
+    let mut rng = Rng(config.seed);
+    match config.search {
+        Search::Grid => evaluate(config, bars, grid(&config.params)),
+        Search::Random => {
+            let batch = (0..config.trials)
+                .map(|_| random(&config.params, &mut rng))
+                .collect();
+            evaluate(config, bars, batch)
+        }
+        Search::Tpe => {
+            let startup = TPE_STARTUP.max(config.trials / 5).min(config.trials);
+            let batch = (0..startup)
+                .map(|_| random(&config.params, &mut rng))
+                .collect();
+            let mut trials = evaluate(config, bars, batch);
+            // a batch per round, so the threads stay busy, each round fitted to the trials before it
+            while trials.len() < config.trials {
+                let size = config.threads.min(config.trials - trials.len());
+                let batch = (0..size)
+                    .map(|_| tpe(&config.params, &trials, config.rank_by, &mut rng))
+                    .collect();
+                trials.extend(evaluate(config, bars, batch));
+            }
+            trials
+        }
+    }
+}
+
+pub fn rank(trials: &[Trial], rank_by: RankBy) -> Vec<usize> {
+    /*
+        Indices of the trials, best first. Failed trials are last.
+    */
+    let by = |objective: RankBy| -> Vec<usize> {
+        let mut order: Vec<usize> = (0..trials.len()).collect();
+        order.sort_by(|a, b| {
+            objective
+                .objective(&trials[*b].result)
+                .partial_cmp(&objective.objective(&trials[*a].result))
+                .unwrap_or(Ordering::Equal)
+        });
+        order
+    };
+    if rank_by != RankBy::Combined {
+        return by(rank_by);
+    }
+    let mut scores = vec![0usize; trials.len()];
+    for objective in [
+        RankBy::Sharpe,
+        RankBy::Sortino,
+        RankBy::Drawdown,
+        RankBy::Return,
+    ]
+    .iter()
+    {
+        for (place, i) in by(*objective).into_iter().enumerate() {
+            scores[i] += place;
+        }
+    }
+    let mut order: Vec<usize> = (0..trials.len()).collect();
+    order.sort_by_key(|i| (trials[*i].result.is_err(), scores[*i]));
+    order
+}
+
+pub fn results_csv(params: &[Param], trials: &[Trial], order: &[usize]) -> String {
+    let names: Vec<&str> = params.iter().map(|x| x.name.as_str()).collect();
+    let mut csv = format!(
+        "rank,{},sharpe,sortino,max_drawdown,total_return,trades,error\n",
+        names.join(",")
+    );
+    for (place, i) in order.iter().enumerate() {
+        let trial = &trials[*i];
+        let settings: Vec<String> = trial.settings.iter().map(|x| x.to_string()).collect();
+        let metrics = match &trial.result {
+            Ok(x) => format!(
+                "{},{},{},{},{},",
+                x.sharpe, x.sortino, x.max_drawdown, x.total_return, x.trades
+            ),
+            Err(e) => format!(",,,,,\"{}\"", e.replace('"', "'")),
+        };
+        csv.push_str(&format!(
+            "{},{},{}\n",
+            place + 1,
+            settings.join(","),
+            metrics
+        ));
+    }
+    csv
+}
+
+pub fn format_top(config: &OptimizerConfig, trials: &[Trial], order: &[usize], n: usize) -> String {
+    let mut table = format!(
+        "{:<5}{:>10}{:>10}{:>10}{:>10}{:>8}  {}\n",
+        "rank", "sharpe", "sortino", "drawdown", "return", "trades", "strategy"
+    );
+    for (place, i) in order.iter().take(n).enumerate() {
+        let trial = &trials[*i];
+        let spec = config.spec(&trial.settings);
+        match &trial.result {
+            Ok(x) => table.push_str(&format!(
+                "{:<5}{:>10.3}{:>10.3}{:>9.2}%{:>9.2}%{:>8}  {}\n",
+                place + 1,
+                x.sharpe,
+                x.sortino,
+                x.max_drawdown * 100.0,
+                x.total_return * 100.0,
+                x.trades,
+                spec
+            )),
+            Err(e) => table.push_str(&format!("{:<5}{:>48}  {}: {}\n", place + 1, "", spec, e)),
+        }
+    }
+    table
+}
+
+fn heatmap_axis(param: &Param, trials: &[Trial], index: usize) -> (Vec<f64>, Vec<usize>) {
+    // the labels of a param's rows or columns, and each trial's row or column
+    match param.grid() {
+        Some(values) => {
+            let positions = trials
+                .iter()
+                .map(|x| {
+                    values
+                        .iter()
+                        .position(|v| *v == x.settings[index])
+                        .unwrap_or(0)
+                })
+                .collect();
+            (values, positions)
+        }
+        None => {
+            let (low, high) = param.bounds();
+            let width = (high - low) / HEATMAP_BINS as f64;
+            let labels = (0..HEATMAP_BINS)
+                .map(|x| low + (x as f64 + 0.5) * width)
+                .collect();
+            let positions = trials
+                .iter()
+                .map(|x| {
+                    let bin = if width > 0.0 {
+                        ((x.settings[index] - low) / width) as usize
+                    } else {
+                        0
+                    };
+                    bin.min(HEATMAP_BINS - 1)
+                })
+                .collect();
+            (labels, positions)
+        }
+    }
+}
+
+pub fn heatmap_csv(config: &OptimizerConfig, trials: &[Trial]) -> Option<String> {
+    /*
+        The best objective of config.rank_by over the trials at each pair of values of the heatmap params, whatever
+        the others are. Combined has no metric of its own, so its cells are the best overall rank, 1 the best.
+        Continuous params are binned, labelled by the middle of the bin. Empty where nothing ran.
+    */
+    let (x, y) = config.heatmap_params?;
+    let (rows, row_of) = heatmap_axis(&config.params[x], trials, x);
+    let (columns, column_of) = heatmap_axis(&config.params[y], trials, y);
+    // higher is better, ranks are negated
+    let mut scores: Vec<f64> = trials
+        .iter()
+        .map(|x| config.rank_by.objective(&x.result))
+        .collect();
+    let combined = config.rank_by == RankBy::Combined;
+    if combined {
+        for (place, i) in rank(trials, RankBy::Combined).into_iter().enumerate() {
+            if trials[i].result.is_ok() {
+                scores[i] = -((place + 1) as f64);
+            }
+        }
+    }
+    let mut cells = vec![vec![f64::NEG_INFINITY; columns.len()]; rows.len()];
+    for (i, score) in scores.iter().enumerate() {
+        let cell = &mut cells[row_of[i]][column_of[i]];
+        *cell = cell.max(*score);
+    }
+    let labels: Vec<String> = columns.iter().map(|x| x.to_string()).collect();
+    let mut csv = format!(
+        "{}\\{},{}\n",
+        config.params[x].name,
+        config.params[y].name,
+        labels.join(",")
+    );
+    for (label, row) in rows.iter().zip(cells) {
+        let row: Vec<String> = row
+            .iter()
+            .map(|x| {
+                if !x.is_finite() {
+                    String::new()
+                } else if combined {
+                    (-x).to_string()
+                } else {
+                    x.to_string()
+                }
+            })
+            .collect();
+        csv.push_str(&format!("{},{}\n", label, row.join(",")));
+    }
+    Some(csv)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(search: &str) -> OptimizerConfig {
+        OptimizerConfig::from_value(&json!({
+            "strategy": "sma_crossover",
+            "params": [
+                {"name": "short", "min": 2, "max": 6, "step": 2},
+                {"name": "long", "values": [8, 12]}
+            ],
+            "search": search,
+            "trials": 16,
+            "threads": 3,
+            "bars": "bars.csv",
+            "backtest": {"fee_bps": 0}
+        }))
+        .unwrap()
+    }
+
+    fn bars() -> Vec<Vec<f64>> {
+        (0..200)
+            .map(|x| {
+                let price = 100.0 + 10.0 * (x as f64 / 15.0).sin();
+                vec![price, price + 0.5, price - 0.5, price, 1.0]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn searches_the_space() {
+        let grid_config = config("grid");
+        assert_eq!(grid_config.spec(&[2.0, 8.0]), "sma_crossover:2,8");
+        let trials = optimize(&grid_config, &bars());
+        assert_eq!(trials.len(), 6);
+        assert_eq!(trials[1].settings, vec![2.0, 12.0]);
+        assert!(trials.iter().all(|x| x.result.is_ok()));
+        let order = rank(&trials, RankBy::Sharpe);
+        let sharpe = |i: usize| trials[order[i]].result.as_ref().unwrap().sharpe;
+        assert!(sharpe(0) >= sharpe(5));
+        let heatmap = heatmap_csv(&grid_config, &trials).unwrap();
+        assert_eq!(heatmap.lines().count(), 4);
+        assert!(heatmap.starts_with("short\\long,8,12\n"));
+        // one trial a cell, so combined shows each one's place
+        let combined_config = OptimizerConfig {
+            rank_by: RankBy::Combined,
+            ..grid_config.clone()
+        };
+        let mut places: Vec<String> = heatmap_csv(&combined_config, &trials)
+            .unwrap()
+            .lines()
+            .skip(1)
+            .flat_map(|x| {
+                x.split(',')
+                    .skip(1)
+                    .map(|x| x.to_string())
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        places.sort_by_key(|x| x.parse::<usize>().unwrap());
+        assert_eq!(places, vec!["1", "2", "3", "4", "5", "6"]);
+
+        // random and tpe stay on the grid, and are the same for the same seed
+        for search in ["random", "tpe"].iter() {
+            let search_config = config(search);
+            let trials = optimize(&search_config, &bars());
+            assert_eq!(trials.len(), 16);
+            assert!(trials
+                .iter()
+                .all(|x| [2.0, 4.0, 6.0].contains(&x.settings[0])
+                    && [8.0, 12.0].contains(&x.settings[1])));
+            let again = optimize(&search_config, &bars());
+            assert!(trials
+                .iter()
+                .zip(again.iter())
+                .all(|(a, b)| a.settings == b.settings));
+        }
+
+        assert!(OptimizerConfig::from_value(&json!({
+            "strategy": "sma_crossover",
+            "params": [{"name": "short", "min": 2, "max": 6}],
+            "bars": "bars.csv"
+        }))
+        .is_err());
+        assert!(OptimizerConfig::from_value(&json!({
+            "strategy": "sma_crossover",
+            "params": [{"name": "short", "values": [2, 4]}, {"name": "long", "values": [8, 12]}],
+            "heatmap_params": ["short", "short"],
+            "bars": "bars.csv"
+        }))
+        .is_err());
+        let fine = json!({
+            "strategy": "sma_crossover",
+            "params": [{"name": "short", "min": 1, "max": 200, "step": 1}, {"name": "long", "min": 1, "max": 1000, "step": 1}],
+            "bars": "bars.csv"
+        });
+        assert!(OptimizerConfig::from_value(&fine).is_err());
+        let mut raised = fine.clone();
+        raised["max_grid"] = json!(200_000);
+        assert!(OptimizerConfig::from_value(&raised).is_ok());
+    }
+
+    #[test]
+    fn a_panicking_trial_fails_alone() {
+        let batch: Vec<Vec<f64>> = (0..6).map(|x| vec![x as f64]).collect();
+        let trials = run_batch(2, batch, |settings| {
+            if settings[0] == 2.0 {
+                panic!("bad setting");
+            }
+            Ok(BacktestResult::default())
+        });
+        assert_eq!(trials.len(), 6);
+        for trial in trials.iter() {
+            if trial.settings[0] == 2.0 {
+                assert_eq!(
+                    trial.result.as_ref().unwrap_err(),
+                    "backtest panicked: bad setting"
+                );
+            } else {
+                assert!(trial.result.is_ok());
+            }
+        }
+    }
+}
@@ -0,0 +1,427 @@
+use crate::binance_interface;
+use crate::metrics;
+use crate::network;
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Mutex, OnceLock};
+
+// local L2 order books, one per symbol, synced from a REST snapshot and the @depth@100ms diff stream.
+// follows binance's update-id rules for managing a local order book. see docs/order_books.txt.
+
+// (price, quantity) per level
+pub type Levels = Vec<(f64, f64)>;
+
+// levels fetched with each snapshot
+pub const SNAPSHOT_LIMIT: u64 = 1000;
+
+// prices as BTreeMap keys. binance prices are always finite.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Price(f64);
+
+impl Eq for Price {}
+
+impl PartialOrd for Price {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Price {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Side {
+    Buy,
+    Sell,
+}
+
+fn parse_levels(levels: &Value) -> Option<Levels> {
+    // [["price", "quantity"], ...]
+    levels
+        .as_array()?
+        .iter()
+        .map(|level| {
+            Some((
+                level[0].as_str()?.parse().ok()?,
+                level[1].as_str()?.parse().ok()?,
+            ))
+        })
+        .collect()
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DepthUpdate {
+    pub symbol: String,
+    pub first_update_id: u64,
+    pub final_update_id: u64,
+    pub bids: Levels,
+    pub asks: Levels,
+}
+
+impl DepthUpdate {
+    pub fn from_value(update: &Value) -> Option<Self> {
+        /*
+            Reads a depthUpdate event: {"e": "depthUpdate", "s": "BNBBTC", "U": 157, "u": 160, "b": [...], "a": [...]}
+        */
+        Some(DepthUpdate {
+            symbol: update["s"].as_str()?.to_string(),
+            first_update_id: update["U"].as_u64()?,
+            final_update_id: update["u"].as_u64()?,
+            bids: parse_levels(&update["b"])?,
+            asks: parse_levels(&update["a"])?,
+        })
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct OrderBook {
+    pub symbol: String,
+    pub last_update_id: u64,
+    // false until the first diff after the snapshot lined up
+    synced: bool,
+    bids: BTreeMap<Price, f64>,
+    asks: BTreeMap<Price, f64>,
+}
+
+impl OrderBook {
+    pub fn from_snapshot(symbol: &str, snapshot: &Value) -> Option<Self> {
+        /*
+            Reads /api/v3/depth: {"lastUpdateId": 1027024, "bids": [...], "asks": [...]}
+        */
+        let mut book = OrderBook {
+            symbol: symbol.to_string(),
+            last_update_id: snapshot["lastUpdateId"].as_u64()?,
+            ..OrderBook::default()
+        };
+        book.set_levels(Side::Buy, &parse_levels(&snapshot["bids"])?);
+        book.set_levels(Side::Sell, &parse_levels(&snapshot["asks"])?);
+        Some(book)
+    }
+
+    pub fn synced_from(
+        symbol: &str,
+        snapshot: &Value,
+        buffered: &[DepthUpdate],
+    ) -> Result<Self, String> {
+        /*
+            Builds the book from a snapshot and the diffs that came in while it was being fetched, in update id order.
+            The diffs the snapshot already has are dropped.
+        */
+        let mut book = OrderBook::from_snapshot(symbol, snapshot)
+            .ok_or_else(|| format!("could not read depth snapshot: {}", snapshot))?;
+        for update in buffered.iter() {
+            book.apply(update)?;
+        }
+        Ok(book)
+    }
+
+    fn set_levels(&mut self, side: Side, levels: &[(f64, f64)]) {
+        // a quantity of 0 removes the level
+        let book_side = match side {
+            Side::Buy => &mut self.bids,
+            Side::Sell => &mut self.asks,
+        };
+        for (price, quantity) in levels.iter() {
+            if *quantity == 0.0 {
+                book_side.remove(&Price(*price));
+            } else {
+                book_side.insert(Price(*price), *quantity);
+            }
+        }
+    }
+
+    pub fn apply(&mut self, update: &DepthUpdate) -> Result<bool, String> {
+        /*
+            Applies one diff. Returns false if the diff is older than the book and was dropped.
+            An error means the book is out of sync and has to be rebuilt from a new snapshot:
+                1. the first diff after the snapshot must cover lastUpdateId + 1.
+                2. every diff after that must start right after the previous one ended.
+        */
+        if update.final_update_id <= self.last_update_id {
+            return Ok(false);
+        }
+        let expected = self.last_update_id + 1;
+        if self.synced && update.first_update_id != expected {
+            return Err(format!(
+                "missed updates {} to {}",
+                expected,
+                update.first_update_id - 1
+            ));
+        }
+        if !self.synced && update.first_update_id > expected {
+            return Err(format!(
+                "snapshot {} is older than the first diff {}",
+                self.last_update_id, update.first_update_id
+            ));
+        }
+        self.set_levels(Side::Buy, &update.bids);
+        self.set_levels(Side::Sell, &update.asks);
+        self.last_update_id = update.final_update_id;
+        self.synced = true;
+        if let (Some(bid), Some(ask)) = (self.best_bid(), self.best_ask()) {
+            if bid.0 >= ask.0 {
+                return Err(format!("book is crossed, bid {} >= ask {}", bid.0, ask.0));
+            }
+        }
+        Ok(true)
+    }
+
+    pub fn is_synced(&self) -> bool {
+        self.synced
+    }
+
+    pub fn best_bid(&self) -> Option<(f64, f64)> {
+        self.bids
+            .iter()
+            .next_back()
+            .map(|(price, quantity)| (price.0, *quantity))
+    }
+
+    pub fn best_ask(&self) -> Option<(f64, f64)> {
+        self.asks
+            .iter()
+            .next()
+            .map(|(price, quantity)| (price.0, *quantity))
+    }
+
+    pub fn mid(&self) -> Option<f64> {
+        Some((self.best_bid()?.0 + self.best_ask()?.0) / 2.0)
+    }
+
+    pub fn spread_bps(&self) -> Option<f64> {
+        Some((self.best_ask()?.0 - self.best_bid()?.0) / self.mid()? * 10000.0)
+    }
+
+    pub fn depth(&self, levels: usize) -> (Levels, Levels) {
+        // the best levels of each side, bids from the highest price and asks from the lowest
+        let bids = self
+            .bids
+            .iter()
+            .rev()
+            .take(levels)
+            .map(|(price, quantity)| (price.0, *quantity))
+            .collect();
+        let asks = self
+            .asks
+            .iter()
+            .take(levels)
+            .map(|(price, quantity)| (price.0, *quantity))
+            .collect();
+        (bids, asks)
+    }
+
+    fn levels_for(&self, side: Side) -> Box<dyn Iterator<Item = (f64, f64)> + '_> {
+        // a buy takes the asks from the lowest price up, a sell the bids from the highest down
+        match side {
+            Side::Buy => Box::new(self.asks.iter().map(|(p, q)| (p.0, *q))),
+            Side::Sell => Box::new(self.bids.iter().rev().map(|(p, q)| (p.0, *q))),
+        }
+    }
+
+    pub fn vwap(&self, side: Side, quantity: f64) -> Option<f64> {
+        /*
+            Average price a market order for quantity(in the base asset) would fill at.
+            None if the book doesn't hold that much.
+        */
+        if quantity <= 0.0 {
+            return None;
+        }
+        let mut remaining = quantity;
+        let mut cost = 0.0;
+        for (price, level_quantity) in self.levels_for(side) {
+            let take = remaining.min(level_quantity);
+            cost += take * price;
+            remaining -= take;
+            if remaining <= 0.0 {
+                return Some(cost / quantity);
+            }
+        }
+        None
+    }
+
+    pub fn vwap_for_quote(&self, side: Side, quote: f64) -> Option<f64> {
+        /*
+            Same as vwap, for an order sized in the quote asset like quoteOrderQty.
+        */
+        if quote <= 0.0 {
+            return None;
+        }
+        let mut remaining = quote;
+        let mut quantity = 0.0;
+        for (price, level_quantity) in self.levels_for(side) {
+            let take = remaining.min(level_quantity * price);
+            quantity += take / price;
+            remaining -= take;
+            if remaining <= 0.0 {
+                return Some(quote / quantity);
+            }
+        }
+        None
+    }
+}
+
+static BOOKS: OnceLock<Mutex<HashMap<String, OrderBook>>> = OnceLock::new();
+// diffs of symbols whose snapshot is on its way. always locked before BOOKS.
+static SYNCING: OnceLock<Mutex<HashMap<String, Vec<DepthUpdate>>>> = OnceLock::new();
+
+fn books() -> &'static Mutex<HashMap<String, OrderBook>> {
+    BOOKS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn syncing() -> &'static Mutex<HashMap<String, Vec<DepthUpdate>>> {
+    SYNCING.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+pub fn with_book<R>(symbol: &str, f: impl FnOnce(&OrderBook) -> R) -> Option<R> {
+    /*
+        Runs f on the symbol's book. None if there is no book or it isn't in sync yet.
+    */
+    let books = books().lock().unwrap();
+    books.get(symbol).filter(|x| x.is_synced()).map(f)
+}
+
+pub fn remove(symbol: &str) {
+    // a snapshot still on its way is dropped when it comes in
+    let mut syncing = syncing().lock().unwrap();
+    syncing.remove(symbol);
+    books().lock().unwrap().remove(symbol);
+}
+
+pub fn on_depth_update(update: &Value) {
+    /*
+        Applies a diff from the depth stream. A symbol without a book gets a snapshot first, fetched on the network runtime
+        so the feed thread keeps going. Its diffs are buffered until the snapshot is in.
+        On a desync the book is dropped, so the next diff starts over from a new snapshot.
+    */
+    let update = match DepthUpdate::from_value(update) {
+        Some(update) => update,
+        None => return,
+    };
+    let mut syncing = syncing().lock().unwrap();
+    if let Some(buffered) = syncing.get_mut(&update.symbol) {
+        buffered.push(update);
+        return;
+    }
+    let mut books = books().lock().unwrap();
+    let result = match books.get_mut(&update.symbol) {
+        Some(book) => book.apply(&update),
+        None => {
+            let symbol = update.symbol.clone();
+            syncing.insert(symbol.clone(), vec![update]);
+            network::spawn(fetch_snapshot(symbol));
+            return;
+        }
+    };
+    if let Err(reason) = result {
+        log_warn!(target: "order_book", "{}: out of sync, {}. resyncing.", update.symbol, reason);
+        metrics::global().inc_order_book_resync(&update.symbol);
+        books.remove(&update.symbol);
+    }
+}
+
+async fn fetch_snapshot(symbol: String) {
+    let snapshot = binance_interface::fetch_depth_snapshot(&symbol, SNAPSHOT_LIMIT).await;
+    let mut syncing = syncing().lock().unwrap();
+    // the symbol was dropped while the snapshot was on its way
+    let buffered = match syncing.remove(&symbol) {
+        Some(buffered) => buffered,
+        None => return,
+    };
+    // a failed sync starts over on the next diff
+    match OrderBook::synced_from(&symbol, &snapshot, &buffered) {
+        Ok(book) => {
+            log_debug!(target: "order_book", "{}: snapshot at {}, {} diffs buffered", symbol, book.last_update_id, buffered.len());
+            books().lock().unwrap().insert(symbol, book);
+        }
+        Err(reason) => {
+            log_warn!(target: "order_book", "{}: could not sync, {}. resyncing.", symbol, reason);
+            metrics::global().inc_order_book_resync(&symbol);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn diff(first: u64, last: u64, bids: Value, asks: Value) -> DepthUpdate {
+        DepthUpdate::from_value(
+            &json!({"e": "depthUpdate", "s": "BNBBTC", "U": first, "u": last, "b": bids, "a": asks}),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn syncs_snapshot_with_diffs() {
+        let snapshot = json!({"lastUpdateId": 100,
+            "bids": [["9.0", "1.0"], ["10.0", "2.0"]],
+            "asks": [["11.0", "1.0"], ["12.0", "3.0"]]});
+        let mut book = OrderBook::from_snapshot("BNBBTC", &snapshot).unwrap();
+        assert!(!book.is_synced());
+
+        // older than the snapshot, dropped
+        assert_eq!(book.apply(&diff(90, 100, json!([]), json!([]))), Ok(false));
+        // first diff overlaps lastUpdateId + 1
+        assert_eq!(
+            book.apply(&diff(
+                95,
+                105,
+                json!([["10.0", "0"]]),
+                json!([["10.5", "1.0"]])
+            )),
+            Ok(true)
+        );
+        assert_eq!(book.best_bid(), Some((9.0, 1.0)));
+        assert_eq!(book.best_ask(), Some((10.5, 1.0)));
+        assert_eq!(book.depth(2).1, vec![(10.5, 1.0), (11.0, 1.0)]);
+        assert_eq!(book.apply(&diff(106, 107, json!([]), json!([]))), Ok(true));
+        // a gap means diffs were missed
+        assert!(book.apply(&diff(109, 110, json!([]), json!([]))).is_err());
+
+        let mut book = OrderBook::from_snapshot("BNBBTC", &snapshot).unwrap();
+        assert!(book.apply(&diff(102, 103, json!([]), json!([]))).is_err());
+    }
+
+    #[test]
+    fn syncs_diffs_buffered_during_the_snapshot() {
+        let snapshot = json!({"lastUpdateId": 100,
+            "bids": [["9.0", "1.0"], ["10.0", "2.0"]],
+            "asks": [["11.0", "1.0"], ["12.0", "3.0"]]});
+        // the stream was subscribed before the snapshot was taken, so the first diffs are already in it
+        let buffered = vec![
+            diff(90, 98, json!([["10.0", "5.0"]]), json!([])),
+            diff(99, 102, json!([["10.0", "0"]]), json!([])),
+            diff(103, 104, json!([]), json!([["10.5", "1.0"]])),
+        ];
+        let book = OrderBook::synced_from("BNBBTC", &snapshot, &buffered).unwrap();
+        assert!(book.is_synced());
+        assert_eq!(book.last_update_id, 104);
+        assert_eq!(book.best_bid(), Some((9.0, 1.0)));
+        assert_eq!(book.best_ask(), Some((10.5, 1.0)));
+
+        // a gap in the buffer, or a snapshot older than every diff
+        let gap = vec![buffered[1].clone(), diff(104, 105, json!([]), json!([]))];
+        assert!(OrderBook::synced_from("BNBBTC", &snapshot, &gap).is_err());
+        assert!(OrderBook::synced_from("BNBBTC", &snapshot, &buffered[2..]).is_err());
+        assert!(OrderBook::synced_from("BNBBTC", &json!({"code": -1}), &buffered).is_err());
+    }
+
+    #[test]
+    fn vwap_walks_the_book() {
+        let snapshot = json!({"lastUpdateId": 1,
+            "bids": [["9.0", "1.0"], ["10.0", "1.0"]],
+            "asks": [["11.0", "1.0"], ["12.0", "1.0"]]});
+        let book = OrderBook::from_snapshot("BNBBTC", &snapshot).unwrap();
+        assert_eq!(book.vwap(Side::Buy, 1.5), Some((11.0 + 6.0) / 1.5));
+        assert_eq!(book.vwap(Side::Sell, 2.0), Some(9.5));
+        assert_eq!(book.vwap(Side::Buy, 3.0), None);
+        // 17 quote buys 1 at 11 and 0.5 at 12
+        assert!((book.vwap_for_quote(Side::Buy, 17.0).unwrap() - 17.0 / 1.5).abs() < 1e-9);
+        assert!((book.spread_bps().unwrap() - 10000.0 / 10.5).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,134 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::io::{BufRead, BufReader};
+
+// program state that survives a restart. see docs/varfile_specifications.txt for the file format.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PersistedState {
+    pub algo_status: Vec<i32>,
+    // epoch ms of the last write, 0 if the file predates the timestamp line
+    pub saved_at: u64,
+    // asset list algo_status indexes into when the file was written. empty in files that predate it.
+    pub assets: Vec<String>,
+}
+
+pub fn load_vars(path: &str) -> Option<PersistedState> {
+    /*
+        Reads the variable file at path.
+        Returns None if the file is missing or malformed.
+    */
+    let var_file = File::open(path).ok()?;
+    let mut lines = BufReader::new(var_file).lines();
+
+    // read number of algorithms
+    let n: u64 = lines.next()?.ok()?.trim().parse().ok()?;
+
+    // read in algo_status
+    let mut algo_status = Vec::new();
+    for _i in 0..n {
+        let status: i32 = lines.next()?.ok()?.trim().parse().ok()?;
+        algo_status.push(status);
+    }
+
+    // optional trailing timestamp and comma separated asset list. blank lines, like a trailing one, are skipped.
+    let mut rest = lines.map_while(Result::ok).filter(|x| !x.trim().is_empty());
+
+    let mut saved_at = 0;
+    if let Some(line) = rest.next() {
+        saved_at = line.trim().parse().ok()?;
+    }
+
+    let mut assets = Vec::new();
+    if let Some(line) = rest.next() {
+        assets = line
+            .trim()
+            .split(',')
+            .filter(|x| !x.is_empty())
+            .map(|x| x.to_string())
+            .collect();
+    }
+
+    Some(PersistedState {
+        algo_status,
+        saved_at,
+        assets,
+    })
+}
+
+pub fn store_vars(path: &str, state: &PersistedState) -> std::io::Result<()> {
+    let mut var_file = OpenOptions::new()
+        .write(true)
+        .truncate(true)
+        .create(true)
+        .open(path)?;
+    let mut write_str = format!("{}\n", state.algo_status.len());
+    for status in state.algo_status.iter() {
+        write_str = format!("{}{}\n", write_str, status);
+    }
+    write_str = format!("{}{}\n", write_str, state.saved_at);
+    if !state.assets.is_empty() {
+        write_str = format!("{}{}\n", write_str, state.assets.join(","));
+    }
+    var_file.write_all(write_str.as_bytes())?;
+    Ok(())
+}
+
+pub fn remap_assets(state: &mut PersistedState, assets: &mut Vec<String>) -> Vec<String> {
+    /*
+        Points algo_status at the same assets in the current asset list, which can differ from the one the file
+        was written with when the universe changed. Assets the list doesn't have are appended and returned.
+        Files without an asset list are taken to index the current list.
+    */
+    let mut missing = Vec::new();
+    if state.assets.is_empty() {
+        return missing;
+    }
+    for status in state.algo_status.iter_mut() {
+        let asset = match state.assets.get(*status as usize) {
+            Some(asset) => asset,
+            None => continue,
+        };
+        let index = match assets.iter().position(|x| x == asset) {
+            Some(index) => index,
+            None => {
+                assets.push(asset.clone());
+                missing.push(asset.clone());
+                assets.len() - 1
+            }
+        };
+        *status = index as i32;
+    }
+    state.assets = assets.clone();
+    missing
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn stores_and_loads_vars() {
+        let path = std::env::temp_dir().join(format!("jane_vars_{}.txt", std::process::id()));
+        let path = path.to_str().unwrap();
+        let state = PersistedState {
+            algo_status: vec![0, 2, 1],
+            saved_at: 1_600_000_000_000,
+            assets: vec!["USDT".to_string(), "ETH".to_string(), "BTC".to_string()],
+        };
+        store_vars(path, &state).unwrap();
+        assert_eq!(load_vars(path), Some(state.clone()));
+
+        // older files without the asset list, and blank lines at the end
+        fs::write(path, "2\n0\n1\n1600000000000\n\n").unwrap();
+        let loaded = load_vars(path).unwrap();
+        assert_eq!(loaded.algo_status, vec![0, 1]);
+        assert_eq!(loaded.saved_at, 1_600_000_000_000);
+        assert!(loaded.assets.is_empty());
+        fs::write(path, "2\n0\n1\n").unwrap();
+        assert_eq!(load_vars(path).unwrap().saved_at, 0);
+        fs::write(path, "2\n0\n").unwrap();
+        assert_eq!(load_vars(path), None);
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,347 @@
+use crate::strategies::indicators::atr::AverageTrueRange;
+use crate::strategies::indicators::{NextI, Ohlcv};
+use crate::strategies::Target;
+use serde_json::Value;
+
+// between a strategy's target and the orders. a target is the share of an algo's capital the strategy wants in a
+// pair's base asset. sizing scales it by the pair's volatility or the algo's kelly fraction, and the algo's position
+// is rebalanced to it. see _Sizing_ in docs/accounts.txt.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Method {
+    // the strategy's weight as it is
+    Fixed,
+    // the weight scaled so the position's volatility, atr over the close, is target_vol
+    VolTarget,
+    // the weight scaled so a stop atr_stop atrs away loses the kelly fraction of the algo's capital
+    Kelly,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SizingConfig {
+    pub method: Method,
+    pub atr_period: u64,
+    // vol_target: volatility of one bar a full position is sized to
+    pub target_vol: f64,
+    // kelly: share of the kelly fraction to bet, and the closed plays it's worked out from
+    pub kelly_fraction: f64,
+    pub min_plays: usize,
+    // a stop this many atrs under the close a position is opened at, when the strategy doesn't set one. 0 for none.
+    pub atr_stop: f64,
+    pub max_weight: f64,
+    // positions less than this far from their target aren't traded
+    pub rebalance_band: f64,
+}
+
+impl Default for SizingConfig {
+    fn default() -> Self {
+        // all in on a buy and all out on a sell, as before targets existed
+        SizingConfig {
+            method: Method::Fixed,
+            atr_period: 14,
+            target_vol: 0.001,
+            kelly_fraction: 0.5,
+            min_plays: 20,
+            atr_stop: 0.0,
+            max_weight: 1.0,
+            rebalance_band: 0.05,
+        }
+    }
+}
+
+impl SizingConfig {
+    pub fn from_value(sizing: &Value) -> Result<Self, String> {
+        /*
+            Reads an account's "sizing" object. kelly sizes against a stop, so its atr_stop defaults to 2.
+        */
+        let method = match sizing["method"].as_str().unwrap_or("fixed") {
+            "fixed" => Method::Fixed,
+            "vol_target" => Method::VolTarget,
+            "kelly" => Method::Kelly,
+            other => return Err(format!("sizing: unknown method {}", other)),
+        };
+        let defaults = SizingConfig::default();
+        let number = |field: &str, default: f64| sizing[field].as_f64().unwrap_or(default);
+        let config = SizingConfig {
+            method,
+            atr_period: sizing["atr_period"].as_u64().unwrap_or(defaults.atr_period),
+            target_vol: number("target_vol", defaults.target_vol),
+            kelly_fraction: number("kelly_fraction", defaults.kelly_fraction),
+            min_plays: sizing["min_plays"]
+                .as_u64()
+                .map(|x| x as usize)
+                .unwrap_or(defaults.min_plays),
+            atr_stop: number("atr_stop", if method == Method::Kelly { 2.0 } else { 0.0 }),
+            max_weight: number("max_weight", defaults.max_weight),
+            rebalance_band: number("rebalance_band", defaults.rebalance_band),
+        };
+        if config.atr_period == 0 {
+            return Err("sizing: atr_period must be at least 1".to_string());
+        }
+        if config.target_vol <= 0.0 || config.kelly_fraction <= 0.0 {
+            return Err("sizing: target_vol and kelly_fraction must be above 0".to_string());
+        }
+        if config.max_weight <= 0.0 || config.max_weight > 1.0 {
+            return Err("sizing: max_weight must be above 0 and at most 1".to_string());
+        }
+        if config.atr_stop < 0.0 || (method == Method::Kelly && config.atr_stop == 0.0) {
+            return Err("sizing: atr_stop can't be negative, or 0 with kelly".to_string());
+        }
+        if config.rebalance_band < 0.0 || config.rebalance_band >= 1.0 {
+            return Err("sizing: rebalance_band must be from 0 to below 1".to_string());
+        }
+        Ok(config)
+    }
+}
+
+// results of an algo's closed plays, for the kelly fraction
+#[derive(Clone, Debug, Default)]
+pub struct PlayStats {
+    wins: usize,
+    losses: usize,
+    // sums of the returns of the winning plays and of minus the losing ones
+    won: f64,
+    lost: f64,
+}
+
+impl PlayStats {
+    pub fn record(&mut self, play_return: f64) {
+        if play_return > 0.0 {
+            self.wins += 1;
+            self.won += play_return;
+        } else {
+            self.losses += 1;
+            self.lost -= play_return;
+        }
+    }
+
+    pub fn kelly(&self, min_plays: usize) -> Option<f64> {
+        /*
+            p - (1 - p) / b, from the share of plays won p and the average win over the average loss b.
+            None until min_plays plays have closed. An algo without an edge gets 0.
+        */
+        let plays = self.wins + self.losses;
+        if plays < min_plays || plays == 0 {
+            return None;
+        }
+        if self.wins == 0 {
+            return Some(0.0);
+        }
+        if self.losses == 0 || self.lost == 0.0 {
+            return Some(1.0);
+        }
+        let p = self.wins as f64 / plays as f64;
+        let b = (self.won / self.wins as f64) / (self.lost / self.losses as f64);
+        Some((p - (1.0 - p) / b).clamp(0.0, 1.0))
+    }
+}
+
+// sizes the targets on one pair, against the pair's atr
+pub struct Sizer {
+    atr: AverageTrueRange,
+    value: f64,
+    close: f64,
+}
+
+impl Sizer {
+    pub fn new(config: &SizingConfig) -> Self {
+        Sizer {
+            atr: AverageTrueRange::new(config.atr_period),
+            value: 0.0,
+            close: 0.0,
+        }
+    }
+
+    pub fn warmed(config: &SizingConfig, bars: &[Vec<f64>]) -> Self {
+        let mut sizer = Sizer::new(config);
+        for bar in bars {
+            sizer.next(bar);
+        }
+        sizer
+    }
+
+    pub fn next(&mut self, ohlc: &[f64]) {
+        let bar = Ohlcv::from_slice(ohlc);
+        self.value = self.atr.next(bar);
+        self.close = bar.close;
+    }
+
+    pub fn size(&self, config: &SizingConfig, target: Target, stats: &PlayStats) -> Target {
+        /*
+            Scales the weight of a target that opens or keeps a position, and sets its stop from the atr.
+            Until the atr has a value, and with too few plays for the kelly fraction, the weight is left as it is.
+        */
+        if target.weight <= 0.0 {
+            return target;
+        }
+        // how much of the position a move of one atr is
+        let volatility = if self.close > 0.0 {
+            self.value / self.close
+        } else {
+            0.0
+        };
+        let scale = match config.method {
+            Method::Fixed => 1.0,
+            Method::VolTarget if volatility > 0.0 => config.target_vol / volatility,
+            Method::Kelly if volatility > 0.0 => match stats.kelly(config.min_plays) {
+                Some(kelly) => config.kelly_fraction * kelly / (config.atr_stop * volatility),
+                None => 1.0,
+            },
+            _ => 1.0,
+        };
+        let stop = match target.stop {
+            None if config.atr_stop > 0.0 && self.value > 0.0 => {
+                Some(self.close - config.atr_stop * self.value)
+            }
+            stop => stop,
+        };
+        Target {
+            weight: (target.weight.min(1.0) * scale).min(config.max_weight),
+            stop,
+            take_profit: target.take_profit,
+        }
+    }
+}
+
+// an algo's position in the pair it's in
+#[derive(Clone, Debug, PartialEq)]
+pub struct Position {
+    // the weight it was last traded to. an algo holding a base asset without one is all in.
+    pub weight: f64,
+    // quote asset the algo keeps out of the base, held back from the other algos' balances
+    pub reserve: f64,
+    // index of the reserve's asset in the account's asset list
+    pub reserve_asset: i32,
+    pub stop: Option<f64>,
+    pub take_profit: Option<f64>,
+}
+
+impl Default for Position {
+    fn default() -> Self {
+        Position {
+            weight: 1.0,
+            reserve: 0.0,
+            reserve_asset: 0,
+            stop: None,
+            take_profit: None,
+        }
+    }
+}
+
+impl Position {
+    pub fn exit_hit(&self, ohlc: &[f64]) -> bool {
+        // the bar traded through the stop or the take profit
+        let bar = Ohlcv::from_slice(ohlc);
+        self.stop.is_some_and(|x| bar.low <= x) || self.take_profit.is_some_and(|x| bar.high >= x)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Rebalance {
+    // quote asset to spend
+    Buy(f64),
+    // base asset to sell, all of it when the target is 0
+    Sell(f64),
+}
+
+pub fn rebalance(base: f64, reserve: f64, price: f64, weight: f64, band: f64) -> Option<Rebalance> {
+    /*
+        The order that takes a position of base, with reserve of the quote asset aside, to weight in the base.
+        None while the position is within band of it.
+    */
+    if weight <= 0.0 {
+        return if base > 0.0 {
+            Some(Rebalance::Sell(base))
+        } else {
+            None
+        };
+    }
+    let value = base * price + reserve;
+    if value <= 0.0 || price <= 0.0 {
+        return None;
+    }
+    let current = base * price / value;
+    if (weight - current).abs() <= band {
+        None
+    } else if weight > current {
+        Some(Rebalance::Buy(((weight - current) * value).min(reserve)))
+    } else {
+        Some(Rebalance::Sell((current - weight) * value / price))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn rebalances_to_the_target() {
+        // 1 base at 100 and 100 quote aside is half in
+        assert_eq!(rebalance(1.0, 100.0, 100.0, 0.5, 0.05), None);
+        assert_eq!(
+            rebalance(1.0, 100.0, 100.0, 0.75, 0.05),
+            Some(Rebalance::Buy(50.0))
+        );
+        assert_eq!(
+            rebalance(1.0, 100.0, 100.0, 0.25, 0.05),
+            Some(Rebalance::Sell(0.5))
+        );
+        assert_eq!(
+            rebalance(1.0, 100.0, 100.0, 0.0, 0.05),
+            Some(Rebalance::Sell(1.0))
+        );
+        // all in stays all in
+        assert_eq!(rebalance(1.0, 0.0, 100.0, 1.0, 0.05), None);
+        assert_eq!(rebalance(0.0, 0.0, 100.0, 0.0, 0.05), None);
+
+        let position = Position {
+            stop: Some(95.0),
+            ..Position::default()
+        };
+        assert!(!position.exit_hit(&[100.0, 101.0, 96.0, 97.0, 1.0]));
+        assert!(position.exit_hit(&[97.0, 98.0, 94.0, 96.0, 1.0]));
+    }
+
+    #[test]
+    fn sizes_by_volatility_and_kelly() {
+        let bar = [100.0, 101.0, 99.0, 100.0, 10.0];
+        let vol_target = SizingConfig::from_value(
+            &json!({"method": "vol_target", "atr_period": 1, "target_vol": 0.005}),
+        )
+        .unwrap();
+        let mut sizer = Sizer::new(&vol_target);
+        sizer.next(&bar);
+        // an atr of 2 is 2% of the close, so a full position is cut to a quarter
+        let sized = sizer.size(&vol_target, Target::weight(1.0), &PlayStats::default());
+        assert!((sized.weight - 0.25).abs() < 1e-12);
+        assert_eq!(sized.stop, None);
+        assert_eq!(
+            sizer
+                .size(&vol_target, Target::weight(0.0), &PlayStats::default())
+                .weight,
+            0.0
+        );
+
+        let kelly =
+            SizingConfig::from_value(&json!({"method": "kelly", "atr_period": 1, "min_plays": 4}))
+                .unwrap();
+        let mut stats = PlayStats::default();
+        for play_return in [0.02, 0.02, 0.02, -0.01].iter() {
+            stats.record(*play_return);
+        }
+        // p 0.75 and b 2 is a kelly fraction of 0.625. half of it over a 4% stop is more than all in.
+        assert!((stats.kelly(4).unwrap() - 0.625).abs() < 1e-12);
+        assert_eq!(stats.kelly(5), None);
+        let sized = sizer.size(&kelly, Target::weight(1.0), &stats);
+        assert_eq!(sized.weight, 1.0);
+        assert_eq!(sized.stop, Some(96.0));
+
+        assert!(SizingConfig::from_value(&json!({"method": "kelly", "atr_stop": 0})).is_err());
+        assert!(SizingConfig::from_value(&json!({"max_weight": 1.5})).is_err());
+        assert_eq!(
+            SizingConfig::from_value(&json!(null)).unwrap(),
+            SizingConfig::default()
+        );
+    }
+}
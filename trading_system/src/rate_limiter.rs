@@ -0,0 +1,342 @@
+use crate::helpers::epoch_ms;
+use serde_json::Value;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use tokio::time::delay_for;
+
+// request governor shared by every binance REST call.
+// tracks request weight and order counts per window, follows the usage headers binance sends back,
+// and stops all requests while binance has us rate limited(429) or banned(418).
+
+// only this share of each limit is used, leaving room for requests we can't see(other processes on the same ip)
+const LIMIT_HEADROOM: f64 = 0.9;
+// used when a 429/418 comes without a Retry-After header
+const DEFAULT_RETRY_AFTER_SECS: u64 = 60;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LimitKind {
+    RequestWeight,
+    Orders,
+    RawRequests,
+}
+
+#[derive(Clone, Debug)]
+pub struct RateLimit {
+    pub kind: LimitKind,
+    pub interval_ms: u64,
+    pub limit: u64,
+    window: u64,
+    used: u64,
+}
+
+impl RateLimit {
+    pub fn new(kind: LimitKind, interval_ms: u64, limit: u64) -> Self {
+        RateLimit {
+            kind,
+            interval_ms,
+            limit,
+            window: 0,
+            used: 0,
+        }
+    }
+
+    fn roll(&mut self, now_ms: u64) {
+        // binance windows are aligned to the clock, e.g. the weight resets at the start of every minute
+        let window = now_ms / self.interval_ms;
+        if window != self.window {
+            self.window = window;
+            self.used = 0;
+        }
+    }
+
+    fn budget(&self) -> u64 {
+        ((self.limit as f64 * LIMIT_HEADROOM) as u64).max(1)
+    }
+}
+
+pub struct RateGovernor {
+    limits: Vec<RateLimit>,
+    banned_until_ms: u64,
+}
+
+impl RateGovernor {
+    pub fn new(limits: Vec<RateLimit>) -> Self {
+        RateGovernor {
+            limits,
+            banned_until_ms: 0,
+        }
+    }
+
+    pub fn binance_us_defaults() -> Self {
+        // as published in exchangeInfo. replaced by the real values once exchange_info is fetched.
+        RateGovernor::new(vec![
+            RateLimit::new(LimitKind::RequestWeight, 60 * 1000, 1200),
+            RateLimit::new(LimitKind::Orders, 10 * 1000, 100),
+            RateLimit::new(LimitKind::Orders, 24 * 60 * 60 * 1000, 200000),
+            RateLimit::new(LimitKind::RawRequests, 5 * 60 * 1000, 6100),
+        ])
+    }
+
+    pub fn set_limits(&mut self, rate_limits: &Value) -> bool {
+        /*
+            Takes the limits from exchangeInfo's rateLimits array, e.g.
+            {"rateLimitType": "REQUEST_WEIGHT", "interval": "MINUTE", "intervalNum": 1, "limit": 1200}
+            Returns false(and keeps the current limits) if nothing usable was found.
+        */
+        let mut limits = Vec::new();
+        for rate_limit in rate_limits.as_array().map(|x| x.as_slice()).unwrap_or(&[]) {
+            let kind = match rate_limit["rateLimitType"].as_str() {
+                Some("REQUEST_WEIGHT") => LimitKind::RequestWeight,
+                Some("ORDERS") => LimitKind::Orders,
+                Some("RAW_REQUESTS") => LimitKind::RawRequests,
+                _ => continue,
+            };
+            let unit_ms = match rate_limit["interval"].as_str() {
+                Some("SECOND") => 1000,
+                Some("MINUTE") => 60 * 1000,
+                Some("HOUR") => 60 * 60 * 1000,
+                Some("DAY") => 24 * 60 * 60 * 1000,
+                _ => continue,
+            };
+            let interval_num = rate_limit["intervalNum"].as_u64().unwrap_or(1);
+            if let Some(limit) = rate_limit["limit"].as_u64() {
+                limits.push(RateLimit::new(kind, unit_ms * interval_num, limit));
+            }
+        }
+        if limits.is_empty() {
+            return false;
+        }
+        self.limits = limits;
+        true
+    }
+
+    pub fn try_acquire(&mut self, now_ms: u64, weight: u64, is_order: bool) -> Result<(), u64> {
+        /*
+            Reserves weight(and an order slot for orders) if every limit allows it.
+            Otherwise nothing is reserved and the number of ms to wait before trying again is returned.
+        */
+        if now_ms < self.banned_until_ms {
+            return Err(self.banned_until_ms - now_ms);
+        }
+        let mut wait_ms = 0;
+        for limit in self.limits.iter_mut() {
+            limit.roll(now_ms);
+            let cost = match limit.kind {
+                LimitKind::RequestWeight => weight,
+                LimitKind::Orders if is_order => 1,
+                LimitKind::Orders => 0,
+                LimitKind::RawRequests => 1,
+            };
+            if cost > 0 && limit.used + cost > limit.budget() {
+                let window_end = (limit.window + 1) * limit.interval_ms;
+                wait_ms = wait_ms.max(window_end - now_ms);
+            }
+        }
+        if wait_ms > 0 {
+            return Err(wait_ms);
+        }
+        for limit in self.limits.iter_mut() {
+            limit.used += match limit.kind {
+                LimitKind::RequestWeight => weight,
+                LimitKind::Orders if is_order => 1,
+                LimitKind::Orders => 0,
+                LimitKind::RawRequests => 1,
+            };
+        }
+        Ok(())
+    }
+
+    pub fn record_usage(&mut self, now_ms: u64, header_name: &str, header_value: &str) {
+        /*
+            Follows binance's own count from headers like X-MBX-USED-WEIGHT-1M or X-MBX-ORDER-COUNT-10S.
+            Our count only ever goes up to match theirs, since other requests may share the ip.
+        */
+        let header_name = header_name.to_lowercase();
+        let (kind, interval) =
+            if let Some(interval) = header_name.strip_prefix("x-mbx-used-weight-") {
+                (LimitKind::RequestWeight, interval)
+            } else if let Some(interval) = header_name.strip_prefix("x-mbx-order-count-") {
+                (LimitKind::Orders, interval)
+            } else {
+                return;
+            };
+        let interval_ms = match parse_interval_ms(interval) {
+            Some(interval_ms) => interval_ms,
+            None => return,
+        };
+        let used: u64 = match header_value.trim().parse() {
+            Ok(used) => used,
+            Err(_) => return,
+        };
+        for limit in self.limits.iter_mut() {
+            if limit.kind == kind && limit.interval_ms == interval_ms {
+                limit.roll(now_ms);
+                limit.used = limit.used.max(used);
+            }
+        }
+    }
+
+    pub fn record_backoff(&mut self, now_ms: u64, retry_after_secs: u64) {
+        // every request waits until the ban is over
+        self.banned_until_ms = self.banned_until_ms.max(now_ms + retry_after_secs * 1000);
+    }
+}
+
+fn parse_interval_ms(interval: &str) -> Option<u64> {
+    // "1m" -> 60000, "10s" -> 10000, "1d" -> 86400000
+    let unit_start = interval.find(|c: char| !c.is_ascii_digit())?;
+    let number: u64 = interval[..unit_start].parse().ok()?;
+    let unit_ms = match &interval[unit_start..] {
+        "s" => 1000,
+        "m" => 60 * 1000,
+        "h" => 60 * 60 * 1000,
+        "d" => 24 * 60 * 60 * 1000,
+        _ => return None,
+    };
+    Some(number * unit_ms)
+}
+
+pub fn endpoint_weight(endpoint: &str, url: &str) -> u64 {
+    // request weights from the binance api docs. calls without a symbol cost more where noted.
+    let has_symbol = url.contains("symbol=");
+    match endpoint {
+        "/api/v3/account" => 10,
+        "/api/v3/myTrades" => 10,
+        "/api/v3/allOrders" => 10,
+        "/api/v3/exchangeInfo" => 10,
+        "/api/v3/ticker/price" if has_symbol => 1,
+        "/api/v3/ticker/price" => 2,
+        "/api/v3/ticker/24hr" if has_symbol => 1,
+        "/api/v3/ticker/24hr" => 40,
+        "/api/v3/openOrders" if has_symbol => 3,
+        "/api/v3/openOrders" => 40,
+        "/api/v3/depth" => match url
+            .split("limit=")
+            .nth(1)
+            .and_then(|x| x.split('&').next()?.parse::<u64>().ok())
+        {
+            Some(limit) if limit > 1000 => 50,
+            Some(limit) if limit > 500 => 10,
+            Some(limit) if limit > 100 => 5,
+            _ => 1,
+        },
+        _ => 1,
+    }
+}
+
+static GOVERNOR: OnceLock<Mutex<RateGovernor>> = OnceLock::new();
+
+pub fn global() -> &'static Mutex<RateGovernor> {
+    GOVERNOR.get_or_init(|| Mutex::new(RateGovernor::binance_us_defaults()))
+}
+
+pub async fn acquire(endpoint: &str, weight: u64, is_order: bool) {
+    /*
+        Waits until the request fits under every limit, then reserves it.
+    */
+    loop {
+        let acquire_status = global()
+            .lock()
+            .unwrap()
+            .try_acquire(epoch_ms(), weight, is_order);
+        match acquire_status {
+            Ok(()) => return,
+            Err(wait_ms) => {
+                log_info!(
+                    "delaying request to {} by {}ms to stay under the rate limits",
+                    endpoint,
+                    wait_ms
+                );
+                delay_for(Duration::from_millis(wait_ms)).await;
+            }
+        }
+    }
+}
+
+pub fn record_response(endpoint: &str, status: u16, headers: &reqwest::header::HeaderMap) {
+    let now_ms = epoch_ms();
+    let mut governor = global().lock().unwrap();
+    for (name, value) in headers.iter() {
+        if let Ok(value) = value.to_str() {
+            governor.record_usage(now_ms, name.as_str(), value);
+        }
+    }
+    if status == 429 || status == 418 {
+        let retry_after_secs = headers
+            .get("Retry-After")
+            .and_then(|x| x.to_str().ok())
+            .and_then(|x| x.trim().parse().ok())
+            .unwrap_or(DEFAULT_RETRY_AFTER_SECS);
+        governor.record_backoff(now_ms, retry_after_secs);
+        if status == 418 {
+            log_error!(
+                "ip banned by binance on {}. all requests are paused for {}s.",
+                endpoint,
+                retry_after_secs
+            );
+        } else {
+            log_warn!(
+                "rate limited by binance on {}. all requests are paused for {}s.",
+                endpoint,
+                retry_after_secs
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn delays_until_the_next_window() {
+        let mut governor =
+            RateGovernor::new(vec![RateLimit::new(LimitKind::RequestWeight, 60000, 20)]);
+        // budget is 18 with the headroom
+        assert_eq!(governor.try_acquire(60000, 10, false), Ok(()));
+        assert_eq!(governor.try_acquire(70000, 8, false), Ok(()));
+        assert_eq!(governor.try_acquire(90000, 1, false), Err(30000));
+        assert_eq!(governor.try_acquire(120000, 10, false), Ok(()));
+    }
+
+    #[test]
+    fn order_limits_only_apply_to_orders() {
+        let mut governor = RateGovernor::new(vec![RateLimit::new(LimitKind::Orders, 10000, 1)]);
+        assert_eq!(governor.try_acquire(0, 1, true), Ok(()));
+        assert_eq!(governor.try_acquire(1000, 1, true), Err(9000));
+        assert_eq!(governor.try_acquire(1000, 10, false), Ok(()));
+    }
+
+    #[test]
+    fn follows_usage_headers() {
+        let mut governor = RateGovernor::binance_us_defaults();
+        governor.record_usage(1000, "X-MBX-USED-WEIGHT-1M", "1075");
+        assert_eq!(governor.try_acquire(2000, 5, false), Ok(()));
+        assert_eq!(governor.try_acquire(3000, 5, false), Err(57000));
+        // a lower count from the server doesn't undo our own
+        governor.record_usage(4000, "x-mbx-used-weight-1m", "3");
+        assert!(governor.try_acquire(5000, 5, false).is_err());
+    }
+
+    #[test]
+    fn backs_off_after_ban() {
+        let mut governor = RateGovernor::binance_us_defaults();
+        governor.record_backoff(1000, 120);
+        assert_eq!(governor.try_acquire(61000, 1, false), Err(60000));
+        assert_eq!(governor.try_acquire(121000, 1, false), Ok(()));
+    }
+
+    #[test]
+    fn reads_exchange_info_limits() {
+        let mut governor = RateGovernor::binance_us_defaults();
+        let rate_limits = json!([
+            {"rateLimitType": "REQUEST_WEIGHT", "interval": "MINUTE", "intervalNum": 1, "limit": 10},
+            {"rateLimitType": "ORDERS", "interval": "SECOND", "intervalNum": 10, "limit": 50},
+        ]);
+        assert!(governor.set_limits(&rate_limits));
+        assert_eq!(governor.try_acquire(0, 9, false), Ok(()));
+        assert!(governor.try_acquire(0, 1, false).is_err());
+        assert!(!governor.set_limits(&json!([])));
+    }
+}
@@ -0,0 +1,625 @@
+use crate::binance_interface;
+use crate::credentials::Credentials;
+use crate::market::{Pair, PriceGraph};
+use crate::notifier::Notification;
+use crate::persistence::PersistedState;
+use crossbeam_channel::Sender;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+
+// how far back to look for fills when the persisted state has no timestamp
+const DEFAULT_LOOKBACK_MS: u64 = 24 * 60 * 60 * 1000;
+// myTrades and allOrders take at most 24h between startTime and endTime
+const HISTORY_WINDOW_MS: u64 = 24 * 60 * 60 * 1000;
+// and return at most this many at a time
+const HISTORY_LIMIT: usize = 1000;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DiscrepancyKind {
+    // the account holds a coin that no algorithm claims
+    UnexpectedBalance,
+    // an algorithm claims a coin that the account no longer holds
+    MissingPosition,
+    // an order was filled after the state was persisted. the algorithm it moved is in the detail.
+    FilledWhileDown,
+    // an order is still open on the exchange
+    OpenOrder,
+}
+
+#[derive(Clone, Debug)]
+pub struct Discrepancy {
+    pub kind: DiscrepancyKind,
+    pub symbol: String,
+    pub detail: String,
+    pub acknowledged: bool,
+}
+
+impl fmt::Display for Discrepancy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ack_str = if self.acknowledged {
+            " (acknowledged)"
+        } else {
+            ""
+        };
+        write!(
+            f,
+            "{:?} on {}: {}{}",
+            self.kind, self.symbol, self.detail, ack_str
+        )
+    }
+}
+
+// what the exchange says about the account, indexed the same way as the action thread's vectors
+pub struct ExchangeState {
+    // free + locked, per symbols_interest
+    pub balances: Vec<f64>,
+    // last price of every pair on the exchange
+    pub prices: PriceGraph,
+    // raw myTrades/allOrders responses, per ticker
+    pub trades: Vec<Vec<Value>>,
+    pub orders: Vec<Vec<Value>>,
+    // epoch ms the state was fetched at
+    pub fetched_at: u64,
+}
+
+pub struct ReconciliationReport {
+    pub algo_status: Vec<i32>,
+    pub discrepancies: Vec<Discrepancy>,
+    // epoch ms of the exchange state it was reconciled with, where the next reconciliation picks up
+    pub reconciled_at: u64,
+}
+
+impl ReconciliationReport {
+    pub fn unresolved(&self) -> usize {
+        self.discrepancies
+            .iter()
+            .filter(|d| !d.acknowledged)
+            .count()
+    }
+
+    pub fn acknowledge(&mut self, index: usize) -> bool {
+        if index >= self.discrepancies.len() {
+            return false;
+        }
+        self.discrepancies[index].acknowledged = true;
+        true
+    }
+
+    pub fn acknowledge_all(&mut self) {
+        for discrepancy in self.discrepancies.iter_mut() {
+            discrepancy.acknowledged = true;
+        }
+    }
+}
+
+pub fn fetch_exchange_state(
+    credentials: &Credentials,
+    symbols_interest: &[String],
+    ticker_list: &[String],
+    all_pairs: &HashMap<String, Pair>,
+    since: u64,
+    time_now: u64,
+) -> ExchangeState {
+    /*
+        Pulls balances, prices and recent trade/order history for every ticker.
+        since is the epoch ms from which fills are fetched. 0 means the last 24h.
+    */
+    let start_time = if since == 0 {
+        time_now - DEFAULT_LOOKBACK_MS
+    } else {
+        since
+    };
+
+    let mut balances = vec![0.0; symbols_interest.len()];
+    let account_info = binance_interface::binance_account_api(credentials, "get_accountinfo", "");
+    if let Some(account_balances) = account_info["balances"].as_array() {
+        for balance in account_balances {
+            let asset = balance["asset"].as_str().unwrap_or("");
+            if let Some(k) = symbols_interest.iter().position(|x| x == asset) {
+                let free: f64 = balance["free"]
+                    .as_str()
+                    .unwrap_or("0")
+                    .parse()
+                    .unwrap_or(0.0);
+                let locked: f64 = balance["locked"]
+                    .as_str()
+                    .unwrap_or("0")
+                    .parse()
+                    .unwrap_or(0.0);
+                balances[k] = free + locked;
+            }
+        }
+    }
+
+    // every price at once, so assets can be valued through pairs the account doesn't trade
+    let prices = PriceGraph::from_ticker_prices(
+        &binance_interface::binance_rest_api("ticker_price", ""),
+        all_pairs,
+    );
+
+    let mut trades = Vec::new();
+    let mut orders = Vec::new();
+    for ticker in ticker_list.iter() {
+        let mut ticker_trades = Vec::new();
+        let mut ticker_orders = Vec::new();
+        let mut window_start = start_time;
+        while window_start < time_now {
+            let window_end = (window_start + HISTORY_WINDOW_MS - 1).min(time_now);
+            ticker_trades.extend(fetch_window(
+                |arguments| {
+                    binance_interface::binance_account_api(credentials, "my_trades", arguments)
+                },
+                ticker,
+                (window_start, window_end),
+                ("id", "fromId"),
+            ));
+            ticker_orders.extend(fetch_window(
+                |arguments| {
+                    binance_interface::binance_account_api(credentials, "all_orders", arguments)
+                },
+                ticker,
+                (window_start, window_end),
+                ("orderId", "orderId"),
+            ));
+            window_start = window_end + 1;
+        }
+        trades.push(ticker_trades);
+        orders.push(ticker_orders);
+    }
+
+    ExchangeState {
+        balances,
+        prices,
+        trades,
+        orders,
+        fetched_at: time_now,
+    }
+}
+
+fn fetch_window<F>(mut fetch: F, ticker: &str, window: (u64, u64), id: (&str, &str)) -> Vec<Value>
+where
+    F: FnMut(&str) -> Value,
+{
+    /*
+        Everything a history endpoint has for a ticker in the window (start, end), oldest first. A full page may
+        have left some out, so it pages on from the id after the last one, id being the field and the parameter
+        it's asked by, until a page comes back short or goes past the window.
+    */
+    let (id_field, id_param) = id;
+    let mut arguments = format!(
+        "symbol={}&startTime={}&endTime={}&limit={}",
+        ticker, window.0, window.1, HISTORY_LIMIT
+    );
+    let mut all = Vec::new();
+    loop {
+        let page = fetch(&arguments).as_array().cloned().unwrap_or_default();
+        let full = page.len() >= HISTORY_LIMIT;
+        let last_id = page.last().and_then(|x| x[id_field].as_u64());
+        let mut past_window = false;
+        for entry in page {
+            if entry["time"].as_u64().is_some_and(|x| x > window.1) {
+                past_window = true;
+            } else {
+                all.push(entry);
+            }
+        }
+        match last_id {
+            Some(last_id) if full && !past_window => {
+                arguments = format!(
+                    "symbol={}&{}={}&limit={}",
+                    ticker,
+                    id_param,
+                    last_id + 1,
+                    HISTORY_LIMIT
+                );
+            }
+            _ => return all,
+        }
+    }
+}
+
+// an order filled while jane was down, from its trades after the state was persisted
+struct MissedFill {
+    time: u64,
+    ticker_i: usize,
+    order_id: u64,
+    buy: bool,
+    quantity: f64,
+    price: f64,
+    // the order didn't fill completely
+    partial: bool,
+}
+
+fn number(value: &Value) -> f64 {
+    // binance sends quantities and prices as strings
+    match value.as_str() {
+        Some(x) => x.parse().unwrap_or(0.0),
+        None => value.as_f64().unwrap_or(0.0),
+    }
+}
+
+fn missed_fills(
+    persisted: &PersistedState,
+    exchange: &ExchangeState,
+    pairs: &[Pair],
+) -> Vec<MissedFill> {
+    /*
+        The trades after persisted.saved_at, added up by order, oldest order first.
+        An order counts as partial unless allOrders says it's FILLED.
+    */
+    let mut fills: Vec<MissedFill> = Vec::new();
+    for ticker_i in 0..pairs.len() {
+        let first = fills.len();
+        for trade in exchange.trades[ticker_i].iter() {
+            let trade_time = trade["time"].as_u64().unwrap_or(0);
+            if trade_time <= persisted.saved_at {
+                continue;
+            }
+            let order_id = trade["orderId"].as_u64().unwrap_or(0);
+            let (quantity, price) = (number(&trade["qty"]), number(&trade["price"]));
+            match fills[first..].iter_mut().find(|x| x.order_id == order_id) {
+                Some(fill) => {
+                    fill.price = (fill.price * fill.quantity + price * quantity)
+                        / (fill.quantity + quantity);
+                    fill.quantity += quantity;
+                    fill.time = fill.time.min(trade_time);
+                }
+                None => fills.push(MissedFill {
+                    time: trade_time,
+                    ticker_i,
+                    order_id,
+                    buy: trade["isBuyer"].as_bool().unwrap_or(false),
+                    quantity,
+                    price,
+                    partial: false,
+                }),
+            }
+        }
+        for fill in fills[first..].iter_mut() {
+            fill.partial = exchange.orders[ticker_i]
+                .iter()
+                .find(|x| x["orderId"].as_u64() == Some(fill.order_id))
+                .map(|x| x["status"].as_str() != Some("FILLED"))
+                .unwrap_or(false);
+        }
+    }
+    fills.sort_by_key(|x| x.time);
+    fills
+}
+
+pub fn reconcile(
+    persisted: &PersistedState,
+    exchange: &ExchangeState,
+    symbols_interest: &[String],
+    pairs: &[Pair],
+    reporting: &str,
+) -> ReconciliationReport {
+    /*
+        Rebuilds algo_status from the persisted state and the exchange's view of the account.
+        Parameters:
+            persisted:
+                last state written by storevars. algo_status[i] is the index of the asset in symbols_interest, 0 for home.
+            exchange:
+                output of fetch_exchange_state
+            pairs:
+                the account's pairs, in ticker order
+            reporting:
+                currency balances are valued in to tell positions from dust
+        Returns:
+            ReconciliationReport:
+                the reconstructed algo_status plus every discrepancy found along the way.
+        Orders filled since the state was persisted are replayed first, oldest first. A buy moves the first algorithm in
+        the pair's quote asset to its base, a sell the first one in the base to the quote. A partially filled buy still
+        moves it, a partially filled sell doesn't, since the algorithm holds the rest. Balances are checked after.
+    */
+    let mut algo_status = persisted.algo_status.clone();
+    let mut discrepancies = Vec::new();
+
+    for fill in missed_fills(persisted, exchange, pairs) {
+        let pair = &pairs[fill.ticker_i];
+        let index = |asset: &String| {
+            symbols_interest
+                .iter()
+                .position(|x| x == asset)
+                .map(|x| x as i32)
+        };
+        let (from, to) = match (index(&pair.base), index(&pair.quote)) {
+            (Some(base), Some(quote)) if fill.buy => (quote, base),
+            (Some(base), Some(quote)) => (base, quote),
+            _ => continue,
+        };
+        let outcome = match algo_status.iter().position(|x| *x == from) {
+            Some(i) if !fill.buy && fill.partial => {
+                format!(
+                    "partially filled, algorithm {} stays in {}",
+                    i, symbols_interest[from as usize]
+                )
+            }
+            Some(i) => {
+                algo_status[i] = to;
+                format!(
+                    "moved algorithm {} from {} to {}",
+                    i, symbols_interest[from as usize], symbols_interest[to as usize]
+                )
+            }
+            None => format!("no algorithm was in {}", symbols_interest[from as usize]),
+        };
+        discrepancies.push(Discrepancy {
+            kind: DiscrepancyKind::FilledWhileDown,
+            symbol: pair.symbol.clone(),
+            detail: format!(
+                "{} {} @ {} at {} (order {}{}), {}",
+                if fill.buy { "BUY" } else { "SELL" },
+                fill.quantity,
+                fill.price,
+                fill.time,
+                fill.order_id,
+                if fill.partial { ", partial" } else { "" },
+                outcome
+            ),
+            acknowledged: false,
+        });
+    }
+
+    // symbols_interest[0] is the home asset, which is where unclaimed capital is supposed to be
+    for (k, asset) in symbols_interest.iter().enumerate().skip(1) {
+        let balance = exchange.balances[k];
+        let value = match exchange.prices.value(asset, balance, reporting) {
+            Some(value) => value,
+            None => {
+                log_warn!(
+                    "reconciliation: can't value {} in {}, skipping its balance check",
+                    asset,
+                    reporting
+                );
+                continue;
+            }
+        };
+        // anything worth less than the smallest order on any of the asset's pairs is dust
+        let min_value = pairs
+            .iter()
+            .filter(|x| &x.base == asset || &x.quote == asset)
+            .filter_map(|x| exchange.prices.value(&x.quote, x.min_notional?, reporting))
+            .fold(f64::INFINITY, f64::min);
+        let min_value = if min_value.is_finite() {
+            min_value
+        } else {
+            0.0
+        };
+        let holders: Vec<usize> = algo_status
+            .iter()
+            .enumerate()
+            .filter(|(_, status)| **status == k as i32)
+            .map(|(i, _)| i)
+            .collect();
+
+        if value >= min_value && balance > 0.0 && holders.is_empty() {
+            discrepancies.push(Discrepancy {
+                kind: DiscrepancyKind::UnexpectedBalance,
+                symbol: asset.clone(),
+                detail: format!(
+                    "holding {} (~{:.2} {}) but no algorithm is in a play",
+                    balance, value, reporting
+                ),
+                acknowledged: false,
+            });
+        } else if value < min_value && !holders.is_empty() {
+            // nothing left to sell, so the algorithms are effectively back in the home asset
+            for i in holders.iter() {
+                algo_status[*i] = 0;
+            }
+            discrepancies.push(Discrepancy {
+                kind: DiscrepancyKind::MissingPosition,
+                symbol: asset.clone(),
+                detail: format!(
+                    "algorithms {:?} were in a play but the balance is {}. reset them to {}.",
+                    holders, balance, symbols_interest[0]
+                ),
+                acknowledged: false,
+            });
+        }
+    }
+
+    for (ticker_i, pair) in pairs.iter().enumerate() {
+        let ticker = &pair.symbol;
+        for order in exchange.orders[ticker_i].iter() {
+            let status = order["status"].as_str().unwrap_or("");
+            if status == "NEW" || status == "PARTIALLY_FILLED" {
+                discrepancies.push(Discrepancy {
+                    kind: DiscrepancyKind::OpenOrder,
+                    symbol: ticker.clone(),
+                    detail: format!(
+                        "{} {} order {} is {} ({} of {} executed)",
+                        order["side"],
+                        order["type"],
+                        order["orderId"],
+                        status,
+                        order["executedQty"],
+                        order["origQty"]
+                    ),
+                    acknowledged: false,
+                });
+            }
+        }
+    }
+
+    ReconciliationReport {
+        algo_status,
+        discrepancies,
+        reconciled_at: exchange.fetched_at,
+    }
+}
+
+pub fn run_reconciliation(
+    credentials: &Credentials,
+    persisted: &PersistedState,
+    symbols_interest: &[String],
+    pairs: &[Pair],
+    all_pairs: &HashMap<String, Pair>,
+    reporting: &str,
+    time_now: u64,
+    logging_tx: &Sender<Notification>,
+) -> ReconciliationReport {
+    // fetches the exchange state, reconciles it and reports the outcome to the human log
+    log_info!("reconciling persisted state with the exchange...");
+    let ticker_list: Vec<String> = pairs.iter().map(|x| x.symbol.clone()).collect();
+    let exchange = fetch_exchange_state(
+        credentials,
+        symbols_interest,
+        &ticker_list,
+        all_pairs,
+        persisted.saved_at,
+        time_now,
+    );
+    let report = reconcile(persisted, &exchange, symbols_interest, pairs, reporting);
+
+    let _ = logging_tx.send(Notification::info(format!(
+        "reconciliation: persisted algo_status {:?}, reconstructed algo_status {:?}",
+        persisted.algo_status, report.algo_status
+    )));
+    if report.discrepancies.is_empty() {
+        let _ = logging_tx.send(Notification::info(
+            "reconciliation: no discrepancies found.".to_string(),
+        ));
+    } else {
+        let _ = logging_tx.send(Notification::warning(format!(
+            "reconciliation: {} discrepancies found. trading is locked until they are resolved or acknowledged.",
+            report.discrepancies.len()
+        )));
+        for (i, discrepancy) in report.discrepancies.iter().enumerate() {
+            let _ = logging_tx.send(Notification::warning(format!(
+                "discrepancy {}: {}",
+                i, discrepancy
+            )));
+        }
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn pages_through_a_busy_window() {
+        // 2500 fills in the window, one a second, and more after it
+        let fills: Vec<Value> = (0..3000u64)
+            .map(|x| json!({"id": 100 + x, "time": 1000 * x}))
+            .collect();
+        let mut requests = Vec::new();
+        let fetched = fetch_window(
+            |arguments| {
+                requests.push(arguments.to_string());
+                let from = match arguments.split("fromId=").nth(1) {
+                    Some(id) => id.split('&').next().unwrap().parse::<usize>().unwrap() - 100,
+                    None => 0,
+                };
+                json!(fills[from..(from + HISTORY_LIMIT).min(fills.len())].to_vec())
+            },
+            "ETHUSDT",
+            (0, 2_499_000),
+            ("id", "fromId"),
+        );
+        assert_eq!(fetched.len(), 2500);
+        assert_eq!(fetched[2499]["id"], json!(2599));
+        assert_eq!(
+            requests,
+            vec![
+                "symbol=ETHUSDT&startTime=0&endTime=2499000&limit=1000",
+                "symbol=ETHUSDT&fromId=1100&limit=1000",
+                "symbol=ETHUSDT&fromId=2100&limit=1000",
+            ]
+        );
+    }
+
+    fn pair() -> Pair {
+        Pair {
+            symbol: "ETHUSDT".to_string(),
+            base: "ETH".to_string(),
+            quote: "USDT".to_string(),
+            status: "TRADING".to_string(),
+            order_types: vec!["MARKET".to_string()],
+            step_size: Some(0.001),
+            min_notional: Some(10.0),
+            tick_size: Some(0.01),
+        }
+    }
+
+    fn exchange(balances: Vec<f64>, trades: Vec<Value>, orders: Vec<Value>) -> ExchangeState {
+        let mut prices = PriceGraph::new();
+        prices.set_price("ETH", "USDT", 2000.0);
+        ExchangeState {
+            balances,
+            prices,
+            trades: vec![trades],
+            orders: vec![orders],
+            fetched_at: 3000,
+        }
+    }
+
+    fn reconciled(algo_status: Vec<i32>, exchange: &ExchangeState) -> ReconciliationReport {
+        let persisted = PersistedState {
+            algo_status,
+            saved_at: 1000,
+            assets: Vec::new(),
+        };
+        let assets = vec!["USDT".to_string(), "ETH".to_string()];
+        reconcile(&persisted, exchange, &assets, &[pair()], "USDT")
+    }
+
+    #[test]
+    fn replays_fills_missed_while_down() {
+        // a buy filled in two trades after the state was saved, and one from before it
+        let trades = vec![
+            json!({"orderId": 7, "time": 900, "isBuyer": false, "qty": "0.5", "price": "1900"}),
+            json!({"orderId": 8, "time": 1500, "isBuyer": true, "qty": "0.2", "price": "1990"}),
+            json!({"orderId": 8, "time": 1600, "isBuyer": true, "qty": "0.3", "price": "2000"}),
+        ];
+        let orders = vec![json!({"orderId": 8, "status": "FILLED", "side": "BUY"})];
+        let report = reconciled(vec![1, 0], &exchange(vec![10.0, 0.5], trades, orders));
+        assert_eq!(report.algo_status, vec![1, 1]);
+        assert_eq!(report.discrepancies.len(), 1);
+        assert_eq!(
+            report.discrepancies[0].kind,
+            DiscrepancyKind::FilledWhileDown
+        );
+        assert!(report.discrepancies[0]
+            .detail
+            .contains("moved algorithm 1 from USDT to ETH"));
+        assert_eq!(report.reconciled_at, 3000);
+    }
+
+    #[test]
+    fn keeps_partially_sold_algorithms_in_the_base() {
+        let trades = vec![
+            json!({"orderId": 9, "time": 2000, "isBuyer": false, "qty": "0.2", "price": "2000"}),
+        ];
+        let orders = vec![json!({"orderId": 9, "status": "CANCELED", "side": "SELL"})];
+        let report = reconciled(vec![1], &exchange(vec![400.0, 0.3], trades, orders));
+        assert_eq!(report.algo_status, vec![1]);
+        assert_eq!(report.discrepancies.len(), 1);
+        assert!(report.discrepancies[0].detail.contains("partial"));
+
+        // a partial buy still moves the algorithm
+        let trades = vec![
+            json!({"orderId": 10, "time": 2000, "isBuyer": true, "qty": "0.2", "price": "2000"}),
+        ];
+        let orders = vec![json!({"orderId": 10, "status": "EXPIRED", "side": "BUY"})];
+        let report = reconciled(vec![0], &exchange(vec![600.0, 0.2], trades, orders));
+        assert_eq!(report.algo_status, vec![1]);
+    }
+
+    #[test]
+    fn leaves_a_matching_state_alone() {
+        let report = reconciled(
+            vec![1, 0],
+            &exchange(vec![1000.0, 0.5], Vec::new(), Vec::new()),
+        );
+        assert_eq!(report.algo_status, vec![1, 0]);
+        assert!(report.discrepancies.is_empty());
+        assert_eq!(report.unresolved(), 0);
+    }
+}
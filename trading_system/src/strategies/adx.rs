@@ -1,28 +1,43 @@
 use super::TradingStrategy;
 
 use crate::strategies::indicators::adx_indicator::AverageDirectionalMovementIndex;
-use crate::strategies::indicators::NextI;
+use crate::strategies::indicators::{NextI, Ohlcv, WarmUp};
 
 pub struct ADXTest {
     strategy_settings: Vec<f64>,
     adx_indicator: AverageDirectionalMovementIndex,
 }
 
-impl TradingStrategy for ADXTest {
-    fn new(strategy_settings: Vec<f64>) -> Self {
+impl ADXTest {
+    pub fn new(strategy_settings: Vec<f64>) -> Self {
         ADXTest {
             strategy_settings: strategy_settings.clone(),
-            adx_indicator: AverageDirectionalMovementIndex::new(
+            adx_indicator: AverageDirectionalMovementIndex::with_smoothing(
                 strategy_settings[0] as u64,
                 strategy_settings[1] as u64,
             ),
         }
     }
+}
+
+impl TradingStrategy for ADXTest {
     fn run(&mut self, ohlc: &Vec<f64>) -> i8 {
-        let curr_adx = self.adx_indicator.next(ohlc[4]);
-        // println!("{}", curr_adx);
+        // settings: [di period, adx period, adx threshold]
+        let curr_adx = self.adx_indicator.next(Ohlcv::from_slice(ohlc)).adx;
+
+        return if curr_adx > self.strategy_settings[2] {
+            1
+        } else {
+            0
+        };
+    }
+
+    fn warm_up(&self) -> u64 {
+        self.adx_indicator.warm_up()
+    }
 
-        return if curr_adx > 25f64 { 1 } else { 0 };
+    fn is_ready(&self) -> bool {
+        self.adx_indicator.is_ready()
     }
 
     fn to_string(&self) -> String {
@@ -0,0 +1,358 @@
+use super::TradingStrategy;
+
+// strategies built from other strategies, configured in the accounts file, see _Strategies_ in docs/accounts.txt.
+// every child runs on every bar, so it stays warm whatever the combination does with its signal.
+
+pub type Child = Box<dyn TradingStrategy>;
+
+fn run_all(children: &mut [Child], ohlc: &Vec<f64>) -> Vec<i8> {
+    children.iter_mut().map(|x| x.run(ohlc)).collect()
+}
+
+fn warm_up(children: &[&Child]) -> u64 {
+    children.iter().map(|x| x.warm_up()).max().unwrap_or(0)
+}
+
+fn is_ready(children: &[&Child]) -> bool {
+    children.iter().all(|x| x.is_ready())
+}
+
+fn names(children: &[&Child]) -> String {
+    children
+        .iter()
+        .map(|x| x.to_string())
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+// 1 or -1 when every child says so, 0 otherwise
+pub struct AllAgree {
+    children: Vec<Child>,
+}
+
+impl AllAgree {
+    pub fn new(children: Vec<Child>) -> Self {
+        AllAgree { children }
+    }
+}
+
+impl TradingStrategy for AllAgree {
+    fn run(&mut self, ohlc: &Vec<f64>) -> i8 {
+        let signals = run_all(&mut self.children, ohlc);
+        match signals.first() {
+            Some(first) if signals.iter().all(|x| x == first) => *first,
+            _ => 0,
+        }
+    }
+
+    fn warm_up(&self) -> u64 {
+        warm_up(&self.children.iter().collect::<Vec<&Child>>())
+    }
+
+    fn is_ready(&self) -> bool {
+        is_ready(&self.children.iter().collect::<Vec<&Child>>())
+    }
+
+    fn to_string(&self) -> String {
+        format!(
+            "all({})",
+            names(&self.children.iter().collect::<Vec<&Child>>())
+        )
+    }
+}
+
+// the side more than half of the children take, 0 without one
+pub struct MajorityVote {
+    children: Vec<Child>,
+}
+
+impl MajorityVote {
+    pub fn new(children: Vec<Child>) -> Self {
+        MajorityVote { children }
+    }
+}
+
+impl TradingStrategy for MajorityVote {
+    fn run(&mut self, ohlc: &Vec<f64>) -> i8 {
+        let signals = run_all(&mut self.children, ohlc);
+        let count = |side: i8| signals.iter().filter(|x| **x == side).count();
+        if 2 * count(1) > signals.len() {
+            1
+        } else if 2 * count(-1) > signals.len() {
+            -1
+        } else {
+            0
+        }
+    }
+
+    fn warm_up(&self) -> u64 {
+        warm_up(&self.children.iter().collect::<Vec<&Child>>())
+    }
+
+    fn is_ready(&self) -> bool {
+        is_ready(&self.children.iter().collect::<Vec<&Child>>())
+    }
+
+    fn to_string(&self) -> String {
+        format!(
+            "vote({})",
+            names(&self.children.iter().collect::<Vec<&Child>>())
+        )
+    }
+}
+
+// the children's signals weighted and divided by the total weight, so the score is -1 to 1.
+// 1 at or above threshold, -1 at or below -threshold.
+pub struct WeightedScore {
+    children: Vec<Child>,
+    weights: Vec<f64>,
+    threshold: f64,
+}
+
+impl WeightedScore {
+    pub fn new(children: Vec<Child>, weights: Vec<f64>, threshold: f64) -> Self {
+        WeightedScore {
+            children,
+            weights,
+            threshold,
+        }
+    }
+}
+
+impl TradingStrategy for WeightedScore {
+    fn run(&mut self, ohlc: &Vec<f64>) -> i8 {
+        let signals = run_all(&mut self.children, ohlc);
+        let total: f64 = self.weights.iter().map(|x| x.abs()).sum();
+        let score: f64 = signals
+            .iter()
+            .zip(self.weights.iter())
+            .map(|(signal, weight)| *signal as f64 * weight)
+            .sum::<f64>()
+            / total;
+        if score >= self.threshold {
+            1
+        } else if score <= -self.threshold {
+            -1
+        } else {
+            0
+        }
+    }
+
+    fn warm_up(&self) -> u64 {
+        warm_up(&self.children.iter().collect::<Vec<&Child>>())
+    }
+
+    fn is_ready(&self) -> bool {
+        is_ready(&self.children.iter().collect::<Vec<&Child>>())
+    }
+
+    fn to_string(&self) -> String {
+        format!(
+            "weighted({}; {:?} >= {})",
+            names(&self.children.iter().collect::<Vec<&Child>>()),
+            self.weights,
+            self.threshold
+        )
+    }
+}
+
+// buys when entry says 1 and sells when exit says -1. the exit wins when both do.
+pub struct EntryExit {
+    entry: Child,
+    exit: Child,
+}
+
+impl EntryExit {
+    pub fn new(entry: Child, exit: Child) -> Self {
+        EntryExit { entry, exit }
+    }
+}
+
+impl TradingStrategy for EntryExit {
+    fn run(&mut self, ohlc: &Vec<f64>) -> i8 {
+        let entry = self.entry.run(ohlc);
+        let exit = self.exit.run(ohlc);
+        if exit == -1 {
+            -1
+        } else if entry == 1 {
+            1
+        } else {
+            0
+        }
+    }
+
+    fn warm_up(&self) -> u64 {
+        warm_up(&[&self.entry, &self.exit])
+    }
+
+    fn is_ready(&self) -> bool {
+        is_ready(&[&self.entry, &self.exit])
+    }
+
+    fn to_string(&self) -> String {
+        format!(
+            "entry({}) exit({})",
+            self.entry.to_string(),
+            self.exit.to_string()
+        )
+    }
+}
+
+// buys only while the regime strategy says 1. sells always go through.
+pub struct RegimeFilter {
+    strategy: Child,
+    regime: Child,
+}
+
+impl RegimeFilter {
+    pub fn new(strategy: Child, regime: Child) -> Self {
+        RegimeFilter { strategy, regime }
+    }
+}
+
+impl TradingStrategy for RegimeFilter {
+    fn run(&mut self, ohlc: &Vec<f64>) -> i8 {
+        let signal = self.strategy.run(ohlc);
+        let regime = self.regime.run(ohlc);
+        if signal == 1 && regime != 1 {
+            0
+        } else {
+            signal
+        }
+    }
+
+    fn warm_up(&self) -> u64 {
+        warm_up(&[&self.strategy, &self.regime])
+    }
+
+    fn is_ready(&self) -> bool {
+        is_ready(&[&self.strategy, &self.regime])
+    }
+
+    fn to_string(&self) -> String {
+        format!(
+            "{} in regime({})",
+            self.strategy.to_string(),
+            self.regime.to_string()
+        )
+    }
+}
+
+// after the strategy turns to a side, the other side is held back for bars bars
+pub struct Cooldown {
+    strategy: Child,
+    bars: u64,
+    // the side it last turned to, and bars since
+    last: i8,
+    since: u64,
+}
+
+impl Cooldown {
+    pub fn new(strategy: Child, bars: u64) -> Self {
+        Cooldown {
+            strategy,
+            bars,
+            last: 0,
+            since: 0,
+        }
+    }
+}
+
+impl TradingStrategy for Cooldown {
+    fn run(&mut self, ohlc: &Vec<f64>) -> i8 {
+        let signal = self.strategy.run(ohlc);
+        // signals from before the strategy is warm don't start a cooldown
+        if !self.strategy.is_ready() {
+            return signal;
+        }
+        self.since = self.since.saturating_add(1);
+        if signal == 0 || signal == self.last {
+            return signal;
+        }
+        if self.last != 0 && self.since <= self.bars {
+            return 0;
+        }
+        self.last = signal;
+        self.since = 0;
+        signal
+    }
+
+    fn warm_up(&self) -> u64 {
+        self.strategy.warm_up()
+    }
+
+    fn is_ready(&self) -> bool {
+        self.strategy.is_ready()
+    }
+
+    fn to_string(&self) -> String {
+        format!("{} cooldown {}", self.strategy.to_string(), self.bars)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // plays back its signals, ready from the first bar
+    struct Scripted(Vec<i8>, usize);
+
+    impl TradingStrategy for Scripted {
+        fn run(&mut self, _ohlc: &Vec<f64>) -> i8 {
+            self.1 += 1;
+            self.0[(self.1 - 1) % self.0.len()]
+        }
+
+        fn warm_up(&self) -> u64 {
+            self.0.len() as u64
+        }
+
+        fn is_ready(&self) -> bool {
+            true
+        }
+
+        fn to_string(&self) -> String {
+            format!("{:?}", self.0)
+        }
+    }
+
+    fn child(signals: &[i8]) -> Child {
+        Box::new(Scripted(signals.to_vec(), 0))
+    }
+
+    fn play(strategy: &mut dyn TradingStrategy, bars: usize) -> Vec<i8> {
+        let bar = vec![1.0, 1.0, 1.0, 1.0, 1.0];
+        (0..bars).map(|_| strategy.run(&bar)).collect()
+    }
+
+    #[test]
+    fn combines_signals() {
+        let a = [1, 1, -1, 0, -1];
+        let b = [1, -1, -1, 1, 0];
+        let c = [1, 1, 0, 0, -1];
+
+        let mut all = AllAgree::new(vec![child(&a), child(&b)]);
+        assert_eq!(play(&mut all, 5), vec![1, 0, -1, 0, 0]);
+        assert_eq!(all.warm_up(), 5);
+
+        let mut vote = MajorityVote::new(vec![child(&a), child(&b), child(&c)]);
+        assert_eq!(play(&mut vote, 5), vec![1, 1, -1, 0, -1]);
+
+        let mut weighted = WeightedScore::new(vec![child(&a), child(&b)], vec![3.0, 1.0], 0.5);
+        assert_eq!(play(&mut weighted, 5), vec![1, 1, -1, 0, -1]);
+
+        let mut entry_exit = EntryExit::new(child(&a), child(&b));
+        assert_eq!(play(&mut entry_exit, 5), vec![1, -1, -1, 0, 0]);
+
+        let mut filtered = RegimeFilter::new(child(&a), child(&b));
+        assert_eq!(play(&mut filtered, 5), vec![1, 0, -1, 0, -1]);
+    }
+
+    #[test]
+    fn cooldown_holds_back_the_other_side() {
+        let mut cooldown = Cooldown::new(child(&[1, -1, -1, 1, 1, -1, 0, 0, 0, 0]), 2);
+        assert_eq!(play(&mut cooldown, 10), vec![1, 0, 0, 1, 1, -1, 0, 0, 0, 0]);
+        let mut cooldown = Cooldown::new(child(&[1, 0, 0, -1, 1, 0, 0, 1]), 2);
+        assert_eq!(play(&mut cooldown, 8), vec![1, 0, 0, -1, 0, 0, 0, 1]);
+    }
+}
@@ -4,34 +4,42 @@ use ta::Next;
 
 use super::TradingStrategy;
 use crate::strategies::indicators::adx_indicator::AverageDirectionalMovementIndex;
-use crate::strategies::indicators::NextI;
+use crate::strategies::indicators::{NextI, Ohlcv, WarmUp};
 
 pub struct EMASMAADX {
     strategy_settings: Vec<f64>,
     short_ema: ExponentialMovingAverage,
     long_sma: SimpleMovingAverage,
     adx_indicator: AverageDirectionalMovementIndex,
+    bars: u64,
 }
 
-impl TradingStrategy for EMASMAADX {
-    fn new(strategy_settings: Vec<f64>) -> Self {
+impl EMASMAADX {
+    pub fn new(strategy_settings: Vec<f64>) -> Self {
         EMASMAADX {
             strategy_settings: strategy_settings.clone(),
             short_ema: ExponentialMovingAverage::new(strategy_settings[0] as u32).unwrap(),
             long_sma: SimpleMovingAverage::new(strategy_settings[1] as u32).unwrap(),
-            adx_indicator: AverageDirectionalMovementIndex::new(
+            adx_indicator: AverageDirectionalMovementIndex::with_smoothing(
                 strategy_settings[2] as u64,
                 strategy_settings[3] as u64,
             ),
+            bars: 0,
         }
     }
+}
+
+impl TradingStrategy for EMASMAADX {
     fn run(&mut self, ohlc: &Vec<f64>) -> i8 {
-        let curr_short_ema = self.short_ema.next(ohlc[4]);
-        let curr_long_sma = self.long_sma.next(ohlc[4]);
-        let curr_adx = self.adx_indicator.next(ohlc[4]);
+        // settings: [ema period, sma period, di period, adx period, adx threshold]
+        let bar = Ohlcv::from_slice(ohlc);
+        self.bars += 1;
+        let curr_short_ema = self.short_ema.next(bar.close);
+        let curr_long_sma = self.long_sma.next(bar.close);
+        let curr_adx = self.adx_indicator.next(bar).adx;
 
         let mut current_signal = 0;
-        if curr_short_ema > curr_long_sma && curr_adx > 25f64 {
+        if curr_short_ema > curr_long_sma && curr_adx > self.strategy_settings[4] {
             current_signal = 1;
         } else if curr_short_ema < curr_long_sma {
             current_signal = -1;
@@ -40,6 +48,15 @@ impl TradingStrategy for EMASMAADX {
         current_signal
     }
 
+    fn warm_up(&self) -> u64 {
+        (self.strategy_settings[0].max(self.strategy_settings[1]) as u64)
+            .max(self.adx_indicator.warm_up())
+    }
+
+    fn is_ready(&self) -> bool {
+        self.bars >= self.warm_up()
+    }
+
     fn to_string(&self) -> String {
         return format!("{:?}", self.strategy_settings);
     }
@@ -1,4 +1,5 @@
 use super::TradingStrategy;
+use crate::strategies::indicators::Ohlcv;
 
 use ta::indicators::ExponentialMovingAverage;
 use ta::indicators::SimpleMovingAverage;
@@ -9,22 +10,28 @@ pub struct EMASMACrossover {
     strategy_settings: Vec<f64>,
     short_ema: ExponentialMovingAverage,
     long_sma: SimpleMovingAverage,
+    bars: u64,
 }
 
-impl TradingStrategy for EMASMACrossover {
-    fn new(strategy_settings: Vec<f64>) -> Self {
+impl EMASMACrossover {
+    pub fn new(strategy_settings: Vec<f64>) -> Self {
         let short_ema_lookback = strategy_settings[0] as u32;
         let long_sma_lookback = strategy_settings[1] as u32;
         EMASMACrossover {
             strategy_settings,
             short_ema: ExponentialMovingAverage::new(short_ema_lookback).unwrap(),
             long_sma: SimpleMovingAverage::new(long_sma_lookback).unwrap(),
+            bars: 0,
         }
     }
+}
 
+impl TradingStrategy for EMASMACrossover {
     fn run(&mut self, ohlc: &Vec<f64>) -> i8 {
-        let curr_short_ema = self.short_ema.next(ohlc[4]);
-        let curr_long_sma = self.long_sma.next(ohlc[4]);
+        let close = Ohlcv::from_slice(ohlc).close;
+        self.bars += 1;
+        let curr_short_ema = self.short_ema.next(close);
+        let curr_long_sma = self.long_sma.next(close);
 
         let mut current_signal = 0;
         if curr_short_ema > curr_long_sma {
@@ -36,6 +43,14 @@ impl TradingStrategy for EMASMACrossover {
         current_signal
     }
 
+    fn warm_up(&self) -> u64 {
+        self.strategy_settings[0].max(self.strategy_settings[1]) as u64
+    }
+
+    fn is_ready(&self) -> bool {
+        self.bars >= self.warm_up()
+    }
+
     fn to_string(&self) -> String {
         return format!("{:?}", self.strategy_settings);
     }
@@ -1,63 +1,256 @@
-use ta::indicators::ExponentialMovingAverage;
-use ta::Next;
+use super::atr::TrueRange;
+use super::moving_average::ExponentialMovingAverage;
+use super::{NextI, Ohlcv, WarmUp};
 
-use super::max_in_range::MaxmimumInRange;
-use super::min_in_range::MinimumInRange;
-use super::NextI;
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DirectionalMovementOutput {
+    pub plus_di: f64,
+    pub minus_di: f64,
+    pub adx: f64,
+}
 
+// wilder's directional movement: +di and -di are the smoothed up and down moves as a percentage of the smoothed
+// true range, adx is the smoothed dx = |+di - -di| / (+di + -di). both 0 to 100.
+// the di lines use what they have from the second bar on. adx is 0 until di_period bars have moved, then the running
+// mean of dx until it has adx_period of them, then wilder smoothed.
 pub struct AverageDirectionalMovementIndex {
-    max_in_prev_range: MaxmimumInRange,
-    min_in_prev_range: MinimumInRange,
-    max_in_curr_range: MaxmimumInRange,
-    min_in_curr_range: MinimumInRange,
-    pdi_ema: ExponentialMovingAverage,
-    ndi_ema: ExponentialMovingAverage,
-    adx_ema: ExponentialMovingAverage,
+    di_period: u64,
+    adx_period: u64,
+    moves: u64,
+    prev: Option<Ohlcv>,
+    true_range: TrueRange,
+    tr_average: ExponentialMovingAverage,
+    plus_dm_average: ExponentialMovingAverage,
+    minus_dm_average: ExponentialMovingAverage,
+    adx_average: ExponentialMovingAverage,
+    adx: f64,
 }
 
 impl AverageDirectionalMovementIndex {
-    pub fn new(period_length: u64, num_periods: u64) -> Self {
+    pub fn new(period: u64) -> Self {
+        AverageDirectionalMovementIndex::with_smoothing(period, period)
+    }
+
+    pub fn with_smoothing(di_period: u64, adx_period: u64) -> Self {
+        /*
+            The di lines over di_period, and the adx smoothed over adx_period. new(n) is wilder's, both n.
+        */
         AverageDirectionalMovementIndex {
-            max_in_prev_range: MaxmimumInRange::new(2 * period_length, period_length),
-            min_in_prev_range: MinimumInRange::new(2 * period_length, period_length),
-            max_in_curr_range: MaxmimumInRange::new(period_length, 0),
-            min_in_curr_range: MinimumInRange::new(period_length, 0),
-            pdi_ema: ExponentialMovingAverage::new((num_periods * period_length) as u32).unwrap(),
-            ndi_ema: ExponentialMovingAverage::new((num_periods * period_length) as u32).unwrap(),
-            adx_ema: ExponentialMovingAverage::new((num_periods * period_length) as u32).unwrap(),
+            di_period,
+            adx_period,
+            moves: 0,
+            prev: None,
+            true_range: TrueRange::new(),
+            tr_average: ExponentialMovingAverage::wilder(di_period),
+            plus_dm_average: ExponentialMovingAverage::wilder(di_period),
+            minus_dm_average: ExponentialMovingAverage::wilder(di_period),
+            adx_average: ExponentialMovingAverage::wilder(adx_period),
+            adx: 0.0,
         }
     }
 }
 
-impl NextI for AverageDirectionalMovementIndex {
-    fn next(&mut self, input: f64) -> f64 {
-        let prev_high = self.max_in_prev_range.next(input);
-        let prev_low = self.min_in_prev_range.next(input);
-
-        let curr_high = self.max_in_curr_range.next(input);
-        let curr_low = self.min_in_curr_range.next(input);
-
-        let up_move = curr_high - prev_high;
-        let down_move = prev_low - curr_low;
+impl NextI<Ohlcv, DirectionalMovementOutput> for AverageDirectionalMovementIndex {
+    fn next(&mut self, bar: Ohlcv) -> DirectionalMovementOutput {
+        let true_range = self.true_range.next(bar);
+        let prev = match self.prev.replace(bar) {
+            Some(x) => x,
+            None => return DirectionalMovementOutput::default(),
+        };
+        self.moves += 1;
 
-        let pdm = if up_move > down_move && up_move > 0f64 {
+        let up_move = bar.high - prev.high;
+        let down_move = prev.low - bar.low;
+        let plus_dm = if up_move > down_move && up_move > 0.0 {
             up_move
         } else {
-            0f64
+            0.0
         };
-        let ndm = if down_move > up_move && down_move > 0f64 {
+        let minus_dm = if down_move > up_move && down_move > 0.0 {
             down_move
         } else {
-            0f64
+            0.0
         };
 
-        let pdi = 100f64 * self.pdi_ema.next(pdm);
-        let ndi = 100f64 * self.ndi_ema.next(ndm);
+        let tr_average = self.tr_average.next(true_range);
+        let plus_dm_average = self.plus_dm_average.next(plus_dm);
+        let minus_dm_average = self.minus_dm_average.next(minus_dm);
+        let (plus_di, minus_di) = if tr_average > 0.0 {
+            (
+                100.0 * plus_dm_average / tr_average,
+                100.0 * minus_dm_average / tr_average,
+            )
+        } else {
+            (0.0, 0.0)
+        };
+
+        if self.moves >= self.di_period {
+            let dx = if plus_di + minus_di > 0.0 {
+                100.0 * (plus_di - minus_di).abs() / (plus_di + minus_di)
+            } else {
+                0.0
+            };
+            self.adx = self.adx_average.next(dx);
+        }
+
+        DirectionalMovementOutput {
+            plus_di,
+            minus_di,
+            adx: self.adx,
+        }
+    }
+}
+
+impl WarmUp for AverageDirectionalMovementIndex {
+    fn warm_up(&self) -> u64 {
+        // di_period moves for the first dx, then adx_period dx values
+        self.di_period + self.adx_period
+    }
+
+    fn is_ready(&self) -> bool {
+        self.adx_average.is_ready()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{assert_close, assert_published, atr_example_bars};
+    use super::*;
+
+    #[test]
+    fn works_a_short_example_by_hand() {
+        // high, low, close
+        let rows = [
+            (10.0, 8.0, 9.0),
+            (12.0, 9.0, 11.0),
+            (13.0, 10.0, 12.0),
+            (12.0, 8.0, 9.0),
+            (11.0, 7.0, 8.0),
+        ];
+        let bars: Vec<Ohlcv> = rows
+            .iter()
+            .map(|(high, low, close)| Ohlcv::new(*close, *high, *low, *close, 1.0))
+            .collect();
+        let mut adx = AverageDirectionalMovementIndex::new(2);
+        let values: Vec<DirectionalMovementOutput> = bars.iter().map(|x| adx.next(*x)).collect();
+        // true range 3, 3, 4, 4. +dm 2, 1, 0, 0. -dm 0, 0, 2, 1.
+        // 2nd move: tr 3, +dm 1.5, -dm 0. +di 50, -di 0, dx 100.
+        assert_close(values[2].plus_di, 50.0);
+        assert_close(values[2].minus_di, 0.0);
+        assert_close(values[2].adx, 100.0);
+        // 3rd: tr 3.5, +dm 0.75, -dm 1. +di 3/14, -di 4/14, dx 1/7. adx the mean of 100 and 100/7.
+        assert_close(values[3].plus_di, 300.0 / 14.0);
+        assert_close(values[3].minus_di, 400.0 / 14.0);
+        assert_close(values[3].adx, (100.0 + 100.0 / 7.0) / 2.0);
+        // 4th: tr 3.75, +dm 0.375, -dm 1. +di 10, -di 26.67, dx 5/11. adx halfway to it.
+        assert_close(values[4].plus_di, 10.0);
+        assert_close(values[4].minus_di, 100.0 / 3.75);
+        assert_close(values[4].adx, (400.0 / 7.0 + 500.0 / 11.0) / 2.0);
+        assert!(adx.is_ready());
 
-        let adx = ((pdi - ndi) / (pdi + ndi)).abs();
+        // smoothed over 1 the adx is the last dx
+        let mut adx = AverageDirectionalMovementIndex::with_smoothing(2, 1);
+        let values: Vec<DirectionalMovementOutput> = bars.iter().map(|x| adx.next(*x)).collect();
+        assert_close(values[4].adx, 500.0 / 11.0);
+        assert_eq!(adx.warm_up(), 3);
+    }
 
-        let curr_adx = 100f64 * self.adx_ema.next(adx);
+    #[test]
+    fn smooths_the_directional_moves() {
+        let bars = atr_example_bars();
+        let mut adx = AverageDirectionalMovementIndex::new(5);
+        let mut true_range = TrueRange::new();
+        let mut averages: Vec<ExponentialMovingAverage> = (0..4)
+            .map(|_| ExponentialMovingAverage::wilder(5))
+            .collect();
+        assert_eq!(adx.next(bars[0]), DirectionalMovementOutput::default());
+        true_range.next(bars[0]);
+        for i in 1..bars.len() {
+            let value = adx.next(bars[i]);
+            let up_move = bars[i].high - bars[i - 1].high;
+            let down_move = bars[i - 1].low - bars[i].low;
+            let plus_dm = if up_move > down_move {
+                up_move.max(0.0)
+            } else {
+                0.0
+            };
+            let minus_dm = if down_move > up_move {
+                down_move.max(0.0)
+            } else {
+                0.0
+            };
+            let tr = averages[0].next(true_range.next(bars[i]));
+            let plus_di = 100.0 * averages[1].next(plus_dm) / tr;
+            let minus_di = 100.0 * averages[2].next(minus_dm) / tr;
+            assert_close(value.plus_di, plus_di);
+            assert_close(value.minus_di, minus_di);
+            // dx from the 5th move on
+            if i < 5 {
+                assert_eq!(value.adx, 0.0);
+            } else {
+                let dx = 100.0 * (plus_di - minus_di).abs() / (plus_di + minus_di);
+                assert_close(value.adx, averages[3].next(dx));
+            }
+        }
+    }
 
-        return curr_adx;
+    #[test]
+    fn matches_the_stockcharts_worksheet() {
+        // the inputs of the stockcharts adx worksheet (chartschool, cs-adx), worked through its formulas: 14 bar sums
+        // of tr, +dm and -dm, then each one the last minus a 14th of it plus the new move. the first adx is the mean of
+        // 14 dx, then wilder smoothed. values to 2 decimals.
+        let highs = [
+            30.1983, 30.2776, 30.4458, 29.3478, 29.3477, 29.2897, 28.8331, 28.7335, 28.6729,
+            28.8553, 28.6427, 27.6839, 27.2168, 26.8699, 27.4097, 26.9405, 26.5224, 26.5224,
+            27.0872, 27.6855, 28.4488, 28.5274, 28.6664, 29.0134, 29.8658, 29.8039, 29.7518,
+            30.6545, 30.5951, 30.7563, 31.1725, 30.8854, 30.0448, 30.6609, 30.5951, 31.9703,
+            32.1001, 32.0296, 31.6287, 31.8477, 32.7141,
+        ];
+        let lows = [
+            29.4072, 29.3182, 29.9611, 28.7443, 28.5566, 28.4097, 28.0766, 27.4340, 27.6602,
+            27.8253, 27.4001, 27.0893, 26.1842, 26.1343, 26.6344, 26.1266, 25.4306, 25.3520,
+            25.8763, 26.9642, 27.1400, 28.0112, 27.8791, 27.9866, 28.7647, 29.1393, 28.7099,
+            28.9287, 30.0320, 29.3883, 30.1365, 30.4270, 29.3468, 29.9935, 29.5180, 30.9416,
+            31.5423, 31.3628, 30.9240, 31.2025, 32.1305,
+        ];
+        let closes = [
+            29.8720, 30.2381, 30.0996, 28.9028, 28.9206, 28.4806, 28.5606, 27.5591, 28.4703,
+            28.2799, 27.4940, 27.2253, 26.3479, 26.3299, 27.0305, 26.2203, 26.0119, 25.4582,
+            27.0314, 27.4489, 28.3631, 28.4305, 27.9462, 29.0105, 29.3799, 29.3566, 28.9071,
+            30.6148, 30.0459, 30.1905, 31.1165, 30.5356, 29.7826, 30.0409, 30.4854, 31.4675,
+            32.0514, 31.9731, 31.1305, 31.6565, 32.6403,
+        ];
+        let mut adx = AverageDirectionalMovementIndex::new(14);
+        let values: Vec<DirectionalMovementOutput> = (0..highs.len())
+            .map(|i| adx.next(Ohlcv::new(closes[i], highs[i], lows[i], closes[i], 1.0)))
+            .collect();
+        // the first 14 bar sums are on the 15th bar
+        let plus_di: Vec<f64> = values[14..].iter().map(|x| x.plus_di).collect();
+        let minus_di: Vec<f64> = values[14..].iter().map(|x| x.minus_di).collect();
+        let adx_values: Vec<f64> = values[27..].iter().map(|x| x.adx).collect();
+        assert_published(
+            &plus_di,
+            &[
+                6.66, 6.21, 5.71, 5.22, 8.57, 12.40, 16.59, 16.54, 16.61, 17.84, 22.59, 21.48,
+                19.83, 23.70, 22.71, 20.54, 21.96, 20.87, 19.12, 22.32, 20.61, 27.94, 27.61, 26.24,
+                24.26, 24.59, 29.01,
+            ],
+        );
+        assert_published(
+            &minus_di,
+            &[
+                32.23, 33.86, 36.28, 33.75, 29.91, 28.37, 25.78, 24.81, 23.38, 21.56, 19.84, 18.87,
+                20.58, 18.08, 17.32, 20.17, 18.71, 17.78, 23.92, 22.42, 24.08, 21.63, 20.66, 20.93,
+                22.51, 21.32, 19.68,
+            ],
+        );
+        assert_published(
+            &adx_values,
+            &[
+                33.69, 32.25, 30.01, 28.44, 26.98, 25.85, 24.02, 22.85, 22.13, 21.58, 20.84, 19.62,
+                18.73, 18.76,
+            ],
+        );
+        assert!(adx.is_ready());
     }
 }
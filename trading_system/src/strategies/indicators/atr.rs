@@ -0,0 +1,95 @@
+use super::moving_average::ExponentialMovingAverage;
+use super::{NextI, Ohlcv, WarmUp};
+
+// the largest of high - low and the gaps from the previous close. the first bar is high - low.
+#[derive(Default)]
+pub struct TrueRange {
+    prev_close: Option<f64>,
+}
+
+impl TrueRange {
+    pub fn new() -> Self {
+        TrueRange::default()
+    }
+}
+
+impl NextI<Ohlcv> for TrueRange {
+    fn next(&mut self, bar: Ohlcv) -> f64 {
+        let range = bar.high - bar.low;
+        match self.prev_close.replace(bar.close) {
+            Some(prev_close) => range
+                .max((bar.high - prev_close).abs())
+                .max((bar.low - prev_close).abs()),
+            None => range,
+        }
+    }
+}
+
+// wilder's average of the true range
+pub struct AverageTrueRange {
+    true_range: TrueRange,
+    average: ExponentialMovingAverage,
+}
+
+impl AverageTrueRange {
+    pub fn new(period: u64) -> Self {
+        AverageTrueRange {
+            true_range: TrueRange::new(),
+            average: ExponentialMovingAverage::wilder(period),
+        }
+    }
+}
+
+impl NextI<Ohlcv> for AverageTrueRange {
+    fn next(&mut self, bar: Ohlcv) -> f64 {
+        let true_range = self.true_range.next(bar);
+        self.average.next(true_range)
+    }
+}
+
+impl WarmUp for TrueRange {
+    fn warm_up(&self) -> u64 {
+        1
+    }
+
+    fn is_ready(&self) -> bool {
+        self.prev_close.is_some()
+    }
+}
+
+impl WarmUp for AverageTrueRange {
+    fn warm_up(&self) -> u64 {
+        self.average.warm_up()
+    }
+
+    fn is_ready(&self) -> bool {
+        self.average.is_ready()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{assert_close, assert_published, atr_example_bars};
+    use super::*;
+
+    #[test]
+    fn matches_the_stockcharts_example() {
+        let mut true_range = TrueRange::new();
+        let mut atr = AverageTrueRange::new(14);
+        let (ranges, values): (Vec<f64>, Vec<f64>) = atr_example_bars()
+            .into_iter()
+            .map(|x| (true_range.next(x), atr.next(x)))
+            .unzip();
+        assert_close(ranges[0], 48.70 - 47.79);
+        // the gap up from the previous close on the 27th bar
+        assert_close(ranges[26], 47.80 - 45.41);
+        // the first is the average of 14 true ranges, then wilder smoothed
+        assert_published(
+            &values[13..],
+            &[
+                0.56, 0.59, 0.59, 0.57, 0.62, 0.62, 0.64, 0.67, 0.69, 0.78, 0.78, 1.21, 1.30, 1.38,
+                1.37, 1.34, 1.32,
+            ],
+        );
+    }
+}
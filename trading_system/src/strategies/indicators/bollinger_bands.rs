@@ -0,0 +1,88 @@
+use std::collections::VecDeque;
+
+use super::{Bands, NextI, Ohlcv, WarmUp};
+
+// simple moving average of the closes with bands a number of standard deviations (population) above and below
+pub struct BollingerBands {
+    period: usize,
+    multiplier: f64,
+    window: VecDeque<f64>,
+}
+
+impl BollingerBands {
+    pub fn new(period: u64, multiplier: f64) -> Self {
+        assert!(period > 0);
+        BollingerBands {
+            period: period as usize,
+            multiplier,
+            window: VecDeque::with_capacity(period as usize + 1),
+        }
+    }
+}
+
+impl NextI<Ohlcv, Bands> for BollingerBands {
+    fn next(&mut self, bar: Ohlcv) -> Bands {
+        self.window.push_back(bar.close);
+        if self.window.len() > self.period {
+            self.window.pop_front();
+        }
+        let n = self.window.len() as f64;
+        let mean = self.window.iter().sum::<f64>() / n;
+        let variance = self.window.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / n;
+        let width = self.multiplier * variance.sqrt();
+        Bands {
+            upper: mean + width,
+            middle: mean,
+            lower: mean - width,
+        }
+    }
+}
+
+impl WarmUp for BollingerBands {
+    fn warm_up(&self) -> u64 {
+        self.period as u64
+    }
+
+    fn is_ready(&self) -> bool {
+        self.window.len() >= self.period
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::moving_average::SimpleMovingAverage;
+    use super::super::{assert_close, assert_published, closes};
+    use super::*;
+
+    #[test]
+    fn matches_the_stockcharts_example() {
+        // the stockcharts bollinger band example (chartschool, cs-bollinger)
+        let bars = closes(&[
+            86.16, 89.09, 88.78, 90.32, 89.07, 91.15, 89.44, 89.18, 86.93, 87.68, 86.96, 89.43,
+            89.32, 88.72, 87.45, 87.26, 89.50, 87.90, 89.13, 90.70, 92.90, 92.98, 91.80, 92.66,
+            92.68, 92.30, 92.77, 92.54, 92.95, 93.20, 91.07, 89.83, 89.74, 90.40, 90.74, 88.02,
+            88.09, 88.84, 90.78, 90.54, 91.39, 90.65,
+        ]);
+        let mut bollinger = BollingerBands::new(20, 2.0);
+        let mut sma = SimpleMovingAverage::new(20);
+        let values: Vec<Bands> = bars.iter().map(|x| bollinger.next(*x)).collect();
+        assert_published(
+            &[values[19].middle, values[19].upper, values[19].lower],
+            &[88.71, 91.29, 86.12],
+        );
+        // the middle is the sma, the bands two population standard deviations of the window around it
+        for (i, bar) in bars.iter().enumerate() {
+            let window: Vec<f64> = bars[(i + 1).saturating_sub(20)..=i]
+                .iter()
+                .map(|x| x.close)
+                .collect();
+            let mean = window.iter().sum::<f64>() / window.len() as f64;
+            let deviation = (window.iter().map(|x| (x - mean).powi(2)).sum::<f64>()
+                / window.len() as f64)
+                .sqrt();
+            assert_close(values[i].middle, sma.next(bar.close));
+            assert_close(values[i].upper, mean + 2.0 * deviation);
+            assert_close(values[i].lower, mean - 2.0 * deviation);
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use super::max_in_range::MaxmimumInRange;
+use super::min_in_range::MinimumInRange;
+use super::{Bands, NextI, Ohlcv, WarmUp};
+
+// highest high and lowest low of the last period bars, and the middle of the two
+pub struct DonchianChannels {
+    highest: MaxmimumInRange,
+    lowest: MinimumInRange,
+}
+
+impl DonchianChannels {
+    pub fn new(period: u64) -> Self {
+        DonchianChannels {
+            highest: MaxmimumInRange::new(period, 0),
+            lowest: MinimumInRange::new(period, 0),
+        }
+    }
+}
+
+impl NextI<Ohlcv, Bands> for DonchianChannels {
+    fn next(&mut self, bar: Ohlcv) -> Bands {
+        let upper = self.highest.next(bar.high);
+        let lower = self.lowest.next(bar.low);
+        Bands {
+            upper,
+            middle: (upper + lower) / 2.0,
+            lower,
+        }
+    }
+}
+
+impl WarmUp for DonchianChannels {
+    fn warm_up(&self) -> u64 {
+        self.highest.warm_up()
+    }
+
+    fn is_ready(&self) -> bool {
+        self.highest.is_ready()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{assert_close, atr_example_bars, range_of};
+    use super::*;
+
+    #[test]
+    fn follows_the_highest_high_and_lowest_low() {
+        let bars = atr_example_bars();
+        let mut donchian = DonchianChannels::new(20);
+        for i in 0..bars.len() {
+            let value = donchian.next(bars[i]);
+            let (high, low) = range_of(&bars[..=i], 20);
+            assert_close(value.upper, high);
+            assert_close(value.lower, low);
+            assert_close(value.middle, (high + low) / 2.0);
+        }
+    }
+}
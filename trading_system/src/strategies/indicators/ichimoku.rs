@@ -0,0 +1,89 @@
+use super::max_in_range::MaxmimumInRange;
+use super::min_in_range::MinimumInRange;
+use super::{NextI, Ohlcv, WarmUp};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct IchimokuOutput {
+    pub tenkan: f64,
+    pub kijun: f64,
+    pub senkou_a: f64,
+    pub senkou_b: f64,
+}
+
+// the middle of the high-low range over three periods (usually 9, 26 and 52).
+// the spans are not shifted forward, a strategy comparing price to the cloud uses the values from kijun bars ago.
+pub struct Ichimoku {
+    tenkan: (MaxmimumInRange, MinimumInRange),
+    kijun: (MaxmimumInRange, MinimumInRange),
+    senkou_b: (MaxmimumInRange, MinimumInRange),
+}
+
+fn midpoint(range: &mut (MaxmimumInRange, MinimumInRange), bar: &Ohlcv) -> f64 {
+    (range.0.next(bar.high) + range.1.next(bar.low)) / 2.0
+}
+
+impl Ichimoku {
+    pub fn new(tenkan_period: u64, kijun_period: u64, senkou_b_period: u64) -> Self {
+        let range = |period| {
+            (
+                MaxmimumInRange::new(period, 0),
+                MinimumInRange::new(period, 0),
+            )
+        };
+        Ichimoku {
+            tenkan: range(tenkan_period),
+            kijun: range(kijun_period),
+            senkou_b: range(senkou_b_period),
+        }
+    }
+}
+
+impl NextI<Ohlcv, IchimokuOutput> for Ichimoku {
+    fn next(&mut self, bar: Ohlcv) -> IchimokuOutput {
+        let tenkan = midpoint(&mut self.tenkan, &bar);
+        let kijun = midpoint(&mut self.kijun, &bar);
+        IchimokuOutput {
+            tenkan,
+            kijun,
+            senkou_a: (tenkan + kijun) / 2.0,
+            senkou_b: midpoint(&mut self.senkou_b, &bar),
+        }
+    }
+}
+
+impl WarmUp for Ichimoku {
+    fn warm_up(&self) -> u64 {
+        self.tenkan
+            .0
+            .warm_up()
+            .max(self.kijun.0.warm_up())
+            .max(self.senkou_b.0.warm_up())
+    }
+
+    fn is_ready(&self) -> bool {
+        self.tenkan.0.is_ready() && self.kijun.0.is_ready() && self.senkou_b.0.is_ready()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{assert_close, atr_example_bars, range_of};
+    use super::*;
+
+    #[test]
+    fn takes_the_middle_of_each_range() {
+        let bars = atr_example_bars();
+        let mut ichimoku = Ichimoku::new(9, 26, 52);
+        let middle = |i: usize, period: usize| {
+            let (high, low) = range_of(&bars[..=i], period);
+            (high + low) / 2.0
+        };
+        for (i, bar) in bars.iter().enumerate() {
+            let value = ichimoku.next(*bar);
+            assert_close(value.tenkan, middle(i, 9));
+            assert_close(value.kijun, middle(i, 26));
+            assert_close(value.senkou_a, (middle(i, 9) + middle(i, 26)) / 2.0);
+            assert_close(value.senkou_b, middle(i, 52));
+        }
+    }
+}
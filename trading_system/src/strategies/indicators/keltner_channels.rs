@@ -0,0 +1,62 @@
+use super::atr::AverageTrueRange;
+use super::moving_average::ExponentialMovingAverage;
+use super::{Bands, NextI, Ohlcv, WarmUp};
+
+// ema of the closes with bands a multiple of the average true range above and below
+pub struct KeltnerChannels {
+    middle: ExponentialMovingAverage,
+    atr: AverageTrueRange,
+    multiplier: f64,
+}
+
+impl KeltnerChannels {
+    pub fn new(period: u64, atr_period: u64, multiplier: f64) -> Self {
+        KeltnerChannels {
+            middle: ExponentialMovingAverage::new(period),
+            atr: AverageTrueRange::new(atr_period),
+            multiplier,
+        }
+    }
+}
+
+impl NextI<Ohlcv, Bands> for KeltnerChannels {
+    fn next(&mut self, bar: Ohlcv) -> Bands {
+        let middle = self.middle.next(bar.close);
+        let width = self.multiplier * self.atr.next(bar);
+        Bands {
+            upper: middle + width,
+            middle,
+            lower: middle - width,
+        }
+    }
+}
+
+impl WarmUp for KeltnerChannels {
+    fn warm_up(&self) -> u64 {
+        self.middle.warm_up().max(self.atr.warm_up())
+    }
+
+    fn is_ready(&self) -> bool {
+        self.middle.is_ready() && self.atr.is_ready()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{assert_close, atr_example_bars};
+    use super::*;
+
+    #[test]
+    fn puts_atr_bands_around_the_ema() {
+        let mut keltner = KeltnerChannels::new(20, 10, 2.0);
+        let mut ema = ExponentialMovingAverage::new(20);
+        let mut atr = AverageTrueRange::new(10);
+        for bar in atr_example_bars() {
+            let value = keltner.next(bar);
+            let (middle, width) = (ema.next(bar.close), 2.0 * atr.next(bar));
+            assert_close(value.middle, middle);
+            assert_close(value.upper, middle + width);
+            assert_close(value.lower, middle - width);
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use super::moving_average::ExponentialMovingAverage;
+use super::{NextI, Ohlcv, WarmUp};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MacdOutput {
+    pub macd: f64,
+    pub signal: f64,
+    pub histogram: f64,
+}
+
+// fast ema minus slow ema of the closes, with an ema of that as the signal line.
+// the signal line starts once the slow ema has a full period, before that it equals the macd line.
+pub struct MovingAverageConvergenceDivergence {
+    fast: ExponentialMovingAverage,
+    slow: ExponentialMovingAverage,
+    signal: ExponentialMovingAverage,
+    slow_period: u64,
+    count: u64,
+}
+
+impl MovingAverageConvergenceDivergence {
+    pub fn new(fast_period: u64, slow_period: u64, signal_period: u64) -> Self {
+        assert!(fast_period < slow_period);
+        MovingAverageConvergenceDivergence {
+            fast: ExponentialMovingAverage::new(fast_period),
+            slow: ExponentialMovingAverage::new(slow_period),
+            signal: ExponentialMovingAverage::new(signal_period),
+            slow_period,
+            count: 0,
+        }
+    }
+}
+
+impl NextI<Ohlcv, MacdOutput> for MovingAverageConvergenceDivergence {
+    fn next(&mut self, bar: Ohlcv) -> MacdOutput {
+        let macd = self.fast.next(bar.close) - self.slow.next(bar.close);
+        self.count += 1;
+        let signal = if self.count < self.slow_period {
+            macd
+        } else {
+            self.signal.next(macd)
+        };
+        MacdOutput {
+            macd,
+            signal,
+            histogram: macd - signal,
+        }
+    }
+}
+
+impl WarmUp for MovingAverageConvergenceDivergence {
+    fn warm_up(&self) -> u64 {
+        self.slow_period + self.signal.warm_up() - 1
+    }
+
+    fn is_ready(&self) -> bool {
+        self.signal.is_ready()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{assert_close, closes};
+    use super::*;
+
+    #[test]
+    fn matches_a_worked_example() {
+        // macd(2, 3, 2) of 10, 12, 11, 14, 16, 15, worked by hand. the emas start on the mean of their first bars.
+        // fast: 10, 11, 11, 11 + 3 * 2/3 = 13, 13 + 3 * 2/3 = 15, 15
+        // slow: 10, 11, 11, 11 + 3 / 2 = 12.5, 12.5 + 3.5 / 2 = 14.25, 14.25 + 0.75 / 2 = 14.625
+        // macd: 0, 0, 0, 0.5, 0.75, 0.375
+        // signal, from the slow ema's 3rd bar: 0, 0.25, 0.25 + 0.5 * 2/3 = 7/12, 7/12 - 5/24 * 2/3 = 4/9
+        let mut macd = MovingAverageConvergenceDivergence::new(2, 3, 2);
+        let values: Vec<MacdOutput> = closes(&[10.0, 12.0, 11.0, 14.0, 16.0, 15.0])
+            .into_iter()
+            .map(|x| macd.next(x))
+            .collect();
+        let expected = [
+            (0.0, 0.0),
+            (0.0, 0.0),
+            (0.0, 0.0),
+            (0.5, 0.25),
+            (0.75, 7.0 / 12.0),
+            (0.375, 4.0 / 9.0),
+        ];
+        for (value, (line, signal)) in values.iter().zip(expected.iter()) {
+            assert_close(value.macd, *line);
+            assert_close(value.signal, *signal);
+            assert_close(value.histogram, line - signal);
+        }
+    }
+}
@@ -1,99 +1,108 @@
-use std::f64::INFINITY;
+use std::collections::VecDeque;
 
-use super::NextI;
+use super::{NextI, WarmUp};
 
-// finds max in last far_n elements that arent in the last near_n elements
+// finds max in last far_n elements that arent in the last near_n elements. 0 until an element is that old.
+// candidates are kept in a deque of decreasing values, so each element is pushed and popped once.
 pub struct MaxmimumInRange {
-    size: usize,
-    vec: Vec<f64>,
-    slow_index: usize,
-    curr_index: usize,
-    max_index: usize,
+    far_n: u64,
+    near_n: usize,
+    count: u64,
+    // the last near_n elements, not in range yet
+    pending: VecDeque<f64>,
+    // (index, value) of elements in range that are bigger than every newer one
+    candidates: VecDeque<(u64, f64)>,
 }
 
 impl MaxmimumInRange {
     pub fn new(far_n: u64, near_n: u64) -> Self {
-        // convert lengths to usize for indexing
-        let far_n = far_n as usize;
-        let near_n = near_n as usize;
-
         // far_n must be greater than near_n
-        assert_eq!(far_n > near_n, true);
+        assert!(far_n > near_n);
 
         MaxmimumInRange {
-            size: far_n,
-            vec: vec![-INFINITY; far_n],
-            slow_index: (far_n - near_n) % far_n,
-            curr_index: 0,
-            max_index: 0,
+            far_n,
+            near_n: near_n as usize,
+            count: 0,
+            pending: VecDeque::with_capacity(near_n as usize + 1),
+            candidates: VecDeque::new(),
         }
     }
-    fn find_max_index(&self) -> usize {
-        let mut max = -INFINITY;
+}
 
-        let mut i = (self.curr_index + 1) % self.size;
-        let mut index = 0;
-        loop {
-            if self.vec[i] > max {
-                max = self.vec[i];
-                index = i;
-            }
+impl NextI for MaxmimumInRange {
+    fn next(&mut self, input: f64) -> f64 {
+        self.count += 1;
+        self.pending.push_back(input);
 
-            i = (i + 1) % self.size;
-            if i == (self.slow_index + 1) % self.size {
-                break;
+        // the element near_n back comes into range, and hides every smaller one before it
+        if self.pending.len() > self.near_n {
+            let value = self.pending.pop_front().unwrap_or(input);
+            let index = self.count - 1 - self.near_n as u64;
+            while matches!(self.candidates.back(), Some((_, x)) if *x <= value) {
+                self.candidates.pop_back();
             }
+            self.candidates.push_back((index, value));
+        }
+
+        // drop what is more than far_n back
+        while matches!(self.candidates.front(), Some((i, _)) if i + self.far_n < self.count) {
+            self.candidates.pop_front();
         }
 
-        return index;
+        self.candidates.front().map(|(_, x)| *x).unwrap_or(0.0)
     }
+}
 
-    pub fn _test() {
-        let mut mir = MaxmimumInRange::new(4u64, 2u64);
-        assert_eq!(mir.next(1f64), -INFINITY);
-        assert_eq!(mir.next(2f64), -INFINITY);
-        assert_eq!(mir.next(3f64), 1f64);
-        assert_eq!(mir.next(4f64), 2f64);
-        assert_eq!(mir.next(2f64), 3f64);
-        assert_eq!(mir.next(3f64), 4f64);
-        assert_eq!(mir.next(1f64), 4f64);
-        assert_eq!(mir.next(-1f64), 3f64);
-        assert_eq!(mir.next(10f64), 3f64);
-        assert_eq!(mir.next(5f64), 1f64);
-        assert_eq!(mir.next(4f64), 10f64);
-        assert_eq!(mir.next(-7f64), 10f64);
+impl WarmUp for MaxmimumInRange {
+    fn warm_up(&self) -> u64 {
+        self.far_n
+    }
 
-        let mut mir = MaxmimumInRange::new(3, 0);
-        dbg!(mir.next(1f64));
-        dbg!(mir.next(2f64));
-        dbg!(mir.next(7f64));
-        dbg!(mir.next(4f64));
-        dbg!(mir.next(-10f64));
-        dbg!(mir.next(3f64));
-        dbg!(mir.next(2f64));
+    fn is_ready(&self) -> bool {
+        self.count >= self.far_n
     }
 }
 
-impl NextI for MaxmimumInRange {
-    fn next(&mut self, input: f64) -> f64 {
-        // increment curr_index and slow_index
-        self.curr_index = (self.curr_index + 1) % self.size;
-        self.slow_index = (self.slow_index + 1) % self.size;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        // insert input
-        self.vec[self.curr_index] = input;
+    #[test]
+    fn max_of_the_far_range() {
+        let mut mir = MaxmimumInRange::new(4, 2);
+        let inputs = [
+            1.0, 2.0, 3.0, 4.0, 2.0, 3.0, 1.0, -1.0, 10.0, 5.0, 4.0, -7.0,
+        ];
+        let expected = [0.0, 0.0, 1.0, 2.0, 3.0, 4.0, 4.0, 3.0, 3.0, 1.0, 10.0, 10.0];
+        let values: Vec<f64> = inputs.iter().map(|x| mir.next(*x)).collect();
+        assert_eq!(values, expected);
 
-        if self.curr_index == self.max_index {
-            // if the max was replaced, find new max
-            self.max_index = self.find_max_index();
-        } else if self.vec[self.slow_index] > self.vec[self.max_index] {
-            // if new slow_index is max, set max
-            self.max_index = self.slow_index;
-        }
+        let mut mir = MaxmimumInRange::new(3, 0);
+        let inputs = [1.0, 2.0, 7.0, 4.0, -10.0, 3.0, 2.0];
+        let expected = [1.0, 2.0, 7.0, 7.0, 7.0, 4.0, 3.0];
+        let values: Vec<f64> = inputs.iter().map(|x| mir.next(*x)).collect();
+        assert_eq!(values, expected);
+    }
 
-        if self.vec[self.max_index] == -INFINITY {
-            return 0f64;
+    #[test]
+    fn matches_a_rescan_of_the_window() {
+        let inputs: Vec<f64> = (0..500u64)
+            .map(|x| ((x * 7919) % 101) as f64 - (x / 50) as f64)
+            .collect();
+        for (far_n, near_n) in [(1, 0), (5, 0), (14, 0), (28, 14), (30, 29), (60, 7)].iter() {
+            let mut mir = MaxmimumInRange::new(*far_n, *near_n);
+            for (i, input) in inputs.iter().enumerate() {
+                let start = (i + 1).saturating_sub(*far_n as usize);
+                let end = (i + 1).saturating_sub(*near_n as usize);
+                let expected = inputs[start..end]
+                    .iter()
+                    .cloned()
+                    .fold(None, |max: Option<f64>, x| {
+                        Some(max.map_or(x, |m| m.max(x)))
+                    })
+                    .unwrap_or(0.0);
+                assert_eq!(mir.next(*input), expected, "{} {} at {}", far_n, near_n, i);
+            }
         }
-        return self.vec[self.max_index];
     }
 }
@@ -1,7 +1,222 @@
 pub mod adx_indicator;
+pub mod atr;
+pub mod bollinger_bands;
+pub mod donchian_channels;
+pub mod ichimoku;
+pub mod keltner_channels;
+pub mod macd;
 pub mod max_in_range;
 pub mod min_in_range;
+pub mod moving_average;
+pub mod obv;
+pub mod parabolic_sar;
+pub mod rsi;
+pub mod stochastic;
+pub mod supertrend;
+pub mod vwap;
 
-pub trait NextI {
-    fn next(&mut self, input: f64) -> f64;
+// indicators take one input at a time and return their value after it. most take a whole bar,
+// see docs/indicators.txt for what each one returns.
+pub trait NextI<I = f64, O = f64> {
+    fn next(&mut self, input: I) -> O;
+}
+
+// how many inputs an indicator needs before its value is what it says it is. before that it works on what it has.
+pub trait WarmUp {
+    fn warm_up(&self) -> u64;
+    fn is_ready(&self) -> bool;
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Ohlcv {
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: f64,
+}
+
+impl Ohlcv {
+    pub fn new(open: f64, high: f64, low: f64, close: f64, volume: f64) -> Self {
+        Ohlcv {
+            open,
+            high,
+            low,
+            close,
+            volume,
+        }
+    }
+
+    pub fn from_slice(row: &[f64]) -> Self {
+        // a row of ohlc_history, [open, high, low, close, volume]
+        Ohlcv::new(row[0], row[1], row[2], row[3], row[4])
+    }
+
+    pub fn typical_price(&self) -> f64 {
+        (self.high + self.low + self.close) / 3.0
+    }
+
+    pub fn median_price(&self) -> f64 {
+        (self.high + self.low) / 2.0
+    }
+}
+
+// upper, middle and lower line of bollinger, keltner and donchian channels
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Bands {
+    pub upper: f64,
+    pub middle: f64,
+    pub lower: f64,
+}
+
+#[cfg(test)]
+pub(crate) fn atr_example_bars() -> Vec<Ohlcv> {
+    // high, low and close of the stockcharts average true range example (chartschool, cs-atr).
+    // it has no opens or volumes, so the open is the close and the volume 1.
+    [
+        [48.70, 47.79, 48.16],
+        [48.72, 48.14, 48.61],
+        [48.90, 48.39, 48.75],
+        [48.87, 48.37, 48.63],
+        [48.82, 48.24, 48.74],
+        [49.05, 48.64, 49.03],
+        [49.20, 48.94, 49.07],
+        [49.35, 48.86, 49.32],
+        [49.92, 49.50, 49.91],
+        [50.19, 49.87, 50.13],
+        [50.12, 49.20, 49.53],
+        [49.66, 48.90, 49.50],
+        [49.88, 49.43, 49.75],
+        [50.19, 49.73, 50.03],
+        [50.36, 49.26, 50.31],
+        [50.57, 50.09, 50.52],
+        [50.65, 50.30, 50.41],
+        [50.43, 49.21, 49.34],
+        [49.63, 48.98, 49.37],
+        [50.33, 49.61, 50.23],
+        [50.29, 49.20, 49.24],
+        [50.17, 49.43, 49.93],
+        [49.32, 48.08, 48.43],
+        [48.50, 47.64, 48.18],
+        [48.32, 41.55, 46.57],
+        [46.80, 44.28, 45.41],
+        [47.80, 47.31, 47.77],
+        [48.39, 47.20, 47.72],
+        [48.66, 47.90, 48.62],
+        [48.79, 47.73, 47.85],
+    ]
+    .iter()
+    .map(|x| Ohlcv::new(x[2], x[0], x[1], x[2], 1.0))
+    .collect()
+}
+
+#[cfg(test)]
+pub(crate) fn closes(closes: &[f64]) -> Vec<Ohlcv> {
+    closes
+        .iter()
+        .map(|x| Ohlcv::new(*x, *x, *x, *x, 1.0))
+        .collect()
+}
+
+#[cfg(test)]
+pub(crate) fn range_of(bars: &[Ohlcv], period: usize) -> (f64, f64) {
+    // highest high and lowest low of the last period bars, scanned
+    let window = &bars[bars.len().saturating_sub(period)..];
+    let high = window.iter().map(|x| x.high).fold(f64::MIN, f64::max);
+    let low = window.iter().map(|x| x.low).fold(f64::MAX, f64::min);
+    (high, low)
+}
+
+#[cfg(test)]
+pub(crate) fn assert_close(value: f64, expected: f64) {
+    assert!(
+        (value - expected).abs() < 1e-6,
+        "{} is not {}",
+        value,
+        expected
+    );
+}
+
+#[cfg(test)]
+pub(crate) fn assert_published(values: &[f64], published: &[f64]) {
+    // published values are rounded to two decimals
+    assert_eq!(values.len(), published.len());
+    for (i, (value, expected)) in values.iter().zip(published.iter()).enumerate() {
+        assert!(
+            (value - expected).abs() <= 0.01,
+            "{}: {} is not {}",
+            i,
+            value,
+            expected
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ready_after<T: WarmUp>(mut indicator: T, mut next: impl FnMut(&mut T, Ohlcv)) -> (u64, u64) {
+        // bars it took to get ready, and the warm-up it reported
+        let mut bars = 0;
+        for bar in atr_example_bars() {
+            if indicator.is_ready() {
+                break;
+            }
+            next(&mut indicator, bar);
+            bars += 1;
+        }
+        (bars, indicator.warm_up())
+    }
+
+    #[test]
+    fn indicators_are_ready_after_their_warm_up() {
+        let checks = vec![
+            ready_after(moving_average::SimpleMovingAverage::new(5), |x, bar| {
+                x.next(bar.close);
+            }),
+            ready_after(
+                moving_average::ExponentialMovingAverage::wilder(5),
+                |x, bar| {
+                    x.next(bar.close);
+                },
+            ),
+            ready_after(max_in_range::MaxmimumInRange::new(6, 2), |x, bar| {
+                x.next(bar.high);
+            }),
+            ready_after(rsi::RelativeStrengthIndex::new(14), |x, bar| {
+                x.next(bar);
+            }),
+            ready_after(
+                macd::MovingAverageConvergenceDivergence::new(3, 6, 4),
+                |x, bar| {
+                    x.next(bar);
+                },
+            ),
+            ready_after(stochastic::StochasticOscillator::new(14, 3), |x, bar| {
+                x.next(bar);
+            }),
+            ready_after(
+                keltner_channels::KeltnerChannels::new(5, 10, 2.0),
+                |x, bar| {
+                    x.next(bar);
+                },
+            ),
+            ready_after(ichimoku::Ichimoku::new(9, 26, 20), |x, bar| {
+                x.next(bar);
+            }),
+            ready_after(parabolic_sar::ParabolicSar::new(0.02, 0.2), |x, bar| {
+                x.next(bar);
+            }),
+            ready_after(
+                adx_indicator::AverageDirectionalMovementIndex::new(14),
+                |x, bar| {
+                    x.next(bar);
+                },
+            ),
+        ];
+        for (bars, warm_up) in checks {
+            assert_eq!(bars, warm_up);
+        }
+    }
 }
@@ -0,0 +1,145 @@
+use std::collections::VecDeque;
+
+use super::{NextI, WarmUp};
+
+// the averages the other indicators smooth with. until a full period has been seen they return
+// the mean of what they have, so there is a value from the first input on.
+
+pub struct SimpleMovingAverage {
+    period: usize,
+    window: VecDeque<f64>,
+    sum: f64,
+}
+
+impl SimpleMovingAverage {
+    pub fn new(period: u64) -> Self {
+        assert!(period > 0);
+        SimpleMovingAverage {
+            period: period as usize,
+            window: VecDeque::with_capacity(period as usize + 1),
+            sum: 0.0,
+        }
+    }
+}
+
+impl NextI for SimpleMovingAverage {
+    fn next(&mut self, input: f64) -> f64 {
+        self.window.push_back(input);
+        self.sum += input;
+        if self.window.len() > self.period {
+            self.sum -= self.window.pop_front().unwrap_or(0.0);
+        }
+        self.sum / self.window.len() as f64
+    }
+}
+
+pub struct ExponentialMovingAverage {
+    period: u64,
+    alpha: f64,
+    count: u64,
+    value: f64,
+}
+
+impl ExponentialMovingAverage {
+    pub fn new(period: u64) -> Self {
+        assert!(period > 0);
+        ExponentialMovingAverage {
+            period,
+            alpha: 2.0 / (period as f64 + 1.0),
+            count: 0,
+            value: 0.0,
+        }
+    }
+
+    pub fn wilder(period: u64) -> Self {
+        /*
+            Wilder's smoothing, used by rsi, atr and adx. The same average with 1/period as the weight
+            of the new input.
+        */
+        let mut average = ExponentialMovingAverage::new(period);
+        average.alpha = 1.0 / period as f64;
+        average
+    }
+}
+
+impl NextI for ExponentialMovingAverage {
+    fn next(&mut self, input: f64) -> f64 {
+        if self.count < self.period {
+            self.count += 1;
+            self.value += (input - self.value) / self.count as f64;
+        } else {
+            self.value += (input - self.value) * self.alpha;
+        }
+        self.value
+    }
+}
+
+impl WarmUp for SimpleMovingAverage {
+    fn warm_up(&self) -> u64 {
+        self.period as u64
+    }
+
+    fn is_ready(&self) -> bool {
+        self.window.len() >= self.period
+    }
+}
+
+impl WarmUp for ExponentialMovingAverage {
+    fn warm_up(&self) -> u64 {
+        self.period
+    }
+
+    fn is_ready(&self) -> bool {
+        self.count >= self.period
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{assert_close, assert_published};
+    use super::*;
+
+    #[test]
+    fn averages_warm_up_on_the_running_mean() {
+        let mut sma = SimpleMovingAverage::new(3);
+        let mut ema = ExponentialMovingAverage::new(3);
+        let mut wilder = ExponentialMovingAverage::wilder(3);
+        let inputs = [1.0, 2.0, 3.0, 7.0];
+        let last: Vec<(f64, f64, f64)> = inputs
+            .iter()
+            .map(|x| (sma.next(*x), ema.next(*x), wilder.next(*x)))
+            .collect();
+        assert_eq!(last[1], (1.5, 1.5, 1.5));
+        assert_eq!(last[2], (2.0, 2.0, 2.0));
+        assert_eq!((last[3].0, last[3].1), (4.0, 4.5));
+        assert_close(last[3].2, 11.0 / 3.0);
+    }
+
+    #[test]
+    fn matches_the_stockcharts_example() {
+        // 10 day averages of the stockcharts moving average example (chartschool, cs-movavg)
+        let closes = [
+            22.27, 22.19, 22.08, 22.17, 22.18, 22.13, 22.23, 22.43, 22.24, 22.29, 22.15, 22.39,
+            22.38, 22.61, 23.36, 24.05, 23.75, 23.83, 23.95, 23.63, 23.82, 23.87, 23.65, 23.19,
+            23.10, 23.33, 22.68, 23.10, 22.40, 22.17,
+        ];
+        let mut sma = SimpleMovingAverage::new(10);
+        let mut ema = ExponentialMovingAverage::new(10);
+        let (smas, emas): (Vec<f64>, Vec<f64>) =
+            closes.iter().map(|x| (sma.next(*x), ema.next(*x))).unzip();
+        assert_published(
+            &smas[9..],
+            &[
+                22.22, 22.21, 22.23, 22.26, 22.31, 22.42, 22.61, 22.77, 22.91, 23.08, 23.21, 23.38,
+                23.53, 23.65, 23.71, 23.69, 23.61, 23.51, 23.43, 23.28, 23.13,
+            ],
+        );
+        assert_published(
+            &emas[9..],
+            &[
+                22.22, 22.21, 22.24, 22.27, 22.33, 22.52, 22.80, 22.97, 23.13, 23.28, 23.34, 23.43,
+                23.51, 23.54, 23.47, 23.40, 23.39, 23.26, 23.23, 23.08, 22.92,
+            ],
+        );
+    }
+}
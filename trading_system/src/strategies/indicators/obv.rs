@@ -0,0 +1,59 @@
+use super::{NextI, Ohlcv, WarmUp};
+
+// running total of volume, added on up closes and taken off on down closes. starts at 0.
+#[derive(Default)]
+pub struct OnBalanceVolume {
+    prev_close: Option<f64>,
+    total: f64,
+}
+
+impl OnBalanceVolume {
+    pub fn new() -> Self {
+        OnBalanceVolume::default()
+    }
+}
+
+impl NextI<Ohlcv> for OnBalanceVolume {
+    fn next(&mut self, bar: Ohlcv) -> f64 {
+        if let Some(prev_close) = self.prev_close.replace(bar.close) {
+            if bar.close > prev_close {
+                self.total += bar.volume;
+            } else if bar.close < prev_close {
+                self.total -= bar.volume;
+            }
+        }
+        self.total
+    }
+}
+
+impl WarmUp for OnBalanceVolume {
+    fn warm_up(&self) -> u64 {
+        1
+    }
+
+    fn is_ready(&self) -> bool {
+        self.prev_close.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adds_volume_on_up_closes() {
+        let mut obv = OnBalanceVolume::new();
+        let bars = [
+            (10.0, 100.0),
+            (11.0, 200.0),
+            (11.0, 300.0),
+            (10.5, 400.0),
+            (12.0, 500.0),
+        ];
+        let values: Vec<f64> = bars
+            .iter()
+            .map(|(close, volume)| obv.next(Ohlcv::new(*close, *close, *close, *close, *volume)))
+            .collect();
+        assert_eq!(values, vec![0.0, 200.0, 200.0, -200.0, 300.0]);
+    }
+}
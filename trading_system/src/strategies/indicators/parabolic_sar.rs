@@ -0,0 +1,120 @@
+use super::{NextI, Ohlcv, WarmUp};
+
+// wilder's parabolic stop and reverse. the trend is set by the first two closes, the stop starts at the first bar's
+// low (or high) and moves toward the extreme price by the acceleration factor, which grows by step on each new
+// extreme up to max_step. it never goes inside the previous two bars, and reverses when price touches it.
+pub struct ParabolicSar {
+    step: f64,
+    max_step: f64,
+    sar: f64,
+    extreme: f64,
+    acceleration: f64,
+    long: bool,
+    // the previous two bars, most recent last
+    prev: Vec<Ohlcv>,
+}
+
+impl ParabolicSar {
+    pub fn new(step: f64, max_step: f64) -> Self {
+        ParabolicSar {
+            step,
+            max_step,
+            sar: 0.0,
+            extreme: 0.0,
+            acceleration: step,
+            long: true,
+            prev: Vec::with_capacity(3),
+        }
+    }
+}
+
+impl NextI<Ohlcv> for ParabolicSar {
+    fn next(&mut self, bar: Ohlcv) -> f64 {
+        match self.prev.len() {
+            0 => self.sar = bar.low,
+            1 => {
+                let first = self.prev[0];
+                self.long = bar.close >= first.close;
+                if self.long {
+                    self.sar = first.low;
+                    self.extreme = first.high.max(bar.high);
+                } else {
+                    self.sar = first.high;
+                    self.extreme = first.low.min(bar.low);
+                }
+                self.acceleration = self.step;
+            }
+            _ => {
+                let (older, last) = (self.prev[0], self.prev[1]);
+                self.sar += self.acceleration * (self.extreme - self.sar);
+                if self.long {
+                    self.sar = self.sar.min(last.low).min(older.low);
+                    if bar.low <= self.sar {
+                        self.long = false;
+                        self.sar = self.extreme;
+                        self.extreme = bar.low;
+                        self.acceleration = self.step;
+                    } else if bar.high > self.extreme {
+                        self.extreme = bar.high;
+                        self.acceleration = (self.acceleration + self.step).min(self.max_step);
+                    }
+                } else {
+                    self.sar = self.sar.max(last.high).max(older.high);
+                    if bar.high >= self.sar {
+                        self.long = true;
+                        self.sar = self.extreme;
+                        self.extreme = bar.high;
+                        self.acceleration = self.step;
+                    } else if bar.low < self.extreme {
+                        self.extreme = bar.low;
+                        self.acceleration = (self.acceleration + self.step).min(self.max_step);
+                    }
+                }
+            }
+        }
+        self.prev.push(bar);
+        if self.prev.len() > 2 {
+            self.prev.remove(0);
+        }
+        self.sar
+    }
+}
+
+impl WarmUp for ParabolicSar {
+    fn warm_up(&self) -> u64 {
+        // the first two bars set the trend
+        2
+    }
+
+    fn is_ready(&self) -> bool {
+        self.prev.len() >= 2
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::assert_close;
+    use super::*;
+
+    #[test]
+    fn trails_and_reverses() {
+        let mut sar = ParabolicSar::new(0.02, 0.2);
+        let bars = [
+            Ohlcv::new(9.5, 10.0, 9.0, 9.5, 1.0),
+            Ohlcv::new(9.5, 11.0, 9.5, 10.5, 1.0),
+            Ohlcv::new(10.5, 12.0, 10.5, 11.5, 1.0),
+            Ohlcv::new(11.5, 13.0, 11.5, 12.5, 1.0),
+            Ohlcv::new(12.5, 12.5, 9.0, 9.5, 1.0),
+            Ohlcv::new(9.5, 10.0, 8.5, 9.0, 1.0),
+        ];
+        let values: Vec<f64> = bars.iter().map(|x| sar.next(*x)).collect();
+        // up from the second close, starting at the first low. 9 + 0.02 * (11 - 9) is held under the first low.
+        assert_eq!(values[..3], [9.0, 9.0, 9.0]);
+        // 9 + 0.04 * (12 - 9)
+        assert_close(values[3], 9.12);
+        // 9.12 + 0.06 * (13 - 9.12) is hit by the low of 9, the stop reverses to the extreme high
+        assert_close(values[4], 13.0);
+        // 13 - 0.02 * (13 - 9) is held over the high of two bars ago
+        assert_close(values[5], 13.0);
+    }
+}
@@ -0,0 +1,77 @@
+use super::moving_average::ExponentialMovingAverage;
+use super::{NextI, Ohlcv, WarmUp};
+
+// wilder's relative strength index of the closes, 0 to 100. 50 on the first bar.
+pub struct RelativeStrengthIndex {
+    gains: ExponentialMovingAverage,
+    losses: ExponentialMovingAverage,
+    prev_close: Option<f64>,
+}
+
+impl RelativeStrengthIndex {
+    pub fn new(period: u64) -> Self {
+        RelativeStrengthIndex {
+            gains: ExponentialMovingAverage::wilder(period),
+            losses: ExponentialMovingAverage::wilder(period),
+            prev_close: None,
+        }
+    }
+}
+
+impl NextI<Ohlcv> for RelativeStrengthIndex {
+    fn next(&mut self, bar: Ohlcv) -> f64 {
+        let prev_close = match self.prev_close.replace(bar.close) {
+            Some(x) => x,
+            None => return 50.0,
+        };
+        let gain = self.gains.next((bar.close - prev_close).max(0.0));
+        let loss = self.losses.next((prev_close - bar.close).max(0.0));
+        if loss == 0.0 {
+            if gain > 0.0 {
+                100.0
+            } else {
+                50.0
+            }
+        } else {
+            100.0 - 100.0 / (1.0 + gain / loss)
+        }
+    }
+}
+
+impl WarmUp for RelativeStrengthIndex {
+    fn warm_up(&self) -> u64 {
+        // the first bar has no change
+        self.gains.warm_up() + 1
+    }
+
+    fn is_ready(&self) -> bool {
+        self.gains.is_ready()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{assert_published, closes};
+    use super::*;
+
+    #[test]
+    fn matches_the_stockcharts_example() {
+        // the stockcharts rsi example (chartschool, cs-rsi)
+        let bars = closes(&[
+            44.3389, 44.0902, 44.1497, 43.6124, 44.3278, 44.8264, 45.0955, 45.4245, 45.8433,
+            46.0826, 45.8931, 46.0328, 45.6140, 46.2820, 46.2820, 46.0028, 46.0328, 46.4116,
+            46.2222, 45.6439, 46.2122, 46.2521, 45.7137, 46.4515, 45.7835, 45.3548, 44.0288,
+            44.1783, 44.2181, 44.5672, 43.4205, 42.6628, 43.1314,
+        ]);
+        let mut rsi = RelativeStrengthIndex::new(14);
+        let values: Vec<f64> = bars.into_iter().map(|x| rsi.next(x)).collect();
+        assert_eq!(values[0], 50.0);
+        assert_published(
+            &values[14..],
+            &[
+                70.53, 66.32, 66.55, 69.41, 66.36, 57.97, 62.93, 63.26, 56.06, 62.38, 54.71, 50.42,
+                39.99, 41.46, 41.87, 45.46, 37.30, 33.08, 37.77,
+            ],
+        );
+    }
+}
@@ -0,0 +1,113 @@
+use super::max_in_range::MaxmimumInRange;
+use super::min_in_range::MinimumInRange;
+use super::moving_average::SimpleMovingAverage;
+use super::{NextI, Ohlcv, WarmUp};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct StochasticOutput {
+    pub k: f64,
+    pub d: f64,
+}
+
+// where the close is in the range of the last period's highs and lows, 0 to 100, and a simple average of that as %d.
+// 50 when the range is empty.
+pub struct StochasticOscillator {
+    highest: MaxmimumInRange,
+    lowest: MinimumInRange,
+    d: SimpleMovingAverage,
+    count: u64,
+}
+
+impl StochasticOscillator {
+    pub fn new(period: u64, d_period: u64) -> Self {
+        StochasticOscillator {
+            highest: MaxmimumInRange::new(period, 0),
+            lowest: MinimumInRange::new(period, 0),
+            d: SimpleMovingAverage::new(d_period),
+            count: 0,
+        }
+    }
+}
+
+impl NextI<Ohlcv, StochasticOutput> for StochasticOscillator {
+    fn next(&mut self, bar: Ohlcv) -> StochasticOutput {
+        self.count += 1;
+        let highest = self.highest.next(bar.high);
+        let lowest = self.lowest.next(bar.low);
+        let k = if highest > lowest {
+            100.0 * (bar.close - lowest) / (highest - lowest)
+        } else {
+            50.0
+        };
+        StochasticOutput {
+            k,
+            d: self.d.next(k),
+        }
+    }
+}
+
+impl WarmUp for StochasticOscillator {
+    fn warm_up(&self) -> u64 {
+        // %d averages %k values that each had a full range
+        self.highest.warm_up() + self.d.warm_up() - 1
+    }
+
+    fn is_ready(&self) -> bool {
+        self.count >= self.warm_up()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::assert_published;
+    use super::*;
+
+    #[test]
+    fn matches_the_stockcharts_example() {
+        // the stockcharts %k example (chartschool, cs-stochastic), highs and lows of 30 bars and the closes from the
+        // 14th. %k only uses closes from its first full range on, so the earlier closes are the midpoint.
+        let highs = [
+            127.0090, 127.6159, 126.5911, 127.3472, 128.1730, 128.4317, 127.3671, 126.4220,
+            126.8995, 126.8498, 125.6460, 125.7156, 127.1582, 127.7154, 127.6855, 128.2228,
+            128.2725, 128.0934, 128.2725, 127.7353, 128.7700, 129.2873, 130.0633, 129.1182,
+            129.2873, 128.4715, 128.0934, 128.6506, 129.1381, 128.6406,
+        ];
+        let lows = [
+            125.3574, 126.1633, 124.9296, 126.0937, 126.8199, 126.4817, 126.0340, 124.8301,
+            126.3921, 125.7156, 124.5615, 124.5715, 125.0689, 126.8597, 126.6309, 126.8001,
+            126.7105, 126.8001, 126.1335, 125.9245, 126.9891, 127.8148, 128.4715, 128.0641,
+            127.6059, 127.5960, 126.9990, 126.8995, 127.4865, 127.3970,
+        ];
+        let closes = [
+            127.2876, 127.1781, 128.0138, 127.1085, 127.7253, 127.0587, 127.3273, 128.7103,
+            127.8745, 128.5809, 128.6008, 127.9342, 128.1133, 127.5960, 127.5960, 128.6904,
+            128.2725,
+        ];
+        let mut stochastic = StochasticOscillator::new(14, 3);
+        let values: Vec<StochasticOutput> = (0..highs.len())
+            .map(|i| {
+                let close = if i < 13 {
+                    (highs[i] + lows[i]) / 2.0
+                } else {
+                    closes[i - 13]
+                };
+                stochastic.next(Ohlcv::new(close, highs[i], lows[i], close, 1.0))
+            })
+            .collect();
+        let k: Vec<f64> = values[13..].iter().map(|x| x.k).collect();
+        assert_published(
+            &k,
+            &[
+                70.44, 67.61, 89.20, 65.81, 81.75, 64.52, 74.53, 98.58, 70.10, 73.06, 73.42, 61.23,
+                60.96, 40.39, 40.39, 66.83, 56.73,
+            ],
+        );
+        // %d starts on the third full %k
+        let d: Vec<f64> = values[15..17].iter().map(|x| x.d).collect();
+        assert_published(&d, &[75.75, 74.21]);
+
+        let mut stochastic = StochasticOscillator::new(14, 3);
+        let flat = Ohlcv::new(1.0, 1.0, 1.0, 1.0, 1.0);
+        assert_eq!(stochastic.next(flat).k, 50.0);
+    }
+}
@@ -0,0 +1,105 @@
+use super::atr::AverageTrueRange;
+use super::{NextI, Ohlcv, WarmUp};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SupertrendOutput {
+    // the lower band in an uptrend, the upper band in a downtrend
+    pub value: f64,
+    pub uptrend: bool,
+}
+
+// bands a multiple of the atr around the bar's median price. the lower band only rises and the upper band only
+// falls while the trend holds, the trend flips when the close crosses the band. starts in an uptrend.
+pub struct Supertrend {
+    atr: AverageTrueRange,
+    multiplier: f64,
+    // (upper, lower, uptrend, close) of the previous bar
+    prev: Option<(f64, f64, bool, f64)>,
+}
+
+impl Supertrend {
+    pub fn new(atr_period: u64, multiplier: f64) -> Self {
+        Supertrend {
+            atr: AverageTrueRange::new(atr_period),
+            multiplier,
+            prev: None,
+        }
+    }
+}
+
+impl NextI<Ohlcv, SupertrendOutput> for Supertrend {
+    fn next(&mut self, bar: Ohlcv) -> SupertrendOutput {
+        let width = self.multiplier * self.atr.next(bar);
+        let basic_upper = bar.median_price() + width;
+        let basic_lower = bar.median_price() - width;
+        let (upper, lower, uptrend) = match self.prev {
+            None => (basic_upper, basic_lower, true),
+            Some((prev_upper, prev_lower, prev_uptrend, prev_close)) => {
+                let upper = if basic_upper < prev_upper || prev_close > prev_upper {
+                    basic_upper
+                } else {
+                    prev_upper
+                };
+                let lower = if basic_lower > prev_lower || prev_close < prev_lower {
+                    basic_lower
+                } else {
+                    prev_lower
+                };
+                let uptrend = if prev_uptrend {
+                    bar.close >= lower
+                } else {
+                    bar.close > upper
+                };
+                (upper, lower, uptrend)
+            }
+        };
+        self.prev = Some((upper, lower, uptrend, bar.close));
+        SupertrendOutput {
+            value: if uptrend { lower } else { upper },
+            uptrend,
+        }
+    }
+}
+
+impl WarmUp for Supertrend {
+    fn warm_up(&self) -> u64 {
+        self.atr.warm_up()
+    }
+
+    fn is_ready(&self) -> bool {
+        self.atr.is_ready()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::assert_close;
+    use super::*;
+
+    #[test]
+    fn ratchets_the_bands_and_flips_on_a_close_through_them() {
+        // the atr of one bar is its true range
+        let mut supertrend = Supertrend::new(1, 1.0);
+        let bars = [
+            Ohlcv::new(10.0, 11.0, 9.0, 10.0, 1.0),
+            Ohlcv::new(10.0, 12.0, 10.0, 11.5, 1.0),
+            Ohlcv::new(11.5, 11.5, 8.0, 8.5, 1.0),
+            Ohlcv::new(8.5, 9.0, 7.0, 7.5, 1.0),
+            Ohlcv::new(7.5, 11.0, 9.0, 10.5, 1.0),
+        ];
+        let values: Vec<SupertrendOutput> = bars.iter().map(|x| supertrend.next(*x)).collect();
+        // 10 - 2, then the lower band rises to 11 - 2
+        assert_eq!((values[0].uptrend, values[1].uptrend), (true, true));
+        assert_close(values[0].value, 8.0);
+        assert_close(values[1].value, 9.0);
+        // the close under 9 turns it down onto the upper band, held at 12
+        assert!(!values[2].uptrend);
+        assert_close(values[2].value, 12.0);
+        // the upper band falls to 8 + 2
+        assert!(!values[3].uptrend);
+        assert_close(values[3].value, 10.0);
+        // the close over 10 turns it up onto the lower band, 10 - 3.5
+        assert!(values[4].uptrend);
+        assert_close(values[4].value, 6.5);
+    }
+}
@@ -0,0 +1,76 @@
+use std::collections::VecDeque;
+
+use super::{NextI, Ohlcv, WarmUp};
+
+// volume weighted typical price of the last period bars. the close if they had no volume.
+pub struct VolumeWeightedAveragePrice {
+    period: usize,
+    // (typical price * volume, volume)
+    window: VecDeque<(f64, f64)>,
+}
+
+impl VolumeWeightedAveragePrice {
+    pub fn new(period: u64) -> Self {
+        assert!(period > 0);
+        VolumeWeightedAveragePrice {
+            period: period as usize,
+            window: VecDeque::with_capacity(period as usize + 1),
+        }
+    }
+}
+
+impl NextI<Ohlcv> for VolumeWeightedAveragePrice {
+    fn next(&mut self, bar: Ohlcv) -> f64 {
+        self.window
+            .push_back((bar.typical_price() * bar.volume, bar.volume));
+        if self.window.len() > self.period {
+            self.window.pop_front();
+        }
+        let (value, volume) = self
+            .window
+            .iter()
+            .fold((0.0, 0.0), |(a, b), (x, y)| (a + x, b + y));
+        if volume > 0.0 {
+            value / volume
+        } else {
+            bar.close
+        }
+    }
+}
+
+impl WarmUp for VolumeWeightedAveragePrice {
+    fn warm_up(&self) -> u64 {
+        self.period as u64
+    }
+
+    fn is_ready(&self) -> bool {
+        self.window.len() >= self.period
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::assert_close;
+    use super::*;
+
+    #[test]
+    fn weighs_typical_prices_by_volume() {
+        let mut vwap = VolumeWeightedAveragePrice::new(3);
+        // typical prices 10.5, 11, 12 and 13
+        let bars = [
+            Ohlcv::new(10.0, 12.0, 9.0, 10.5, 100.0),
+            Ohlcv::new(10.5, 12.0, 10.0, 11.0, 300.0),
+            Ohlcv::new(11.0, 13.0, 11.0, 12.0, 100.0),
+            Ohlcv::new(12.0, 14.0, 12.0, 13.0, 0.0),
+        ];
+        let values: Vec<f64> = bars.iter().map(|x| vwap.next(*x)).collect();
+        assert_close(values[0], 10.5);
+        assert_close(values[1], (1050.0 + 3300.0) / 400.0);
+        assert_close(values[2], (1050.0 + 3300.0 + 1200.0) / 500.0);
+        // the first bar left the window
+        assert_close(values[3], (3300.0 + 1200.0) / 400.0);
+
+        let mut vwap = VolumeWeightedAveragePrice::new(10);
+        assert_eq!(vwap.next(Ohlcv::new(1.0, 2.0, 0.5, 1.5, 0.0)), 1.5);
+    }
+}
@@ -1,11 +1,43 @@
 pub mod adx;
+pub mod combinators;
 pub mod ema_sma_adx;
 pub mod ema_sma_crossover;
-pub mod indicators;
+pub mod script;
 pub mod sma_crossover;
 
+pub use trading_system::indicators;
+
+// the position a strategy wants: the share of its algo's capital in the pair's base asset, from 0 to 1,
+// and optionally the prices to get out at
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Target {
+    pub weight: f64,
+    pub stop: Option<f64>,
+    pub take_profit: Option<f64>,
+}
+
+impl Target {
+    pub fn weight(weight: f64) -> Self {
+        Target {
+            weight,
+            stop: None,
+            take_profit: None,
+        }
+    }
+}
+
 pub trait TradingStrategy {
-    fn new(strategy_settings: Vec<f64>) -> Self;
     fn run(&mut self, ohlcs_in_window: &Vec<f64>) -> i8;
+    // the position wanted after the bar, None to hold. by default 1 is all in and -1 all out.
+    fn target(&mut self, ohlc: &Vec<f64>) -> Option<Target> {
+        match self.run(ohlc) {
+            1 => Some(Target::weight(1.0)),
+            -1 => Some(Target::weight(0.0)),
+            _ => None,
+        }
+    }
+    // bars run has to see before its signals mean anything, and whether it has seen them
+    fn warm_up(&self) -> u64;
+    fn is_ready(&self) -> bool;
     fn to_string(&self) -> String;
 }
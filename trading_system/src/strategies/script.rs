@@ -0,0 +1,610 @@
+use std::cell::{Cell, RefCell};
+use std::fs;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use rhai::module_resolvers::DummyModuleResolver;
+use rhai::{Array, CallFnOptions, Dynamic, Engine, EvalAltResult, Map, Scope, AST};
+
+use super::indicators::adx_indicator::AverageDirectionalMovementIndex;
+use super::indicators::atr::AverageTrueRange;
+use super::indicators::bollinger_bands::BollingerBands;
+use super::indicators::donchian_channels::DonchianChannels;
+use super::indicators::keltner_channels::KeltnerChannels;
+use super::indicators::macd::MovingAverageConvergenceDivergence;
+use super::indicators::max_in_range::MaxmimumInRange;
+use super::indicators::min_in_range::MinimumInRange;
+use super::indicators::moving_average::{ExponentialMovingAverage, SimpleMovingAverage};
+use super::indicators::obv::OnBalanceVolume;
+use super::indicators::parabolic_sar::ParabolicSar;
+use super::indicators::rsi::RelativeStrengthIndex;
+use super::indicators::stochastic::StochasticOscillator;
+use super::indicators::supertrend::Supertrend;
+use super::indicators::vwap::VolumeWeightedAveragePrice;
+use super::indicators::{Bands, NextI, Ohlcv, WarmUp};
+use super::{Target, TradingStrategy};
+
+// strategies written in rhai, see _Scripts_ in docs/accounts.txt. a script defines
+//     fn init(settings) { .. }   optional, makes the indicators and returns the state, a map by default
+//     fn run(bar) { .. }         called on every bar with the state as `this`, returns -1, 0 or 1, or a target
+// statements outside of them run once when the script loads.
+// scripts can't reach files, modules or eval, and every call has an operation and a time budget.
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+// longest period a script can give an indicator, far past the history jane keeps
+const MAX_PERIOD: u64 = 1_000_000;
+
+// budget of one call into a script
+#[derive(Clone, Copy, Debug)]
+pub struct ScriptLimits {
+    pub max_operations: u64,
+    pub timeout_ms: u64,
+}
+
+impl Default for ScriptLimits {
+    fn default() -> Self {
+        ScriptLimits {
+            max_operations: 1_000_000,
+            timeout_ms: 50,
+        }
+    }
+}
+
+// an indicator made by a script, with how its output looks to the script
+trait Feed {
+    fn feed(&mut self, bar: Ohlcv) -> Dynamic;
+    fn warm_up(&self) -> u64;
+    fn is_ready(&self) -> bool;
+}
+
+struct Fed<T> {
+    indicator: T,
+    output: fn(&mut T, Ohlcv) -> Dynamic,
+}
+
+impl<T: WarmUp> Feed for Fed<T> {
+    fn feed(&mut self, bar: Ohlcv) -> Dynamic {
+        (self.output)(&mut self.indicator, bar)
+    }
+
+    fn warm_up(&self) -> u64 {
+        self.indicator.warm_up()
+    }
+
+    fn is_ready(&self) -> bool {
+        self.indicator.is_ready()
+    }
+}
+
+// the handle a script keeps in its state. copies of it share the indicator.
+#[derive(Clone)]
+pub struct Indicator(Rc<RefCell<dyn Feed>>);
+
+// what the indicator constructors share with the strategy
+#[derive(Clone)]
+struct Made {
+    indicators: Rc<RefCell<Vec<Indicator>>>,
+    in_init: Rc<Cell<bool>>,
+}
+
+impl Made {
+    fn make<T: WarmUp + 'static>(
+        &self,
+        indicator: T,
+        output: fn(&mut T, Ohlcv) -> Dynamic,
+    ) -> ScriptResult<Indicator> {
+        // made on every bar they would never warm up, and pile up
+        if !self.in_init.get() {
+            return Err("indicators can only be made in init".into());
+        }
+        let indicator = Indicator(Rc::new(RefCell::new(Fed { indicator, output })));
+        self.indicators.borrow_mut().push(indicator.clone());
+        Ok(indicator)
+    }
+}
+
+fn number(x: &Dynamic) -> ScriptResult<f64> {
+    x.as_float()
+        .or_else(|_| x.as_int().map(|x| x as f64))
+        .map_err(|_| format!("expected a number, not {}", x.type_name()).into())
+}
+
+fn period(x: &Dynamic) -> ScriptResult<u64> {
+    // indicators set aside room for a period of bars when they're made
+    let x = number(x)?;
+    if x.is_nan() || x < 1.0 || x > MAX_PERIOD as f64 {
+        return Err(format!("a period must be from 1 to {}, not {}", MAX_PERIOD, x).into());
+    }
+    Ok(x as u64)
+}
+
+fn map(fields: &[(&str, f64)]) -> Dynamic {
+    let mut map = Map::new();
+    for (key, value) in fields {
+        map.insert((*key).into(), Dynamic::from(*value));
+    }
+    map.into()
+}
+
+fn bands(x: Bands) -> Dynamic {
+    map(&[("upper", x.upper), ("middle", x.middle), ("lower", x.lower)])
+}
+
+fn register_indicators(engine: &mut Engine, made: &Made) {
+    /*
+        The indicators of indicators/ as functions making a handle, e.g. `let fast = ema(12);`.
+        `fast.next(bar)` feeds it a bar and returns its value, a number or a map for the ones with several lines.
+        `fast.next(x)` feeds it a number, as a bar with x for every price.
+    */
+    let m = made.clone();
+    engine.register_fn("sma", move |n: Dynamic| {
+        m.make(SimpleMovingAverage::new(period(&n)?), |x, bar| {
+            x.next(bar.close).into()
+        })
+    });
+    let m = made.clone();
+    engine.register_fn("ema", move |n: Dynamic| {
+        m.make(ExponentialMovingAverage::new(period(&n)?), |x, bar| {
+            x.next(bar.close).into()
+        })
+    });
+    let m = made.clone();
+    engine.register_fn("wilder", move |n: Dynamic| {
+        m.make(ExponentialMovingAverage::wilder(period(&n)?), |x, bar| {
+            x.next(bar.close).into()
+        })
+    });
+    let m = made.clone();
+    engine.register_fn("highest", move |far: Dynamic, near: Dynamic| {
+        let (far, near) = (period(&far)?, number(&near)?.max(0.0) as u64);
+        if far <= near {
+            return Err("highest needs more bars far back than near".into());
+        }
+        m.make(MaxmimumInRange::new(far, near), |x, bar| {
+            x.next(bar.high).into()
+        })
+    });
+    let m = made.clone();
+    engine.register_fn("lowest", move |far: Dynamic, near: Dynamic| {
+        let (far, near) = (period(&far)?, number(&near)?.max(0.0) as u64);
+        if far <= near {
+            return Err("lowest needs more bars far back than near".into());
+        }
+        m.make(MinimumInRange::new(far, near), |x, bar| {
+            x.next(bar.low).into()
+        })
+    });
+    let m = made.clone();
+    engine.register_fn("rsi", move |n: Dynamic| {
+        m.make(RelativeStrengthIndex::new(period(&n)?), |x, bar| {
+            x.next(bar).into()
+        })
+    });
+    let m = made.clone();
+    engine.register_fn("atr", move |n: Dynamic| {
+        m.make(AverageTrueRange::new(period(&n)?), |x, bar| {
+            x.next(bar).into()
+        })
+    });
+    let m = made.clone();
+    engine.register_fn("adx", move |n: Dynamic| {
+        m.make(
+            AverageDirectionalMovementIndex::new(period(&n)?),
+            |x, bar| {
+                let out = x.next(bar);
+                map(&[
+                    ("plus_di", out.plus_di),
+                    ("minus_di", out.minus_di),
+                    ("adx", out.adx),
+                ])
+            },
+        )
+    });
+    let m = made.clone();
+    engine.register_fn(
+        "macd",
+        move |fast: Dynamic, slow: Dynamic, signal: Dynamic| {
+            let (fast, slow) = (period(&fast)?, period(&slow)?);
+            if fast >= slow {
+                return Err("macd needs a fast period shorter than the slow one".into());
+            }
+            let indicator = MovingAverageConvergenceDivergence::new(fast, slow, period(&signal)?);
+            m.make(indicator, |x, bar| {
+                let out = x.next(bar);
+                map(&[
+                    ("macd", out.macd),
+                    ("signal", out.signal),
+                    ("histogram", out.histogram),
+                ])
+            })
+        },
+    );
+    let m = made.clone();
+    engine.register_fn("bollinger", move |n: Dynamic, k: Dynamic| {
+        m.make(BollingerBands::new(period(&n)?, number(&k)?), |x, bar| {
+            bands(x.next(bar))
+        })
+    });
+    let m = made.clone();
+    engine.register_fn("keltner", move |n: Dynamic, atr_n: Dynamic, k: Dynamic| {
+        let indicator = KeltnerChannels::new(period(&n)?, period(&atr_n)?, number(&k)?);
+        m.make(indicator, |x, bar| bands(x.next(bar)))
+    });
+    let m = made.clone();
+    engine.register_fn("donchian", move |n: Dynamic| {
+        m.make(DonchianChannels::new(period(&n)?), |x, bar| {
+            bands(x.next(bar))
+        })
+    });
+    let m = made.clone();
+    engine.register_fn("stochastic", move |n: Dynamic, d: Dynamic| {
+        m.make(
+            StochasticOscillator::new(period(&n)?, period(&d)?),
+            |x, bar| {
+                let out = x.next(bar);
+                map(&[("k", out.k), ("d", out.d)])
+            },
+        )
+    });
+    let m = made.clone();
+    engine.register_fn("supertrend", move |n: Dynamic, k: Dynamic| {
+        m.make(Supertrend::new(period(&n)?, number(&k)?), |x, bar| {
+            let out = x.next(bar);
+            let mut map = Map::new();
+            map.insert("value".into(), Dynamic::from(out.value));
+            map.insert("uptrend".into(), Dynamic::from(out.uptrend));
+            map.into()
+        })
+    });
+    let m = made.clone();
+    engine.register_fn("sar", move |step: Dynamic, max_step: Dynamic| {
+        m.make(
+            ParabolicSar::new(number(&step)?, number(&max_step)?),
+            |x, bar| x.next(bar).into(),
+        )
+    });
+    let m = made.clone();
+    engine.register_fn("obv", move || {
+        m.make(OnBalanceVolume::new(), |x, bar| x.next(bar).into())
+    });
+    let m = made.clone();
+    engine.register_fn("vwap", move |n: Dynamic| {
+        m.make(VolumeWeightedAveragePrice::new(period(&n)?), |x, bar| {
+            x.next(bar).into()
+        })
+    });
+
+    engine
+        .register_type_with_name::<Indicator>("Indicator")
+        .register_fn("next", |x: &mut Indicator, bar: Ohlcv| {
+            x.0.borrow_mut().feed(bar)
+        })
+        .register_fn("next", |x: &mut Indicator, value: f64| {
+            x.0.borrow_mut()
+                .feed(Ohlcv::new(value, value, value, value, 0.0))
+        })
+        .register_fn("next", |x: &mut Indicator, value: i64| {
+            let value = value as f64;
+            x.0.borrow_mut()
+                .feed(Ohlcv::new(value, value, value, value, 0.0))
+        })
+        .register_get("ready", |x: &mut Indicator| x.0.borrow().is_ready())
+        .register_get("warm_up", |x: &mut Indicator| x.0.borrow().warm_up() as i64);
+
+    engine
+        .register_type_with_name::<Ohlcv>("Bar")
+        .register_get("open", |x: &mut Ohlcv| x.open)
+        .register_get("high", |x: &mut Ohlcv| x.high)
+        .register_get("low", |x: &mut Ohlcv| x.low)
+        .register_get("close", |x: &mut Ohlcv| x.close)
+        .register_get("volume", |x: &mut Ohlcv| x.volume)
+        .register_get("typical_price", |x: &mut Ohlcv| x.typical_price())
+        .register_get("median_price", |x: &mut Ohlcv| x.median_price());
+}
+
+fn to_target(x: &Dynamic) -> Result<Option<Target>, String> {
+    /*
+        What run returns: -1, 0 or 1 like any strategy, a weight from 0.0 to 1.0,
+        or a map #{weight: .., stop: .., take_profit: ..} where the prices are optional.
+    */
+    let weight = |x: f64| {
+        if (0.0..=1.0).contains(&x) {
+            Ok(x)
+        } else {
+            Err(format!("weight {} is not from 0 to 1", x))
+        }
+    };
+    if let Ok(signal) = x.as_int() {
+        return match signal {
+            1 => Ok(Some(Target::weight(1.0))),
+            -1 => Ok(Some(Target::weight(0.0))),
+            0 => Ok(None),
+            _ => Err(format!("returned {}, not -1, 0 or 1", signal)),
+        };
+    }
+    if let Ok(x) = x.as_float() {
+        return Ok(Some(Target::weight(weight(x)?)));
+    }
+    if let Some(map) = x.read_lock::<Map>() {
+        let price = |key: &str| match map.get(key) {
+            Some(x) if !x.is_unit() => number(x).map(Some).map_err(|e| e.to_string()),
+            _ => Ok(None),
+        };
+        return Ok(Some(Target {
+            weight: weight(price("weight")?.ok_or("the map has no weight")?)?,
+            stop: price("stop")?,
+            take_profit: price("take_profit")?,
+        }));
+    }
+    Err(format!("returned a {}", x.type_name()))
+}
+
+pub struct ScriptStrategy {
+    name: String,
+    strategy_settings: Vec<f64>,
+    engine: Engine,
+    ast: AST,
+    // what init returned, `this` in run
+    state: Dynamic,
+    indicators: Rc<RefCell<Vec<Indicator>>>,
+    // when the running call runs out of time
+    deadline: Rc<Cell<Instant>>,
+    timeout: Duration,
+    bars: u64,
+}
+
+impl ScriptStrategy {
+    pub fn new(
+        path: &str,
+        strategy_settings: Vec<f64>,
+        limits: ScriptLimits,
+    ) -> Result<Self, String> {
+        let source = fs::read_to_string(path).map_err(|e| format!("script {}: {}", path, e))?;
+        ScriptStrategy::from_source(path, &source, strategy_settings, limits)
+    }
+
+    pub fn from_source(
+        name: &str,
+        source: &str,
+        strategy_settings: Vec<f64>,
+        limits: ScriptLimits,
+    ) -> Result<Self, String> {
+        /*
+            Compiles the script and runs its init. Errors if it doesn't compile, has no run(bar),
+            or init fails.
+        */
+        log_debug!(
+            "Constructing ScriptStrategy {} with settings: {:?}",
+            name,
+            strategy_settings
+        );
+        let indicators = Rc::new(RefCell::new(Vec::new()));
+        let in_init = Rc::new(Cell::new(false));
+        let deadline = Rc::new(Cell::new(Instant::now()));
+
+        let mut engine = Engine::new();
+        engine
+            .set_module_resolver(DummyModuleResolver::new())
+            .disable_symbol("eval")
+            .set_max_operations(limits.max_operations)
+            .set_max_call_levels(32)
+            .set_max_expr_depths(64, 32)
+            .set_max_string_size(10_000)
+            .set_max_array_size(10_000)
+            .set_max_map_size(1_000);
+        let running_until = deadline.clone();
+        engine.on_progress(move |operations| {
+            // the clock is only read every so often, it costs more than an operation
+            if operations % 1024 == 0 && Instant::now() > running_until.get() {
+                Some(Dynamic::UNIT)
+            } else {
+                None
+            }
+        });
+        let source_name = name.to_string();
+        engine.on_print(move |x| log_info!("script {}: {}", source_name, x));
+        let source_name = name.to_string();
+        engine.on_debug(move |x, _, _| log_debug!("script {}: {}", source_name, x));
+        register_indicators(
+            &mut engine,
+            &Made {
+                indicators: indicators.clone(),
+                in_init: in_init.clone(),
+            },
+        );
+
+        let ast = engine
+            .compile(source)
+            .map_err(|e| format!("script {}: {}", name, e))?;
+        let has = |f: &str| {
+            ast.iter_functions()
+                .any(|x| x.name == f && x.params.len() == 1)
+        };
+        if !has("run") {
+            return Err(format!("script {} has no fn run(bar)", name));
+        }
+
+        let timeout = Duration::from_millis(limits.timeout_ms);
+        // top level statements run once, when the script loads
+        deadline.set(Instant::now() + timeout);
+        engine
+            .run_ast(&ast)
+            .map_err(|e| format!("script {}: {}", name, e))?;
+        let mut state: Dynamic = Map::new().into();
+        if has("init") {
+            let settings: Array = strategy_settings
+                .iter()
+                .map(|x| Dynamic::from(*x))
+                .collect();
+            in_init.set(true);
+            deadline.set(Instant::now() + timeout);
+            let result = engine.call_fn_with_options::<Dynamic>(
+                CallFnOptions::new().eval_ast(false),
+                &mut Scope::new(),
+                &ast,
+                "init",
+                (settings,),
+            );
+            in_init.set(false);
+            state = result.map_err(|e| format!("script {}: init: {}", name, e))?;
+        }
+
+        Ok(ScriptStrategy {
+            name: name.to_string(),
+            strategy_settings,
+            engine,
+            ast,
+            state,
+            indicators,
+            deadline,
+            timeout,
+            bars: 0,
+        })
+    }
+}
+
+impl TradingStrategy for ScriptStrategy {
+    fn run(&mut self, ohlc: &Vec<f64>) -> i8 {
+        // in a combination only the side of the target counts
+        match self.target(ohlc) {
+            Some(x) if x.weight > 0.0 => 1,
+            Some(_) => -1,
+            None => 0,
+        }
+    }
+
+    fn target(&mut self, ohlc: &Vec<f64>) -> Option<Target> {
+        /*
+            A script that fails, runs out of budget or returns something it can't, holds on that bar.
+        */
+        let bar = Ohlcv::from_slice(ohlc);
+        self.bars += 1;
+        self.deadline.set(Instant::now() + self.timeout);
+        let result = self.engine.call_fn_with_options::<Dynamic>(
+            CallFnOptions::new()
+                .eval_ast(false)
+                .bind_this_ptr(&mut self.state),
+            &mut Scope::new(),
+            &self.ast,
+            "run",
+            (bar,),
+        );
+        match result
+            .map_err(|e| e.to_string())
+            .and_then(|x| to_target(&x))
+        {
+            Ok(target) => target,
+            Err(e) => {
+                log_warn!("script {}: {}. holding.", self.name, e);
+                None
+            }
+        }
+    }
+
+    fn warm_up(&self) -> u64 {
+        self.indicators
+            .borrow()
+            .iter()
+            .map(|x| x.0.borrow().warm_up())
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn is_ready(&self) -> bool {
+        self.bars >= self.warm_up()
+            && self
+                .indicators
+                .borrow()
+                .iter()
+                .all(|x| x.0.borrow().is_ready())
+    }
+
+    fn to_string(&self) -> String {
+        format!("script {} {:?}", self.name, self.strategy_settings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::strategies::sma_crossover::SMACrossover;
+
+    const CROSSOVER: &str = r#"
+        fn init(settings) {
+            #{ fast: sma(settings[0]), slow: sma(settings[1]) }
+        }
+
+        fn run(bar) {
+            let fast = this.fast.next(bar.close);
+            let slow = this.slow.next(bar);
+            if fast > slow { 1 } else if fast < slow { -1 } else { 0 }
+        }
+    "#;
+
+    fn rows() -> Vec<Vec<f64>> {
+        // up from 100 to 115 and back down, a point either side of the close
+        (0..15)
+            .chain((0..15).rev())
+            .map(|x| {
+                let close = 100.0 + x as f64;
+                vec![close, close + 1.0, close - 1.0, close, 1.0]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn runs_like_the_rust_strategy() {
+        let mut script = ScriptStrategy::from_source(
+            "crossover",
+            CROSSOVER,
+            vec![3.0, 8.0],
+            ScriptLimits::default(),
+        )
+        .unwrap();
+        let mut rust = SMACrossover::new(vec![3.0, 8.0]);
+        assert_eq!(script.warm_up(), 8);
+        for (i, row) in rows().iter().enumerate() {
+            assert_eq!(script.run(row), rust.run(row), "bar {}", i);
+            assert_eq!(script.is_ready(), rust.is_ready(), "bar {}", i);
+        }
+    }
+
+    #[test]
+    fn is_sandboxed() {
+        let limits = ScriptLimits {
+            max_operations: 10_000,
+            timeout_ms: 1_000,
+        };
+        let endless = "fn run(bar) { loop { } }";
+        let mut script = ScriptStrategy::from_source("endless", endless, vec![], limits).unwrap();
+        assert_eq!(script.run(&rows()[0]), 0);
+
+        let late = "fn run(bar) { let x = sma(5); 1 }";
+        let mut script = ScriptStrategy::from_source("late", late, vec![], limits).unwrap();
+        assert_eq!(script.run(&rows()[0]), 0);
+        assert_eq!(script.warm_up(), 0);
+
+        let wrong = "fn run(bar) { 2 }";
+        let mut script = ScriptStrategy::from_source("wrong", wrong, vec![], limits).unwrap();
+        assert_eq!(script.run(&rows()[0]), 0);
+
+        let weighted = "fn run(bar) { #{ weight: 0.35, stop: bar.low } }";
+        let mut script = ScriptStrategy::from_source("weighted", weighted, vec![], limits).unwrap();
+        let target = script.target(&rows()[0]).unwrap();
+        assert_eq!((target.weight, target.stop), (0.35, Some(rows()[0][2])));
+        assert_eq!(script.run(&rows()[0]), 1);
+
+        for source in &[
+            "fn init(settings) { #{} }",
+            "fn run(bar) { 1",
+            "fn init(settings) { sma(0) } fn run(bar) { 1 }",
+            "fn init(settings) { sma(1e18) } fn run(bar) { 1 }",
+            "fn init(settings) { macd(26, 12, 9) } fn run(bar) { 1 }",
+            "fn init(settings) { macd(12, 12, 9) } fn run(bar) { 1 }",
+            "import \"other\" as other; fn run(bar) { 1 }",
+        ] {
+            assert!(ScriptStrategy::from_source("bad", source, vec![], limits).is_err());
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use super::TradingStrategy;
+use crate::strategies::indicators::Ohlcv;
 
 use ta::indicators::SimpleMovingAverage;
 use ta::Next;
@@ -8,14 +9,15 @@ pub struct SMACrossover {
     strategy_settings: Vec<f64>,
     short_sma: SimpleMovingAverage,
     long_sma: SimpleMovingAverage,
+    bars: u64,
 }
 
-impl TradingStrategy for SMACrossover {
-    fn new(strategy_settings: Vec<f64>) -> Self {
+impl SMACrossover {
+    pub fn new(strategy_settings: Vec<f64>) -> Self {
         let short_lookback = strategy_settings[0] as u32;
         let long_lookback = strategy_settings[1] as u32;
 
-        println!(
+        log_debug!(
             "Constructing SMACrossover with short_lookback: {}, and long_lookback: {}",
             short_lookback, long_lookback
         );
@@ -24,11 +26,17 @@ impl TradingStrategy for SMACrossover {
             strategy_settings,
             short_sma: SimpleMovingAverage::new(short_lookback).unwrap(),
             long_sma: SimpleMovingAverage::new(long_lookback).unwrap(),
+            bars: 0,
         }
     }
+}
+
+impl TradingStrategy for SMACrossover {
     fn run(&mut self, ohlc: &Vec<f64>) -> i8 {
-        let curr_short_sma = self.short_sma.next(ohlc[4]);
-        let curr_long_sma = self.long_sma.next(ohlc[4]);
+        let close = Ohlcv::from_slice(ohlc).close;
+        self.bars += 1;
+        let curr_short_sma = self.short_sma.next(close);
+        let curr_long_sma = self.long_sma.next(close);
         let mut current_signal = 0;
         if curr_short_sma > curr_long_sma {
             current_signal = 1;
@@ -39,6 +47,14 @@ impl TradingStrategy for SMACrossover {
         return current_signal;
     }
 
+    fn warm_up(&self) -> u64 {
+        self.strategy_settings[0].max(self.strategy_settings[1]) as u64
+    }
+
+    fn is_ready(&self) -> bool {
+        self.bars >= self.warm_up()
+    }
+
     fn to_string(&self) -> String {
         return format!("{:?}", self.strategy_settings);
     }
@@ -4,16 +4,28 @@ pub struct TemplateStrategy {
     strategy_settings: Vec<f64>,
 }
 
-impl TradingStrategy for TemplateStrategy {
-    fn new(strategy_settings: Vec<f64>) -> Self {
+impl TemplateStrategy {
+    pub fn new(strategy_settings: Vec<f64>) -> Self {
         TemplateStrategy {
             strategy_settings
         }
     }
+}
+
+impl TradingStrategy for TemplateStrategy {
     fn run(&mut self, ohlcs_in_window: &[Vec<f64>]) -> i8 {
         return 0;
     }
 
+    fn warm_up(&self) -> u64 {
+        // bars run needs before its signal counts. until is_ready, live trading ignores it.
+        0
+    }
+
+    fn is_ready(&self) -> bool {
+        true
+    }
+
     fn to_string(&self) -> String {
         return format!("{:?}", self.strategy_settings);
     }
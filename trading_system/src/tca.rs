@@ -0,0 +1,256 @@
+use crate::journal::{Event, JournalEntry};
+use crate::order_book::Side;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+// transaction cost analysis. every order the execution algorithms send is journaled as an OrderCost with its decision
+// price, the mid when it was sent, its fill vwap and commissions. the report adds them up by symbol, algo and hour.
+// see docs/tca.txt.
+
+const HOUR_MS: u64 = 60 * 60 * 1000;
+
+pub fn slippage_bps(side: Side, reference: f64, fill_price: f64) -> Option<f64> {
+    /*
+        How much worse than reference the fill was, in basis points. Positive is a cost.
+    */
+    if reference <= 0.0 || fill_price <= 0.0 {
+        return None;
+    }
+    let slippage = (fill_price - reference) / reference * 10000.0;
+    match side {
+        Side::Buy => Some(slippage),
+        Side::Sell => Some(-slippage),
+    }
+}
+
+pub fn commissions(response: &Value) -> Vec<(String, f64)> {
+    // commission of an order response's fills, added up per asset
+    let mut commissions: Vec<(String, f64)> = Vec::new();
+    for fill in response["fills"]
+        .as_array()
+        .map(|x| x.as_slice())
+        .unwrap_or(&[])
+    {
+        let asset = fill["commissionAsset"].as_str().unwrap_or("").to_string();
+        let amount: f64 = fill["commission"]
+            .as_str()
+            .and_then(|x| x.parse().ok())
+            .unwrap_or(0.0);
+        match commissions.iter_mut().find(|(x, _)| x == &asset) {
+            Some((_, total)) => *total += amount,
+            None => commissions.push((asset, amount)),
+        }
+    }
+    commissions
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CostSummary {
+    pub orders: usize,
+    // in the reporting currency
+    pub notional: f64,
+    // notional-weighted sums, divided by the weight when read
+    decision_bps: f64,
+    arrival_bps: f64,
+    arrival_notional: f64,
+    fee_bps: f64,
+}
+
+impl CostSummary {
+    fn add(&mut self, notional: f64, decision_bps: f64, arrival_bps: Option<f64>, fee_bps: f64) {
+        self.orders += 1;
+        self.notional += notional;
+        self.decision_bps += decision_bps * notional;
+        self.fee_bps += fee_bps * notional;
+        if let Some(arrival_bps) = arrival_bps {
+            self.arrival_bps += arrival_bps * notional;
+            self.arrival_notional += notional;
+        }
+    }
+
+    pub fn decision_bps(&self) -> f64 {
+        if self.notional > 0.0 {
+            self.decision_bps / self.notional
+        } else {
+            0.0
+        }
+    }
+
+    pub fn arrival_bps(&self) -> Option<f64> {
+        if self.arrival_notional > 0.0 {
+            Some(self.arrival_bps / self.arrival_notional)
+        } else {
+            None
+        }
+    }
+
+    pub fn fee_bps(&self) -> f64 {
+        if self.notional > 0.0 {
+            self.fee_bps / self.notional
+        } else {
+            0.0
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct TcaReport {
+    pub total: CostSummary,
+    pub by_symbol: BTreeMap<String, CostSummary>,
+    pub by_algo: BTreeMap<usize, CostSummary>,
+    // utc hour of day the order was sent
+    pub by_hour: BTreeMap<u64, CostSummary>,
+}
+
+impl TcaReport {
+    pub fn from_entries(entries: &[JournalEntry], since_ms: u64) -> Self {
+        /*
+            Adds up every OrderCost at or after since_ms. Orders are weighted by their notional in the
+            reporting currency, so big orders count for more than small ones.
+        */
+        let mut report = TcaReport::default();
+        for entry in entries.iter().filter(|x| x.timestamp >= since_ms) {
+            if let Event::OrderCost {
+                symbol,
+                side,
+                algo,
+                decision_price,
+                arrival_mid,
+                fill_price,
+                quote_qty,
+                fee_quote,
+                notional,
+                ..
+            } = &entry.event
+            {
+                let side = if side == "BUY" { Side::Buy } else { Side::Sell };
+                let decision_bps = match slippage_bps(side, *decision_price, *fill_price) {
+                    Some(x) => x,
+                    None => continue,
+                };
+                let arrival_bps = arrival_mid.and_then(|x| slippage_bps(side, x, *fill_price));
+                let fee_bps = if *quote_qty > 0.0 {
+                    fee_quote / quote_qty * 10000.0
+                } else {
+                    0.0
+                };
+                let add = |summary: &mut CostSummary| {
+                    summary.add(*notional, decision_bps, arrival_bps, fee_bps)
+                };
+                add(&mut report.total);
+                add(report.by_symbol.entry(symbol.clone()).or_default());
+                add(report.by_algo.entry(*algo).or_default());
+                add(report
+                    .by_hour
+                    .entry(entry.timestamp / HOUR_MS % 24)
+                    .or_default());
+            }
+        }
+        report
+    }
+
+    pub fn format(&self, reporting: &str) -> String {
+        let row = |name: String, summary: &CostSummary| {
+            format!(
+                "{:<12} {:>6} {:>16.2} {:>12.2} {:>12} {:>10.2} {:>10.2}\n",
+                name,
+                summary.orders,
+                summary.notional,
+                summary.decision_bps(),
+                summary
+                    .arrival_bps()
+                    .map(|x| format!("{:.2}", x))
+                    .unwrap_or_else(|| "-".to_string()),
+                summary.fee_bps(),
+                summary.decision_bps() + summary.fee_bps(),
+            )
+        };
+        let header = format!(
+            "{:<12} {:>6} {:>16} {:>12} {:>12} {:>10} {:>10}\n",
+            "",
+            "orders",
+            format!("notional {}", reporting),
+            "vs decision",
+            "vs arrival",
+            "fees",
+            "total"
+        );
+        let mut text = String::from("transaction costs in bps, positive is a cost\n");
+        text += &header;
+        text += &row("all".to_string(), &self.total);
+        text += "\nby symbol\n";
+        for (symbol, summary) in self.by_symbol.iter() {
+            text += &row(symbol.clone(), summary);
+        }
+        text += "\nby algo\n";
+        for (algo, summary) in self.by_algo.iter() {
+            text += &row(format!("algo {}", algo), summary);
+        }
+        text += "\nby hour(utc)\n";
+        for (hour, summary) in self.by_hour.iter() {
+            text += &row(format!("{:02}:00", hour), summary);
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn order_cost(
+        timestamp: u64,
+        symbol: &str,
+        side: &str,
+        algo: usize,
+        fill_price: f64,
+    ) -> JournalEntry {
+        JournalEntry {
+            seq: timestamp,
+            timestamp,
+            event: Event::OrderCost {
+                symbol: symbol.to_string(),
+                side: side.to_string(),
+                algo,
+                parent: Some(1),
+                order_id: 1,
+                decision_price: 100.0,
+                arrival_mid: Some(100.5),
+                fill_price,
+                quantity: 1.0,
+                quote_qty: fill_price,
+                commissions: vec![("BNB".to_string(), 0.0001)],
+                fee_quote: fill_price * 0.001,
+                notional: fill_price,
+            },
+        }
+    }
+
+    #[test]
+    fn adds_up_costs_by_symbol_algo_and_hour() {
+        let entries = vec![
+            order_cost(0, "ETHUSDT", "BUY", 0, 101.0),
+            order_cost(HOUR_MS, "ETHUSDT", "SELL", 1, 99.0),
+            order_cost(HOUR_MS + 1, "BTCUSDT", "BUY", 1, 100.0),
+        ];
+        let report = TcaReport::from_entries(&entries, 0);
+        assert_eq!(report.total.orders, 3);
+        // a buy at 101 and a sell at 99 both lose 100bps against the 100 close
+        let eth = &report.by_symbol["ETHUSDT"];
+        assert!((eth.decision_bps() - 100.0).abs() < 1e-9);
+        assert!((eth.fee_bps() - 10.0).abs() < 1e-9);
+        assert!((report.by_symbol["BTCUSDT"].arrival_bps().unwrap() + 49.75).abs() < 0.01);
+        assert_eq!(report.by_algo[&1].orders, 2);
+        assert_eq!(report.by_hour[&1].orders, 2);
+        assert_eq!(TcaReport::from_entries(&entries, HOUR_MS).total.orders, 2);
+
+        let response = json!({"fills": [
+            {"price": "100.0", "qty": "0.5", "commission": "0.001", "commissionAsset": "BNB"},
+            {"price": "100.1", "qty": "0.5", "commission": "0.002", "commissionAsset": "BNB"},
+        ]});
+        let commissions = commissions(&response);
+        assert_eq!(commissions.len(), 1);
+        assert!((commissions[0].1 - 0.003).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,153 @@
+use crate::binance_interface;
+use crate::helpers::epoch_ms;
+use crate::metrics;
+use crate::notifier::Notification;
+use crossbeam_channel::Sender;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use tokio::time::delay_for;
+
+// keeps an estimate of binance's clock so signed requests aren't rejected for bad timestamps(-1021).
+
+pub const SYNC_INTERVAL_SECS: u64 = 5 * 60;
+// requests per sync. the one with the shortest round trip gives the best offset.
+const SAMPLES_PER_SYNC: usize = 3;
+// local clock drift past this is reported
+pub const DRIFT_WARN_MS: i64 = 1000;
+const BASE_RECV_WINDOW_MS: u64 = 5000;
+// binance rejects anything above this
+const MAX_RECV_WINDOW_MS: u64 = 60000;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ClockEstimate {
+    // server time - local time
+    pub offset_ms: i64,
+    pub rtt_ms: u64,
+    pub synced_at: u64,
+}
+
+impl ClockEstimate {
+    pub fn from_sample(sent_ms: u64, server_ms: u64, received_ms: u64) -> Self {
+        /*
+            Assumes the server read its clock halfway through the round trip.
+        */
+        let rtt_ms = received_ms.saturating_sub(sent_ms);
+        ClockEstimate {
+            offset_ms: server_ms as i64 - (sent_ms + rtt_ms / 2) as i64,
+            rtt_ms,
+            synced_at: received_ms,
+        }
+    }
+
+    pub fn server_time_ms(&self, local_ms: u64) -> u64 {
+        (local_ms as i64 + self.offset_ms).max(0) as u64
+    }
+
+    pub fn recv_window_ms(&self) -> u64 {
+        // a slow link needs a wider window, since the request has to arrive before it expires
+        (self.rtt_ms * 2).clamp(BASE_RECV_WINDOW_MS, MAX_RECV_WINDOW_MS)
+    }
+}
+
+static CLOCK: OnceLock<Mutex<ClockEstimate>> = OnceLock::new();
+
+fn clock() -> &'static Mutex<ClockEstimate> {
+    CLOCK.get_or_init(|| Mutex::new(ClockEstimate::default()))
+}
+
+pub fn estimate() -> ClockEstimate {
+    *clock().lock().unwrap()
+}
+
+pub fn server_time_ms() -> u64 {
+    estimate().server_time_ms(epoch_ms())
+}
+
+pub fn recv_window_ms() -> u64 {
+    estimate().recv_window_ms()
+}
+
+pub async fn sync() -> Result<ClockEstimate, String> {
+    /*
+        Measures the offset against /api/v3/time and makes it the current estimate.
+    */
+    let mut best: Option<ClockEstimate> = None;
+    for _ in 0..SAMPLES_PER_SYNC {
+        let sent_ms = epoch_ms();
+        let server_time = binance_interface::binance_rest_api_async("test_time", "").await;
+        let received_ms = epoch_ms();
+        if let Some(server_ms) = server_time["serverTime"].as_u64() {
+            let sample = ClockEstimate::from_sample(sent_ms, server_ms, received_ms);
+            if best.map(|x| sample.rtt_ms < x.rtt_ms).unwrap_or(true) {
+                best = Some(sample);
+            }
+        }
+    }
+    let best = best.ok_or("no usable response from /api/v3/time")?;
+    *clock().lock().unwrap() = best;
+    metrics::global().set_clock(best.offset_ms, best.rtt_ms);
+    Ok(best)
+}
+
+pub async fn sync_and_report(logging_tx: &Sender<Notification>) {
+    match sync().await {
+        Ok(estimate) => {
+            log_debug!(
+                "clock offset: {}ms, rtt: {}ms, recvWindow: {}ms",
+                estimate.offset_ms,
+                estimate.rtt_ms,
+                estimate.recv_window_ms()
+            );
+            if estimate.offset_ms.abs() > DRIFT_WARN_MS {
+                log_warn!(
+                    "local clock is {}ms off from binance. signed requests are corrected, but check ntp.",
+                    estimate.offset_ms
+                );
+                let _ = logging_tx.send(Notification::warning(format!(
+                    "warning: local clock is {}ms off from binance server time.",
+                    estimate.offset_ms
+                )));
+            }
+        }
+        Err(e) => log_warn!("time sync failed: {}. keeping the last offset.", e),
+    }
+}
+
+pub async fn run(logging_tx: Sender<Notification>) {
+    // resyncs in the background for as long as the system runs
+    loop {
+        delay_for(Duration::from_secs(SYNC_INTERVAL_SECS)).await;
+        sync_and_report(&logging_tx).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offset_from_sample() {
+        // request left at 1000, came back at 1100, server said 1550 -> server is 500ms ahead
+        let estimate = ClockEstimate::from_sample(1000, 1550, 1100);
+        assert_eq!(estimate.rtt_ms, 100);
+        assert_eq!(estimate.offset_ms, 500);
+        assert_eq!(estimate.server_time_ms(2000), 2500);
+
+        let behind = ClockEstimate::from_sample(1000, 200, 1100);
+        assert_eq!(behind.offset_ms, -850);
+        assert_eq!(behind.server_time_ms(2000), 1150);
+    }
+
+    #[test]
+    fn recv_window_grows_with_rtt() {
+        assert_eq!(ClockEstimate::from_sample(0, 0, 100).recv_window_ms(), 5000);
+        assert_eq!(
+            ClockEstimate::from_sample(0, 0, 4000).recv_window_ms(),
+            8000
+        );
+        assert_eq!(
+            ClockEstimate::from_sample(0, 0, 90000).recv_window_ms(),
+            60000
+        );
+    }
+}
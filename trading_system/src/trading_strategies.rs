@@ -1,5 +1,15 @@
+use crate::notifier::Notification;
+use crate::strategies::adx::ADXTest;
+use crate::strategies::combinators::{
+    AllAgree, Child, Cooldown, EntryExit, MajorityVote, RegimeFilter, WeightedScore,
+};
+use crate::strategies::ema_sma_adx::EMASMAADX;
+use crate::strategies::ema_sma_crossover::EMASMACrossover;
+use crate::strategies::script::{ScriptLimits, ScriptStrategy};
+use crate::strategies::sma_crossover::SMACrossover;
 use crate::strategies::*;
-use std::sync::mpsc::Sender;
+use crossbeam_channel::Sender;
+use serde_json::Value;
 
 fn _sma_crossover(trades: &Vec<Vec<f64>>, _i_p_data: &Vec<f64>) -> (i32, Vec<f64>, String) {
     // NOT FUNCTIONAL
@@ -21,19 +31,19 @@ fn _sma_crossover(trades: &Vec<Vec<f64>>, _i_p_data: &Vec<f64>) -> (i32, Vec<f64
     let short_avg = short_sum / short_period as f64;
 
     if long_avg > short_avg {
-        println!(
+        log_debug!(
             "SMA Crossover: long_avg: {}, short_avg: {}. returning sell signal.",
             long_avg, short_avg
         );
         return (0, Vec::new(), String::new());
     } else if long_avg < short_avg {
-        println!(
+        log_debug!(
             "SMA Crossover: long_avg: {}, short_avg: {}. returning buy signal.",
             long_avg, short_avg
         );
         return (1, Vec::new(), String::new());
     } else {
-        println!(
+        log_debug!(
             "SMA Crossover: long_avg: {}, short_avg: {}. returning neutral signal.",
             long_avg, short_avg
         );
@@ -88,7 +98,7 @@ fn ema_sma_crossover(trades: &Vec<Vec<f64>>, i_p_data: &Vec<f64>) -> (i32, Vec<f
         signal = 0;
     }
 
-    println!("{}", log_str);
+    log_debug!("{}", log_str);
     return (signal, vec![new_ema], log_str);
 }
 
@@ -98,36 +108,299 @@ fn experimental_ema_sma_crossover(trades: &Vec<Vec<f64>>, i_p_data: &Vec<f64>) -
 }
 */
 
+type Strategy = fn(&Vec<Vec<f64>>, &Vec<f64>) -> (i32, Vec<f64>, String);
+
+// no signal: the strategy isn't warm yet, or it holds
+pub const NO_SIGNAL: i32 = -2;
+
+// bars of history kept per pair, a day of 1m bars. the action loop trims to it, and so does replay.
+pub const HISTORY_BARS: u64 = 24 * 60;
+
+pub fn strategy_by_name(name: &str) -> Option<(Strategy, u64)> {
+    // strategies an account can list in accounts.json, with the bars of history they look at
+    match name {
+        "ema_sma_crossover" => Some((ema_sma_crossover, 24 * 60)),
+        _ => None,
+    }
+}
+
+fn stateful_by_name(
+    name: &str,
+    settings: &[f64],
+) -> Option<Result<Box<dyn TradingStrategy>, String>> {
+    /*
+        The strategies in strategies/, listed as "<name>:<setting>,<setting>,...".
+        Checks the number of settings, and that the periods among them are at least 1.
+    */
+    // number of settings, how many of them are periods, and what they are
+    let (count, periods, usage) = match name {
+        "sma_crossover" => (2, 2, "<short>,<long>"),
+        "ema_sma_crossover" => (2, 2, "<ema>,<sma>"),
+        "adx" => (3, 2, "<di period>,<adx period>,<threshold>"),
+        "ema_sma_adx" => (5, 4, "<ema>,<sma>,<di period>,<adx period>,<threshold>"),
+        _ => return None,
+    };
+    if settings.len() != count {
+        return Some(Err(format!(
+            "strategy {} takes {} settings, {}:{}",
+            name, count, name, usage
+        )));
+    }
+    if settings[..periods].iter().any(|x| x.is_nan() || *x < 1.0) {
+        return Some(Err(format!(
+            "strategy {}: periods must be at least 1",
+            name
+        )));
+    }
+    let settings = settings.to_vec();
+    Some(Ok(match name {
+        "sma_crossover" => Box::new(SMACrossover::new(settings)),
+        "ema_sma_crossover" => Box::new(EMASMACrossover::new(settings)),
+        "adx" => Box::new(ADXTest::new(settings)),
+        _ => Box::new(EMASMAADX::new(settings)),
+    }))
+}
+
+fn stateful_from_value(value: &Value) -> Result<Box<dyn TradingStrategy>, String> {
+    /*
+        A stateful strategy from the strategy config: "<name>:<settings>", or an object combining others,
+            {"all": [..]}, {"vote": [..]}, {"weighted": [..], "weights": [..], "threshold": 0.5},
+            {"entry": .., "exit": ..}, {"strategy": .., "regime": ..} or {"strategy": .., "cooldown": <bars>}
+        where every .. is a strategy of its own. see combinators.rs.
+        A rhai script is "script:<path>" or {"script": <path>, "settings": [..]}, see script.rs.
+    */
+    if let Some(spec) = value.as_str() {
+        let (name, settings) = spec.split_once(':').ok_or(format!(
+            "{} has no settings, combinations take stateful strategies",
+            spec
+        ))?;
+        if name.trim() == "script" {
+            return ScriptStrategy::new(settings.trim(), vec![], ScriptLimits::default())
+                .map(|x| Box::new(x) as Box<dyn TradingStrategy>);
+        }
+        let settings = settings
+            .split(',')
+            .map(|x| {
+                x.trim()
+                    .parse::<f64>()
+                    .map_err(|_| format!("strategy {}: {} is not a number", spec, x))
+            })
+            .collect::<Result<Vec<f64>, String>>()?;
+        return stateful_by_name(name.trim(), &settings)
+            .unwrap_or_else(|| Err(format!("unknown strategy {}", name)));
+    }
+
+    let list = |field: &str| -> Result<Vec<Child>, String> {
+        value[field]
+            .as_array()
+            .filter(|x| !x.is_empty())
+            .ok_or(format!("{} must be a list of strategies", field))?
+            .iter()
+            .map(stateful_from_value)
+            .collect()
+    };
+    let one = |field: &str| stateful_from_value(&value[field]);
+    if let Some(path) = value["script"].as_str() {
+        let settings = match value["settings"].as_array() {
+            Some(settings) => settings
+                .iter()
+                .map(|x| x.as_f64())
+                .collect::<Option<Vec<f64>>>()
+                .ok_or("script settings must be numbers")?,
+            None => vec![],
+        };
+        let defaults = ScriptLimits::default();
+        let limits = ScriptLimits {
+            max_operations: value["max_operations"]
+                .as_u64()
+                .unwrap_or(defaults.max_operations),
+            timeout_ms: value["timeout_ms"].as_u64().unwrap_or(defaults.timeout_ms),
+        };
+        Ok(Box::new(ScriptStrategy::new(path, settings, limits)?))
+    } else if !value["all"].is_null() {
+        Ok(Box::new(AllAgree::new(list("all")?)))
+    } else if !value["vote"].is_null() {
+        Ok(Box::new(MajorityVote::new(list("vote")?)))
+    } else if !value["weighted"].is_null() {
+        let children = list("weighted")?;
+        let weights = match value["weights"].as_array() {
+            Some(weights) => weights
+                .iter()
+                .map(|x| x.as_f64())
+                .collect::<Option<Vec<f64>>>()
+                .ok_or("weights must be numbers")?,
+            None => vec![1.0; children.len()],
+        };
+        if weights.len() != children.len() || weights.iter().all(|x| *x == 0.0) {
+            return Err("weighted needs one weight per strategy, not all 0".to_string());
+        }
+        let threshold = value["threshold"].as_f64().unwrap_or(0.5);
+        if !(threshold > 0.0 && threshold <= 1.0) {
+            return Err("weighted threshold must be above 0 and at most 1".to_string());
+        }
+        Ok(Box::new(WeightedScore::new(children, weights, threshold)))
+    } else if !value["entry"].is_null() || !value["exit"].is_null() {
+        Ok(Box::new(EntryExit::new(one("entry")?, one("exit")?)))
+    } else if !value["regime"].is_null() {
+        Ok(Box::new(RegimeFilter::new(
+            one("strategy")?,
+            one("regime")?,
+        )))
+    } else if !value["cooldown"].is_null() {
+        let bars = value["cooldown"]
+            .as_u64()
+            .ok_or("cooldown must be a number of bars")?;
+        Ok(Box::new(Cooldown::new(one("strategy")?, bars)))
+    } else {
+        Err(format!("{} is not a strategy", value))
+    }
+}
+
+// what one algo runs on one pair
+pub enum PairStrategy {
+    // looks at the whole history window on every bar, and needs that many bars
+    Window(Strategy, u64),
+    // keeps its own state, fed one bar at a time
+    Stateful(Box<dyn TradingStrategy>),
+    // a spec that passed when the account was loaded but can't be built now, e.g. a script that was
+    // changed since. holds its algo's place and never signals.
+    Failed(String),
+}
+
+impl PairStrategy {
+    pub fn from_spec(spec: &str) -> Result<Self, String> {
+        /*
+            "ema_sma_crossover" is a window strategy, "sma_crossover:50,200" a stateful one with its settings,
+            and a json object a combination of stateful ones.
+        */
+        let spec = spec.trim();
+        if spec.starts_with('{') {
+            let value: Value = serde_json::from_str(spec)
+                .map_err(|e| format!("strategy {} is not valid json: {}", spec, e))?;
+            return stateful_from_value(&value).map(PairStrategy::Stateful);
+        }
+        if !spec.contains(':') {
+            return strategy_by_name(spec)
+                .map(|(strategy, warm_up)| PairStrategy::Window(strategy, warm_up))
+                .ok_or(format!("unknown strategy {}", spec));
+        }
+        stateful_from_value(&Value::String(spec.to_string())).map(PairStrategy::Stateful)
+    }
+
+    pub fn warm_up(&self) -> u64 {
+        match self {
+            PairStrategy::Window(_, warm_up) => *warm_up,
+            PairStrategy::Stateful(strategy) => strategy.warm_up(),
+            PairStrategy::Failed(_) => 0,
+        }
+    }
+
+    pub fn is_ready(&self, history_len: usize) -> bool {
+        match self {
+            PairStrategy::Window(_, warm_up) => history_len as u64 >= *warm_up,
+            PairStrategy::Stateful(strategy) => strategy.is_ready(),
+            PairStrategy::Failed(_) => false,
+        }
+    }
+}
+
+pub fn pair_strategies(specs: &[String]) -> Vec<PairStrategy> {
+    /*
+        Fresh strategies for one pair, one per algo. Specs are checked when accounts are loaded, but one that
+        fails now still takes its algo's place, so the ones after it stay on their own algos.
+    */
+    specs
+        .iter()
+        .map(|x| {
+            PairStrategy::from_spec(x).unwrap_or_else(|e| {
+                log_error!("strategy {} can't be built, its algo won't trade: {}", x, e);
+                PairStrategy::Failed(e)
+            })
+        })
+        .collect()
+}
+
+pub fn warm(strategies: &mut [PairStrategy], bars: &[Vec<f64>]) {
+    /*
+        Feeds history through the stateful strategies, ignoring their signals, so they are ready when
+        live bars come in. Window strategies only need the history itself.
+    */
+    for strategy in strategies.iter_mut() {
+        if let PairStrategy::Stateful(strategy) = strategy {
+            for bar in bars {
+                strategy.run(bar);
+            }
+            log_debug!(
+                "warmed {} on {} bars, ready: {}",
+                strategy.to_string(),
+                bars.len(),
+                strategy.is_ready()
+            );
+        }
+    }
+}
+
+pub fn signal_of(target: &Option<Target>) -> i32 {
+    // the signal a target is journaled as, 1 for any position, 0 for none
+    match target {
+        Some(target) if target.weight > 0.0 => 1,
+        Some(_) => 0,
+        None => NO_SIGNAL,
+    }
+}
+
 pub fn master_strategy(
+    strategies: &mut [PairStrategy],
     trades: &Vec<Vec<f64>>,
     incoming_p_data: &Vec<Vec<f64>>,
-    logging_tx: &Sender<String>,
-) -> (Vec<i32>, Vec<Vec<f64>>) {
+    logging_tx: &Sender<Notification>,
+) -> (Vec<Option<Target>>, Vec<Vec<f64>>) {
     /*
         One function to call all the strategies that are needed.
         Parameters:
+            strategies:
+                the pair's strategies, one per algorithm.
             trades:
-                trades that are within the window required
+                trades that are within the window required. stateful strategies get the last one.
             incoming_p_data:
                 p_data that was returned from the algorithms last run
         Returns:
-            HashMap:
-                maps the ID of the algorithm to the result it returned.
+            the target of every algorithm, or None to hold, and its new p_data. a window strategy's 1 is all in
+            and 0 all out. an algorithm whose strategy isn't warm yet holds and places no orders.
     */
 
-    let strategies_list: Vec<&dyn Fn(&Vec<Vec<f64>>, &Vec<f64>) -> (i32, Vec<f64>, String)> =
-        vec![&ema_sma_crossover];
-
-    let mut signals = Vec::new();
+    let mut targets = Vec::new();
     let mut p_data = Vec::new();
-    let mut i = 0;
-    for strategy in strategies_list {
-        let (signal, p_data_piece, logging_str) = strategy(trades, &incoming_p_data[i]);
-        let _ = logging_tx.send(format!("algo_logs: {}", logging_str));
-        signals.push(signal);
+    for (i, strategy) in strategies.iter_mut().enumerate() {
+        let (target, p_data_piece) = match strategy {
+            PairStrategy::Window(strategy, warm_up) => {
+                if (trades.len() as u64) < *warm_up {
+                    (None, incoming_p_data[i].clone())
+                } else {
+                    let (signal, p_data_piece, logging_str) = strategy(trades, &incoming_p_data[i]);
+                    let _ = logging_tx
+                        .send(Notification::routine(format!("algo_logs: {}", logging_str)));
+                    (Some(Target::weight(if signal == 1 { 1.0 } else { 0.0 })), p_data_piece)
+                }
+            }
+            PairStrategy::Stateful(strategy) => {
+                let target = trades.last().and_then(|x| strategy.target(x));
+                (target.filter(|_| strategy.is_ready()), Vec::new())
+            }
+            PairStrategy::Failed(_) => (None, incoming_p_data[i].clone()),
+        };
+        if let PairStrategy::Failed(e) = strategy {
+            log_debug!("algo {} holds, its strategy failed: {}", i, e);
+        } else if !strategy.is_ready(trades.len()) {
+            log_debug!(
+                "algo {} is warming up, it needs {} bars.",
+                i,
+                strategy.warm_up()
+            );
+        }
+        targets.push(target);
         p_data.push(p_data_piece);
-        i += 1;
     }
 
-    return (signals, p_data);
+    return (targets, p_data);
 }
@@ -0,0 +1,324 @@
+use crate::account::AccountConfig;
+use crate::binance_interface;
+use crate::helpers::epoch_ms;
+use crate::market::{self, Pair};
+use crossbeam_channel::Sender;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::thread;
+use std::time::Duration;
+
+// picks an account's pairs from exchange info and the 24h ticker stats, e.g. "the top 10 USDT pairs by quote volume".
+// the universe is rebuilt on a schedule and sent to the account's action thread. see _Universe_ in docs/accounts.txt.
+
+const DEFAULT_REFRESH_MINUTES: u64 = 60;
+// how often the refresh thread checks whether an account's universe is due
+const POLL_SECS: u64 = 60;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RankBy {
+    QuoteVolume,
+    Volume,
+    Trades,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TickerStats {
+    pub symbol: String,
+    pub volume: f64,
+    pub quote_volume: f64,
+    pub trades: u64,
+    pub bid: f64,
+    pub ask: f64,
+}
+
+impl TickerStats {
+    pub fn from_value(ticker: &Value) -> Option<Self> {
+        /*
+            Reads one entry of /api/v3/ticker/24hr.
+        */
+        let number = |field: &str| -> Option<f64> { ticker[field].as_str()?.parse().ok() };
+        Some(TickerStats {
+            symbol: ticker["symbol"].as_str()?.to_string(),
+            volume: number("volume")?,
+            quote_volume: number("quoteVolume")?,
+            trades: ticker["count"].as_u64().unwrap_or(0),
+            bid: number("bidPrice").unwrap_or(0.0),
+            ask: number("askPrice").unwrap_or(0.0),
+        })
+    }
+
+    pub fn spread_bps(&self) -> Option<f64> {
+        // None when the book is empty or crossed
+        if self.bid <= 0.0 || self.ask < self.bid {
+            return None;
+        }
+        let mid = (self.bid + self.ask) / 2.0;
+        Some((self.ask - self.bid) / mid * 10000.0)
+    }
+
+    fn rank(&self, rank_by: RankBy) -> f64 {
+        match rank_by {
+            RankBy::QuoteVolume => self.quote_volume,
+            RankBy::Volume => self.volume,
+            RankBy::Trades => self.trades as f64,
+        }
+    }
+}
+
+pub fn stats_from_ticker_24hr(tickers: &Value) -> HashMap<String, TickerStats> {
+    let mut stats = HashMap::new();
+    for ticker in tickers.as_array().map(|x| x.as_slice()).unwrap_or(&[]) {
+        if let Some(ticker_stats) = TickerStats::from_value(ticker) {
+            stats.insert(ticker_stats.symbol.clone(), ticker_stats);
+        }
+    }
+    stats
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct UniverseRules {
+    // quote assets to pick pairs from, default the account's home asset
+    pub quote_assets: Vec<String>,
+    // keep only the best ranked pairs. None keeps every pair that passes the filters.
+    pub top: Option<usize>,
+    pub rank_by: RankBy,
+    pub min_quote_volume: Option<f64>,
+    pub max_spread_bps: Option<f64>,
+    // order types a pair must accept. the trading loop sends market orders.
+    pub order_types: Vec<String>,
+    pub exclude: Vec<String>,
+    pub refresh_minutes: u64,
+}
+
+impl UniverseRules {
+    pub fn from_value(rules: &Value, home: &str) -> Result<Self, String> {
+        let string_list = |field: &str, default: Vec<String>| -> Result<Vec<String>, String> {
+            if rules[field].is_null() {
+                return Ok(default);
+            }
+            rules[field]
+                .as_array()
+                .and_then(|x| {
+                    x.iter()
+                        .map(|y| y.as_str().map(|z| z.to_uppercase()))
+                        .collect()
+                })
+                .ok_or(format!("universe: {} must be a list of strings", field))
+        };
+        let rank_by = match rules["rank_by"].as_str().unwrap_or("quote_volume") {
+            "quote_volume" => RankBy::QuoteVolume,
+            "volume" => RankBy::Volume,
+            "trades" => RankBy::Trades,
+            other => return Err(format!("universe: unknown rank_by {}", other)),
+        };
+        let refresh_minutes = rules["refresh_minutes"]
+            .as_u64()
+            .unwrap_or(DEFAULT_REFRESH_MINUTES);
+        if refresh_minutes == 0 {
+            return Err("universe: refresh_minutes must be at least 1".to_string());
+        }
+        Ok(UniverseRules {
+            quote_assets: string_list("quote", vec![home.to_string()])?,
+            top: rules["top"].as_u64().map(|x| x as usize),
+            rank_by,
+            min_quote_volume: rules["min_quote_volume"].as_f64(),
+            max_spread_bps: rules["max_spread_bps"].as_f64(),
+            order_types: string_list("order_types", vec!["MARKET".to_string()])?,
+            exclude: string_list("exclude", Vec::new())?,
+            refresh_minutes,
+        })
+    }
+
+    pub fn refresh_ms(&self) -> u64 {
+        self.refresh_minutes * 60 * 1000
+    }
+
+    pub fn select(
+        &self,
+        all_pairs: &HashMap<String, Pair>,
+        stats: &HashMap<String, TickerStats>,
+    ) -> Vec<String> {
+        /*
+            Symbols that pass every rule, best ranked first.
+            Pairs without 24h stats or without the lot size and notional filters are left out.
+        */
+        let mut candidates: Vec<(&Pair, &TickerStats)> = all_pairs
+            .values()
+            .filter(|pair| {
+                pair.status == "TRADING"
+                    && pair.step_size.is_some()
+                    && pair.min_notional.is_some()
+                    && self.quote_assets.contains(&pair.quote)
+                    && !self.exclude.contains(&pair.symbol)
+                    && self
+                        .order_types
+                        .iter()
+                        .all(|x| pair.order_types.contains(x))
+            })
+            .filter_map(|pair| stats.get(&pair.symbol).map(|x| (pair, x)))
+            .filter(|(_, ticker)| {
+                self.min_quote_volume
+                    .is_none_or(|x| ticker.quote_volume >= x)
+                    && self
+                        .max_spread_bps
+                        .is_none_or(|x| ticker.spread_bps().is_some_and(|spread| spread <= x))
+            })
+            .collect();
+        candidates.sort_by(|a, b| {
+            b.1.rank(self.rank_by)
+                .partial_cmp(&a.1.rank(self.rank_by))
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then_with(|| a.0.symbol.cmp(&b.0.symbol))
+        });
+        if let Some(top) = self.top {
+            candidates.truncate(top);
+        }
+        candidates
+            .into_iter()
+            .map(|(pair, _)| pair.symbol.clone())
+            .collect()
+    }
+}
+
+pub fn realign<T: Clone>(
+    old_symbols: &[String],
+    new_symbols: &[String],
+    values: &[T],
+    fill: T,
+) -> Vec<T> {
+    /*
+        Moves per-pair values over to a new symbol list. Pairs that stay keep their value, new pairs get fill.
+    */
+    new_symbols
+        .iter()
+        .map(|symbol| {
+            old_symbols
+                .iter()
+                .position(|x| x == symbol)
+                .and_then(|x| values.get(x).cloned())
+                .unwrap_or_else(|| fill.clone())
+        })
+        .collect()
+}
+
+pub fn realign_with<T>(
+    old_symbols: &[String],
+    new_symbols: &[String],
+    values: Vec<T>,
+    mut fill: impl FnMut(&str) -> T,
+) -> Vec<T> {
+    /*
+        realign for values that can't be cloned. They are moved over, and new pairs get fill(symbol).
+    */
+    let mut values: Vec<Option<T>> = values.into_iter().map(Some).collect();
+    new_symbols
+        .iter()
+        .map(|symbol| {
+            old_symbols
+                .iter()
+                .position(|x| x == symbol)
+                .and_then(|x| values.get_mut(x).and_then(|x| x.take()))
+                .unwrap_or_else(|| fill(symbol))
+        })
+        .collect()
+}
+
+pub fn run(accounts: Vec<(AccountConfig, Sender<Vec<Pair>>)>) {
+    /*
+        Rebuilds the universe of every account whose refresh is due and sends the selected pairs to its action thread.
+        Exchange info and the 24h stats are fetched once per round, for all the accounts that are due.
+    */
+    let mut next_refresh: Vec<u64> = accounts
+        .iter()
+        .map(|(config, _)| epoch_ms() + config.universe.as_ref().map_or(0, |x| x.refresh_ms()))
+        .collect();
+    loop {
+        thread::sleep(Duration::from_secs(POLL_SECS));
+        let time_now = epoch_ms();
+        if !next_refresh.iter().any(|x| *x <= time_now) {
+            continue;
+        }
+        let all_pairs = market::pairs_from_exchange_info(&binance_interface::binance_rest_api(
+            "exchange_info",
+            "",
+        ));
+        let stats = stats_from_ticker_24hr(&binance_interface::binance_rest_api("ticker_24hr", ""));
+        if all_pairs.is_empty() || stats.is_empty() {
+            log_warn!(target: "universe", "could not fetch exchange info or 24h stats, keeping the current universe.");
+            continue;
+        }
+        for (i, (config, universe_tx)) in accounts.iter().enumerate() {
+            if next_refresh[i] > time_now {
+                continue;
+            }
+            let pairs: Vec<Pair> = config
+                .select_symbols(&all_pairs, &stats)
+                .iter()
+                .filter_map(|x| all_pairs.get(x).cloned())
+                .collect();
+            log_debug!(target: "universe", "account {}: {} pairs selected", config.name, pairs.len());
+            let _ = universe_tx.send(pairs);
+            next_refresh[i] = time_now + config.universe.as_ref().map_or(0, |x| x.refresh_ms());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn selects_top_pairs_by_rules() {
+        let pair = |symbol: &str, quote: &str, status: &str, order_types: Value| {
+            json!({"symbol": symbol, "status": status, "baseAsset": &symbol[..symbol.len() - quote.len()], "quoteAsset": quote,
+                   "orderTypes": order_types, "filters": [
+                {"filterType": "LOT_SIZE", "stepSize": "0.00100000"},
+                {"filterType": "MIN_NOTIONAL", "minNotional": "10.00000000"},
+            ]})
+        };
+        let exchange_info = json!({"symbols": [
+            pair("ETHUSDT", "USDT", "TRADING", json!(["LIMIT", "MARKET"])),
+            pair("BTCUSDT", "USDT", "TRADING", json!(["LIMIT", "MARKET"])),
+            pair("LTCUSDT", "USDT", "TRADING", json!(["LIMIT", "MARKET"])),
+            pair("XRPUSDT", "USDT", "BREAK", json!(["LIMIT", "MARKET"])),
+            pair("DOGEUSDT", "USDT", "TRADING", json!(["LIMIT"])),
+            pair("ETHBTC", "BTC", "TRADING", json!(["LIMIT", "MARKET"])),
+            pair("ADAUSDT", "USDT", "TRADING", json!(["LIMIT", "MARKET"])),
+        ]});
+        let all_pairs = market::pairs_from_exchange_info(&exchange_info);
+        let ticker = |symbol: &str, quote_volume: &str, bid: &str, ask: &str| json!({"symbol": symbol, "volume": "1.0", "quoteVolume": quote_volume, "count": 100, "bidPrice": bid, "askPrice": ask});
+        let stats = stats_from_ticker_24hr(&json!([
+            ticker("ETHUSDT", "5000000", "2000.00", "2000.10"),
+            ticker("BTCUSDT", "9000000", "50000.00", "50001.00"),
+            ticker("LTCUSDT", "100000", "100.00", "100.01"),
+            ticker("XRPUSDT", "99000000", "0.50", "0.51"),
+            ticker("DOGEUSDT", "99000000", "0.10", "0.11"),
+            ticker("ETHBTC", "99000000", "0.04", "0.0401"),
+            ticker("ADAUSDT", "3000000", "1.00", "1.10"),
+        ]));
+        assert!((stats["ADAUSDT"].spread_bps().unwrap() - 952.38).abs() < 0.01);
+
+        let rules =
+            UniverseRules::from_value(&json!({"top": 2, "max_spread_bps": 50}), "USDT").unwrap();
+        assert_eq!(rules.quote_assets, vec!["USDT"]);
+        assert_eq!(rules.select(&all_pairs, &stats), vec!["BTCUSDT", "ETHUSDT"]);
+
+        let rules = UniverseRules::from_value(
+            &json!({"min_quote_volume": 1000000, "exclude": ["btcusdt"]}),
+            "USDT",
+        )
+        .unwrap();
+        assert_eq!(rules.select(&all_pairs, &stats), vec!["ETHUSDT", "ADAUSDT"]);
+
+        assert!(UniverseRules::from_value(&json!({"rank_by": "price"}), "USDT").is_err());
+    }
+
+    #[test]
+    fn realigns_values_to_new_symbols() {
+        let old = vec!["ETHUSDT".to_string(), "BTCUSDT".to_string()];
+        let new = vec!["BTCUSDT".to_string(), "ADAUSDT".to_string()];
+        assert_eq!(realign(&old, &new, &[1, 2], -2), vec![2, -2]);
+    }
+}